@@ -0,0 +1,311 @@
+//! Long-running agent holding the decrypted profiles in memory, which other invocations ask for
+//! them over a local socket instead of decrypting them again, like `ssh-agent`
+//!
+//! The agent only answers processes of the same user, checked through the credentials of the
+//! peer of each connection, and never writes what it decrypted to disk.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Name of the socket inside the project directory
+pub const SOCKET_FILE: &str = "agent.sock";
+
+/// Variable overriding the path of the socket
+pub const SOCKET_VARIABLE: &str = "TERRAFORM_PROFILE_AGENT_SOCK";
+
+/// Project directory the profiles are asked for in, set once at startup
+static PROJECT_DIRECTORY: OnceLock<PathBuf> = OnceLock::new();
+
+/// Whether this process is the agent, which reads the profiles itself
+static SERVING: AtomicBool = AtomicBool::new(false);
+
+/// Request of a profile, by name
+#[derive(Debug, Serialize, Deserialize)]
+struct Request {
+    profile: String,
+}
+
+/// Content of the profile asked for, or why the agent couldn't read it
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Response {
+    Content(String),
+    Error(String),
+}
+
+/// Ask the agent of a project directory for the profiles from now on
+pub fn configure(project_directory: &Path) {
+    let _ = PROJECT_DIRECTORY.set(project_directory.to_path_buf());
+}
+
+/// Get the path of the socket of the agent of a project directory
+pub fn socket_path(project_directory: &Path) -> PathBuf {
+    std::env::var_os(SOCKET_VARIABLE)
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| project_directory.join(SOCKET_FILE))
+}
+
+/// Get the name of a stored profile from its path, unless it is outside the store
+fn profile_name(profile_path: &Path) -> Option<&str> {
+    if profile_path.parent()? != PROJECT_DIRECTORY.get()? {
+        return None;
+    }
+    profile_path
+        .file_name()?
+        .to_str()?
+        .strip_suffix(".tfrc.json")
+}
+
+/// Ask the running agent for the decrypted content of a stored profile, or nothing when no agent
+/// runs or it couldn't answer, so the caller reads the profile itself
+pub fn read(profile_path: &Path) -> Option<String> {
+    if SERVING.load(Ordering::Relaxed) {
+        return None;
+    }
+    let name = profile_name(profile_path)?;
+    let socket = socket_path(PROJECT_DIRECTORY.get()?);
+    if !socket.exists() {
+        return None;
+    }
+    match platform::request(&socket, name) {
+        Ok(Response::Content(content)) => {
+            tracing::debug!("The agent on {} served `{name}`", socket.display());
+            Some(content)
+        }
+        Ok(Response::Error(error)) => {
+            tracing::info!("The agent couldn't read `{name}`: {error}");
+            None
+        }
+        Err(error) => {
+            tracing::info!(
+                "Couldn't reach the agent on {}: {error:#}",
+                socket.display()
+            );
+            None
+        }
+    }
+}
+
+/// Serve the profiles of a project directory until interrupted, forgetting the decrypted ones
+/// after `lifetime`
+pub fn serve(project_directory: &Path, lifetime: Option<std::time::Duration>) -> Result<()> {
+    SERVING.store(true, Ordering::Relaxed);
+    platform::serve(project_directory, &socket_path(project_directory), lifetime)
+}
+
+#[cfg(target_family = "unix")]
+mod platform {
+    use std::{
+        collections::HashMap,
+        io::{BufRead, BufReader, Write},
+        os::{fd::AsRawFd, unix::net::UnixStream},
+        path::Path,
+        time::{Duration, Instant, SystemTime},
+    };
+
+    use anyhow::{Context, Result};
+
+    use super::{Request, Response};
+    use crate::{encryption, output, permissions};
+
+    /// How long a client waits for the agent, which may be asking for a passphrase
+    const CLIENT_TIMEOUT: Duration = Duration::from_secs(120);
+
+    /// A decrypted profile, along with what tells whether the stored one changed since
+    struct Cached {
+        content: String,
+        modified: SystemTime,
+        decrypted_at: Instant,
+    }
+
+    /// Send a request to the agent listening on a socket
+    pub fn request(socket: &Path, profile: &str) -> Result<Response> {
+        let mut stream = UnixStream::connect(socket)?;
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        let mut request = serde_json::to_string(&Request {
+            profile: profile.to_string(),
+        })?;
+        request.push('\n');
+        stream.write_all(request.as_bytes())?;
+        let mut response = String::new();
+        BufReader::new(stream).read_line(&mut response)?;
+        serde_json::from_str(&response).context("The agent answered with invalid JSON")
+    }
+
+    /// Get the user identifier of the process on the other end of a connection
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn peer_uid(stream: &UnixStream) -> Result<libc::uid_t> {
+        let mut credentials = libc::ucred {
+            pid: 0,
+            uid: 0,
+            gid: 0,
+        };
+        let mut length = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        // SAFETY: the buffer and its length describe a valid `ucred`
+        let result = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                std::ptr::addr_of_mut!(credentials).cast(),
+                &mut length,
+            )
+        };
+        if result != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(credentials.uid)
+    }
+
+    /// Get the user identifier of the process on the other end of a connection
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn peer_uid(stream: &UnixStream) -> Result<libc::uid_t> {
+        let (mut uid, mut gid) = (0, 0);
+        // SAFETY: both pointers are to valid identifiers
+        if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(uid)
+    }
+
+    /// Read a stored profile, from memory unless it changed or outlived its lifetime
+    fn read(
+        project_directory: &Path,
+        name: &str,
+        cache: &mut HashMap<String, Cached>,
+        lifetime: Option<Duration>,
+    ) -> Result<String> {
+        crate::store::validate_profile_name(name)?;
+        let profile_path = project_directory.join(format!("{name}.tfrc.json"));
+        let modified = std::fs::metadata(&profile_path)
+            .with_context(|| format!("There is no profile `{name}`"))?
+            .modified()?;
+        if let Some(cached) = cache.get(name) {
+            let expired = lifetime.is_some_and(|lifetime| cached.decrypted_at.elapsed() > lifetime);
+            if cached.modified == modified && !expired {
+                return Ok(cached.content.clone());
+            }
+        }
+        tracing::info!("Decrypting profile `{name}`");
+        let content = encryption::read_profile(&profile_path)?;
+        cache.insert(
+            name.to_string(),
+            Cached {
+                content: content.clone(),
+                modified,
+                decrypted_at: Instant::now(),
+            },
+        );
+        Ok(content)
+    }
+
+    /// Answer a connection, once its peer was checked
+    fn answer(
+        stream: UnixStream,
+        project_directory: &Path,
+        cache: &mut HashMap<String, Cached>,
+        lifetime: Option<Duration>,
+    ) -> Result<()> {
+        let mut reader = BufReader::new(&stream);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let request: Request = serde_json::from_str(&line).context("Invalid request")?;
+        let response = match read(project_directory, &request.profile, cache, lifetime) {
+            Ok(content) => Response::Content(content),
+            Err(error) => Response::Error(format!("{error:#}")),
+        };
+        let mut response = serde_json::to_string(&response)?;
+        response.push('\n');
+        (&stream).write_all(response.as_bytes())?;
+        Ok(())
+    }
+
+    /// Listen on a socket, answering the processes of the same user one at a time
+    pub fn serve(
+        project_directory: &Path,
+        socket: &Path,
+        lifetime: Option<Duration>,
+    ) -> Result<()> {
+        if socket.exists() {
+            if UnixStream::connect(socket).is_ok() {
+                anyhow::bail!("An agent already listens on {}", socket.display());
+            }
+            tracing::info!("Removing the stale socket {}", socket.display());
+            std::fs::remove_file(socket)?;
+        }
+        // The socket is created readable by its owner only, as other users could connect to it
+        // before it is restricted otherwise
+        // SAFETY: `umask` can't fail, and no other thread creates files meanwhile
+        let umask = unsafe { libc::umask(0o077) };
+        let listener = std::os::unix::net::UnixListener::bind(socket);
+        // SAFETY: as above, putting back the mask of the user
+        unsafe { libc::umask(umask) };
+        let listener =
+            listener.with_context(|| format!("Couldn't listen on {}", socket.display()))?;
+        permissions::restrict_file(socket)?;
+        output::notice(format!(
+            "Serving the profiles on {}, until interrupted",
+            socket.display()
+        ));
+
+        // SAFETY: `getuid` can't fail
+        let uid = unsafe { libc::getuid() };
+        let mut cache = HashMap::new();
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(error) => {
+                    tracing::warn!("Couldn't accept a connection: {error}");
+                    continue;
+                }
+            };
+            match peer_uid(&stream) {
+                Ok(peer) if peer == uid => {}
+                Ok(peer) => {
+                    tracing::warn!("Refused a connection from user {peer}");
+                    continue;
+                }
+                Err(error) => {
+                    tracing::warn!("Couldn't check the peer of a connection: {error}");
+                    continue;
+                }
+            }
+            if let Err(error) = answer(stream, project_directory, &mut cache, lifetime) {
+                tracing::warn!("Couldn't answer a request: {error:#}");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_family = "windows")]
+mod platform {
+    use std::{path::Path, time::Duration};
+
+    use anyhow::Result;
+
+    use super::Response;
+
+    /// There is no agent without Unix sockets
+    pub fn request(_socket: &Path, _profile: &str) -> Result<Response> {
+        anyhow::bail!("The agent isn't available on Windows")
+    }
+
+    /// There is no agent without Unix sockets
+    pub fn serve(
+        _project_directory: &Path,
+        _socket: &Path,
+        _lifetime: Option<Duration>,
+    ) -> Result<()> {
+        anyhow::bail!("The agent isn't available on Windows")
+    }
+}
@@ -0,0 +1,393 @@
+//! Minimal blocking client for the Terraform Cloud / Enterprise API
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+
+/// Timeout of the requests in seconds, set once from the configuration
+static TIMEOUT: AtomicU64 = AtomicU64::new(10);
+
+/// Use a different timeout for the requests of the clients created from now on
+pub fn set_timeout(seconds: u64) {
+    TIMEOUT.store(seconds, Ordering::Relaxed);
+}
+
+/// Timeout of the requests in seconds
+pub fn timeout() -> u64 {
+    TIMEOUT.load(Ordering::Relaxed)
+}
+
+/// Outcome of checking a token against its hostname
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenStatus {
+    /// The token was accepted by the API
+    Valid,
+    /// The API reported the token as expired
+    Expired,
+    /// The API refused the token
+    Unauthorized,
+}
+
+impl std::fmt::Display for TokenStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenStatus::Valid => write!(f, "valid"),
+            TokenStatus::Expired => write!(f, "expired"),
+            TokenStatus::Unauthorized => write!(f, "unauthorized"),
+        }
+    }
+}
+
+/// Every item of a paginated collection, along with their included resources
+#[derive(Debug, Default)]
+pub struct Collection {
+    /// The primary items of the collection
+    pub data: Vec<serde_json::Value>,
+    /// The related resources requested through `include`
+    pub included: Vec<serde_json::Value>,
+}
+
+impl Collection {
+    /// Find an included resource by its type and identifier
+    pub fn find_included(&self, kind: &str, id: &str) -> Option<&serde_json::Value> {
+        self.included
+            .iter()
+            .find(|resource| resource["type"] == kind && resource["id"] == id)
+    }
+}
+
+/// Client bound to a single hostname and token
+pub struct Client {
+    hostname: String,
+    token: String,
+    agent: ureq::Agent,
+}
+
+impl Client {
+    /// Create a client for the given hostname, authenticating with `token`
+    pub fn new(hostname: &str, token: &str) -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_secs(TIMEOUT.load(Ordering::Relaxed)))
+            .user_agent(concat!(
+                env!("CARGO_PKG_NAME"),
+                "/",
+                env!("CARGO_PKG_VERSION")
+            ))
+            .build();
+        Client {
+            hostname: hostname.to_string(),
+            token: token.to_string(),
+            agent,
+        }
+    }
+
+    /// Build an authenticated request on an API path such as `/api/v2/account/details`
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        tracing::info!("{method} https://{}{path}", self.hostname);
+        self.agent
+            .request(method, &format!("https://{}{path}", self.hostname))
+            .set("Authorization", &format!("Bearer {}", self.token))
+            .set("Accept", "application/vnd.api+json")
+    }
+
+    /// Perform an authenticated `GET` and parse the JSON response
+    fn get(&self, path: &str) -> Result<serde_json::Value> {
+        let body = self
+            .request("GET", path)
+            .call()
+            .with_context(|| format!("Request to {}{path} failed", self.hostname))?
+            .into_string()?;
+        serde_json::from_str(&body)
+            .with_context(|| format!("Invalid response from {}{path}", self.hostname))
+    }
+
+    /// Fetch the details of the account owning the token
+    pub fn account_details(&self) -> Result<serde_json::Value> {
+        self.get("/api/v2/account/details")
+    }
+
+    /// Fetch every item of a paginated collection such as `/api/v2/organizations`
+    fn get_all(&self, path: &str) -> Result<Collection> {
+        let separator = if path.contains('?') { '&' } else { '?' };
+        let mut collection = Collection::default();
+        let mut page = 1;
+        loop {
+            let response = self.get(&format!(
+                "{path}{separator}page[number]={page}&page[size]=100"
+            ))?;
+            if let Some(data) = response["data"].as_array() {
+                collection.data.extend(data.iter().cloned());
+            }
+            if let Some(included) = response["included"].as_array() {
+                collection.included.extend(included.iter().cloned());
+            }
+            match response["meta"]["pagination"]["next-page"].as_u64() {
+                Some(next) => page = next,
+                None => break,
+            }
+        }
+        Ok(collection)
+    }
+
+    /// List the organizations the account belongs to
+    pub fn organizations(&self) -> Result<Vec<serde_json::Value>> {
+        Ok(self.get_all("/api/v2/organizations")?.data)
+    }
+
+    /// List the workspaces of an organization, including their current run
+    pub fn workspaces(&self, organization: &str) -> Result<Collection> {
+        self.get_all(&format!(
+            "/api/v2/organizations/{organization}/workspaces?include=current_run"
+        ))
+    }
+
+    /// Fetch the entitlements granted to an organization by its plan
+    pub fn entitlements(&self, organization: &str) -> Result<serde_json::Value> {
+        self.get(&format!(
+            "/api/v2/organizations/{organization}/entitlement-set"
+        ))
+    }
+
+    /// Fetch the identifier of the user owning the token
+    fn user_id(&self) -> Result<String> {
+        Ok(self.account_details()?["data"]["id"]
+            .as_str()
+            .context("The account details are missing the user id")?
+            .to_string())
+    }
+
+    /// Create a new user token with the given description
+    pub fn create_token(&self, description: &str) -> Result<CreatedToken> {
+        let user_id = self.user_id()?;
+        let body = serde_json::json!({
+            "data": {
+                "type": "authentication-tokens",
+                "attributes": { "description": description },
+            }
+        });
+        let response = self
+            .request(
+                "POST",
+                &format!("/api/v2/users/{user_id}/authentication-tokens"),
+            )
+            .set("Content-Type", "application/vnd.api+json")
+            .send_string(&body.to_string())
+            .with_context(|| format!("Couldn't create a new token on {}", self.hostname))?
+            .into_string()?;
+        let response: serde_json::Value = serde_json::from_str(&response)?;
+        Ok(CreatedToken {
+            id: response["data"]["id"]
+                .as_str()
+                .context("The created token is missing its id")?
+                .to_string(),
+            token: response["data"]["attributes"]["token"]
+                .as_str()
+                .context("The created token is missing its value")?
+                .to_string(),
+        })
+    }
+
+    /// List the tokens of the account owning the client's token
+    pub fn tokens(&self) -> Result<Vec<TokenDetails>> {
+        let user_id = self.user_id()?;
+        self.get_all(&format!("/api/v2/users/{user_id}/authentication-tokens"))?
+            .data
+            .iter()
+            .map(TokenDetails::from_resource)
+            .collect()
+    }
+
+    /// Fetch when the client's own token expires, in seconds since the Unix epoch, none when it
+    /// never does, finding it by its identifier when known or else by guessing
+    pub fn token_expiry(&self, token_id: Option<&str>) -> Result<Option<u64>> {
+        let tokens = self.tokens()?;
+        let token = match token_id {
+            Some(token_id) => tokens
+                .into_iter()
+                .find(|token| token.id == token_id)
+                .with_context(|| format!("The account has no token `{token_id}` anymore"))?,
+            None => guess_current_token(tokens)?,
+        };
+        let Some(expired_at) = token.expired_at else {
+            return Ok(None);
+        };
+        let expired_at = humantime::parse_rfc3339_weak(&expired_at)
+            .with_context(|| format!("The token expiration `{expired_at}` isn't a date"))?;
+        Ok(Some(
+            expired_at
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default(),
+        ))
+    }
+
+    /// Revoke an authentication token by its identifier
+    pub fn revoke_token(&self, token_id: &str) -> Result<()> {
+        self.request(
+            "DELETE",
+            &format!("/api/v2/authentication-tokens/{token_id}"),
+        )
+        .call()
+        .with_context(|| format!("Couldn't revoke the token on {}", self.hostname))?;
+        Ok(())
+    }
+
+    /// Check whether the token is accepted by the API
+    pub fn token_status(&self) -> Result<TokenStatus> {
+        match self.request("GET", "/api/v2/account/details").call() {
+            Ok(_) => Ok(TokenStatus::Valid),
+            Err(ureq::Error::Status(401 | 403, response)) => {
+                let body = response.into_string().unwrap_or_default();
+                if body.to_lowercase().contains("expired") {
+                    Ok(TokenStatus::Expired)
+                } else {
+                    Ok(TokenStatus::Unauthorized)
+                }
+            }
+            Err(e) => Err(e).with_context(|| format!("Couldn't reach {}", self.hostname)),
+        }
+    }
+}
+
+/// A token just created, the only time the API tells its value
+#[derive(Debug, Clone)]
+pub struct CreatedToken {
+    /// Identifier of the token, e.g. `at-XXXXXXXXXXXXXXXX`
+    pub id: String,
+    /// Value of the token
+    pub token: String,
+}
+
+/// A token of the account, as the API describes it, never with its value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenDetails {
+    /// Identifier of the token, e.g. `at-XXXXXXXXXXXXXXXX`
+    pub id: String,
+    /// What the token was created for
+    pub description: Option<String>,
+    /// When the token was created, as an RFC 3339 date
+    pub created_at: Option<String>,
+    /// When the token was last used, as an RFC 3339 date the API updates lazily
+    pub last_used_at: Option<String>,
+    /// When the token expires, as an RFC 3339 date, none when it never does
+    pub expired_at: Option<String>,
+}
+
+impl TokenDetails {
+    /// Read the details of an `authentication-tokens` resource
+    fn from_resource(resource: &serde_json::Value) -> Result<Self> {
+        let attribute = |name: &str| resource["attributes"][name].as_str().map(String::from);
+        Ok(TokenDetails {
+            id: resource["id"]
+                .as_str()
+                .context("The token is missing its id")?
+                .to_string(),
+            description: attribute("description"),
+            created_at: attribute("created-at"),
+            last_used_at: attribute("last-used-at"),
+            expired_at: attribute("expired-at"),
+        })
+    }
+}
+
+impl std::fmt::Display for TokenDetails {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "`{}`", self.id)?;
+        let mut details = Vec::new();
+        if let Some(description) = &self.description {
+            details.push(format!("\"{description}\""));
+        }
+        if let Some(created_at) = &self.created_at {
+            details.push(format!("created {created_at}"));
+        }
+        details.push(match &self.last_used_at {
+            Some(last_used_at) => format!("last used {last_used_at}"),
+            None => String::from("never used"),
+        });
+        write!(f, " ({})", details.join(", "))
+    }
+}
+
+/// Guess which of the account's tokens a client authenticates with, the API never exposing
+/// token values
+///
+/// This relies on the token being the most recently used one, which the API updates lazily
+/// while other machines use the other tokens, so it is only a guess to show or confirm, and
+/// refuses to guess when `last-used-at` doesn't single one out.
+pub fn guess_current_token(tokens: Vec<TokenDetails>) -> Result<TokenDetails> {
+    let latest = tokens
+        .iter()
+        .filter_map(|token| token.last_used_at.clone())
+        .max()
+        .context("None of the account tokens were ever used")?;
+    let mut candidates = tokens
+        .into_iter()
+        .filter(|token| token.last_used_at.as_ref() == Some(&latest));
+    match (candidates.next(), candidates.next()) {
+        (Some(token), None) => Ok(token),
+        _ => anyhow::bail!("Couldn't tell which of the account tokens is in use"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A token of the account, last used then if ever
+    fn token(id: &str, last_used_at: Option<&str>) -> TokenDetails {
+        TokenDetails {
+            id: id.to_string(),
+            description: None,
+            created_at: None,
+            last_used_at: last_used_at.map(String::from),
+            expired_at: None,
+        }
+    }
+
+    #[test]
+    fn guesses_the_most_recently_used_token() {
+        let tokens = vec![
+            token("at-old", Some("2024-01-01T00:00:00.000Z")),
+            token("at-new", Some("2024-03-01T00:00:00.000Z")),
+            token("at-unused", None),
+        ];
+        assert_eq!(guess_current_token(tokens).unwrap().id, "at-new");
+    }
+
+    #[test]
+    fn refuses_to_guess_between_tokens_used_at_once() {
+        let tokens = vec![
+            token("at-one", Some("2024-03-01T00:00:00.000Z")),
+            token("at-two", Some("2024-03-01T00:00:00.000Z")),
+        ];
+        assert!(guess_current_token(tokens).is_err());
+        assert!(guess_current_token(vec![token("at-unused", None)]).is_err());
+    }
+
+    #[test]
+    fn reads_and_describes_tokens() {
+        let resource = serde_json::json!({
+            "id": "at-123",
+            "type": "authentication-tokens",
+            "attributes": {
+                "description": "laptop",
+                "created-at": "2024-01-01T00:00:00.000Z",
+                "last-used-at": null,
+                "expired-at": "2025-01-01T00:00:00.000Z",
+            }
+        });
+        let details = TokenDetails::from_resource(&resource).unwrap();
+        assert_eq!(
+            details.expired_at.as_deref(),
+            Some("2025-01-01T00:00:00.000Z")
+        );
+        assert_eq!(
+            details.to_string(),
+            "`at-123` (\"laptop\", created 2024-01-01T00:00:00.000Z, never used)"
+        );
+    }
+}
@@ -0,0 +1,386 @@
+//! Archives of every profile, for backups and migrations to another machine
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use age::secrecy::SecretString;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backup,
+    credentials::{self, CredentialsFile},
+    encryption,
+    error::CliError,
+    journal,
+    state::{self, State},
+    store::{validate_credentials, validate_profile_name, ProfileStore},
+};
+
+/// Variable the passphrase of the archives is read from, for scripts
+pub const PASSPHRASE_VARIABLE: &str = "TERRAFORM_PROFILE_PASSPHRASE";
+
+/// Version of the archive format, bumped on incompatible changes
+const FORMAT_VERSION: u32 = 1;
+
+/// First line of the archives encrypted with age
+const AGE_HEADER: &[u8] = b"age-encryption.org/v1";
+
+/// Read the passphrase of an archive from its variable, or else ask for it, twice when
+/// `confirm` is set
+pub fn read_passphrase(confirm: bool) -> Result<SecretString> {
+    if let Some(passphrase) = std::env::var(PASSPHRASE_VARIABLE)
+        .ok()
+        .filter(|passphrase| !passphrase.is_empty())
+    {
+        return Ok(passphrase.into());
+    }
+    if !crate::output::is_interactive() {
+        anyhow::bail!("The CLI can't prompt, set the passphrase in `{PASSPHRASE_VARIABLE}`.");
+    }
+    let mut prompt = dialoguer::Password::new().with_prompt("Passphrase");
+    if confirm {
+        prompt = prompt.with_confirmation("Confirm the passphrase", "The passphrases don't match");
+    }
+    Ok(prompt.interact()?.into())
+}
+
+/// What restoring does with the archived profiles whose name is already registered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Conflicts {
+    /// Restore nothing
+    Fail,
+    /// Leave the registered profiles as they are
+    Skip,
+    /// Replace the registered profiles
+    Overwrite,
+    /// Register the archived profiles under their name followed by a suffix
+    Rename(String),
+}
+
+/// Outcome of restoring an archive
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Restored {
+    /// Names the profiles were registered under, in the order of the archive
+    pub names: Vec<String>,
+    /// How many archived profiles were left out, their name being registered already
+    pub skipped: usize,
+}
+
+/// Every registered profile along with what is remembered about it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Archive {
+    /// Version of the archive format
+    pub version: u32,
+    /// Version of the CLI that made the archive
+    pub created_by: String,
+    /// When the archive was made, in seconds since the Unix epoch
+    pub created_at: u64,
+    /// Archived profiles, sorted by name
+    pub profiles: Vec<ArchivedProfile>,
+}
+
+/// A profile inside an archive
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchivedProfile {
+    /// Name the profile was registered under
+    pub name: String,
+    /// When the profile was last switched to, in seconds since the Unix epoch
+    pub last_switched: Option<u64>,
+    /// Content of the profile
+    pub credentials: serde_json::Value,
+    /// When the profile was last modified, in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified_at: Option<u64>,
+    /// How many times each machine changed the profile, in the bundles of `sync`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub versions: BTreeMap<String, u64>,
+}
+
+impl Archive {
+    /// Gather every registered profile
+    pub fn new(profiles: &HashMap<String, PathBuf>, state: &State) -> Result<Self> {
+        let mut archived = Vec::new();
+        for (name, path) in profiles {
+            let content = encryption::decrypt_profile(path)?;
+            let modified_at = std::fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|duration| duration.as_secs());
+            archived.push(ArchivedProfile {
+                name: name.clone(),
+                last_switched: state.last_switched.get(name).copied(),
+                credentials: serde_json::from_str(&content)
+                    .with_context(|| format!("The profile `{name}` isn't valid JSON"))?,
+                modified_at,
+                versions: BTreeMap::new(),
+            });
+        }
+        archived.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Archive {
+            version: FORMAT_VERSION,
+            created_by: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            created_at: state::now(),
+            profiles: archived,
+        })
+    }
+
+    /// Serialize the archive, encrypting it with the passphrase if any
+    pub fn to_bytes(&self, passphrase: Option<SecretString>) -> Result<Vec<u8>> {
+        let plaintext = serde_json::to_vec_pretty(self)?;
+        match passphrase {
+            Some(passphrase) => Ok(age::encrypt(
+                &age::scrypt::Recipient::new(passphrase),
+                &plaintext,
+            )?),
+            None => Ok(plaintext),
+        }
+    }
+
+    /// Deserialize an archive, decrypting it with the passphrase given when it is encrypted
+    pub fn from_bytes(
+        bytes: &[u8],
+        passphrase: impl FnOnce() -> Result<SecretString>,
+    ) -> Result<Self> {
+        let decrypted;
+        let plaintext = if bytes.starts_with(AGE_HEADER) {
+            decrypted = age::decrypt(&age::scrypt::Identity::new(passphrase()?), bytes)
+                .context("Couldn't decrypt the archive, the passphrase may be wrong")?;
+            &decrypted[..]
+        } else {
+            bytes
+        };
+        let value: serde_json::Value =
+            serde_json::from_slice(plaintext).context("The archive isn't valid JSON")?;
+        let version = value
+            .get("version")
+            .and_then(serde_json::Value::as_u64)
+            .context("The archive is missing its format version")?;
+        if version > u64::from(FORMAT_VERSION) {
+            anyhow::bail!(
+                "The archive has format version {version}, which needs a newer terraform-profile"
+            );
+        }
+        serde_json::from_value(value).context("The archive is malformed")
+    }
+
+    /// Register the archived profiles in a store, checking all of them before registering any,
+    /// and bring the credentials up to date with the profiles overwritten
+    ///
+    /// `origin` names where the archive came from in the backups and the journal.
+    pub fn restore(
+        self,
+        store: &ProfileStore,
+        credentials_file: &CredentialsFile,
+        origin: &str,
+        conflicts: &Conflicts,
+        backup_retention: usize,
+    ) -> Result<Restored> {
+        let project_directory = store.directory();
+        let terraform_directory = credentials_file.terraform_directory();
+        let profiles = store.profiles()?;
+        let mut taken: HashSet<String> = profiles.keys().cloned().collect();
+        let mut restored = Vec::new();
+        let mut skipped = 0;
+        for profile in self.profiles {
+            validate_profile_name(&profile.name)?;
+            let content = serde_json::to_string_pretty(&profile.credentials)?;
+            validate_credentials(&content)
+                .with_context(|| format!("The archived profile `{}` is invalid", profile.name))?;
+            let name = match conflicts {
+                _ if !taken.contains(&profile.name) => profile.name.clone(),
+                Conflicts::Fail => return Err(CliError::ProfileExists(profile.name).into()),
+                Conflicts::Skip => {
+                    skipped += 1;
+                    continue;
+                }
+                Conflicts::Overwrite => profile.name.clone(),
+                Conflicts::Rename(suffix) => {
+                    let name = format!("{}{suffix}", profile.name);
+                    validate_profile_name(&name)?;
+                    if taken.contains(&name) {
+                        return Err(CliError::ProfileExists(name).into());
+                    }
+                    name
+                }
+            };
+            taken.insert(name.clone());
+            restored.push((name, content, profile.last_switched));
+        }
+
+        let profile_paths: Vec<PathBuf> = restored
+            .iter()
+            .map(|(name, ..)| store.profile_path(name))
+            .collect();
+        let mut paths: Vec<&Path> = profile_paths.iter().map(PathBuf::as_path).collect();
+        if let Conflicts::Overwrite = conflicts {
+            backup::snapshot(
+                project_directory,
+                &format!("restore {origin} --overwrite"),
+                &paths,
+                backup_retention,
+            )?;
+        }
+        let credentials_path = credentials_file.path();
+        paths.push(&credentials_path);
+        journal::transaction(
+            project_directory,
+            &format!("restore {origin}"),
+            &paths,
+            || {
+                let mut state = State::load(project_directory)?;
+                for (name, content, last_switched) in &restored {
+                    let profile_path = store.overwrite(name, content)?;
+                    if profiles.contains_key(name) {
+                        credentials::refresh(
+                            name,
+                            &profile_path,
+                            terraform_directory,
+                            project_directory,
+                        )?;
+                    }
+                    if let Some(last_switched) = *last_switched {
+                        let known = state.last_switched.entry(name.clone()).or_default();
+                        *known = (*known).max(last_switched);
+                    }
+                }
+                state.save(project_directory)
+            },
+        )?;
+        Ok(Restored {
+            names: restored.into_iter().map(|(name, ..)| name).collect(),
+            skipped,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Credentials holding a single token for `app.terraform.io`
+    fn credentials(token: &str) -> String {
+        serde_json::json!({ "credentials": { "app.terraform.io": { "token": token } } }).to_string()
+    }
+
+    /// A store in a temporary directory holding profiles, along with a credentials file
+    fn store(profiles: &[(&str, &str)]) -> (tempfile::TempDir, ProfileStore, CredentialsFile) {
+        let directory = tempfile::tempdir().unwrap();
+        let store_directory = directory.path().join("store");
+        let terraform_directory = directory.path().join("terraform.d");
+        std::fs::create_dir_all(&store_directory).unwrap();
+        std::fs::create_dir_all(&terraform_directory).unwrap();
+        let store = ProfileStore::open(store_directory);
+        for (name, token) in profiles {
+            store.register(name, &credentials(token)).unwrap();
+        }
+        (directory, store, CredentialsFile::new(terraform_directory))
+    }
+
+    /// Export every profile of a store, through an archive encrypted with a passphrase
+    fn export(store: &ProfileStore, passphrase: &str) -> Vec<u8> {
+        let state = State::load(store.directory()).unwrap();
+        Archive::new(&store.profiles().unwrap(), &state)
+            .unwrap()
+            .to_bytes(Some(passphrase.to_string().into()))
+            .unwrap()
+    }
+
+    /// Read back an archive and restore it into a store
+    fn restore(
+        bytes: &[u8],
+        store: &ProfileStore,
+        credentials: &CredentialsFile,
+        conflicts: Conflicts,
+    ) -> Result<Restored> {
+        Archive::from_bytes(bytes, || Ok(String::from("hunter2").into()))?.restore(
+            store,
+            credentials,
+            "archive",
+            &conflicts,
+            10,
+        )
+    }
+
+    /// The token a registered profile holds for `app.terraform.io`
+    fn token(store: &ProfileStore, name: &str) -> String {
+        store.tokens(name).unwrap()["app.terraform.io"].clone()
+    }
+
+    #[test]
+    fn round_trips_through_an_encrypted_archive() {
+        let (_source, source, _) = store(&[("work", "work-token"), ("personal", "own-token")]);
+        let bytes = export(&source, "hunter2");
+        assert!(bytes.starts_with(AGE_HEADER));
+
+        let (_target, target, credentials) = store(&[]);
+        let restored = restore(&bytes, &target, &credentials, Conflicts::Fail).unwrap();
+        assert_eq!(restored.names, ["personal", "work"]);
+        assert_eq!(restored.skipped, 0);
+        assert_eq!(token(&target, "work"), "work-token");
+        assert_eq!(token(&target, "personal"), "own-token");
+    }
+
+    #[test]
+    fn refuses_conflicts_unless_told_what_to_do() {
+        let (_source, source, _) = store(&[("work", "new-token"), ("personal", "own-token")]);
+        let bytes = export(&source, "hunter2");
+        let (_target, target, credentials) = store(&[("work", "old-token")]);
+
+        assert!(restore(&bytes, &target, &credentials, Conflicts::Fail).is_err());
+        assert_eq!(token(&target, "work"), "old-token");
+        assert!(!target.profile_path("personal").exists());
+    }
+
+    #[test]
+    fn skips_the_existing_profiles() {
+        let (_source, source, _) = store(&[("work", "new-token"), ("personal", "own-token")]);
+        let bytes = export(&source, "hunter2");
+        let (_target, target, credentials) = store(&[("work", "old-token")]);
+
+        let restored = restore(&bytes, &target, &credentials, Conflicts::Skip).unwrap();
+        assert_eq!(restored.names, ["personal"]);
+        assert_eq!(restored.skipped, 1);
+        assert_eq!(token(&target, "work"), "old-token");
+        assert_eq!(token(&target, "personal"), "own-token");
+    }
+
+    #[test]
+    fn overwrites_the_existing_profiles() {
+        let (_source, source, _) = store(&[("work", "new-token")]);
+        let bytes = export(&source, "hunter2");
+        let (_target, target, credentials) = store(&[("work", "old-token")]);
+
+        let restored = restore(&bytes, &target, &credentials, Conflicts::Overwrite).unwrap();
+        assert_eq!(restored.names, ["work"]);
+        assert_eq!(token(&target, "work"), "new-token");
+    }
+
+    #[test]
+    fn renames_the_conflicting_profiles_with_a_suffix() {
+        let (_source, source, _) = store(&[("work", "new-token"), ("personal", "own-token")]);
+        let bytes = export(&source, "hunter2");
+        let (_target, target, credentials) = store(&[("work", "old-token")]);
+
+        let conflicts = Conflicts::Rename(String::from("-restored"));
+        let restored = restore(&bytes, &target, &credentials, conflicts).unwrap();
+        assert_eq!(restored.names, ["personal", "work-restored"]);
+        assert_eq!(token(&target, "work"), "old-token");
+        assert_eq!(token(&target, "work-restored"), "new-token");
+        assert_eq!(token(&target, "personal"), "own-token");
+    }
+
+    #[test]
+    fn refuses_a_wrong_passphrase() {
+        let (_source, source, _) = store(&[("work", "work-token")]);
+        let bytes = export(&source, "correct horse");
+        let (_target, target, credentials) = store(&[]);
+
+        let error = restore(&bytes, &target, &credentials, Conflicts::Fail).unwrap_err();
+        assert!(format!("{error:#}").contains("the passphrase may be wrong"));
+        assert!(target.profiles().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,138 @@
+//! Snapshots of the files destructive operations delete or overwrite, which `undo` reverts
+
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{permissions, state};
+
+/// Name of the directory inside the project directory the backups are kept in
+pub const BACKUPS_DIRECTORY: &str = "backups";
+
+/// Content of the files an operation was about to change
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Backup {
+    /// Command line of the operation, as shown by `undo`
+    pub operation: String,
+    /// When the operation happened, in seconds since the Unix epoch
+    pub created_at: u64,
+    /// Files the operation was about to change
+    pub files: Vec<BackedUpFile>,
+}
+
+/// A file as it was before an operation
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackedUpFile {
+    /// Absolute path of the file
+    pub path: PathBuf,
+    /// Content of the file, or nothing when it didn't exist yet
+    pub content: Option<String>,
+}
+
+/// Path of the backups directory inside a project directory
+fn directory(project_directory: &Path) -> PathBuf {
+    project_directory.join(BACKUPS_DIRECTORY)
+}
+
+/// Get the paths of the backups, oldest first
+fn list(project_directory: &Path) -> Result<Vec<PathBuf>> {
+    let directory = directory(project_directory);
+    if !directory.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut backups: Vec<PathBuf> = std::fs::read_dir(&directory)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    backups.sort();
+    Ok(backups)
+}
+
+/// Save the current content of files before an operation changes them, then remove the oldest
+/// backups beyond the retention
+///
+/// Does nothing when the retention is zero
+pub fn snapshot(
+    project_directory: &Path,
+    operation: &str,
+    paths: &[&Path],
+    retention: usize,
+) -> Result<()> {
+    if retention == 0 {
+        return Ok(());
+    }
+    let mut files = Vec::new();
+    for path in paths {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => Some(content),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+            Err(error) => {
+                return Err(error).with_context(|| format!("Couldn't back up {}", path.display()))
+            }
+        };
+        files.push(BackedUpFile {
+            path: path.to_path_buf(),
+            content,
+        });
+    }
+    let backup = Backup {
+        operation: operation.to_string(),
+        created_at: state::now(),
+        files,
+    };
+
+    let directory = directory(project_directory);
+    if !directory.exists() {
+        std::fs::create_dir(&directory)?;
+        permissions::restrict_directory(&directory)?;
+    }
+    // Nanoseconds sort the backups by age, even for operations within the same second
+    let nanoseconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default();
+    let path = directory.join(format!("{nanoseconds:039}.json"));
+    tracing::info!("Backing up to {}", path.display());
+    crate::store::write_file_atomically(&path, serde_json::to_string_pretty(&backup)?)?;
+    permissions::restrict_file(&path)?;
+
+    let backups = list(project_directory)?;
+    for expired in backups.iter().take(backups.len().saturating_sub(retention)) {
+        tracing::info!("Removing the expired backup {}", expired.display());
+        std::fs::remove_file(expired)?;
+    }
+    Ok(())
+}
+
+/// Put back the files of the most recent backup and remove it, returning it
+pub fn undo(project_directory: &Path) -> Result<Option<Backup>> {
+    let Some(path) = list(project_directory)?.pop() else {
+        return Ok(None);
+    };
+    let backup: Backup = serde_json::from_str(&std::fs::read_to_string(&path)?)
+        .with_context(|| format!("The backup {} is invalid", path.display()))?;
+    for file in &backup.files {
+        match &file.content {
+            Some(content) => {
+                crate::store::write_file_atomically(&file.path, content)?;
+                permissions::restrict_file(&file.path)?;
+            }
+            None if file.path.is_symlink() || file.path.exists() => {
+                tracing::info!("Removing {}", file.path.display());
+                std::fs::remove_file(&file.path)?;
+            }
+            None => {}
+        }
+    }
+    tracing::info!("Removing the backup {}", path.display());
+    std::fs::remove_file(&path)?;
+    Ok(Some(backup))
+}
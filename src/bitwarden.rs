@@ -0,0 +1,121 @@
+//! Tokens kept in the secure notes of Bitwarden or Vaultwarden, which the `bw` CLI reads with a
+//! session key cached between runs so switching doesn't ask for the master password every time
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Mutex, OnceLock},
+};
+
+use anyhow::{Context, Result};
+
+use crate::permissions;
+
+/// Prefix of the sources naming a Bitwarden item, by name or identifier
+pub const REFERENCE_PREFIX: &str = "bw://";
+
+/// Name of the file inside the project directory the session key is cached in
+pub const SESSION_FILE: &str = "bitwarden-session";
+
+/// Path of the session cache, set once at startup
+static SESSION_CACHE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Session key of this run, once known
+static SESSION: Mutex<Option<String>> = Mutex::new(None);
+
+/// Cache the session key in the project directory from now on
+pub fn configure(project_directory: &Path) {
+    let _ = SESSION_CACHE.set(project_directory.join(SESSION_FILE));
+}
+
+/// Run `bw` with arguments and a session key, returning its output
+fn run(arguments: &[&str], session: &str) -> Result<std::process::Output> {
+    tracing::info!("Running `bw {}`", arguments.join(" "));
+    Command::new("bw")
+        .args(arguments)
+        .arg("--nointeraction")
+        .env("BW_SESSION", session)
+        .output()
+        .context("Couldn't run `bw`, is the Bitwarden CLI installed?")
+}
+
+/// Unlock the vault with the master password, caching the new session key
+fn unlock() -> Result<String> {
+    if !crate::output::is_interactive() {
+        anyhow::bail!("The Bitwarden vault is locked and the CLI can't prompt, set `BW_SESSION`.");
+    }
+    let password = dialoguer::Password::new()
+        .with_prompt("Bitwarden master password")
+        .interact()?;
+    tracing::info!("Running `bw unlock`");
+    let output = Command::new("bw")
+        .args(["unlock", "--raw", "--passwordenv", "BW_PASSWORD"])
+        .env("BW_PASSWORD", password)
+        .output()
+        .context("Couldn't run `bw`, is the Bitwarden CLI installed?")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "`bw unlock` exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let session = String::from_utf8(output.stdout)?.trim().to_string();
+    if let Some(cache) = SESSION_CACHE.get() {
+        tracing::debug!("Caching the Bitwarden session in {}", cache.display());
+        crate::store::write_file_atomically(cache, &session)?;
+        permissions::restrict_file(cache)?;
+    }
+    Ok(session)
+}
+
+/// Get the session key, from `BW_SESSION`, this run or the cache, whether it can be renewed, or
+/// else unlock the vault
+fn session() -> Result<(String, bool)> {
+    if let Some(session) = std::env::var("BW_SESSION")
+        .ok()
+        .filter(|session| !session.is_empty())
+    {
+        return Ok((session, false));
+    }
+    let mut current = SESSION.lock().unwrap_or_else(|error| error.into_inner());
+    if let Some(session) = current.as_ref() {
+        return Ok((session.clone(), true));
+    }
+    let cached = SESSION_CACHE
+        .get()
+        .and_then(|cache| std::fs::read_to_string(cache).ok())
+        .map(|session| session.trim().to_string())
+        .filter(|session| !session.is_empty());
+    let session = match cached {
+        Some(session) => session,
+        None => unlock()?,
+    };
+    *current = Some(session.clone());
+    Ok((session, true))
+}
+
+/// Read the token of an item, the first line of its notes
+pub fn read(item: &str) -> Result<String> {
+    let (session, renewable) = session()?;
+    let mut output = run(&["get", "notes", item], &session)?;
+    // The cached session expires when the vault is locked again, e.g. by `bw lock`
+    if !output.status.success()
+        && renewable
+        && String::from_utf8_lossy(&output.stderr).contains("locked")
+    {
+        tracing::info!("The cached Bitwarden session was refused, unlocking again");
+        let session = unlock()?;
+        *SESSION.lock().unwrap_or_else(|error| error.into_inner()) = Some(session.clone());
+        output = run(&["get", "notes", item], &session)?;
+    }
+    if !output.status.success() {
+        anyhow::bail!(
+            "`bw get notes {item}` exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let notes = String::from_utf8(output.stdout)?;
+    Ok(notes.lines().next().unwrap_or_default().trim().to_string())
+}
@@ -0,0 +1,108 @@
+//! Shell completion scripts, completing registered profile names on top of the static ones
+
+use anyhow::Result;
+use clap::CommandFactory;
+use clap_complete::Shell;
+
+use crate::Cli;
+
+/// Subcommands, and their aliases, whose first argument is an existing profile name
+const PROFILE_SUBCOMMANDS: &[&str] = &[
+    "switch",
+    "rename",
+    "copy",
+    "duplicate",
+    "show",
+    "annotate",
+    "edit",
+    "verify",
+    "whoami",
+    "orgs",
+    "exec",
+    "env",
+    "logout",
+    "delete",
+    "rotate",
+];
+
+/// Hidden subcommand the completion scripts call back into to list the profile names
+pub const CALLBACK: &str = "_complete";
+
+/// Print the completion script for a shell
+///
+/// The bash, zsh and fish scripts call back into the binary to complete profile names, the
+/// other shells only get the static completions
+pub fn print(shell: Shell) -> Result<()> {
+    let bin_name = env!("CARGO_PKG_NAME");
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut Cli::command(), bin_name, &mut script);
+    let script = String::from_utf8(script)?;
+
+    let script = match shell {
+        Shell::Bash => format!("{script}\n{}", bash_dynamic(bin_name)),
+        Shell::Zsh => zsh_dynamic(&script, bin_name),
+        Shell::Fish => format!("{script}{}", fish_dynamic(bin_name)),
+        _ => script,
+    };
+    print!("{script}");
+    Ok(())
+}
+
+/// Bash wrapper completing profile names before falling back on the generated function
+fn bash_dynamic(bin_name: &str) -> String {
+    format!(
+        r#"_{bin_name}_dynamic() {{
+    if [[ ${{COMP_CWORD}} -eq 2 ]]; then
+        case "${{COMP_WORDS[1]}}" in
+            {subcommands})
+                COMPREPLY=( $(compgen -W "$({bin_name} {CALLBACK} 2>/dev/null)" -- "${{COMP_WORDS[COMP_CWORD]}}") )
+                return 0
+                ;;
+        esac
+    fi
+    _{bin_name} "$@"
+}}
+
+complete -F _{bin_name}_dynamic -o bashdefault -o default {bin_name}
+"#,
+        subcommands = PROFILE_SUBCOMMANDS.join("|"),
+    )
+}
+
+/// Rewrite the generated zsh script so the profile name arguments call back into the binary
+fn zsh_dynamic(script: &str, bin_name: &str) -> String {
+    let function = format!("_{bin_name}_profiles");
+    let mut section = String::new();
+    let mut lines = Vec::new();
+
+    for line in script.lines() {
+        let trimmed = line.trim();
+        if let Some(name) = trimmed.strip_prefix('(').and_then(|l| l.strip_suffix(')')) {
+            section = name.to_string();
+        }
+        let is_profile_section = section
+            .split('|')
+            .any(|name| PROFILE_SUBCOMMANDS.contains(&name));
+
+        if is_profile_section && (trimmed == "':name:' \\" || trimmed == "'::name:' \\") {
+            lines.push(line.replacen("name:'", &format!("name:{function}'"), 1));
+            section.clear();
+        } else if trimmed == format!("_{bin_name} \"$@\"") {
+            lines.push(format!(
+                "(( $+functions[{function}] )) ||\n{function}() {{\n    local profiles; profiles=(${{(f)\"$({bin_name} {CALLBACK} 2>/dev/null)\"}})\n    _describe -t profiles 'profiles' profiles \"$@\"\n}}\n"
+            ));
+            lines.push(line.to_string());
+        } else {
+            lines.push(line.to_string());
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Fish completion listing the profile names after the subcommands taking one
+fn fish_dynamic(bin_name: &str) -> String {
+    format!(
+        "complete -c {bin_name} -n \"__fish_seen_subcommand_from {}\" -f -a \"({bin_name} {CALLBACK} 2>/dev/null)\"\n",
+        PROFILE_SUBCOMMANDS.join(" ")
+    )
+}
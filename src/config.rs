@@ -0,0 +1,317 @@
+//! User configuration read at startup from `config.toml` in the configuration directory
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// Name of the configuration file inside the configuration directory
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// When the output is colored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    /// Only when printing to a terminal
+    #[default]
+    Auto,
+    /// Even when printing to a pipe or a file
+    Always,
+    /// Never
+    Never,
+}
+
+/// How the credentials are put in place when switching
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SwitchMode {
+    /// A symbolic link to the registered profile
+    #[default]
+    Symlink,
+    /// A hard link to the registered profile, for tools refusing symbolic links, falling back to
+    /// a copy when the profile is on another filesystem
+    Hardlink,
+    /// A copy of the registered profile, for filesystems and platforms without symbolic links
+    Copy,
+    /// No credentials file at all, terraform asking the `credentials_helper` of its CLI
+    /// configuration for the tokens of the active profile instead
+    Helper,
+}
+
+impl SwitchMode {
+    /// Name of the mode in the configuration file
+    pub fn name(self) -> &'static str {
+        match self {
+            SwitchMode::Symlink => "symlink",
+            SwitchMode::Hardlink => "hardlink",
+            SwitchMode::Copy => "copy",
+            SwitchMode::Helper => "helper",
+        }
+    }
+}
+
+/// Tool the stored profiles are encrypted with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EncryptionBackend {
+    /// age, to the identities of a file or with a passphrase
+    #[default]
+    Age,
+    /// GnuPG, to one or more recipients, decrypting through the gpg-agent
+    Gpg,
+    /// The OS keyring, which keeps the tokens while the stored profiles keep the rest
+    Keyring,
+    /// SOPS, with the keys of the creation rules of `.sops.yaml`
+    Sops,
+}
+
+/// Encryption at rest of the stored profiles
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Encryption {
+    /// Tool the profiles are encrypted with
+    pub backend: EncryptionBackend,
+    /// File holding the age identities, `~` meaning the home, a passphrase being asked without
+    pub identity: Option<PathBuf>,
+    /// Key identifiers or emails of the GnuPG recipients
+    pub recipients: Vec<String>,
+}
+
+impl Encryption {
+    /// File holding the age identities, if any
+    pub fn identity_file(&self, home_dir: &Path) -> Option<PathBuf> {
+        self.identity
+            .as_ref()
+            .map(|identity| expand_home(identity, home_dir))
+    }
+}
+
+/// HashiCorp Vault the profiles are shared through
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Vault {
+    /// Address of the Vault server, `VAULT_ADDR` being used without
+    pub address: Option<String>,
+    /// Path of the profiles in a KV version 2 engine, its mount first
+    pub path: String,
+}
+
+impl Default for Vault {
+    fn default() -> Self {
+        Vault {
+            address: None,
+            path: "secret/terraform-profile".to_string(),
+        }
+    }
+}
+
+/// Commands run around the profile switches and imports
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hooks {
+    /// Shell command run before every switch, a failure cancelling it
+    pub pre_switch: Option<String>,
+    /// Shell command run after every switch
+    pub post_switch: Option<String>,
+    /// Shell command run after every import
+    pub post_import: Option<String>,
+}
+
+/// When a hook runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    /// Before a switch
+    PreSwitch,
+    /// After a switch
+    PostSwitch,
+    /// After an import
+    PostImport,
+}
+
+impl Hook {
+    /// Name of the hook in the messages, e.g. `pre-switch`
+    pub fn name(self) -> &'static str {
+        match self {
+            Hook::PreSwitch => "pre-switch",
+            Hook::PostSwitch => "post-switch",
+            Hook::PostImport => "post-import",
+        }
+    }
+}
+
+impl Hooks {
+    /// Build the command of a hook, if it is configured, with `TERRAFORM_PROFILE` set to the
+    /// profile switched to or imported, and `TERRAFORM_PROFILE_PREVIOUS` to the profile in use
+    /// before, empty when there was none
+    pub fn command(
+        &self,
+        hook: Hook,
+        profile: &str,
+        previous: Option<&str>,
+    ) -> Option<std::process::Command> {
+        let script = match hook {
+            Hook::PreSwitch => &self.pre_switch,
+            Hook::PostSwitch => &self.post_switch,
+            Hook::PostImport => &self.post_import,
+        };
+        let script = script.as_ref()?;
+        #[cfg(target_family = "windows")]
+        let mut command = {
+            let mut command = std::process::Command::new("cmd");
+            command.arg("/C").arg(script);
+            command
+        };
+        #[cfg(target_family = "unix")]
+        let mut command = {
+            let mut command = std::process::Command::new("sh");
+            command.arg("-c").arg(script);
+            command
+        };
+        command
+            .env("TERRAFORM_PROFILE", profile)
+            .env("TERRAFORM_PROFILE_PREVIOUS", previous.unwrap_or_default())
+            .env("TERRAFORM_PROFILE_HOOK", hook.name());
+        Some(command)
+    }
+}
+
+/// Defaults of the CLI, which the command line flags take precedence over
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Directory the profiles, the state and the history are stored in, `~` meaning the home
+    pub storage_directory: Option<PathBuf>,
+    /// Directory terraform reads its credentials from, `~` meaning the home
+    pub terraform_directory: Option<PathBuf>,
+    /// Profile `switch` falls back to when no name is given and the terminal isn't interactive
+    pub default_profile: Option<String>,
+    /// Short names standing for profiles, by alias, along with those set with `alias`
+    pub aliases: BTreeMap<String, String>,
+    /// Only accept the full names of the profiles, instead of the start of a single one or its
+    /// letters in order
+    pub exact_names: bool,
+    /// How the credentials are put in place when switching
+    pub mode: SwitchMode,
+    /// When the output is colored
+    pub color: ColorChoice,
+    /// Ask for a confirmation before removing or rotating a profile
+    pub confirm: bool,
+    /// Timeout of the terraform cloud API requests, in seconds
+    pub api_timeout: u64,
+    /// Number of backups kept for `undo`, none being taken at zero
+    pub backup_retention: usize,
+    /// Commands run around the profile switches
+    pub hooks: Hooks,
+    /// Encryption at rest of the stored profiles, which are stored in plain text without
+    pub encryption: Option<Encryption>,
+    /// HashiCorp Vault the profiles are shared through, if any
+    pub vault: Option<Vault>,
+    /// Tell when a newer release is available, checking GitHub at most once a day
+    pub update_check: bool,
+    /// Fingerprint of the GnuPG key signing the releases, `self-update` then refusing the
+    /// unsigned ones
+    pub update_signing_key: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            storage_directory: None,
+            terraform_directory: None,
+            default_profile: None,
+            aliases: BTreeMap::new(),
+            exact_names: false,
+            mode: SwitchMode::Symlink,
+            color: ColorChoice::Auto,
+            confirm: false,
+            api_timeout: 10,
+            backup_retention: 10,
+            hooks: Hooks::default(),
+            encryption: None,
+            vault: None,
+            update_check: false,
+            update_signing_key: None,
+        }
+    }
+}
+
+impl Config {
+    /// Load the configuration file, falling back to the defaults when there is none
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        toml::from_str(&content)
+            .with_context(|| format!("The configuration file {} is invalid", path.display()))
+    }
+
+    /// Directory the profiles are stored in, given the default one
+    pub fn storage_directory(&self, home_dir: &Path, default: &Path) -> PathBuf {
+        match &self.storage_directory {
+            Some(directory) => expand_home(directory, home_dir),
+            None => default.to_path_buf(),
+        }
+    }
+
+    /// Directory terraform reads its credentials from, given the default one
+    pub fn terraform_directory(&self, home_dir: &Path, default: &Path) -> PathBuf {
+        match &self.terraform_directory {
+            Some(directory) => expand_home(directory, home_dir),
+            None => default.to_path_buf(),
+        }
+    }
+}
+
+/// Set the switching mode in the content of a configuration file, keeping the rest as written
+pub fn set_mode(content: &str, mode: SwitchMode) -> String {
+    set_setting(content, "mode", Some(mode.name()))
+}
+
+/// Set the default profile in the content of a configuration file, or remove it, keeping the
+/// rest as written
+pub fn set_default_profile(content: &str, profile: Option<&str>) -> String {
+    set_setting(content, "default_profile", profile)
+}
+
+/// Set a top-level string setting in the content of a configuration file, or remove it, keeping
+/// the rest as written
+fn set_setting(content: &str, key: &str, value: Option<&str>) -> String {
+    let setting = value
+        .map(|value| format!("{key} = {}\n", toml::Value::String(value.to_string())))
+        .unwrap_or_default();
+    let mut updated = String::with_capacity(content.len() + setting.len());
+    let mut replaced = false;
+    let mut in_table = false;
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        in_table |= trimmed.starts_with('[');
+        let is_setting = trimmed
+            .strip_prefix(key)
+            .is_some_and(|rest| rest.trim_start().starts_with('='));
+        if !in_table && !replaced && is_setting {
+            updated.push_str(&setting);
+            replaced = true;
+        } else {
+            updated.push_str(line);
+        }
+    }
+    if replaced {
+        updated
+    } else {
+        // The top-level settings have to come before the tables
+        setting + &updated
+    }
+}
+
+/// Replace a leading `~` of a configured path with the home directory
+fn expand_home(path: &Path, home_dir: &Path) -> PathBuf {
+    match path.strip_prefix("~") {
+        Ok(relative) => home_dir.join(relative),
+        Err(_) => path.to_path_buf(),
+    }
+}
@@ -0,0 +1,807 @@
+//! The credentials file terraform reads, and how the registered profiles are put in its place
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::SwitchMode,
+    encryption,
+    error::CliError,
+    helper, output, permissions,
+    state::{CopiedProfile, State},
+    store::{self, ProfileStore},
+};
+
+/// Name of the credentials file inside the terraform directory
+pub const CREDENTIALS_FILE: &str = "credentials.tfrc.json";
+
+/// Get the path of the credentials file inside a terraform directory
+pub fn path(terraform_directory: &Path) -> PathBuf {
+    terraform_directory.join(CREDENTIALS_FILE)
+}
+
+/// Run `terraform login` for a hostname in a scratch home, returning the credentials it wrote
+///
+/// Terraform always writes to `~/.terraform.d/credentials.tfrc.json`, which may be a managed
+/// symlink: pointing it at a scratch home keeps the registered profiles untouched.
+pub fn terraform_login(hostname: &str) -> Result<String> {
+    let scratch_home = tempfile::tempdir()?;
+    tracing::info!(
+        "Running `terraform login {hostname}` with HOME={}",
+        scratch_home.path().display()
+    );
+    let status = std::process::Command::new("terraform")
+        .arg("login")
+        .arg(hostname)
+        .env("HOME", scratch_home.path())
+        .env("APPDATA", scratch_home.path())
+        .env_remove("TF_CLI_CONFIG_FILE")
+        .status()
+        .context("Couldn't run `terraform login`, is terraform installed?")?;
+    if !status.success() {
+        anyhow::bail!("`terraform login` didn't complete, no profile was registered.");
+    }
+
+    let terraform_directory = if cfg!(target_family = "windows") {
+        scratch_home.path().join("terraform.d")
+    } else {
+        scratch_home.path().join(".terraform.d")
+    };
+    let content = std::fs::read_to_string(path(&terraform_directory))
+        .context("`terraform login` didn't produce any credentials")?;
+    store::validate_new_credentials(&content)?;
+    Ok(content)
+}
+
+/// What the credentials file currently is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Credentials {
+    /// There are no credentials
+    Missing,
+    /// The credentials link to a path, which may not exist anymore
+    Symlink(PathBuf),
+    /// The credentials are a hard link to a profile, `broken` once either was replaced
+    Hardlink {
+        /// Name of the profile linked to
+        profile: String,
+        /// Whether the link broke, the profile or the credentials having been replaced
+        broken: bool,
+    },
+    /// The credentials are a copy of a profile, `drifted` once changed since the switch
+    Copy {
+        /// Name of the profile copied
+        profile: String,
+        /// Whether the credentials changed since the switch
+        drifted: bool,
+    },
+    /// The credentials are a file no profile was copied to
+    Unmanaged,
+    /// There are no credentials, terraform asking the credentials helper for those of a profile
+    Helper(String),
+}
+
+impl Credentials {
+    /// Inspect the credentials of a terraform directory, using the state to recognize copies and
+    /// hard links
+    pub fn inspect(terraform_directory: &Path, project_directory: &Path) -> Result<Self> {
+        let credentials_files = path(terraform_directory);
+        if credentials_files.is_symlink() {
+            let link = credentials_files.read_link()?;
+            tracing::debug!(
+                "{} links to {}",
+                credentials_files.display(),
+                link.display()
+            );
+            return Ok(Credentials::Symlink(link));
+        }
+        let state = State::load(project_directory)?;
+        if !credentials_files.exists() {
+            return Ok(match state.helper_profile {
+                Some(profile) => {
+                    tracing::debug!("The credentials helper serves `{profile}`");
+                    Credentials::Helper(profile)
+                }
+                None => Credentials::Missing,
+            });
+        }
+        if let Some(profile) = state.hard_linked_profile {
+            let profile_path = project_directory.join(format!("{profile}.tfrc.json"));
+            let broken = !same_file::is_same_file(&credentials_files, profile_path)?;
+            tracing::debug!(
+                "{} is a hard link to `{profile}`{}",
+                credentials_files.display(),
+                if broken { " that broke" } else { "" }
+            );
+            return Ok(Credentials::Hardlink { profile, broken });
+        }
+        match state.copied_profile {
+            Some(copied) => {
+                let drifted = sha256_file(&credentials_files)? != copied.sha256;
+                tracing::debug!(
+                    "{} is a copy of `{}`{}",
+                    credentials_files.display(),
+                    copied.profile,
+                    if drifted { " that drifted" } else { "" }
+                );
+                Ok(Credentials::Copy {
+                    profile: copied.profile,
+                    drifted,
+                })
+            }
+            None => Ok(Credentials::Unmanaged),
+        }
+    }
+
+    /// Get the name of the registered profile the credentials are, unless they drifted from it
+    pub fn active_profile<'a>(&self, profiles: &'a HashMap<String, PathBuf>) -> Option<&'a String> {
+        match self {
+            Credentials::Symlink(link) => store::profile_name_for_path(link, profiles),
+            Credentials::Hardlink {
+                profile,
+                broken: false,
+            }
+            | Credentials::Copy {
+                profile,
+                drifted: false,
+            }
+            | Credentials::Helper(profile) => profiles.get_key_value(profile).map(|(name, _)| name),
+            _ => None,
+        }
+    }
+}
+
+/// The credentials file of a terraform directory, managed with the profiles of a store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CredentialsFile {
+    terraform_directory: PathBuf,
+}
+
+impl CredentialsFile {
+    /// Use the credentials file of a terraform directory, e.g. `~/.terraform.d`
+    pub fn new(terraform_directory: impl Into<PathBuf>) -> Self {
+        CredentialsFile {
+            terraform_directory: terraform_directory.into(),
+        }
+    }
+
+    /// Get the terraform directory holding the credentials
+    pub fn terraform_directory(&self) -> &Path {
+        &self.terraform_directory
+    }
+
+    /// Get the path of the credentials file
+    pub fn path(&self) -> PathBuf {
+        path(&self.terraform_directory)
+    }
+
+    /// Inspect what the credentials currently are
+    pub fn inspect(&self, store: &ProfileStore) -> Result<Credentials> {
+        Credentials::inspect(&self.terraform_directory, store.directory())
+    }
+
+    /// Get the name of the registered profile the credentials are, unless they drifted from it
+    pub fn active_profile(&self, store: &ProfileStore) -> Result<Option<String>> {
+        Ok(self
+            .inspect(store)?
+            .active_profile(&store.profiles()?)
+            .cloned())
+    }
+
+    /// Explain why the credentials aren't made from a registered profile
+    pub fn inactive_error(&self) -> CliError {
+        let credentials_files = self.path();
+        match (credentials_files.exists(), credentials_files.read_link()) {
+            (false, Ok(link)) => CliError::DanglingCredentials(link),
+            (true, Ok(link)) => CliError::UnknownSymlink(link),
+            (true, Err(_)) => CliError::UnmanagedCredentials,
+            (false, Err(_)) => CliError::NoActiveProfile,
+        }
+    }
+
+    /// Bring the credentials up to date with the registered profile they are made from, once
+    /// some profiles changed, e.g. after `refresh` indexed the store
+    pub fn follow_changes(
+        &self,
+        store: &ProfileStore,
+        changed: &[String],
+    ) -> Result<Option<Followed>> {
+        let profiles = store.profiles()?;
+        let refresh = |profile: &String| {
+            refresh(
+                profile,
+                &profiles[profile],
+                &self.terraform_directory,
+                store.directory(),
+            )
+        };
+        Ok(match self.inspect(store)? {
+            Credentials::Symlink(link) if !link.exists() => {
+                repair(&self.terraform_directory, &profiles)?.map(Followed::Repaired)
+            }
+            Credentials::Hardlink {
+                profile,
+                broken: true,
+            } if profiles.contains_key(&profile) => {
+                refresh(&profile)?;
+                Some(Followed::Relinked(profile))
+            }
+            Credentials::Copy {
+                profile,
+                drifted: false,
+            } if changed.contains(&profile) => {
+                refresh(&profile)?;
+                Some(Followed::Updated(profile))
+            }
+            Credentials::Copy {
+                profile,
+                drifted: true,
+            } if changed.contains(&profile) => Some(Followed::Drifted(profile)),
+            _ => None,
+        })
+    }
+
+    /// Put a registered profile in place of the credentials, refusing to overwrite unmanaged
+    /// ones
+    pub fn install(&self, store: &ProfileStore, name: &str, mode: SwitchMode) -> Result<()> {
+        install(
+            name,
+            &store.find(name)?,
+            &self.terraform_directory,
+            store.directory(),
+            mode,
+        )
+    }
+
+    /// Remove the credentials and forget the profile they were made from
+    pub fn remove(&self, store: &ProfileStore) -> Result<()> {
+        remove(&self.terraform_directory, store.directory())
+    }
+}
+
+/// Put a registered profile in place of the credentials, refusing to overwrite unmanaged ones
+pub fn install(
+    name: &str,
+    profile_path: &Path,
+    terraform_directory: &Path,
+    project_directory: &Path,
+    mode: SwitchMode,
+) -> Result<()> {
+    match Credentials::inspect(terraform_directory, project_directory)? {
+        Credentials::Unmanaged
+        | Credentials::Hardlink { broken: true, .. }
+        | Credentials::Copy { drifted: true, .. } => Err(CliError::UnmanagedCredentials.into()),
+        _ => put(
+            name,
+            profile_path,
+            terraform_directory,
+            project_directory,
+            mode,
+        ),
+    }
+}
+
+/// Replace the entry of one hostname in the credentials with that of a registered profile,
+/// leaving the entries of the other hostnames untouched, and return the hostname as the
+/// profile spells it
+///
+/// The credentials become a copy of the profile they were, along with the hostnames taken from
+/// other profiles
+pub fn install_host(
+    name: &str,
+    profile_path: &Path,
+    hostname: &str,
+    terraform_directory: &Path,
+    project_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+) -> Result<String> {
+    let credentials_files = path(terraform_directory);
+    let base = match Credentials::inspect(terraform_directory, project_directory)? {
+        Credentials::Unmanaged
+        | Credentials::Hardlink { broken: true, .. }
+        | Credentials::Copy { drifted: true, .. } => {
+            return Err(CliError::UnmanagedCredentials.into())
+        }
+        Credentials::Missing | Credentials::Helper(_) => anyhow::bail!(
+            "There are no credentials to switch a hostname in, switch to a whole profile first."
+        ),
+        Credentials::Symlink(link) if !credentials_files.exists() => {
+            return Err(CliError::DanglingCredentials(link).into())
+        }
+        Credentials::Symlink(link) => match store::profile_name_for_path(&link, profiles) {
+            Some(base) => base.clone(),
+            None => return Err(CliError::UnknownSymlink(link).into()),
+        },
+        Credentials::Hardlink { profile, .. } | Credentials::Copy { profile, .. } => profile,
+    };
+
+    let profile: serde_json::Value =
+        serde_json::from_str(&encryption::read_profile(profile_path)?)?;
+    let (host, entry) = profile["credentials"]
+        .as_object()
+        .and_then(|hosts| {
+            let host = helper::find_host(hosts, hostname)?;
+            Some((host.clone(), hosts[&host].clone()))
+        })
+        .with_context(|| format!("Profile `{name}` holds no credentials for {hostname}"))?;
+
+    let original = std::fs::read_to_string(&credentials_files)?;
+    let mut live: serde_json::Value =
+        serde_json::from_str(&original).context("The credentials aren't valid JSON")?;
+    let live_hosts = live
+        .get_mut("credentials")
+        .and_then(serde_json::Value::as_object_mut)
+        .context("The credentials are missing a `credentials` object")?;
+    // Replacing the entry in place keeps the hostnames in the order they were written
+    match live_hosts.get_mut(&host) {
+        Some(live_entry) => *live_entry = entry,
+        None => {
+            live_hosts.retain(|live_host, _| !live_host.eq_ignore_ascii_case(&host));
+            live_hosts.insert(host.clone(), entry);
+        }
+    }
+    let content = store::format_like(&original, &live)?;
+
+    let mut state = State::load(project_directory)?;
+    let mut hosts = state
+        .copied_profile
+        .take()
+        .filter(|copied| copied.profile == base)
+        .map(|copied| copied.hosts)
+        .unwrap_or_default();
+    hosts.retain(|switched, _| !switched.eq_ignore_ascii_case(&host));
+    if name != base {
+        hosts.insert(host.clone(), name.to_string());
+    }
+    replace(terraform_directory, |temporary| {
+        tracing::info!(
+            "Writing the `{host}` credentials of `{name}` to {}",
+            temporary.display()
+        );
+        permissions::write_restricted(temporary, &content)
+    })?;
+    state.hard_linked_profile = None;
+    state.copied_profile = Some(CopiedProfile {
+        profile: base,
+        sha256: sha256_file(&credentials_files)?,
+        hosts,
+    });
+    state.save(project_directory)?;
+    Ok(host)
+}
+
+/// Bring the credentials up to date with a profile rewritten through a rename, which leaves
+/// copies and hard links holding the previous content, or encrypted, which terraform can't read
+/// through a link
+pub fn refresh(
+    name: &str,
+    profile_path: &Path,
+    terraform_directory: &Path,
+    project_directory: &Path,
+) -> Result<()> {
+    let mode = match Credentials::inspect(terraform_directory, project_directory)? {
+        Credentials::Symlink(link)
+            if link == profile_path && encryption::is_encrypted_file(profile_path)? =>
+        {
+            SwitchMode::Copy
+        }
+        Credentials::Hardlink {
+            profile,
+            broken: true,
+        } if profile == name => SwitchMode::Hardlink,
+        Credentials::Copy {
+            profile,
+            drifted: false,
+        } if profile == name => SwitchMode::Copy,
+        _ => return Ok(()),
+    };
+    put(
+        name,
+        profile_path,
+        terraform_directory,
+        project_directory,
+        mode,
+    )
+}
+
+/// Replace the credentials with a profile, and remember how they were made
+fn put(
+    name: &str,
+    profile_path: &Path,
+    terraform_directory: &Path,
+    project_directory: &Path,
+    mode: SwitchMode,
+) -> Result<()> {
+    // Terraform can't read an encrypted profile, so it is decrypted into a copy, while the
+    // helper decrypts it whenever terraform asks
+    let mode = if let SwitchMode::Symlink | SwitchMode::Hardlink = mode {
+        if encryption::is_encrypted_file(profile_path)? {
+            SwitchMode::Copy
+        } else {
+            mode
+        }
+    } else {
+        mode
+    };
+    // Terraform would fail on malformed credentials, linked ones being read as they are stored
+    if let SwitchMode::Symlink | SwitchMode::Hardlink = mode {
+        store::validate_credentials(&std::fs::read_to_string(profile_path)?)
+            .with_context(|| format!("Profile `{name}` can't be switched to"))?;
+    }
+    let mut state = State::load(project_directory)?;
+    state.copied_profile = None;
+    state.hard_linked_profile = None;
+    state.helper_profile = None;
+    if mode == SwitchMode::Helper {
+        // Terraform only asks the helper for the hostnames the credentials file lacks
+        let credentials_files = path(terraform_directory);
+        if credentials_files.is_symlink() || credentials_files.exists() {
+            tracing::info!("Removing {}", credentials_files.display());
+            std::fs::remove_file(&credentials_files)?;
+        }
+        state.helper_profile = Some(name.to_string());
+        return state.save(project_directory);
+    }
+    replace(terraform_directory, |temporary| match mode {
+        SwitchMode::Symlink => symlink(profile_path, temporary),
+        SwitchMode::Hardlink => {
+            tracing::info!(
+                "Hard linking {} to {}",
+                temporary.display(),
+                profile_path.display()
+            );
+            // Hard links can't cross filesystems, which the store and terraform directory may
+            match std::fs::hard_link(profile_path, temporary) {
+                Ok(()) => state.hard_linked_profile = Some(name.to_string()),
+                Err(error) => {
+                    output::notice(format!(
+                        "Couldn't hard link the profile ({error}), copying it instead"
+                    ));
+                    state.copied_profile = Some(copy(name, profile_path, temporary)?);
+                }
+            }
+            Ok(())
+        }
+        SwitchMode::Copy => {
+            state.copied_profile = Some(copy(name, profile_path, temporary)?);
+            Ok(())
+        }
+        SwitchMode::Helper => unreachable!("the helper mode doesn't make credentials"),
+    })?;
+    state.save(project_directory)
+}
+
+/// Create the new credentials at a temporary name next to the current ones, then rename them over
+/// the current ones, so terraform never finds the credentials missing or half-written
+fn replace(terraform_directory: &Path, create: impl FnOnce(&Path) -> Result<()>) -> Result<()> {
+    let credentials_files = path(terraform_directory);
+    let temporary = terraform_directory.join(format!(".{CREDENTIALS_FILE}.{}", std::process::id()));
+    // A leftover of a crashed run that had the same process identifier
+    if temporary.is_symlink() || temporary.exists() {
+        std::fs::remove_file(&temporary)?;
+    }
+    let renamed = create(&temporary).and_then(|()| {
+        tracing::info!(
+            "Renaming {} to {}",
+            temporary.display(),
+            credentials_files.display()
+        );
+        Ok(std::fs::rename(&temporary, &credentials_files)?)
+    });
+    if renamed.is_err() && (temporary.is_symlink() || temporary.exists()) {
+        let _ = std::fs::remove_file(&temporary);
+    }
+    renamed
+}
+
+/// Copy a profile in place of the credentials, returning what identifies the copy
+fn copy(name: &str, profile_path: &Path, credentials_files: &Path) -> Result<CopiedProfile> {
+    tracing::info!(
+        "Copying {} to {}",
+        profile_path.display(),
+        credentials_files.display()
+    );
+    let content = encryption::read_profile(profile_path)?;
+    store::validate_credentials(&content)
+        .with_context(|| format!("Profile `{name}` can't be switched to"))?;
+    permissions::write_restricted(credentials_files, content)?;
+    Ok(CopiedProfile {
+        profile: name.to_string(),
+        sha256: sha256_file(credentials_files)?,
+        hosts: BTreeMap::new(),
+    })
+}
+
+/// Copy unmanaged credentials into the store under a name, then track them as a copy of the new
+/// profile so the next switch can replace them, returning the path of the new profile
+///
+/// Returns nothing when the credentials are missing or a symbolic link, so callers check
+/// themselves that they aren't a registered profile, and fails when the name isn't safe as a
+/// file name
+pub fn adopt(
+    name: &str,
+    terraform_directory: &Path,
+    project_directory: &Path,
+) -> Result<Option<PathBuf>> {
+    store::validate_profile_name(name)?;
+    if let Credentials::Missing | Credentials::Symlink(_) | Credentials::Helper(_) =
+        Credentials::inspect(terraform_directory, project_directory)?
+    {
+        return Ok(None);
+    }
+    let credentials_files = path(terraform_directory);
+    let profile_path = project_directory.join(format!("{name}.tfrc.json"));
+    tracing::info!(
+        "Copying {} to {}",
+        credentials_files.display(),
+        profile_path.display()
+    );
+    let content = std::fs::read_to_string(&credentials_files)?;
+    permissions::write_restricted(&profile_path, encryption::seal(&content)?)?;
+
+    let mut state = State::load(project_directory)?;
+    state.hard_linked_profile = None;
+    state.copied_profile = Some(CopiedProfile {
+        profile: name.to_string(),
+        sha256: sha256_file(&credentials_files)?,
+        hosts: BTreeMap::new(),
+    });
+    state.save(project_directory)?;
+    Ok(Some(profile_path))
+}
+
+/// Remove the credentials and forget the profile they were made from or the helper serves
+pub fn remove(terraform_directory: &Path, project_directory: &Path) -> Result<()> {
+    let credentials_files = path(terraform_directory);
+    if credentials_files.is_symlink() || credentials_files.exists() {
+        tracing::info!("Removing {}", credentials_files.display());
+        std::fs::remove_file(&credentials_files)?;
+    }
+    untrack(project_directory)
+}
+
+/// Forget the profile the credentials were copied or hard linked from, or the helper serves,
+/// once they aren't anymore
+pub fn untrack(project_directory: &Path) -> Result<()> {
+    let mut state = State::load(project_directory)?;
+    if state.copied_profile.is_some()
+        || state.hard_linked_profile.is_some()
+        || state.helper_profile.is_some()
+    {
+        state.copied_profile = None;
+        state.hard_linked_profile = None;
+        state.helper_profile = None;
+        state.save(project_directory)?;
+    }
+    Ok(())
+}
+
+/// What bringing the credentials up to date with the changed profiles did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Followed {
+    /// The credentials linked to a missing profile and were repaired
+    Repaired(Repair),
+    /// The credentials were hard linked again to the profile, whose file was replaced
+    Relinked(String),
+    /// The credentials were copied again from the profile, which changed
+    Updated(String),
+    /// The credentials were left as they are, as both the profile and its copy changed
+    Drifted(String),
+}
+
+/// What repairing dangling credentials did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Repair {
+    /// The credentials were re-pointed to the registered profile with the same file name
+    Relinked(String),
+    /// The credentials were removed, as no registered profile has the same file name
+    Removed,
+}
+
+/// Get the registered profile with the file name a dangling link points to, as when the store
+/// was moved
+pub fn repair_target<'a>(
+    link: &Path,
+    profiles: &'a HashMap<String, PathBuf>,
+) -> Option<(&'a String, &'a PathBuf)> {
+    let name = link.file_name()?.to_str()?.strip_suffix(".tfrc.json")?;
+    profiles.get_key_value(name)
+}
+
+/// Re-point the credentials linking to a missing file to the registered profile with the same
+/// file name, or else remove them, doing nothing unless the link is dangling
+pub fn repair(
+    terraform_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+) -> Result<Option<Repair>> {
+    let credentials_files = path(terraform_directory);
+    if !credentials_files.is_symlink() || credentials_files.exists() {
+        return Ok(None);
+    }
+    let link = credentials_files.read_link()?;
+    match repair_target(&link, profiles) {
+        Some((name, profile_path)) => {
+            replace(terraform_directory, |temporary| {
+                symlink(profile_path, temporary)
+            })?;
+            Ok(Some(Repair::Relinked(name.clone())))
+        }
+        None => {
+            tracing::info!("Removing the link {}", credentials_files.display());
+            std::fs::remove_file(&credentials_files)?;
+            Ok(Some(Repair::Removed))
+        }
+    }
+}
+
+/// Re-point the credentials to the new path of a moved profile, when they link to the old one
+pub fn follow_move(terraform_directory: &Path, old_path: &Path, new_path: &Path) -> Result<()> {
+    if path(terraform_directory).read_link().ok().as_deref() == Some(old_path) {
+        replace(terraform_directory, |temporary| {
+            symlink(new_path, temporary)
+        })?;
+    }
+    Ok(())
+}
+
+/// Symlink credentials with new profiles credentials depending on platform
+pub fn symlink(profile_path: &Path, credentials_files: &Path) -> Result<()> {
+    tracing::info!(
+        "Linking {} to {}",
+        credentials_files.display(),
+        profile_path.display()
+    );
+    #[cfg(target_family = "windows")]
+    std::os::windows::fs::symlink_file(profile_path, credentials_files)?;
+    #[cfg(target_family = "unix")]
+    std::os::unix::fs::symlink(profile_path, credentials_files)?;
+    Ok(())
+}
+
+/// Hash the content of a file, as a lowercase hexadecimal SHA-256
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let digest = Sha256::digest(std::fs::read(path)?);
+    Ok(digest.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Switcher;
+
+    /// Credentials indented with three spaces, with top-level keys terraform doesn't know about
+    const WORK: &str = r#"{
+   "credentials": {
+      "tfe.example.com": {
+         "token": "work-tfe"
+      },
+      "app.terraform.io": {
+         "token": "work-app"
+      }
+   },
+   "x-owner": "platform"
+}
+"#;
+
+    /// Credentials for one of the work hostnames and another one, formatted differently
+    const PERSONAL: &str = r#"{"credentials": {"app.terraform.io": {"token": "own-app"}, "private.example.org": {"token": "own-private"}}}"#;
+
+    /// A switcher copying the profiles of a temporary store, switched to the work profile
+    fn switched_to_work() -> (tempfile::TempDir, Switcher) {
+        let directory = tempfile::tempdir().unwrap();
+        let store = ProfileStore::open(directory.path().join("store"));
+        let terraform_directory = directory.path().join("terraform.d");
+        std::fs::create_dir_all(store.directory()).unwrap();
+        std::fs::create_dir_all(&terraform_directory).unwrap();
+        store.register("work", WORK).unwrap();
+        store.register("personal", PERSONAL).unwrap();
+        let switcher = Switcher::new(
+            store,
+            CredentialsFile::new(terraform_directory),
+            SwitchMode::Copy,
+        );
+        switcher.switch("work").unwrap();
+        (directory, switcher)
+    }
+
+    /// The content of the credentials file
+    fn credentials(switcher: &Switcher) -> String {
+        std::fs::read_to_string(switcher.credentials().path()).unwrap()
+    }
+
+    #[test]
+    fn switching_a_host_only_changes_its_entry() {
+        let (_directory, switcher) = switched_to_work();
+        assert_eq!(credentials(&switcher), WORK);
+        let host = switcher
+            .switch_host("personal", "APP.terraform.io")
+            .unwrap();
+        assert_eq!(host, "app.terraform.io");
+        assert_eq!(credentials(&switcher), WORK.replace("work-app", "own-app"));
+    }
+
+    #[test]
+    fn switching_a_new_host_appends_it() {
+        let (_directory, switcher) = switched_to_work();
+        switcher
+            .switch_host("personal", "private.example.org")
+            .unwrap();
+        assert_eq!(
+            credentials(&switcher),
+            r#"{
+   "credentials": {
+      "tfe.example.com": {
+         "token": "work-tfe"
+      },
+      "app.terraform.io": {
+         "token": "work-app"
+      },
+      "private.example.org": {
+         "token": "own-private"
+      }
+   },
+   "x-owner": "platform"
+}
+"#
+        );
+    }
+
+    #[test]
+    fn switching_a_host_back_restores_the_content() {
+        let (_directory, switcher) = switched_to_work();
+        switcher
+            .switch_host("personal", "app.terraform.io")
+            .unwrap();
+        switcher.switch_host("work", "app.terraform.io").unwrap();
+        assert_eq!(credentials(&switcher), WORK);
+        let state = State::load(switcher.store().directory()).unwrap();
+        assert!(state.copied_profile.unwrap().hosts.is_empty());
+    }
+
+    #[test]
+    fn following_changes_updates_the_copy() {
+        let (_directory, switcher) = switched_to_work();
+        let (store, credentials_file) = (switcher.store(), switcher.credentials());
+        let changed = WORK.replace("work-tfe", "new-tfe");
+        std::fs::write(store.profile_path("work"), &changed).unwrap();
+        assert_eq!(
+            credentials_file
+                .follow_changes(store, &[String::from("personal")])
+                .unwrap(),
+            None
+        );
+        assert_eq!(credentials(&switcher), WORK);
+
+        assert_eq!(
+            credentials_file
+                .follow_changes(store, &[String::from("work")])
+                .unwrap(),
+            Some(Followed::Updated(String::from("work")))
+        );
+        assert_eq!(credentials(&switcher), changed);
+    }
+
+    #[test]
+    fn following_changes_leaves_an_edited_copy_alone() {
+        let (_directory, switcher) = switched_to_work();
+        let (store, credentials_file) = (switcher.store(), switcher.credentials());
+        std::fs::write(
+            store.profile_path("work"),
+            WORK.replace("work-tfe", "new-tfe"),
+        )
+        .unwrap();
+        let edited = WORK.replace("work-app", "edited-app");
+        std::fs::write(credentials_file.path(), &edited).unwrap();
+        assert_eq!(
+            credentials_file
+                .follow_changes(store, &[String::from("work")])
+                .unwrap(),
+            Some(Followed::Drifted(String::from("work")))
+        );
+        assert_eq!(credentials(&switcher), edited);
+    }
+}
@@ -0,0 +1,63 @@
+//! Detection of the terraform cloud hostname a configuration talks to
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+/// Hostname terraform uses when a `cloud` or `remote` backend block doesn't set one
+pub const DEFAULT_HOSTNAME: &str = "app.terraform.io";
+
+/// Terraform cloud instance and organization targeted by a configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloudTarget {
+    /// Hostname of the terraform cloud or enterprise instance
+    pub hostname: String,
+    /// Organization owning the workspaces, when set in the configuration
+    pub organization: Option<String>,
+}
+
+/// Find the `cloud {}` or `backend "remote" {}` block among the `.tf` files of a directory
+pub fn detect_cloud_target(directory: &Path) -> Result<Option<CloudTarget>> {
+    let mut files: Vec<_> = std::fs::read_dir(directory)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "tf"))
+        .collect();
+    files.sort();
+
+    for file in files {
+        tracing::debug!("Parsing {}", file.display());
+        let content = std::fs::read_to_string(&file)?;
+        let body: hcl::Body = hcl::from_str(&content)
+            .with_context(|| format!("Couldn't parse {}", file.display()))?;
+
+        for terraform in body
+            .blocks()
+            .filter(|block| block.identifier() == "terraform")
+        {
+            for block in terraform.body().blocks() {
+                let is_cloud = block.identifier() == "cloud";
+                let is_remote = block.identifier() == "backend"
+                    && block.labels().first().map(|label| label.as_str()) == Some("remote");
+                if is_cloud || is_remote {
+                    return Ok(Some(CloudTarget {
+                        hostname: string_attribute(block.body(), "hostname")
+                            .unwrap_or_else(|| DEFAULT_HOSTNAME.to_string()),
+                        organization: string_attribute(block.body(), "organization"),
+                    }));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Get the value of an attribute set to a literal string
+fn string_attribute(body: &hcl::Body, key: &str) -> Option<String> {
+    body.attributes()
+        .find(|attribute| attribute.key() == key)
+        .and_then(|attribute| match attribute.expr() {
+            hcl::Expression::String(value) => Some(value.clone()),
+            _ => None,
+        })
+}
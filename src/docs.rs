@@ -0,0 +1,178 @@
+//! Reference documentation generated from the command line definition, for packagers to ship
+//! man pages and markdown that can't drift from the CLI
+
+use std::{fmt::Write as _, path::Path};
+
+use anyhow::{Context, Result};
+use clap::{Arg, Command, CommandFactory};
+
+use crate::Cli;
+
+/// Directory inside the output directory the man pages are written to
+const MAN_DIRECTORY: &str = "man";
+
+/// Directory inside the output directory the markdown pages are written to
+const MARKDOWN_DIRECTORY: &str = "markdown";
+
+/// Write a man page and a markdown page for the CLI and each of its visible subcommands, named
+/// after the path to them, e.g. `man/terraform-profile-tag-add.1` and
+/// `markdown/terraform-profile-tag-add.md`
+pub fn generate(directory: &Path) -> Result<()> {
+    let mut command = Cli::command();
+    command.build();
+    for subdirectory in [MAN_DIRECTORY, MARKDOWN_DIRECTORY] {
+        std::fs::create_dir_all(directory.join(subdirectory))
+            .with_context(|| format!("Couldn't create {}", directory.display()))?;
+    }
+    let pages = write_pages(&command, env!("CARGO_PKG_NAME"), directory)?;
+    crate::output::notice(format!(
+        "Wrote {pages} man and markdown pages to {}",
+        directory.display()
+    ));
+    Ok(())
+}
+
+/// Write the pages of a command then of its subcommands, returning how many were written
+fn write_pages(command: &Command, page_name: &str, directory: &Path) -> Result<usize> {
+    let man_path = directory.join(MAN_DIRECTORY).join(format!("{page_name}.1"));
+    let mut man_page = Vec::new();
+    clap_mangen::Man::new(man_command(command, page_name)).render(&mut man_page)?;
+    std::fs::write(&man_path, man_page)
+        .with_context(|| format!("Couldn't write {}", man_path.display()))?;
+
+    let markdown_path = directory
+        .join(MARKDOWN_DIRECTORY)
+        .join(format!("{page_name}.md"));
+    std::fs::write(&markdown_path, markdown(command, page_name)?)
+        .with_context(|| format!("Couldn't write {}", markdown_path.display()))?;
+
+    let mut pages = 1;
+    for subcommand in visible_subcommands(command) {
+        pages += write_pages(
+            subcommand,
+            &format!("{page_name}-{}", subcommand.get_name()),
+            directory,
+        )?;
+    }
+    Ok(pages)
+}
+
+/// Prepare a built command for its man page, under the page name, with its flags stripped of
+/// the value name, default and possible values clap_mangen would otherwise describe them with
+///
+/// Every argument goes through `mut_arg`, which moves it last, so they keep their order
+fn man_command<'help>(command: &Command<'help>, page_name: &str) -> Command<'help> {
+    let arguments: Vec<(&str, bool)> = command
+        .get_arguments()
+        .map(|arg| {
+            (
+                arg.get_id(),
+                !arg.is_positional() && !arg.is_takes_value_set(),
+            )
+        })
+        .collect();
+    arguments
+        .into_iter()
+        .fold(command.clone().name(page_name), |command, (id, is_flag)| {
+            command.mut_arg(id, |arg| {
+                if is_flag {
+                    arg.value_names(&[])
+                        .default_values(&[])
+                        .hide_possible_values(true)
+                } else {
+                    arg
+                }
+            })
+        })
+}
+
+/// Subcommands worth a page, leaving out the hidden ones and `help`
+fn visible_subcommands<'a, 'help>(
+    command: &'a Command<'help>,
+) -> impl Iterator<Item = &'a Command<'help>> {
+    command
+        .get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set() && subcommand.get_name() != "help")
+}
+
+/// Render the markdown page of a command: its description, usage, arguments, options and links
+/// to the pages of its subcommands
+fn markdown(command: &Command, page_name: &str) -> Result<String> {
+    let mut page = String::new();
+    let invocation = command.get_bin_name().unwrap_or(command.get_name());
+    writeln!(page, "# {invocation}\n")?;
+    if let Some(about) = command.get_long_about().or(command.get_about()) {
+        writeln!(page, "{}\n", about.trim())?;
+    }
+    let usage = command.clone().render_usage();
+    let usage = usage.trim_start_matches("USAGE:").trim();
+    writeln!(page, "## Usage\n\n```\n{usage}\n```\n")?;
+
+    let (positionals, options): (Vec<&Arg>, Vec<&Arg>) = command
+        .get_arguments()
+        .filter(|arg| !arg.is_hide_set())
+        .partition(|arg| arg.is_positional());
+    for (title, args) in [("Arguments", positionals), ("Options", options)] {
+        if args.is_empty() {
+            continue;
+        }
+        writeln!(page, "## {title}\n")?;
+        for arg in args {
+            write!(page, "- `{}`", describe_arg(arg))?;
+            if let Some(help) = arg.get_long_help().or(arg.get_help()) {
+                write!(page, ": {}", help.replace('\n', " "))?;
+            }
+            let defaults: Vec<_> = arg
+                .get_default_values()
+                .iter()
+                .map(|value| value.to_string_lossy())
+                .collect();
+            if arg.is_takes_value_set() && !defaults.is_empty() {
+                write!(page, " [default: {}]", defaults.join(", "))?;
+            }
+            writeln!(page)?;
+        }
+        writeln!(page)?;
+    }
+
+    let subcommands: Vec<&Command> = visible_subcommands(command).collect();
+    if !subcommands.is_empty() {
+        writeln!(page, "## Subcommands\n")?;
+        for subcommand in subcommands {
+            writeln!(
+                page,
+                "- [`{}`]({page_name}-{}.md): {}",
+                subcommand.get_name(),
+                subcommand.get_name(),
+                subcommand.get_about().unwrap_or_default()
+            )?;
+        }
+        writeln!(page)?;
+    }
+    Ok(page)
+}
+
+/// Spell an argument as it is typed, e.g. `-o, --output <OUTPUT>` or `<NAME>`
+fn describe_arg(arg: &Arg) -> String {
+    let value_names: Vec<String> = match arg.get_value_names() {
+        _ if !arg.is_takes_value_set() => Vec::new(),
+        Some(names) => names.iter().map(|name| format!("<{name}>")).collect(),
+        None => vec![format!("<{}>", arg.get_id().to_uppercase())],
+    };
+    if arg.is_positional() {
+        return value_names.join(" ");
+    }
+    let mut flags: Vec<String> = Vec::new();
+    if let Some(short) = arg.get_short() {
+        flags.push(format!("-{short}"));
+    }
+    if let Some(long) = arg.get_long() {
+        flags.push(format!("--{long}"));
+    }
+    let mut described = flags.join(", ");
+    for value_name in value_names {
+        described.push(' ');
+        described.push_str(&value_name);
+    }
+    described
+}
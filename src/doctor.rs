@@ -0,0 +1,390 @@
+//! Diagnostics of the store, the credentials and the environment, with fixes for the safe ones
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    credentials::{self, Credentials},
+    encryption, paths, permissions, source, status, store, vault,
+};
+
+/// Fix `doctor --fix` can apply without risking any credentials
+#[derive(Debug)]
+enum Fix {
+    /// Restrict the store to its owner
+    RestrictDirectory(PathBuf),
+    /// Restrict a stored profile to its owner
+    RestrictFile(PathBuf),
+    /// Remove a leftover of an interrupted write
+    Remove(PathBuf),
+    /// Re-point or remove dangling credentials in a terraform directory
+    Repair(PathBuf, HashMap<String, PathBuf>),
+    /// Encrypt a stored profile, then decrypt the credentials linking to it into a copy
+    Encrypt {
+        name: String,
+        profile_path: PathBuf,
+        terraform_directory: PathBuf,
+        project_directory: PathBuf,
+    },
+}
+
+/// Problem found in the store, the credentials or the environment
+#[derive(Serialize, Debug)]
+pub struct Finding {
+    /// What is wrong
+    pub problem: String,
+    /// How to fix it
+    pub suggestion: String,
+    /// Whether `doctor --fix` applies the suggestion itself
+    pub fixable: bool,
+    /// Whether the suggestion was applied by this run
+    pub fixed: bool,
+    #[serde(skip)]
+    fix: Option<Fix>,
+}
+
+impl Finding {
+    /// Problem the user has to fix themselves
+    fn manual(problem: String, suggestion: String) -> Self {
+        Finding {
+            problem,
+            suggestion,
+            fixable: false,
+            fixed: false,
+            fix: None,
+        }
+    }
+
+    /// Problem `doctor --fix` can fix
+    fn fixable(problem: String, suggestion: String, fix: Fix) -> Self {
+        Finding {
+            problem,
+            suggestion,
+            fixable: true,
+            fixed: false,
+            fix: Some(fix),
+        }
+    }
+
+    /// Apply the fix of the finding, if it has one
+    pub fn apply(&mut self) -> Result<()> {
+        match &self.fix {
+            None => return Ok(()),
+            Some(Fix::RestrictDirectory(path)) => permissions::restrict_directory(path)?,
+            Some(Fix::RestrictFile(path)) => permissions::restrict_file(path)?,
+            Some(Fix::Remove(path)) => {
+                tracing::info!("Removing {}", path.display());
+                std::fs::remove_file(path)?;
+            }
+            Some(Fix::Repair(terraform_directory, profiles)) => {
+                credentials::repair(terraform_directory, profiles)?;
+            }
+            Some(Fix::Encrypt {
+                name,
+                profile_path,
+                terraform_directory,
+                project_directory,
+            }) => {
+                encryption::seal_file(profile_path)?;
+                credentials::refresh(name, profile_path, terraform_directory, project_directory)?;
+            }
+        }
+        self.fixed = true;
+        Ok(())
+    }
+}
+
+/// Run every check, returning the problems found
+pub fn diagnose(
+    home_dir: &Path,
+    terraform_directory: &Path,
+    project_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+    default_profile: Option<&str>,
+) -> Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+    check_store(project_directory, profiles, &mut findings)?;
+    check_encryption(
+        terraform_directory,
+        project_directory,
+        profiles,
+        &mut findings,
+    )?;
+    check_credentials(
+        terraform_directory,
+        project_directory,
+        profiles,
+        default_profile,
+        &mut findings,
+    )?;
+    check_legacy_directory(home_dir, project_directory, &mut findings);
+    check_terraform(&mut findings);
+    check_environment(&mut findings);
+    Ok(findings)
+}
+
+/// Check the permissions of the store and for files in it that aren't profiles
+fn check_store(
+    project_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+    findings: &mut Vec<Finding>,
+) -> Result<()> {
+    if permissions::is_exposed(project_directory) {
+        findings.push(Finding::fixable(
+            format!(
+                "{} can be accessed by other users",
+                project_directory.display()
+            ),
+            String::from("Restrict it to its owner, with mode 0700"),
+            Fix::RestrictDirectory(project_directory.to_path_buf()),
+        ));
+    }
+    let mut paths: Vec<&PathBuf> = profiles.values().collect();
+    paths.sort();
+    for path in paths {
+        if permissions::is_exposed(path) {
+            findings.push(Finding::fixable(
+                format!("{} can be read by other users", path.display()),
+                String::from("Restrict it to its owner, with mode 0600"),
+                Fix::RestrictFile(path.clone()),
+            ));
+        }
+    }
+
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(project_directory)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| !profiles.values().any(|profile| profile == path))
+        .collect();
+    entries.sort();
+    for path in entries {
+        let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if store::is_reserved_file(file_name) {
+            continue;
+        }
+        // Leftovers of the atomic writes, which are only made while holding the lock
+        if file_name.starts_with(".tmp") && path.is_file() {
+            findings.push(Finding::fixable(
+                format!("{} is left over from an interrupted write", path.display()),
+                String::from("Remove it"),
+                Fix::Remove(path),
+            ));
+        } else {
+            findings.push(Finding::manual(
+                format!("{} isn't a profile", path.display()),
+                String::from(
+                    "Move it out of the store, rename it to `<name>.tfrc.json`, or run `prune`",
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Check that the stored profiles are encrypted when encryption is on
+fn check_encryption(
+    terraform_directory: &Path,
+    project_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+    findings: &mut Vec<Finding>,
+) -> Result<()> {
+    let mut profiles: Vec<(&String, &PathBuf)> = profiles.iter().collect();
+    profiles.sort();
+    for (name, path) in profiles {
+        // Vault and the sources keep the tokens of their profiles, leaving nothing to encrypt
+        let content = std::fs::read_to_string(path)?;
+        if vault::is_skeleton(&content) || source::has_sources(&content) {
+            continue;
+        }
+        let encrypted = encryption::is_encrypted_file(path)?;
+        if encryption::is_enabled() && !encrypted {
+            findings.push(Finding::fixable(
+                format!("{} holds its tokens in plain text", path.display()),
+                String::from("Protect them with the configured `[encryption]`"),
+                Fix::Encrypt {
+                    name: name.clone(),
+                    profile_path: path.clone(),
+                    terraform_directory: terraform_directory.to_path_buf(),
+                    project_directory: project_directory.to_path_buf(),
+                },
+            ));
+        } else if !encryption::is_enabled() && encrypted {
+            findings.push(Finding::manual(
+                format!(
+                    "{} is encrypted, but no encryption is configured",
+                    path.display()
+                ),
+                String::from("Configure the `[encryption]` it was encrypted with"),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Check that the credentials are a registered profile, and for leftovers of interrupted switches
+fn check_credentials(
+    terraform_directory: &Path,
+    project_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+    default_profile: Option<&str>,
+    findings: &mut Vec<Finding>,
+) -> Result<()> {
+    let credentials_files = credentials::path(terraform_directory);
+    let current = Credentials::inspect(terraform_directory, project_directory)?;
+    if let Some(default) = default_profile.filter(|default| !profiles.contains_key(*default)) {
+        findings.push(Finding::manual(
+            format!("The default profile `{default}` isn't registered"),
+            String::from("Set another one with `default set <name>`, or `default unset` it"),
+        ));
+    }
+    if current.active_profile(profiles).is_none() {
+        match current {
+            Credentials::Missing => {
+                if let Some(default) = default_profile.filter(|d| profiles.contains_key(*d)) {
+                    findings.push(Finding::manual(
+                        String::from("No profile is active"),
+                        format!("Switch back to the default profile `{default}` with `reset`"),
+                    ));
+                }
+            }
+            Credentials::Symlink(link) if !credentials_files.exists() => {
+                let suggestion = match credentials::repair_target(&link, profiles) {
+                    Some((name, path)) => format!(
+                        "Re-point it to profile `{name}`, now at {}, with `switch --repair`",
+                        path.display()
+                    ),
+                    None => String::from("Remove it with `switch --repair`"),
+                };
+                findings.push(Finding::fixable(
+                    format!(
+                        "{} links to {}, which doesn't exist",
+                        credentials_files.display(),
+                        link.display()
+                    ),
+                    suggestion,
+                    Fix::Repair(terraform_directory.to_path_buf(), profiles.clone()),
+                ))
+            }
+            Credentials::Symlink(link) => findings.push(Finding::manual(
+                format!(
+                    "{} links to {}, which isn't a registered profile",
+                    credentials_files.display(),
+                    link.display()
+                ),
+                String::from("Move the file into the store, or switch to a registered profile"),
+            )),
+            Credentials::Hardlink {
+                profile,
+                broken: false,
+            }
+            | Credentials::Copy {
+                profile,
+                drifted: false,
+            } => findings.push(Finding::manual(
+                format!(
+                    "{} was made from `{profile}`, which isn't registered anymore",
+                    credentials_files.display()
+                ),
+                String::from("Import it as a new profile, or remove it"),
+            )),
+            Credentials::Hardlink { profile, .. } | Credentials::Copy { profile, .. } => findings
+                .push(Finding::manual(
+                    format!(
+                        "{} was changed since it was made from `{profile}`",
+                        credentials_files.display()
+                    ),
+                    String::from("Import it as a new profile, or remove it"),
+                )),
+            Credentials::Unmanaged => findings.push(Finding::manual(
+                format!("{} isn't a registered profile", credentials_files.display()),
+                String::from("Import it with `terraform-profile import <name>`"),
+            )),
+            Credentials::Helper(profile) => findings.push(Finding::manual(
+                format!(
+                    "The credentials helper serves `{profile}`, which isn't registered anymore"
+                ),
+                String::from("Switch to a registered profile"),
+            )),
+        }
+    }
+
+    let temporary_prefix = format!(".{}.", credentials::CREDENTIALS_FILE);
+    let Ok(entries) = std::fs::read_dir(terraform_directory) else {
+        return Ok(());
+    };
+    for entry in entries.flatten() {
+        if entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.starts_with(&temporary_prefix))
+        {
+            findings.push(Finding::fixable(
+                format!(
+                    "{} is left over from an interrupted switch",
+                    entry.path().display()
+                ),
+                String::from("Remove it"),
+                Fix::Remove(entry.path()),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Check for a legacy directory the migration skipped as the data directory already existed
+fn check_legacy_directory(home_dir: &Path, project_directory: &Path, findings: &mut Vec<Finding>) {
+    let legacy = paths::legacy_directory(home_dir);
+    if legacy.is_dir() {
+        findings.push(Finding::manual(
+            format!("{} is ignored", legacy.display()),
+            format!(
+                "Move the profiles left in it to {}, then remove it",
+                project_directory.display()
+            ),
+        ));
+    }
+}
+
+/// Check that terraform or OpenTofu can be found
+fn check_terraform(findings: &mut Vec<Finding>) {
+    if !["terraform", "tofu"].iter().any(|program| on_path(program)) {
+        findings.push(Finding::manual(
+            String::from("Neither `terraform` nor `tofu` is on the PATH"),
+            String::from("Install one of them, or add its directory to the PATH"),
+        ));
+    }
+}
+
+/// Whether an executable can be found in one of the PATH directories
+fn on_path(program: &str) -> bool {
+    let file_name = if cfg!(target_family = "windows") {
+        format!("{program}.exe")
+    } else {
+        program.to_string()
+    };
+    std::env::var_os("PATH").is_some_and(|path| {
+        std::env::split_paths(&path).any(|directory| directory.join(&file_name).is_file())
+    })
+}
+
+/// Check for the variables terraform reads before the managed credentials
+fn check_environment(findings: &mut Vec<Finding>) {
+    for name in status::environment_overrides() {
+        let problem = if name == "TF_CLI_CONFIG_FILE" {
+            String::from("`TF_CLI_CONFIG_FILE` is set, so terraform may read credentials from another configuration file")
+        } else {
+            format!("`{name}` is set, and takes precedence over the managed credentials for its hostname")
+        };
+        findings.push(Finding::manual(
+            problem,
+            String::from("Unset it to use the managed credentials"),
+        ));
+    }
+}
@@ -0,0 +1,426 @@
+//! Encryption at rest of the stored profiles, which are only decrypted into the credentials
+//! terraform reads or into the environment of `exec`
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    str::FromStr,
+    sync::OnceLock,
+};
+
+use age::secrecy::SecretString;
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    agent,
+    config::{Encryption, EncryptionBackend},
+    permissions, source, vault,
+};
+
+/// First line of the profiles encrypted with age
+const AGE_HEADER: &str = "-----BEGIN AGE ENCRYPTED FILE-----";
+
+/// First line of the profiles encrypted with GnuPG
+const GPG_HEADER: &str = "-----BEGIN PGP MESSAGE-----";
+
+/// Configuration of SOPS, which may be kept in the store along with the profiles
+pub const SOPS_CONFIG: &str = ".sops.yaml";
+
+/// Prefix of the tokens kept in the OS keyring, followed by the user of their entry
+const KEYRING_PREFIX: &str = "keyring:";
+
+/// Backend the stored profiles are encrypted with, set once from the configuration
+static BACKEND: OnceLock<Box<dyn Backend>> = OnceLock::new();
+
+/// Way of encrypting the stored profiles
+trait Backend: Send + Sync {
+    /// Whether content was encrypted by this backend
+    fn recognizes(&self, content: &str) -> bool;
+    /// Encrypt the content of a profile
+    fn encrypt(&self, plaintext: &str) -> Result<String>;
+    /// Decrypt the content of a profile
+    fn decrypt(&self, ciphertext: &str) -> Result<String>;
+}
+
+/// Profiles encrypted with age, to the identities of a file or else with a passphrase
+struct Age {
+    /// File holding the identities, as written by `age-keygen`
+    identity_file: Option<PathBuf>,
+    /// Passphrase asked for once per run
+    passphrase: OnceLock<SecretString>,
+}
+
+impl Age {
+    /// Read the identities of the identity file
+    fn identities(identity_file: &Path) -> Result<Vec<age::x25519::Identity>> {
+        let content = std::fs::read_to_string(identity_file)
+            .with_context(|| format!("Couldn't read {}", identity_file.display()))?;
+        let identities = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                age::x25519::Identity::from_str(line).map_err(|error| {
+                    anyhow::anyhow!(
+                        "{} holds an invalid identity: {error}",
+                        identity_file.display()
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if identities.is_empty() {
+            anyhow::bail!("{} holds no identity", identity_file.display());
+        }
+        Ok(identities)
+    }
+
+    /// Get the passphrase, asking for it the first time, twice when about to encrypt
+    fn passphrase(&self, confirm: bool) -> Result<SecretString> {
+        if let Some(passphrase) = self.passphrase.get() {
+            return Ok(passphrase.clone());
+        }
+        let passphrase = crate::archive::read_passphrase(confirm)?;
+        let _ = self.passphrase.set(passphrase.clone());
+        Ok(passphrase)
+    }
+}
+
+impl Backend for Age {
+    fn recognizes(&self, content: &str) -> bool {
+        content.starts_with(AGE_HEADER)
+    }
+
+    fn encrypt(&self, plaintext: &str) -> Result<String> {
+        let ciphertext = match &self.identity_file {
+            Some(identity_file) => age::encrypt_and_armor(
+                &Self::identities(identity_file)?[0].to_public(),
+                plaintext.as_bytes(),
+            ),
+            None => age::encrypt_and_armor(
+                &age::scrypt::Recipient::new(self.passphrase(true)?),
+                plaintext.as_bytes(),
+            ),
+        }?;
+        Ok(ciphertext)
+    }
+
+    fn decrypt(&self, ciphertext: &str) -> Result<String> {
+        let plaintext = match &self.identity_file {
+            Some(identity_file) => {
+                let identities = Self::identities(identity_file)?;
+                let decryptor = age::Decryptor::new_buffered(age::armor::ArmoredReader::new(
+                    ciphertext.as_bytes(),
+                ))?;
+                let mut reader = decryptor.decrypt(
+                    identities
+                        .iter()
+                        .map(|identity| identity as &dyn age::Identity),
+                )?;
+                let mut plaintext = Vec::new();
+                std::io::Read::read_to_end(&mut reader, &mut plaintext)?;
+                plaintext
+            }
+            None => age::decrypt(
+                &age::scrypt::Identity::new(self.passphrase(false)?),
+                ciphertext.as_bytes(),
+            )?,
+        };
+        Ok(String::from_utf8(plaintext)?)
+    }
+}
+
+/// Profiles encrypted with GnuPG to recipients, whose keys may live on a smartcard the
+/// gpg-agent asks for
+struct Gpg {
+    /// Key identifiers or emails of the recipients
+    recipients: Vec<String>,
+}
+
+impl Gpg {
+    /// Run `gpg` with arguments, feeding it the input and returning its output
+    fn run(arguments: &[&str], input: &str) -> Result<String> {
+        tracing::info!("Running `gpg {}`", arguments.join(" "));
+        let mut child = Command::new("gpg")
+            .args(["--batch", "--quiet", "--armor"])
+            .args(arguments)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("Couldn't run `gpg`, is GnuPG installed?")?;
+        child
+            .stdin
+            .take()
+            .context("Couldn't write to `gpg`")?
+            .write_all(input.as_bytes())?;
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            anyhow::bail!("`gpg` exited with {}", output.status);
+        }
+        Ok(String::from_utf8(output.stdout)?)
+    }
+}
+
+impl Backend for Gpg {
+    fn recognizes(&self, content: &str) -> bool {
+        content.starts_with(GPG_HEADER)
+    }
+
+    fn encrypt(&self, plaintext: &str) -> Result<String> {
+        let mut arguments = vec!["--encrypt"];
+        for recipient in &self.recipients {
+            arguments.extend(["--recipient", recipient]);
+        }
+        Self::run(&arguments, plaintext)
+    }
+
+    fn decrypt(&self, ciphertext: &str) -> Result<String> {
+        Self::run(&["--decrypt"], ciphertext)
+    }
+}
+
+/// Profiles encrypted with SOPS, following the creation rules of the `.sops.yaml` found from the
+/// store directory up, so the store can be committed and shared through git
+struct Sops {
+    /// Directory the profiles are stored in, where `sops` looks for its configuration
+    directory: PathBuf,
+}
+
+impl Sops {
+    /// Whether a profile is a SOPS document, whose values are encrypted but keys aren't
+    fn is_document(content: &str) -> bool {
+        serde_json::from_str::<serde_json::Value>(content)
+            .is_ok_and(|value| value["sops"]["mac"].is_string())
+    }
+
+    /// Run `sops` with an argument on a scratch profile holding the input, returning its output
+    ///
+    /// The scratch profile is named like the stored ones, so the creation rules matching them by
+    /// path apply to it too
+    fn run(&self, argument: &str, input: &str) -> Result<String> {
+        let mut scratch = tempfile::Builder::new()
+            .prefix(".tmp")
+            .suffix(".tfrc.json")
+            .tempfile_in(&self.directory)?;
+        scratch.write_all(input.as_bytes())?;
+        scratch.flush()?;
+        tracing::info!("Running `sops {argument}`");
+        let output = Command::new("sops")
+            .arg(argument)
+            .arg(scratch.path())
+            .current_dir(&self.directory)
+            .stderr(Stdio::inherit())
+            .output()
+            .context("Couldn't run `sops`, is SOPS installed?")?;
+        if !output.status.success() {
+            anyhow::bail!("`sops {argument}` exited with {}", output.status);
+        }
+        Ok(String::from_utf8(output.stdout)?)
+    }
+}
+
+impl Backend for Sops {
+    fn recognizes(&self, content: &str) -> bool {
+        Self::is_document(content)
+    }
+
+    fn encrypt(&self, plaintext: &str) -> Result<String> {
+        self.run("--encrypt", plaintext)
+    }
+
+    fn decrypt(&self, ciphertext: &str) -> Result<String> {
+        self.run("--decrypt", ciphertext)
+    }
+}
+
+/// Profiles whose tokens are kept in the OS keyring, leaving a token-less skeleton on disk
+///
+/// Each token is filed under the hash of its value, so copies of a profile share their entries
+struct Keyring;
+
+impl Keyring {
+    /// Whether a profile is a skeleton with tokens in the keyring
+    fn is_skeleton(content: &str) -> bool {
+        serde_json::from_str::<serde_json::Value>(content).is_ok_and(|value| {
+            value["credentials"].as_object().is_some_and(|hosts| {
+                hosts.values().any(|entry| {
+                    entry["token"]
+                        .as_str()
+                        .is_some_and(|token| token.starts_with(KEYRING_PREFIX))
+                })
+            })
+        })
+    }
+
+    /// Replace every token of a profile
+    fn replace_tokens(content: &str, replace: impl Fn(&str) -> Result<String>) -> Result<String> {
+        let mut value: serde_json::Value =
+            serde_json::from_str(content).context("The profile isn't valid JSON")?;
+        if let Some(hosts) = value["credentials"].as_object_mut() {
+            for entry in hosts.values_mut() {
+                if let Some(token) = entry["token"].as_str() {
+                    entry["token"] = serde_json::Value::String(replace(token)?);
+                }
+            }
+        }
+        crate::store::format_like(content, &value)
+    }
+}
+
+impl Backend for Keyring {
+    fn recognizes(&self, content: &str) -> bool {
+        Self::is_skeleton(content)
+    }
+
+    fn encrypt(&self, plaintext: &str) -> Result<String> {
+        Self::replace_tokens(plaintext, |token| {
+            if token.starts_with(KEYRING_PREFIX) {
+                return Ok(token.to_string());
+            }
+            let digest = Sha256::digest(token.as_bytes());
+            let user: String = digest[..16]
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect();
+            tracing::info!("Storing a token in the OS keyring as {user}");
+            keyring::Entry::new(env!("CARGO_PKG_NAME"), &user)?
+                .set_password(token)
+                .context("Couldn't store the token in the OS keyring")?;
+            Ok(format!("{KEYRING_PREFIX}{user}"))
+        })
+    }
+
+    fn decrypt(&self, ciphertext: &str) -> Result<String> {
+        Self::replace_tokens(ciphertext, |token| {
+            match token.strip_prefix(KEYRING_PREFIX) {
+                Some(user) => {
+                    tracing::debug!("Reading the token {user} from the OS keyring");
+                    keyring::Entry::new(env!("CARGO_PKG_NAME"), user)?
+                        .get_password()
+                        .with_context(|| {
+                            format!("Couldn't read the token {user} from the OS keyring")
+                        })
+                }
+                None => Ok(token.to_string()),
+            }
+        })
+    }
+}
+
+/// Encrypt the profiles written from now on with the configured backend, if any
+pub fn configure(
+    encryption: Option<&Encryption>,
+    home_dir: &Path,
+    project_directory: &Path,
+) -> Result<()> {
+    let Some(encryption) = encryption else {
+        return Ok(());
+    };
+    let backend: Box<dyn Backend> = match encryption.backend {
+        EncryptionBackend::Age => Box::new(Age {
+            identity_file: encryption.identity_file(home_dir),
+            passphrase: OnceLock::new(),
+        }),
+        EncryptionBackend::Gpg if encryption.recipients.is_empty() => {
+            anyhow::bail!("The gpg encryption backend needs at least one recipient in `recipients`")
+        }
+        EncryptionBackend::Gpg => Box::new(Gpg {
+            recipients: encryption.recipients.clone(),
+        }),
+        EncryptionBackend::Keyring => Box::new(Keyring),
+        EncryptionBackend::Sops => Box::new(Sops {
+            directory: project_directory.to_path_buf(),
+        }),
+    };
+    let _ = BACKEND.set(backend);
+    Ok(())
+}
+
+/// Whether the profiles written are encrypted
+pub fn is_enabled() -> bool {
+    BACKEND.get().is_some()
+}
+
+/// Whether stored content is encrypted, whatever the configuration
+pub fn is_encrypted(content: &str) -> bool {
+    content.starts_with(AGE_HEADER)
+        || content.starts_with(GPG_HEADER)
+        || Keyring::is_skeleton(content)
+        || Sops::is_document(content)
+}
+
+/// Encrypt the content of a profile about to be stored, unless encryption is off
+pub fn seal(plaintext: &str) -> Result<String> {
+    match BACKEND.get() {
+        Some(backend) => backend.encrypt(plaintext),
+        None => Ok(plaintext.to_string()),
+    }
+}
+
+/// Encrypt the new content of a stored profile, writing its tokens back to Vault instead when
+/// the profile keeps them there
+pub fn reseal(profile_path: &Path, plaintext: &str) -> Result<String> {
+    let stored = std::fs::read_to_string(profile_path)?;
+    if vault::is_skeleton(&stored) {
+        return vault::store(&stored, plaintext);
+    }
+    seal(plaintext)
+}
+
+/// Read a stored profile, decrypting it when it is encrypted or fetching its tokens when they
+/// are kept in Vault, but leaving its sources as they are
+pub fn decrypt_profile(profile_path: &Path) -> Result<String> {
+    let content = std::fs::read_to_string(profile_path)
+        .with_context(|| format!("Couldn't read {}", profile_path.display()))?;
+    if vault::is_skeleton(&content) {
+        return vault::resolve(&content)
+            .with_context(|| format!("Couldn't fetch the tokens of {}", profile_path.display()));
+    }
+    if !is_encrypted(&content) {
+        return Ok(content);
+    }
+    match BACKEND.get() {
+        Some(backend) if backend.recognizes(&content) => backend
+            .decrypt(&content)
+            .with_context(|| format!("Couldn't decrypt {}", profile_path.display())),
+        _ => anyhow::bail!(
+            "{} is encrypted, and can't be decrypted without its `[encryption]` configuration",
+            profile_path.display()
+        ),
+    }
+}
+
+/// Read the tokens of a stored profile, decrypting it and fetching them from wherever they are
+/// kept
+pub fn read_profile(profile_path: &Path) -> Result<String> {
+    if is_encrypted_file(profile_path)? {
+        if let Some(content) = agent::read(profile_path) {
+            return Ok(content);
+        }
+    }
+    let content = decrypt_profile(profile_path)?;
+    if !source::has_sources(&content) {
+        return Ok(content);
+    }
+    source::resolve(&content)
+        .with_context(|| format!("Couldn't fetch the tokens of {}", profile_path.display()))
+}
+
+/// Whether a stored profile is encrypted or keeps its tokens in Vault or another source, so
+/// terraform can't read it directly
+pub fn is_encrypted_file(profile_path: &Path) -> Result<bool> {
+    let content = std::fs::read_to_string(profile_path)?;
+    Ok(is_encrypted(&content) || vault::is_skeleton(&content) || source::has_sources(&content))
+}
+
+/// Encrypt a stored profile in place, unless encryption is off or it already is
+pub fn seal_file(profile_path: &Path) -> Result<()> {
+    if !is_enabled() || is_encrypted_file(profile_path)? {
+        return Ok(());
+    }
+    tracing::info!("Encrypting {}", profile_path.display());
+    let sealed = seal(&std::fs::read_to_string(profile_path)?)?;
+    crate::store::write_file_atomically(profile_path, sealed)?;
+    permissions::restrict_file(profile_path)
+}
@@ -0,0 +1,165 @@
+//! Failures scripts can tell apart through the exit code of the CLI
+
+use std::path::PathBuf;
+
+/// Exit code of the failures that aren't a [`CliError`]
+pub const GENERAL_EXIT_CODE: i32 = 1;
+
+/// Exit code of invalid command lines, `EX_USAGE` from `sysexits.h`
+pub const USAGE_EXIT_CODE: i32 = 64;
+
+/// Failure with a dedicated exit code
+#[derive(Debug, thiserror::Error)]
+pub enum CliError {
+    /// The credentials don't exist, so no profile is in use
+    #[error("No profile is currently in use.")]
+    NoActiveProfile,
+    /// The credentials exist but aren't a registered profile
+    #[error(
+        "A non-profile credentials already exists, you should import or delete it first, or switch with `--adopt <name>`."
+    )]
+    UnmanagedCredentials,
+    /// No profile is registered under the name
+    #[error("Couldn't find the profile `{name}`.{}", did_you_mean(.suggestions))]
+    UnknownProfile {
+        /// Name that was given
+        name: String,
+        /// Registered names close to it, closest first
+        suggestions: Vec<String>,
+    },
+    /// A profile is already registered under the name
+    #[error("A profile named `{0}` already exists.")]
+    ProfileExists(String),
+    /// Some tokens were refused or found expired by terraform cloud
+    #[error("Some tokens are expired or were refused.")]
+    InvalidTokens,
+    /// Another invocation holds the lock of the registry
+    #[error("Another terraform-profile operation is in progress.")]
+    OperationInProgress,
+    /// The credentials are a symbolic link to a file that doesn't exist
+    #[error(
+        "The credentials link to {}, which doesn't exist. Run `switch --repair` to fix them.",
+        .0.display()
+    )]
+    DanglingCredentials(PathBuf),
+    /// The name can't be used for a profile
+    #[error(transparent)]
+    InvalidProfileName(#[from] InvalidName),
+    /// The credentials are a symbolic link to a file that isn't a registered profile
+    #[error(
+        "The credentials link to {}, which isn't a registered profile. Remove the link or import the file with `--from-file`.",
+        .0.display()
+    )]
+    UnknownSymlink(PathBuf),
+    /// No profile is registered at all
+    #[error("No profiles is currently available")]
+    NoProfiles,
+    /// The credentials don't have the structure terraform expects
+    #[error(transparent)]
+    InvalidCredentials(#[from] SchemaError),
+    /// Several registered profiles match a partial name
+    #[error(
+        "`{name}` matches several profiles: {}. Type more of the name.",
+        .candidates.iter().map(|name| format!("`{name}`")).collect::<Vec<_>>().join(", ")
+    )]
+    AmbiguousProfile {
+        /// Partial name that was given
+        name: String,
+        /// Registered names it matches
+        candidates: Vec<String>,
+    },
+    /// The command already told why it failed, so only its exit code is left to end with
+    #[error("Exited with code {0}")]
+    Exit(i32),
+}
+
+/// Why a name can't be used for a profile, as it names a file in the registry
+#[derive(Debug, thiserror::Error)]
+pub enum InvalidName {
+    /// The name is empty
+    #[error("The profile name can't be empty")]
+    Empty,
+    /// The name would put the profile in another directory
+    #[error("The profile name `{0}` can't contain path separators")]
+    PathSeparator(String),
+    /// The name would hide the profile, along with the files the registry keeps for itself
+    #[error("The profile name `{0}` can't start with a dot")]
+    LeadingDot(String),
+    /// The name holds characters that would garble the listings or the file name
+    #[error("The profile name `{}` can't contain control characters", .0.escape_debug())]
+    ControlCharacter(String),
+    /// The name is a device name Windows won't create a file for, whatever the extension
+    #[error("The profile name `{0}` is reserved on Windows")]
+    Reserved(String),
+}
+
+/// Where and why credentials don't have the structure terraform expects,
+/// `{"credentials": {"<hostname>": {"token": "<token>"}}}`
+#[derive(Debug, thiserror::Error)]
+#[error("line {line}, column {column}: {problem}")]
+pub struct SchemaError {
+    /// Line of the problem, from 1
+    pub line: usize,
+    /// Column of the problem, from 1
+    pub column: usize,
+    /// What is wrong there
+    pub problem: String,
+}
+
+impl CliError {
+    /// No profile is registered under a name, suggesting the closest of the registered names
+    pub fn unknown_profile<'a>(name: &str, names: impl IntoIterator<Item = &'a String>) -> Self {
+        CliError::UnknownProfile {
+            name: name.to_string(),
+            suggestions: crate::store::closest_names(name, names),
+        }
+    }
+
+    /// Exit code the CLI ends with on this failure
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::NoActiveProfile => 2,
+            CliError::UnmanagedCredentials => 3,
+            CliError::UnknownProfile { .. } => 4,
+            CliError::ProfileExists(_) => 5,
+            CliError::InvalidTokens => 6,
+            CliError::OperationInProgress => 7,
+            CliError::DanglingCredentials(_) => 8,
+            CliError::InvalidProfileName(_) => 9,
+            CliError::UnknownSymlink(_) => 10,
+            CliError::NoProfiles => 11,
+            CliError::InvalidCredentials(_) => 12,
+            CliError::AmbiguousProfile { .. } => 13,
+            CliError::Exit(code) => *code,
+        }
+    }
+
+    /// Whether the failure is worth a message, or was already told about
+    pub fn is_silent(&self) -> bool {
+        matches!(self, CliError::Exit(_))
+    }
+}
+
+/// Suggest names in place of a mistyped one, e.g. ` Did you mean `work`?`
+fn did_you_mean(suggestions: &[String]) -> String {
+    let quoted: Vec<String> = suggestions.iter().map(|name| format!("`{name}`")).collect();
+    match quoted.as_slice() {
+        [] => String::new(),
+        [name] => format!(" Did you mean {name}?"),
+        [names @ .., last] => format!(" Did you mean {} or {last}?", names.join(", ")),
+    }
+}
+
+/// Exit code the CLI ends with on an error
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    error
+        .downcast_ref::<CliError>()
+        .map_or(GENERAL_EXIT_CODE, CliError::exit_code)
+}
+
+/// Whether the CLI ends on an error without printing it
+pub fn is_silent(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<CliError>()
+        .is_some_and(CliError::is_silent)
+}
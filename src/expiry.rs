@@ -0,0 +1,195 @@
+//! Expirations of the tokens, fetched from the API at most once a day and remembered in the
+//! state, so `status`, `list --long` and `switch` can warn before a token stops working
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+use crate::{agent, api, encryption, output, state::State, store};
+
+/// How long a fetched expiration is trusted before it is fetched again, in seconds
+pub const CHECK_INTERVAL: u64 = 24 * 60 * 60;
+
+/// How long before a token expires the warnings start, in seconds
+pub const WARNING_PERIOD: u64 = 7 * 24 * 60 * 60;
+
+/// Expirations fetched for the hostnames of a profile, to record in the state
+type Fetched = BTreeMap<String, Option<u64>>;
+
+/// Fetch the expirations of a profile's tokens that weren't checked in the last day, returning
+/// the hostnames of its tokens along with what was fetched
+///
+/// Only the tokens written in the profile are looked at, its sources aren't read so a password
+/// manager is never asked. Failing to fetch an expiration is only logged, the last known one
+/// being kept until the next check.
+fn fetch_due(state: &State, name: &str, profile_path: &Path) -> (BTreeSet<String>, Fetched) {
+    let tokens = agent::read(profile_path)
+        .map_or_else(|| encryption::decrypt_profile(profile_path), Ok)
+        .and_then(|content| store::tokens(&content))
+        .unwrap_or_default();
+    let now = crate::state::now();
+    let mut fetched = Fetched::new();
+    for (host, token) in &tokens {
+        let cached = state
+            .token_expiries
+            .get(name)
+            .and_then(|expiries| expiries.get(host))
+            .copied();
+        if cached.is_some_and(|cached| now.saturating_sub(cached.checked_at) < CHECK_INTERVAL) {
+            continue;
+        }
+        let token_id = state.minted_token_id(name, host, token);
+        let expires_at = match api::Client::new(host, token).token_expiry(token_id) {
+            Ok(expires_at) => expires_at,
+            Err(error) => {
+                tracing::debug!("Couldn't fetch the expiration of the {host} token: {error:#}");
+                cached.and_then(|cached| cached.expires_at)
+            }
+        };
+        fetched.insert(host.clone(), expires_at);
+    }
+    (tokens.into_keys().collect(), fetched)
+}
+
+/// Record fetched expirations in the state as it is now, returning it
+///
+/// The fetches take long enough for another invocation to change the state meanwhile, e.g. a
+/// switch, so it is read again and only the expirations are changed.
+fn record(project_directory: &Path, fetched: Vec<(&str, Fetched)>) -> Result<State> {
+    let mut state = State::load(project_directory)?;
+    if fetched.iter().all(|(_, fetched)| fetched.is_empty()) {
+        return Ok(state);
+    }
+    for (name, fetched) in fetched {
+        for (host, expires_at) in fetched {
+            state.record_token_expiry(name, &host, expires_at);
+        }
+    }
+    state.save(project_directory)?;
+    Ok(state)
+}
+
+/// Get when the tokens of a profile expire, per hostname, as last fetched
+pub fn known(state: &State, name: &str) -> BTreeMap<String, u64> {
+    state
+        .token_expiries
+        .get(name)
+        .map(|expiries| {
+            expiries
+                .iter()
+                .filter_map(|(host, cached)| Some((host.clone(), cached.expires_at?)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Get when the tokens of a profile expire, per hostname, fetching the expirations that are due
+/// a check and remembering them in the state
+pub fn refresh_profile(
+    project_directory: &Path,
+    name: &str,
+    profile_path: &Path,
+) -> Result<BTreeMap<String, u64>> {
+    let (hosts, fetched) = fetch_due(&State::load(project_directory)?, name, profile_path);
+    let state = record(project_directory, vec![(name, fetched)])?;
+    Ok(known(&state, name)
+        .into_iter()
+        .filter(|(host, _)| hosts.contains(host))
+        .collect())
+}
+
+/// Fetch the expirations of every profile's tokens that are due a check, and remember them in
+/// the state
+pub fn refresh_profiles(
+    project_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+) -> Result<()> {
+    let state = State::load(project_directory)?;
+    let fetched = profiles
+        .iter()
+        .map(|(name, profile_path)| (name.as_str(), fetch_due(&state, name, profile_path).1))
+        .collect();
+    record(project_directory, fetched)?;
+    Ok(())
+}
+
+/// Warn about the tokens of a profile that expired or expire within the warning period
+pub fn warn(name: &str, expiries: &BTreeMap<String, u64>) {
+    for (host, expires_at) in expiries {
+        if is_soon(*expires_at) {
+            output::warning(format!(
+                "The `{host}` token of profile `{name}` {}, run `rotate {name}` to replace it",
+                describe(*expires_at)
+            ));
+        }
+    }
+}
+
+/// Whether a token expiring then has expired or expires within the warning period
+pub fn is_soon(expires_at: u64) -> bool {
+    expires_at.saturating_sub(crate::state::now()) < WARNING_PERIOD
+}
+
+/// Describe when a token expires, e.g. `expires in 3 days` or `expired 2 hours ago`
+pub fn describe(expires_at: u64) -> String {
+    let now = crate::state::now();
+    let (seconds, template) = if expires_at > now {
+        (expires_at - now, "expires in {}")
+    } else {
+        (now - expires_at, "expired {} ago")
+    };
+    let amount = match seconds {
+        0..=59 => String::from("less than a minute"),
+        60..=3599 => plural(seconds / 60, "minute"),
+        3600..=86399 => plural(seconds / 3600, "hour"),
+        _ => plural(seconds / 86400, "day"),
+    };
+    template.replace("{}", &amount)
+}
+
+/// Count a unit, e.g. `1 day` or `3 days`
+fn plural(count: u64, unit: &str) -> String {
+    if count == 1 {
+        format!("{count} {unit}")
+    } else {
+        format!("{count} {unit}s")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recording_keeps_what_changed_meanwhile() {
+        let directory = tempfile::tempdir().unwrap();
+        let fetched_from = State::load(directory.path()).unwrap();
+        let mut switched = State::load(directory.path()).unwrap();
+        switched.previous_profile = Some(String::from("personal"));
+        switched.save(directory.path()).unwrap();
+        assert!(fetched_from.previous_profile.is_none());
+
+        let fetched = Fetched::from([(String::from("app.terraform.io"), Some(1_000))]);
+        record(directory.path(), vec![("work", fetched)]).unwrap();
+        let state = State::load(directory.path()).unwrap();
+        assert_eq!(state.previous_profile.as_deref(), Some("personal"));
+        assert_eq!(
+            known(&state, "work"),
+            BTreeMap::from([(String::from("app.terraform.io"), 1_000)])
+        );
+    }
+
+    #[test]
+    fn describes_when_tokens_expire() {
+        let now = crate::state::now();
+        assert_eq!(describe(now + 3 * 86400 + 60), "expires in 3 days");
+        assert_eq!(describe(now + 3600 + 60), "expires in 1 hour");
+        assert_eq!(describe(now - 2 * 3600 - 60), "expired 2 hours ago");
+        assert!(is_soon(now + 86400));
+        assert!(is_soon(now - 86400));
+        assert!(!is_soon(now + 30 * 86400));
+    }
+}
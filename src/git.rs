@@ -0,0 +1,128 @@
+//! Versioning of the store when it is a git repository, committing the profiles after every
+//! change and syncing them with a remote
+
+use std::{
+    path::Path,
+    process::{Command, Output},
+};
+
+use anyhow::{Context, Result};
+
+use crate::{agent, backup, bitwarden, history, journal, lock, prune, state, trash, update};
+
+/// Name of the directory git keeps the repository in, inside the project directory
+pub const GIT_DIRECTORY: &str = ".git";
+
+/// Whether the store is a git repository
+pub fn is_repository(project_directory: &Path) -> bool {
+    project_directory.join(GIT_DIRECTORY).exists()
+}
+
+/// Run `git` in the store with arguments, returning its output
+fn run(project_directory: &Path, arguments: &[&str]) -> Result<Output> {
+    tracing::info!("Running `git {}`", arguments.join(" "));
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(project_directory)
+        .args(arguments)
+        .output()
+        .context("Couldn't run `git`, is it installed?")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "`git {}` exited with {}: {}",
+            arguments.join(" "),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output)
+}
+
+/// Describe the staged changes, e.g. `Add dev, update work`
+fn describe(name_status: &str) -> String {
+    let profile = |file: &str| file.strip_suffix(".tfrc.json").unwrap_or(file).to_string();
+    let changes: Vec<String> = name_status
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let status = fields.next()?;
+            let file = profile(fields.next()?);
+            Some(match status.chars().next()? {
+                'A' => format!("add {file}"),
+                'D' => format!("remove {file}"),
+                'R' => format!("rename {file} to {}", profile(fields.next()?)),
+                _ => format!("update {file}"),
+            })
+        })
+        .collect();
+    let description = changes.join(", ");
+    let mut chars = description.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => description,
+    }
+}
+
+/// Commit the changes to the profiles, leaving out the files only relevant to this machine
+///
+/// Does nothing when the store isn't a git repository or nothing changed
+pub fn commit(project_directory: &Path) -> Result<()> {
+    if !is_repository(project_directory) {
+        return Ok(());
+    }
+    let excluded = [
+        state::STATE_FILE,
+        history::HISTORY_FILE,
+        lock::LOCK_FILE,
+        journal::JOURNAL_FILE,
+        backup::BACKUPS_DIRECTORY,
+        trash::TRASH_DIRECTORY,
+        prune::QUARANTINE_DIRECTORY,
+        bitwarden::SESSION_FILE,
+        agent::SOCKET_FILE,
+        update::CACHE_FILE,
+        ".tmp*",
+    ]
+    .map(|file| format!(":(exclude){file}"));
+    let mut arguments = vec!["add", "--all", "--", "."];
+    arguments.extend(excluded.iter().map(String::as_str));
+    run(project_directory, &arguments)?;
+
+    let staged = run(
+        project_directory,
+        &["diff", "--cached", "--name-status", "--find-renames"],
+    )?;
+    let message = describe(&String::from_utf8(staged.stdout)?);
+    if message.is_empty() {
+        return Ok(());
+    }
+    run(
+        project_directory,
+        &["commit", "--quiet", "--message", &message],
+    )?;
+    Ok(())
+}
+
+/// Fetch the profiles committed elsewhere, replaying the local commits on top of them
+pub fn pull(project_directory: &Path) -> Result<()> {
+    run(project_directory, &["pull", "--rebase", "--quiet"])?;
+    Ok(())
+}
+
+/// Send the local commits to the remote, to `origin` the first time
+pub fn push(project_directory: &Path) -> Result<()> {
+    if run(
+        project_directory,
+        &["rev-parse", "--abbrev-ref", "@{upstream}"],
+    )
+    .is_ok()
+    {
+        run(project_directory, &["push", "--quiet"])?;
+    } else {
+        run(
+            project_directory,
+            &["push", "--quiet", "--set-upstream", "origin", "HEAD"],
+        )?;
+    }
+    Ok(())
+}
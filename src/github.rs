@@ -0,0 +1,51 @@
+//! Workflow commands of GitHub Actions, masking the tokens in the logs of a job and handing the
+//! exports and outputs to its next steps
+
+use std::io::Write;
+
+use anyhow::{Context, Result};
+
+/// Whether the CLI runs in a GitHub Actions job, which sets `GITHUB_ACTIONS=true`
+pub fn is_detected() -> bool {
+    std::env::var("GITHUB_ACTIONS").is_ok_and(|value| value == "true")
+}
+
+/// Hide a secret in the logs of the job, line by line as GitHub matches them
+pub fn mask(secret: &str) {
+    for line in secret.lines().filter(|line| !line.trim().is_empty()) {
+        println!("::add-mask::{line}");
+    }
+}
+
+/// Set an environment variable for the next steps of the job, through `$GITHUB_ENV`
+pub fn export(key: &str, value: &str) -> Result<()> {
+    append("GITHUB_ENV", key, value)
+}
+
+/// Set an output of the step, through `$GITHUB_OUTPUT`
+pub fn set_output(key: &str, value: &str) -> Result<()> {
+    append("GITHUB_OUTPUT", key, value)
+}
+
+/// Append a value to one of the files GitHub reads once the step is done, with a delimiter
+/// when it spans several lines
+fn append(file_variable: &str, key: &str, value: &str) -> Result<()> {
+    let path = std::env::var_os(file_variable)
+        .filter(|path| !path.is_empty())
+        .with_context(|| format!("`{file_variable}` isn't set, is this a GitHub Actions job?"))?;
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Couldn't open `${file_variable}`"))?;
+    if value.contains(['\n', '\r']) {
+        let mut delimiter = format!("ghadelimiter_{}", crate::state::now());
+        while value.contains(&delimiter) {
+            delimiter.push('_');
+        }
+        writeln!(file, "{key}<<{delimiter}\n{value}\n{delimiter}")?;
+    } else {
+        writeln!(file, "{key}={value}")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,362 @@
+//! The credentials helper protocol of terraform, through which it asks for, stores and forgets
+//! the tokens of a hostname instead of reading the credentials file
+//!
+//! Terraform runs `terraform-credentials-<name> get|store|forget <hostname>`, exchanging JSON
+//! objects holding a `token` on the standard streams.
+
+use std::{
+    ffi::OsStr,
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    backup,
+    credentials::{self, CredentialsFile},
+    encryption,
+    error::CliError,
+    journal,
+    store::{self, ProfileStore},
+};
+
+/// Prefix of the programs terraform runs as credentials helpers
+pub const PROGRAM_PREFIX: &str = "terraform-credentials-";
+
+/// Name of the helper in the `credentials_helper` block of the CLI configuration
+pub const HELPER_NAME: &str = env!("CARGO_PKG_NAME");
+
+/// Name of the directory terraform looks for plugins in, inside the terraform directory
+pub const PLUGINS_DIRECTORY: &str = "plugins";
+
+/// Get the path terraform runs the helper from, inside a terraform directory
+pub fn program_path(terraform_directory: &Path) -> PathBuf {
+    terraform_directory.join(PLUGINS_DIRECTORY).join(format!(
+        "{PROGRAM_PREFIX}{HELPER_NAME}{}",
+        std::env::consts::EXE_SUFFIX
+    ))
+}
+
+/// Put the running binary where terraform looks for the helper, as a symbolic link that follows
+/// the upgrades, or a copy on Windows where links need developer mode
+pub fn install_program(terraform_directory: &Path) -> Result<PathBuf> {
+    let program = program_path(terraform_directory);
+    let plugins_directory = terraform_directory.join(PLUGINS_DIRECTORY);
+    std::fs::create_dir_all(&plugins_directory)
+        .with_context(|| format!("Couldn't create {}", plugins_directory.display()))?;
+    let executable = std::env::current_exe()?;
+    if program.is_symlink() || program.exists() {
+        tracing::info!("Removing {}", program.display());
+        std::fs::remove_file(&program)?;
+    }
+    #[cfg(target_family = "windows")]
+    {
+        tracing::info!("Copying {} to {}", executable.display(), program.display());
+        std::fs::copy(&executable, &program)?;
+    }
+    #[cfg(target_family = "unix")]
+    {
+        tracing::info!("Linking {} to {}", program.display(), executable.display());
+        std::os::unix::fs::symlink(&executable, &program)?;
+    }
+    Ok(program)
+}
+
+/// Ask the installed helper for the credentials of a hostname, as terraform would
+pub fn request(program: &Path, hostname: &str) -> Result<serde_json::Value> {
+    tracing::info!("Running `{} get {hostname}`", program.display());
+    let output = Command::new(program)
+        .args(["get", hostname])
+        .stdin(Stdio::null())
+        .output()
+        .with_context(|| format!("Couldn't run {}", program.display()))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "`{} get {hostname}` exited with {}: {}",
+            program.display(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let response: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .with_context(|| format!("{} didn't answer with JSON", program.display()))?;
+    if !response.is_object() {
+        anyhow::bail!("{} didn't answer with a JSON object", program.display());
+    }
+    Ok(response)
+}
+
+/// Whether the binary was run under the name of a credentials helper
+pub fn is_helper_program(program: &OsStr) -> bool {
+    Path::new(program)
+        .file_stem()
+        .and_then(OsStr::to_str)
+        .is_some_and(|stem| stem.starts_with(PROGRAM_PREFIX))
+}
+
+/// Find the key of a hostname among the hosts of a profile, as terraform gives it in lowercase
+pub fn find_host(
+    hosts: &serde_json::Map<String, serde_json::Value>,
+    hostname: &str,
+) -> Option<String> {
+    hosts
+        .keys()
+        .find(|host| host.eq_ignore_ascii_case(hostname))
+        .cloned()
+}
+
+/// Read the credentials terraform asks to store, an object with a `token` string
+pub fn read_request() -> Result<serde_json::Value> {
+    let mut input = String::new();
+    std::io::stdin().read_to_string(&mut input)?;
+    let request: serde_json::Value =
+        serde_json::from_str(&input).context("Terraform sent credentials that aren't JSON")?;
+    request
+        .get("token")
+        .and_then(serde_json::Value::as_str)
+        .context("Terraform sent credentials without a `token` string")?;
+    Ok(request)
+}
+
+/// Get the profile the helper serves, the one named if any, e.g. by `TERRAFORM_PROFILE`, or
+/// else the active one
+pub fn served_profile(
+    store: &ProfileStore,
+    credentials_file: &CredentialsFile,
+    profile_override: Option<&str>,
+) -> Result<Option<String>> {
+    match profile_override {
+        Some(name) => {
+            let profiles = store.profiles()?;
+            if !profiles.contains_key(name) {
+                return Err(CliError::unknown_profile(name, profiles.keys()).into());
+            }
+            Ok(Some(name.to_string()))
+        }
+        None => credentials_file.active_profile(store),
+    }
+}
+
+/// Get the credentials of a hostname in a registered profile, as terraform gets them
+pub fn read_entry(
+    store: &ProfileStore,
+    name: &str,
+    hostname: &str,
+) -> Result<Option<serde_json::Value>> {
+    let credentials: serde_json::Value = serde_json::from_str(&store.read(name)?)?;
+    let hosts = credentials
+        .get("credentials")
+        .and_then(serde_json::Value::as_object);
+    Ok(hosts.and_then(|hosts| Some(hosts[&find_host(hosts, hostname)?].clone())))
+}
+
+/// Store the credentials terraform obtained for a hostname in a registered profile, replacing
+/// those it held
+pub fn store_entry(
+    store: &ProfileStore,
+    credentials_file: &CredentialsFile,
+    name: &str,
+    hostname: &str,
+    entry: serde_json::Value,
+    backup_retention: usize,
+) -> Result<()> {
+    update(
+        store,
+        credentials_file,
+        name,
+        hostname,
+        Some(entry),
+        backup_retention,
+    )
+}
+
+/// Remove the credentials of a hostname from a registered profile
+pub fn forget_entry(
+    store: &ProfileStore,
+    credentials_file: &CredentialsFile,
+    name: &str,
+    hostname: &str,
+    backup_retention: usize,
+) -> Result<()> {
+    update(
+        store,
+        credentials_file,
+        name,
+        hostname,
+        None,
+        backup_retention,
+    )
+}
+
+/// Rewrite the credentials of a hostname in a registered profile, storing the given ones or
+/// forgetting them, and bring the credentials made from the profile up to date
+fn update(
+    store: &ProfileStore,
+    credentials_file: &CredentialsFile,
+    name: &str,
+    hostname: &str,
+    entry: Option<serde_json::Value>,
+    backup_retention: usize,
+) -> Result<()> {
+    let profile_path = store.find(name)?;
+    let original = encryption::decrypt_profile(&profile_path)?;
+    let mut credentials: serde_json::Value = serde_json::from_str(&original)?;
+    let hosts = credentials
+        .get_mut("credentials")
+        .and_then(serde_json::Value::as_object_mut)
+        .with_context(|| format!("The profile `{name}` is missing a `credentials` object"))?;
+    let operation = match entry {
+        Some(_) => format!("helper store {hostname}"),
+        None => format!("helper forget {hostname}"),
+    };
+    match (find_host(hosts, hostname), entry) {
+        // Replacing the entry in place keeps the hostnames in the order they were written
+        (Some(host), Some(entry)) => {
+            *hosts = std::mem::take(hosts)
+                .into_iter()
+                .map(|(known, known_entry)| {
+                    if known == host {
+                        (hostname.to_string(), entry.clone())
+                    } else {
+                        (known, known_entry)
+                    }
+                })
+                .collect();
+        }
+        (Some(host), None) => {
+            hosts.shift_remove(&host);
+        }
+        (None, Some(entry)) => {
+            hosts.insert(hostname.to_string(), entry);
+        }
+        (None, None) => {}
+    }
+
+    backup::snapshot(
+        store.directory(),
+        &operation,
+        &[&profile_path],
+        backup_retention,
+    )?;
+    journal::transaction(
+        store.directory(),
+        &operation,
+        &[&profile_path, &credentials_file.path()],
+        || {
+            store::write_file_atomically(
+                &profile_path,
+                encryption::reseal(&profile_path, &store::format_like(&original, &credentials)?)?,
+            )?;
+            credentials::refresh(
+                name,
+                &profile_path,
+                credentials_file.terraform_directory(),
+                store.directory(),
+            )
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SwitchMode;
+
+    /// A profile indented with two spaces, with a key terraform doesn't know about
+    const WORK: &str = r#"{
+  "credentials": {
+    "app.terraform.io": {
+      "token": "work-app"
+    },
+    "TFE.example.com": {
+      "token": "work-tfe"
+    }
+  },
+  "comment": "work"
+}
+"#;
+
+    /// A store holding the work profile, copied in place of the credentials
+    fn copied_store() -> (tempfile::TempDir, ProfileStore, CredentialsFile) {
+        let directory = tempfile::tempdir().unwrap();
+        let store = ProfileStore::open(directory.path().join("store"));
+        let credentials_file = CredentialsFile::new(directory.path().join("terraform.d"));
+        std::fs::create_dir_all(store.directory()).unwrap();
+        std::fs::create_dir_all(credentials_file.terraform_directory()).unwrap();
+        store.register("work", WORK).unwrap();
+        credentials_file
+            .install(&store, "work", SwitchMode::Copy)
+            .unwrap();
+        (directory, store, credentials_file)
+    }
+
+    #[test]
+    fn serves_the_named_or_active_profile() {
+        let (_directory, store, credentials_file) = copied_store();
+        store.register("personal", WORK).unwrap();
+        let served = |profile_override| {
+            served_profile(&store, &credentials_file, profile_override)
+                .map(|name| name.unwrap_or_default())
+        };
+        assert_eq!(served(None).unwrap(), "work");
+        assert_eq!(served(Some("personal")).unwrap(), "personal");
+        assert!(served(Some("missing")).is_err());
+    }
+
+    #[test]
+    fn reads_entries_whatever_the_case() {
+        let (_directory, store, _) = copied_store();
+        assert_eq!(
+            read_entry(&store, "work", "tfe.example.com").unwrap(),
+            Some(serde_json::json!({ "token": "work-tfe" }))
+        );
+        assert_eq!(
+            read_entry(&store, "work", "other.example.com").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn storing_replaces_the_entry_and_refreshes_the_copy() {
+        let (_directory, store, credentials_file) = copied_store();
+        store_entry(
+            &store,
+            &credentials_file,
+            "work",
+            "app.terraform.io",
+            serde_json::json!({ "token": "new-app" }),
+            0,
+        )
+        .unwrap();
+        let expected = WORK.replace("work-app", "new-app");
+        assert_eq!(store.read("work").unwrap(), expected);
+        assert_eq!(
+            std::fs::read_to_string(credentials_file.path()).unwrap(),
+            expected
+        );
+        assert_eq!(
+            credentials_file.active_profile(&store).unwrap().as_deref(),
+            Some("work")
+        );
+    }
+
+    #[test]
+    fn forgetting_removes_the_entry() {
+        let (_directory, store, credentials_file) = copied_store();
+        forget_entry(&store, &credentials_file, "work", "tfe.example.com", 0).unwrap();
+        assert_eq!(
+            store.hostnames("work").unwrap(),
+            vec![String::from("app.terraform.io")]
+        );
+        assert!(store
+            .read("work")
+            .unwrap()
+            .contains("\"comment\": \"work\""));
+        assert_eq!(
+            std::fs::read_to_string(credentials_file.path()).unwrap(),
+            store.read("work").unwrap()
+        );
+    }
+}
@@ -0,0 +1,64 @@
+//! Append-only history of the profile switches
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Name of the history file inside the project directory
+pub const HISTORY_FILE: &str = "history.jsonl";
+
+/// A single profile switch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Switch {
+    /// When the switch happened, in seconds since the Unix epoch
+    pub timestamp: u64,
+    /// Profile that was active before the switch, if any
+    pub from: Option<String>,
+    /// Profile that was switched to
+    pub to: String,
+    /// Hostnames held by the profile that was switched to
+    pub hostnames: Vec<String>,
+}
+
+/// Path of the history file inside a project directory
+fn path(project_directory: &Path) -> PathBuf {
+    project_directory.join(HISTORY_FILE)
+}
+
+/// Append a switch to the history
+pub fn record(project_directory: &Path, switch: &Switch) -> Result<()> {
+    tracing::debug!("Recording the switch to `{}` in the history", switch.to);
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path(project_directory))?;
+    writeln!(file, "{}", serde_json::to_string(switch)?)?;
+    Ok(())
+}
+
+/// Read the whole history, oldest switch first
+pub fn read(project_directory: &Path) -> Result<Vec<Switch>> {
+    let path = path(project_directory);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let mut switches = Vec::new();
+    for (index, line) in BufReader::new(std::fs::File::open(&path)?)
+        .lines()
+        .enumerate()
+    {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        switches.push(
+            serde_json::from_str(&line)
+                .with_context(|| format!("Invalid entry at {}:{}", path.display(), index + 1))?,
+        );
+    }
+    Ok(switches)
+}
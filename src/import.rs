@@ -0,0 +1,471 @@
+//! The sources `import` registers new profiles from: the current credentials, another
+//! credentials file, a single token, a token kept in a password manager, the `TF_TOKEN_*`
+//! environment variables, the CLI configuration and whole directories of credentials files
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    backup, bitwarden,
+    credentials::{self, Credentials, CredentialsFile},
+    encryption,
+    error::CliError,
+    journal, pass, permissions, source,
+    store::{self, ProfileStore},
+    switcher::Switcher,
+    terraformrc,
+};
+
+/// Register the current credentials as a new profile, moving them into the store or, when `copy`
+/// is set, copying them and putting the new profile in their place
+pub fn current(switcher: &Switcher, name: &str, copy: bool) -> Result<PathBuf> {
+    let store = switcher.store();
+    let terraform_directory = switcher.credentials().terraform_directory();
+    let project_directory = store.directory();
+    let credentials_files = switcher.credentials().path();
+    let new_path = store.vacant_path(name)?;
+
+    let current = switcher.credentials().inspect(store)?;
+    if let Some(key) = current.active_profile(&store.profiles()?) {
+        anyhow::bail!("The profile is already imported under `{key}`")
+    }
+    match current {
+        Credentials::Missing | Credentials::Helper(_) => {
+            anyhow::bail!("There are no credentials to import.")
+        }
+        Credentials::Symlink(link) => return Err(CliError::UnknownSymlink(link).into()),
+        Credentials::Hardlink { .. } | Credentials::Copy { .. } | Credentials::Unmanaged => {}
+    }
+    let content = std::fs::read_to_string(&credentials_files)?;
+    store::validate_new_credentials(&content)
+        .with_context(|| format!("{} can't be imported", credentials_files.display()))?;
+
+    if copy {
+        journal::transaction(
+            project_directory,
+            &format!("import {name} --copy"),
+            &[&credentials_files, &new_path],
+            || {
+                if credentials::adopt(name, terraform_directory, project_directory)?.is_some() {
+                    permissions::restrict_directory(project_directory)?;
+                    credentials::install(
+                        name,
+                        &new_path,
+                        terraform_directory,
+                        project_directory,
+                        switcher.mode(),
+                    )?;
+                }
+                Ok(())
+            },
+        )?;
+    } else {
+        tracing::info!(
+            "Moving {} to {}",
+            credentials_files.display(),
+            new_path.display()
+        );
+        // The profile is sealed into a temporary file of the store and renamed in place before
+        // the credentials are removed, so they are never lost nor left unencrypted in the store
+        permissions::restrict_directory(project_directory)?;
+        store::write_file_atomically(&new_path, encryption::seal(&content)?)?;
+        permissions::restrict_file(&new_path)?;
+        std::fs::remove_file(&credentials_files)?;
+        // A drifted copy or broken hard link no longer stands for its profile
+        credentials::untrack(project_directory)?;
+    }
+    Ok(new_path)
+}
+
+/// Register a copy of a credentials file other than the current credentials
+pub fn file(store: &ProfileStore, name: &str, path: &Path) -> Result<PathBuf> {
+    store.vacant_path(name)?;
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Couldn't read {}", path.display()))?;
+    store::validate_new_credentials(&content)
+        .with_context(|| format!("{} isn't a credentials file", path.display()))?;
+    store.register(name, &content)
+}
+
+/// Register a profile holding a single token for a hostname
+pub fn token(store: &ProfileStore, name: &str, hostname: &str, token: &str) -> Result<PathBuf> {
+    store.vacant_path(name)?;
+    if token.is_empty() {
+        anyhow::bail!("The token can't be empty");
+    }
+    store.register(name, &store::credentials_with_tokens([(hostname, token)]))
+}
+
+/// Register a profile referencing a token kept in 1Password, the password store or Bitwarden,
+/// which is never written to the store, after checking the token can be read
+pub fn source(
+    store: &ProfileStore,
+    name: &str,
+    hostname: &str,
+    reference: &str,
+) -> Result<PathBuf> {
+    store.vacant_path(name)?;
+    if ![
+        source::ONEPASSWORD_PREFIX,
+        pass::REFERENCE_PREFIX,
+        bitwarden::REFERENCE_PREFIX,
+    ]
+    .iter()
+    .any(|prefix| reference.starts_with(prefix))
+    {
+        anyhow::bail!(
+            "The source must be a 1Password reference, e.g. `op://vault/item/field`, a password \
+             store entry, e.g. `pass://terraform-profile/work`, or a Bitwarden item, e.g. \
+             `bw://terraform-work`"
+        );
+    }
+
+    let credentials = serde_json::json!({ "credentials": { hostname: { "source": reference } } });
+    let content = serde_json::to_string_pretty(&credentials)?;
+    source::resolve(&content).context("The token couldn't be read from its source")?;
+    store.register(name, &content)
+}
+
+/// Move the tokens of a registered profile to the password store, leaving sources referencing
+/// them in their place
+pub fn to_pass(
+    store: &ProfileStore,
+    credentials_file: &CredentialsFile,
+    name: &str,
+    backup_retention: usize,
+) -> Result<()> {
+    let profile_path = store.find(name)?;
+    if source::has_sources(&encryption::decrypt_profile(&profile_path)?) {
+        anyhow::bail!("The tokens of profile `{name}` are already kept outside the store");
+    }
+    let referencing = pass::store(name, &encryption::read_profile(&profile_path)?)?;
+    backup::snapshot(
+        store.directory(),
+        &format!("import {name} --to pass"),
+        &[&profile_path],
+        backup_retention,
+    )?;
+    journal::transaction(
+        store.directory(),
+        &format!("import {name} --to pass"),
+        &[&profile_path, &credentials_file.path()],
+        || {
+            store::write_file_atomically(&profile_path, encryption::seal(&referencing)?)?;
+            permissions::restrict_file(&profile_path)?;
+            credentials::refresh(
+                name,
+                &profile_path,
+                credentials_file.terraform_directory(),
+                store.directory(),
+            )
+        },
+    )
+}
+
+/// Register a profile from the tokens of the `TF_TOKEN_<hostname>` environment variables among
+/// `variables`, as CI systems inject them, returning the hostnames it holds tokens for
+pub fn environment(
+    store: &ProfileStore,
+    name: &str,
+    variables: impl IntoIterator<Item = (String, String)>,
+) -> Result<Vec<String>> {
+    store.vacant_path(name)?;
+    let tokens: BTreeMap<String, String> = variables
+        .into_iter()
+        .filter_map(|(key, token)| Some((token_env_var_host(&key)?, token)))
+        .filter(|(_, token)| !token.is_empty())
+        .collect();
+    if tokens.is_empty() {
+        anyhow::bail!("No `TF_TOKEN_<hostname>` variable is set");
+    }
+    store.register(name, &store::credentials_with_tokens(&tokens))?;
+    Ok(tokens.into_keys().collect())
+}
+
+/// Get the hostname a `TF_TOKEN_*` environment variable holds the token of, undoing the
+/// encoding terraform expects, where dots become underscores and dashes double underscores
+pub fn token_env_var_host(variable: &str) -> Option<String> {
+    let encoded = variable.strip_prefix("TF_TOKEN_")?;
+    if encoded.is_empty() {
+        return None;
+    }
+    Some(encoded.replace("__", "-").replace('_', ".").to_lowercase())
+}
+
+/// Register a profile from the credentials blocks of the HCL CLI configuration at `path`,
+/// commenting them out of it when asked to
+pub fn terraformrc(
+    store: &ProfileStore,
+    name: &str,
+    path: &Path,
+    comment_out: bool,
+) -> Result<PathBuf> {
+    store.vacant_path(name)?;
+    tracing::debug!("Reading the CLI configuration in {}", path.display());
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Couldn't read {}", path.display()))?;
+    let tokens = terraformrc::read_tokens(&content)
+        .with_context(|| format!("Couldn't read the credentials of {}", path.display()))?;
+    if tokens.is_empty() {
+        anyhow::bail!("{} has no credentials blocks", path.display());
+    }
+
+    let profile_path = store.register(name, &store::credentials_with_tokens(tokens))?;
+    if comment_out {
+        store::write_through_link(path, terraformrc::comment_out_credentials(&content))?;
+    }
+    Ok(profile_path)
+}
+
+/// What became of one file of a directory imported in bulk
+#[derive(Debug)]
+pub struct DirectoryFile {
+    /// Name of the profile, the file name without its `.tfrc.json` or `.tfrc` extension
+    pub name: String,
+    /// Path of the imported file
+    pub path: PathBuf,
+    /// Path of the new profile, or why the file couldn't be registered
+    pub registered: Result<PathBuf>,
+}
+
+/// Register every `*.tfrc.json` and `*.tfrc` file of a directory as the profile named after it,
+/// in the order of their names, carrying on past those that can't be
+pub fn directory(store: &ProfileStore, directory: &Path) -> Result<Vec<DirectoryFile>> {
+    let mut files: Vec<(String, PathBuf)> = std::fs::read_dir(directory)
+        .with_context(|| format!("Couldn't read the directory {}", directory.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .filter_map(|path| {
+            let file_name = path.file_name()?.to_str()?;
+            let name = file_name
+                .strip_suffix(".tfrc.json")
+                .or_else(|| file_name.strip_suffix(".tfrc"))?;
+            Some((name.to_string(), path.clone()))
+        })
+        .collect();
+    if files.is_empty() {
+        anyhow::bail!(
+            "{} has no `*.tfrc.json` or `*.tfrc` files",
+            directory.display()
+        );
+    }
+    files.sort();
+
+    // The files registered so far are in the store, so two files can't be imported under one name
+    Ok(files
+        .into_iter()
+        .map(|(name, path)| DirectoryFile {
+            registered: directory_file(store, &name, &path),
+            name,
+            path,
+        })
+        .collect())
+}
+
+/// Register one file of a directory imported in bulk, either credentials JSON or the credentials
+/// blocks of a CLI configuration
+fn directory_file(store: &ProfileStore, name: &str, path: &Path) -> Result<PathBuf> {
+    store.vacant_path(name)?;
+    let content = std::fs::read_to_string(path).context("Couldn't read it")?;
+    let content = if path
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        store::validate_new_credentials(&content).context("It isn't a credentials file")?;
+        content
+    } else {
+        let tokens =
+            terraformrc::read_tokens(&content).context("Couldn't read its credentials blocks")?;
+        if tokens.is_empty() {
+            anyhow::bail!("It has no credentials blocks");
+        }
+        store::credentials_with_tokens(tokens)
+    };
+    store.register(name, &content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SwitchMode;
+
+    /// Credentials holding a single token for `app.terraform.io`
+    const CREDENTIALS: &str = r#"{"credentials": {"app.terraform.io": {"token": "secret"}}}"#;
+
+    /// A CLI configuration holding a credentials block among other settings
+    const TERRAFORMRC: &str = "plugin_cache_dir = \"/tmp/plugins\"\n\
+                               credentials \"app.terraform.io\" {\n  token = \"secret\"\n}\n";
+
+    /// An empty store, and a switcher to it from credentials that aren't installed yet
+    fn switcher() -> (tempfile::TempDir, Switcher) {
+        let home = tempfile::tempdir().unwrap();
+        let store = ProfileStore::open(home.path().join("store"));
+        let credentials_file = CredentialsFile::new(home.path().join(".terraform.d"));
+        std::fs::create_dir_all(store.directory()).unwrap();
+        std::fs::create_dir_all(credentials_file.terraform_directory()).unwrap();
+        (
+            home,
+            Switcher::new(store, credentials_file, SwitchMode::Symlink),
+        )
+    }
+
+    #[test]
+    fn moving_the_credentials_leaves_none_behind() {
+        let (_home, switcher) = switcher();
+        let credentials_files = switcher.credentials().path();
+        std::fs::write(&credentials_files, CREDENTIALS).unwrap();
+
+        let profile_path = current(&switcher, "work", false).unwrap();
+        assert_eq!(profile_path, switcher.store().profile_path("work"));
+        assert_eq!(switcher.store().read("work").unwrap(), CREDENTIALS);
+        assert!(!credentials_files.exists());
+        assert!(!permissions::is_exposed(&profile_path));
+    }
+
+    #[test]
+    fn copying_the_credentials_puts_the_profile_in_their_place() {
+        let (_home, switcher) = switcher();
+        std::fs::write(switcher.credentials().path(), CREDENTIALS).unwrap();
+
+        current(&switcher, "work", true).unwrap();
+        assert_eq!(switcher.store().read("work").unwrap(), CREDENTIALS);
+        assert_eq!(switcher.active().unwrap().as_deref(), Some("work"));
+
+        let error = current(&switcher, "again", true).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The profile is already imported under `work`"
+        );
+    }
+
+    #[test]
+    fn refuses_missing_credentials_and_registered_names() {
+        let (_home, switcher) = switcher();
+        let error = current(&switcher, "work", false).unwrap_err();
+        assert_eq!(error.to_string(), "There are no credentials to import.");
+
+        switcher.store().register("work", CREDENTIALS).unwrap();
+        std::fs::write(switcher.credentials().path(), CREDENTIALS).unwrap();
+        let error = current(&switcher, "work", false).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(CliError::ProfileExists(name)) if name == "work"
+        ));
+    }
+
+    #[test]
+    fn imports_files_and_tokens() {
+        let (home, switcher) = switcher();
+        let store = switcher.store();
+        let path = home.path().join("ci.tfrc.json");
+        std::fs::write(&path, CREDENTIALS).unwrap();
+        file(store, "ci", &path).unwrap();
+        assert_eq!(store.read("ci").unwrap(), CREDENTIALS);
+
+        std::fs::write(&path, "{}").unwrap();
+        assert!(file(store, "empty", &path).is_err());
+
+        token(store, "single", "tfe.example.com", "secret").unwrap();
+        assert_eq!(store.hostnames("single").unwrap(), ["tfe.example.com"]);
+        let error = token(store, "blank", "tfe.example.com", "").unwrap_err();
+        assert_eq!(error.to_string(), "The token can't be empty");
+    }
+
+    #[test]
+    fn refuses_unknown_sources() {
+        let (_home, switcher) = switcher();
+        let error = source(
+            switcher.store(),
+            "work",
+            "app.terraform.io",
+            "https://example.com",
+        )
+        .unwrap_err();
+        assert!(error.to_string().starts_with("The source must be"));
+    }
+
+    #[test]
+    fn imports_the_token_variables() {
+        let (_home, switcher) = switcher();
+        let store = switcher.store();
+        let variables = [
+            ("TF_TOKEN_app_terraform_io", "secret"),
+            ("TF_TOKEN_tfe__internal_example_com", "other"),
+            ("TF_TOKEN_empty_example_com", ""),
+            ("TF_LOG", "debug"),
+        ]
+        .map(|(key, value)| (key.to_string(), value.to_string()));
+
+        let hostnames = environment(store, "ci", variables).unwrap();
+        assert_eq!(hostnames, ["app.terraform.io", "tfe-internal.example.com"]);
+        assert_eq!(
+            store.tokens("ci").unwrap(),
+            BTreeMap::from([
+                (String::from("app.terraform.io"), String::from("secret")),
+                (
+                    String::from("tfe-internal.example.com"),
+                    String::from("other")
+                ),
+            ])
+        );
+
+        let error = environment(store, "none", Vec::new()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "No `TF_TOKEN_<hostname>` variable is set"
+        );
+    }
+
+    #[test]
+    fn tells_the_host_of_token_variables() {
+        assert_eq!(
+            token_env_var_host("TF_TOKEN_app_terraform_io").as_deref(),
+            Some("app.terraform.io")
+        );
+        assert_eq!(token_env_var_host("TF_TOKEN_"), None);
+        assert_eq!(token_env_var_host("TF_LOG"), None);
+    }
+
+    #[test]
+    fn imports_the_cli_configuration_and_comments_it_out() {
+        let (home, switcher) = switcher();
+        let store = switcher.store();
+        let path = home.path().join("terraformrc");
+        std::fs::write(&path, TERRAFORMRC).unwrap();
+
+        terraformrc(store, "work", &path, true).unwrap();
+        assert_eq!(store.hostnames("work").unwrap(), ["app.terraform.io"]);
+        let commented = std::fs::read_to_string(&path).unwrap();
+        assert!(commented.starts_with("plugin_cache_dir"));
+        assert!(terraformrc::read_tokens(&commented).unwrap().is_empty());
+
+        let error = terraformrc(store, "again", &path, false).unwrap_err();
+        assert!(error.to_string().ends_with("has no credentials blocks"));
+    }
+
+    #[test]
+    fn imports_a_directory_past_the_files_that_fail() {
+        let (home, switcher) = switcher();
+        let store = switcher.store();
+        let profiles = home.path().join("profiles");
+        std::fs::create_dir(&profiles).unwrap();
+        std::fs::write(profiles.join("work.tfrc.json"), CREDENTIALS).unwrap();
+        std::fs::write(profiles.join("work.tfrc"), TERRAFORMRC).unwrap();
+        std::fs::write(profiles.join("broken.tfrc.json"), "{").unwrap();
+        std::fs::write(profiles.join("notes.txt"), "").unwrap();
+
+        let files = directory(store, &profiles).unwrap();
+        let names: Vec<&str> = files.iter().map(|file| file.name.as_str()).collect();
+        assert_eq!(names, ["broken", "work", "work"]);
+        assert!(files[0].registered.is_err());
+        assert!(files[1].registered.is_ok());
+        assert!(matches!(
+            files[2].registered.as_ref().unwrap_err().downcast_ref(),
+            Some(CliError::ProfileExists(name)) if name == "work"
+        ));
+    }
+}
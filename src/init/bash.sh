@@ -0,0 +1,22 @@
+# terraform-profile shell integration for bash
+# Add `eval "$(terraform-profile init bash)"` to your ~/.bashrc
+
+_terraform_profile_hook() {
+    if [[ "$PWD" != "${_TERRAFORM_PROFILE_PWD:-}" ]]; then
+        _TERRAFORM_PROFILE_PWD="$PWD"
+        command terraform-profile auto
+    fi
+    TERRAFORM_PROFILE_ACTIVE="$(command terraform-profile prompt)"
+    export TERRAFORM_PROFILE_ACTIVE
+}
+
+tfp() {
+    command terraform-profile "$@"
+    local exit_code=$?
+    _terraform_profile_hook
+    return $exit_code
+}
+
+if [[ ";${PROMPT_COMMAND:-};" != *";_terraform_profile_hook;"* ]]; then
+    PROMPT_COMMAND="_terraform_profile_hook${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+fi
@@ -0,0 +1,19 @@
+# terraform-profile shell integration for fish
+# Add `terraform-profile init fish | source` to your ~/.config/fish/config.fish
+
+function _terraform_profile_hook --on-event fish_prompt
+    set -gx TERRAFORM_PROFILE_ACTIVE (command terraform-profile prompt)
+end
+
+function _terraform_profile_auto --on-variable PWD
+    command terraform-profile auto
+end
+
+function tfp --wraps terraform-profile
+    command terraform-profile $argv
+    set -l exit_code $status
+    _terraform_profile_hook
+    return $exit_code
+end
+
+_terraform_profile_auto
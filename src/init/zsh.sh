@@ -0,0 +1,22 @@
+# terraform-profile shell integration for zsh
+# Add `eval "$(terraform-profile init zsh)"` to your ~/.zshrc
+
+_terraform_profile_hook() {
+    export TERRAFORM_PROFILE_ACTIVE="$(command terraform-profile prompt)"
+}
+
+_terraform_profile_auto() {
+    command terraform-profile auto
+}
+
+tfp() {
+    command terraform-profile "$@"
+    local exit_code=$?
+    _terraform_profile_hook
+    return $exit_code
+}
+
+autoload -Uz add-zsh-hook
+add-zsh-hook precmd _terraform_profile_hook
+add-zsh-hook chpwd _terraform_profile_auto
+_terraform_profile_auto
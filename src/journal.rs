@@ -0,0 +1,209 @@
+//! Write-ahead journal of the operations changing several files, so one interrupted midway is
+//! rolled back by the next run instead of leaving the store and the credentials inconsistent
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{credentials, output, permissions, state};
+
+/// Name of the journal file inside the project directory
+pub const JOURNAL_FILE: &str = "journal.json";
+
+/// What a file was before the operation started
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum Prior {
+    /// The file didn't exist
+    Missing,
+    /// The file held this content
+    File { content: String },
+    /// The file was a symbolic link to this path
+    Symlink { target: PathBuf },
+}
+
+/// An operation in progress, along with the files it may change
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    /// Command line of the operation
+    operation: String,
+    /// When the operation started, in seconds since the Unix epoch
+    started_at: u64,
+    /// Files the operation may change, as they were before it started
+    files: Vec<(PathBuf, Prior)>,
+}
+
+/// Path of the journal inside a project directory
+fn path(project_directory: &Path) -> PathBuf {
+    project_directory.join(JOURNAL_FILE)
+}
+
+/// Run an operation changing several files, putting them back as they were when it fails, and
+/// journaling them beforehand so the next run puts them back if it is interrupted
+///
+/// The state is always journaled along with the given files
+pub fn transaction<T>(
+    project_directory: &Path,
+    operation: &str,
+    paths: &[&Path],
+    body: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    begin(project_directory, operation, paths)?;
+    match body() {
+        Ok(value) => {
+            tracing::debug!("Committing the journal of `{operation}`");
+            std::fs::remove_file(path(project_directory))?;
+            Ok(value)
+        }
+        Err(error) => {
+            if let Err(rollback) = recover(project_directory) {
+                output::warning(format!("Couldn't roll back `{operation}`: {rollback:#}"));
+            }
+            Err(error)
+        }
+    }
+}
+
+/// Record what the files an operation may change are before it starts
+fn begin(project_directory: &Path, operation: &str, paths: &[&Path]) -> Result<()> {
+    let state_file = project_directory.join(state::STATE_FILE);
+    let mut files = Vec::new();
+    for path in paths.iter().copied().chain([state_file.as_path()]) {
+        let prior = if path.is_symlink() {
+            Prior::Symlink {
+                target: path.read_link()?,
+            }
+        } else if path.exists() {
+            Prior::File {
+                content: std::fs::read_to_string(path)
+                    .with_context(|| format!("Couldn't journal {}", path.display()))?,
+            }
+        } else {
+            Prior::Missing
+        };
+        files.push((path.to_path_buf(), prior));
+    }
+    let entry = Entry {
+        operation: operation.to_string(),
+        started_at: state::now(),
+        files,
+    };
+
+    let path = path(project_directory);
+    tracing::debug!("Journaling `{operation}` in {}", path.display());
+    crate::store::write_file_atomically(&path, serde_json::to_string_pretty(&entry)?)?;
+    permissions::restrict_file(&path)
+}
+
+/// Roll back the operation left in the journal, returning its command line
+///
+/// Only to be called while holding the lock, as the journal of a running operation is expected
+pub fn recover(project_directory: &Path) -> Result<Option<String>> {
+    let path = path(project_directory);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let entry: Entry = serde_json::from_str(&std::fs::read_to_string(&path)?)
+        .with_context(|| format!("The journal {} is invalid", path.display()))?;
+    tracing::info!("Rolling back `{}`", entry.operation);
+    for (file, prior) in &entry.files {
+        if let Prior::File { content } = prior {
+            crate::store::write_file_atomically(file, content)?;
+            permissions::restrict_file(file)?;
+            continue;
+        }
+        if file.is_symlink() || file.exists() {
+            tracing::info!("Removing {}", file.display());
+            std::fs::remove_file(file)?;
+        }
+        if let Prior::Symlink { target } = prior {
+            credentials::symlink(target, file)?;
+        }
+    }
+    std::fs::remove_file(&path)?;
+    Ok(Some(entry.operation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A project directory holding a profile, and credentials linked to it
+    fn project() -> (tempfile::TempDir, PathBuf, PathBuf) {
+        let directory = tempfile::tempdir().unwrap();
+        let profile_path = directory.path().join("work.tfrc.json");
+        let credentials_path = directory.path().join("credentials.tfrc.json");
+        std::fs::write(&profile_path, "before").unwrap();
+        credentials::symlink(&profile_path, &credentials_path).unwrap();
+        (directory, profile_path, credentials_path)
+    }
+
+    #[test]
+    fn failing_restores_every_file() {
+        let (directory, profile_path, credentials_path) = project();
+        let created = directory.path().join("personal.tfrc.json");
+        let result: Result<()> = transaction(
+            directory.path(),
+            "switch --adopt personal",
+            &[&profile_path, &credentials_path, &created],
+            || {
+                std::fs::write(&profile_path, "after")?;
+                std::fs::write(&created, "adopted")?;
+                std::fs::remove_file(&credentials_path)?;
+                std::fs::write(&credentials_path, "copied")?;
+                anyhow::bail!("switching failed")
+            },
+        );
+
+        assert_eq!(result.unwrap_err().to_string(), "switching failed");
+        assert_eq!(std::fs::read_to_string(&profile_path).unwrap(), "before");
+        assert!(!created.exists());
+        assert_eq!(credentials_path.read_link().unwrap(), profile_path);
+        assert!(!path(directory.path()).exists());
+    }
+
+    #[test]
+    fn an_interrupted_operation_is_rolled_back_next_time() {
+        let (directory, profile_path, credentials_path) = project();
+        begin(
+            directory.path(),
+            "rotate work",
+            &[&profile_path, &credentials_path],
+        )
+        .unwrap();
+        // The run stops right after changing the profile, leaving the journal behind
+        std::fs::write(&profile_path, "rotated").unwrap();
+        std::fs::remove_file(&credentials_path).unwrap();
+
+        assert_eq!(
+            recover(directory.path()).unwrap().as_deref(),
+            Some("rotate work")
+        );
+        assert_eq!(std::fs::read_to_string(&profile_path).unwrap(), "before");
+        assert_eq!(credentials_path.read_link().unwrap(), profile_path);
+        assert!(!path(directory.path()).exists());
+        assert_eq!(recover(directory.path()).unwrap(), None);
+    }
+
+    #[test]
+    fn committing_leaves_no_journal() {
+        let (directory, profile_path, credentials_path) = project();
+        let value = transaction(
+            directory.path(),
+            "restore work",
+            &[&profile_path, &credentials_path],
+            || {
+                assert!(path(directory.path()).exists());
+                std::fs::write(&profile_path, "restored")?;
+                Ok(42)
+            },
+        )
+        .unwrap();
+
+        assert_eq!(value, 42);
+        assert!(!path(directory.path()).exists());
+        assert_eq!(std::fs::read_to_string(&profile_path).unwrap(), "restored");
+        assert_eq!(recover(directory.path()).unwrap(), None);
+    }
+}
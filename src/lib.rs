@@ -0,0 +1,69 @@
+#![deny(missing_docs)]
+
+//! `terraform_profile` manages multiple terraform cloud profiles, putting the one in use in
+//! place of the `~/.terraform.d/credentials.tfrc.json` file terraform reads
+//!
+//! The `terraform-profile` CLI is a front-end to this library, which other tooling can embed:
+//! [`ProfileStore`] holds the registered profiles, [`CredentialsFile`] is the file terraform
+//! reads, and [`Switcher`] puts the former in place of the latter.
+//!
+//! ```no_run
+//! use terraform_profile::{config::SwitchMode, CredentialsFile, ProfileStore, Switcher};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let store = ProfileStore::open("/home/me/.local/share/terraform-profile");
+//! let credentials = CredentialsFile::new("/home/me/.terraform.d");
+//! let switcher = Switcher::new(store, credentials, SwitchMode::Symlink);
+//! switcher.switch("work")?;
+//! assert_eq!(switcher.active()?.as_deref(), Some("work"));
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod agent;
+pub mod api;
+pub mod archive;
+pub mod backup;
+pub mod bitwarden;
+pub mod config;
+pub mod credentials;
+pub mod detect;
+pub mod encryption;
+pub mod error;
+pub mod expiry;
+pub mod git;
+pub mod github;
+pub mod helper;
+pub mod history;
+pub mod import;
+pub mod journal;
+pub mod listing;
+pub mod lock;
+pub mod metadata;
+pub mod migration;
+pub mod onepassword;
+pub mod output;
+pub mod pass;
+pub mod paths;
+pub mod permissions;
+pub mod pin;
+pub mod prune;
+pub mod render;
+pub mod revocation;
+pub mod rotation;
+pub mod s3;
+pub mod source;
+pub mod state;
+pub mod status;
+pub mod store;
+pub mod switcher;
+pub mod sync;
+pub mod terraformrc;
+pub mod trash;
+pub mod update;
+pub mod vault;
+pub mod watch;
+
+pub use credentials::CredentialsFile;
+pub use store::ProfileStore;
+pub use switcher::Switcher;
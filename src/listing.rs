@@ -0,0 +1,217 @@
+//! The details `list` shows about every registered profile, and the profiles only in Vault
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+    time::SystemTime,
+};
+
+use anyhow::Result;
+
+use crate::{
+    agent,
+    credentials::CredentialsFile,
+    encryption, expiry,
+    metadata::Metadata,
+    output,
+    state::State,
+    store::{self, ProfileStore},
+    vault,
+};
+
+/// Everything known about a registered profile
+#[derive(serde::Serialize, Debug)]
+pub struct ProfileEntry {
+    /// Name of the profile
+    pub name: String,
+    /// Short names standing for the profile
+    pub aliases: Vec<String>,
+    /// Path of the stored credentials file, none for the profiles only in Vault
+    pub path: Option<PathBuf>,
+    /// Whether the profile is only in Vault, and gets fetched when switched to
+    pub remote: bool,
+    /// What the profile is for, as described with `import --description` or `annotate`
+    pub description: Option<String>,
+    /// Values noted about the profile with `annotate`, by key
+    pub fields: BTreeMap<String, String>,
+    /// Tags grouping the profile with others
+    pub tags: BTreeSet<String>,
+    /// Hostnames the profile holds credentials for
+    pub hostnames: Vec<String>,
+    /// Fingerprint of the token of each hostname, the start of its SHA-256, leaving out the
+    /// hostnames whose token is read from a `source`
+    pub fingerprints: BTreeMap<String, String>,
+    /// When the token of each hostname expires, in seconds since the Unix epoch, as last
+    /// fetched by `list --long`, `status` or `switch`, leaving out those that never do
+    pub token_expiries: BTreeMap<String, u64>,
+    /// Size of the stored credentials file, in bytes
+    pub size: Option<u64>,
+    /// Whether the credentials currently point to the profile
+    pub active: bool,
+    /// When the profile was last switched to, in seconds since the Unix epoch
+    pub last_switched: Option<u64>,
+    /// When the credentials file was last read, in seconds since the Unix epoch
+    ///
+    /// This is the access time of the file, whose precision depends on the filesystem mount
+    /// options
+    pub last_read: Option<u64>,
+    /// When the credentials file was created, in seconds since the Unix epoch, unless the
+    /// filesystem doesn't record it
+    pub created: Option<u64>,
+    /// When the credentials file was last modified, in seconds since the Unix epoch
+    pub modified: Option<u64>,
+}
+
+/// Gather the details of every registered profile, and of those only in Vault when it is
+/// enabled, sorted by name
+///
+/// `aliases` maps each alias to the profile it stands for. Failing to list the profiles in Vault
+/// is only warned about.
+pub fn entries(
+    store: &ProfileStore,
+    credentials_file: &CredentialsFile,
+    aliases: &BTreeMap<String, String>,
+) -> Result<Vec<ProfileEntry>> {
+    let profiles = store.profiles()?;
+    let active = credentials_file.inspect(store)?.active_profile(&profiles);
+    let state = State::load(store.directory())?;
+    let notes = Metadata::load(store.directory())?;
+    let aliases_of = |name: &str| {
+        aliases
+            .iter()
+            .filter(|(_, profile)| *profile == name)
+            .map(|(alias, _)| alias.clone())
+            .collect()
+    };
+    let unix_time = |time: std::io::Result<SystemTime>| {
+        time.ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs())
+    };
+
+    let mut entries: Vec<ProfileEntry> = profiles
+        .iter()
+        .map(|(name, path)| {
+            let metadata = std::fs::metadata(path).ok();
+            // The sources aren't read, listing shouldn't ask a password manager for every token
+            let content = agent::read(path)
+                .map_or_else(|| encryption::decrypt_profile(path), Ok)
+                .ok();
+            let noted = notes.get(name);
+            ProfileEntry {
+                name: name.clone(),
+                aliases: aliases_of(name),
+                path: Some(path.clone()),
+                remote: false,
+                description: noted.description,
+                fields: noted.fields,
+                tags: noted.tags,
+                hostnames: content
+                    .as_deref()
+                    .and_then(|content| store::hostnames(content).ok())
+                    .unwrap_or_default(),
+                fingerprints: content
+                    .as_deref()
+                    .and_then(|content| store::tokens(content).ok())
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(host, token)| (host, store::fingerprint(&token)))
+                    .collect(),
+                token_expiries: expiry::known(&state, name),
+                size: metadata.as_ref().map(std::fs::Metadata::len),
+                active: active == Some(name),
+                last_switched: state.last_switched.get(name).copied(),
+                last_read: metadata.as_ref().and_then(|m| unix_time(m.accessed())),
+                created: metadata.as_ref().and_then(|m| unix_time(m.created())),
+                modified: metadata.as_ref().and_then(|m| unix_time(m.modified())),
+            }
+        })
+        .collect();
+    if vault::is_enabled() {
+        match vault::remote_profiles() {
+            Ok(names) => entries.extend(
+                names
+                    .into_iter()
+                    .filter(|name| !profiles.contains_key(name))
+                    .map(|name| ProfileEntry {
+                        aliases: aliases_of(&name),
+                        path: None,
+                        remote: true,
+                        description: notes.get(&name).description,
+                        fields: notes.get(&name).fields,
+                        tags: notes.get(&name).tags,
+                        hostnames: Vec::new(),
+                        fingerprints: BTreeMap::new(),
+                        token_expiries: BTreeMap::new(),
+                        size: None,
+                        active: false,
+                        last_switched: state.last_switched.get(&name).copied(),
+                        last_read: None,
+                        created: None,
+                        modified: None,
+                        name,
+                    }),
+            ),
+            Err(error) => {
+                output::warning(format!("Couldn't list the profiles in Vault: {error:#}"))
+            }
+        }
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SwitchMode;
+
+    #[test]
+    fn lists_the_profiles_by_name() {
+        let home = tempfile::tempdir().unwrap();
+        let store = ProfileStore::open(home.path().join("store"));
+        let credentials_file = CredentialsFile::new(home.path().join(".terraform.d"));
+        std::fs::create_dir_all(store.directory()).unwrap();
+        std::fs::create_dir_all(credentials_file.terraform_directory()).unwrap();
+        store
+            .register(
+                "work",
+                r#"{"credentials": {"app.terraform.io": {"token": "secret"}}}"#,
+            )
+            .unwrap();
+        store
+            .register(
+                "personal",
+                r#"{"credentials": {"tfe.example.com": {"source": "pass://terraform/tfe"}}}"#,
+            )
+            .unwrap();
+        credentials_file
+            .install(&store, "work", SwitchMode::Symlink)
+            .unwrap();
+        crate::metadata::update(store.directory(), "work", |metadata| {
+            metadata.tags.insert(String::from("prod"));
+        })
+        .unwrap();
+        let aliases = BTreeMap::from([(String::from("w"), String::from("work"))]);
+
+        let entries = entries(&store, &credentials_file, &aliases).unwrap();
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["personal", "work"]);
+        let (personal, work) = (&entries[0], &entries[1]);
+        assert!(!personal.active);
+        assert_eq!(personal.hostnames, ["tfe.example.com"]);
+        assert!(personal.fingerprints.is_empty());
+        assert!(work.active);
+        assert_eq!(work.aliases, ["w"]);
+        assert!(work.tags.contains("prod"));
+        assert_eq!(
+            work.fingerprints,
+            BTreeMap::from([(
+                String::from("app.terraform.io"),
+                store::fingerprint("secret")
+            )])
+        );
+        assert_eq!(work.path, Some(store.profile_path("work")));
+        assert!(work.size.is_some());
+    }
+}
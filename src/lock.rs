@@ -0,0 +1,48 @@
+//! Advisory lock keeping concurrent invocations from interleaving their changes
+
+use std::{
+    fs::{File, TryLockError},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use crate::error::CliError;
+
+/// Name of the lock file inside the project directory
+pub const LOCK_FILE: &str = "lock";
+
+/// How long to wait for another invocation to finish before giving up
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Lock held until dropped, which the system also releases if the process dies
+#[derive(Debug)]
+pub struct Lock {
+    _file: File,
+}
+
+/// Acquire the lock of a project directory, waiting briefly for another invocation holding it
+pub fn acquire(project_directory: &Path) -> Result<Lock> {
+    let path = project_directory.join(LOCK_FILE);
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)?;
+    let started = Instant::now();
+    loop {
+        match file.try_lock() {
+            Ok(()) => {
+                tracing::debug!("Locked {}", path.display());
+                return Ok(Lock { _file: file });
+            }
+            Err(TryLockError::WouldBlock) if started.elapsed() < TIMEOUT => {
+                tracing::debug!("Waiting for the lock on {}", path.display());
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            Err(TryLockError::WouldBlock) => return Err(CliError::OperationInProgress.into()),
+            Err(TryLockError::Error(error)) => return Err(error.into()),
+        }
+    }
+}
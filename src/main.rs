@@ -5,13 +5,33 @@
 //! The `~/.terraform.d/credentials.tfrc.json` file is unfortunately unique, and for governance issues,
 //! you can't switch easily between teams with different terraform cloud accounts
 
+mod completions;
+mod docs;
+mod doctor;
+mod tui;
+
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
+    io::{IsTerminal, Write},
     path::{Path, PathBuf},
+    time::SystemTime,
 };
 
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use terraform_profile::{
+    agent, api, archive, backup, bitwarden,
+    config::{self, Hook},
+    credentials, detect, encryption, error, expiry, git, github, helper, history, import, journal,
+    listing, lock, metadata, migration,
+    output::{self, Output},
+    paths, permissions, pin, prune, render, revocation, rotation, source, state,
+    status::{self, Status},
+    store::{self, read_profile_tokens, validate_credentials, validate_profile_name, ProfileStore},
+    sync, terraformrc, trash, update, vault,
+    watch::{self, CredentialsWatch},
+    CredentialsFile, Switcher,
+};
 
 /// Select a subcommand to interact with your terraform cloud profile.
 ///
@@ -20,6 +40,29 @@ use clap::{Parser, Subcommand};
 #[clap(author, version, about, long_about = None)]
 #[clap(propagate_version = true)]
 struct Cli {
+    /// Format of the command results
+    #[clap(long, short, value_enum, global = true, default_value_t = Output::Text)]
+    output: Output,
+    /// Don't print what the commands did, only their results and errors
+    #[clap(long, short, value_parser, global = true)]
+    quiet: bool,
+    /// Don't color the output, which `NO_COLOR` also turns off
+    #[clap(long, value_parser, global = true)]
+    no_color: bool,
+    /// Manage the credentials in this directory instead of `~/.terraform.d`
+    #[clap(long, value_parser, global = true, value_name = "DIR")]
+    terraform_dir: Option<PathBuf>,
+    /// Log the paths read, the links created and the API calls made, `-vv` and `-vvv` for more
+    #[clap(long, short, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+    /// Hand the exports and outputs to the next steps of a GitHub Actions job, masking the
+    /// tokens, which `GITHUB_ACTIONS=true` also turns on
+    #[clap(long, value_parser, global = true)]
+    github_actions: bool,
+    /// Never prompt, accepting the confirmations and failing where a choice is needed, as when
+    /// stdin isn't a terminal
+    #[clap(long, short = 'y', visible_alias = "yes", value_parser, global = true)]
+    non_interactive: bool,
     #[clap(subcommand)]
     command: Commands,
 }
@@ -27,193 +70,4297 @@ struct Cli {
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Switch the current terraform cloud profile for another.
+    ///
+    /// Leave the name blank to pick the profile interactively, or use `-` to go back to the
+    /// previous one
     Switch {
         #[clap(value_parser)]
-        name: String,
+        name: Option<String>,
+        /// Re-point credentials linking to a missing profile, or remove them, instead of switching
+        #[clap(long, value_parser, conflicts_with = "name")]
+        repair: bool,
+        /// Register unmanaged credentials under this name before switching away from them
+        #[clap(long, value_parser, value_name = "BACKUP_NAME", requires = "name")]
+        adopt: Option<String>,
+        /// Only replace the credentials of this hostname, keeping those of the others
+        #[clap(
+            long,
+            value_parser,
+            value_name = "HOSTNAME",
+            requires = "name",
+            conflicts_with = "adopt"
+        )]
+        host: Option<String>,
+        /// Only pick among the profiles with this tag, repeatable
+        #[clap(
+            long,
+            value_parser,
+            multiple_occurrences = true,
+            conflicts_with_all = &["name", "repair"]
+        )]
+        tag: Vec<String>,
+        /// Switch back to the default profile, set with `default set`
+        #[clap(
+            long,
+            value_parser,
+            conflicts_with_all = &["name", "repair", "adopt", "tag"]
+        )]
+        default: bool,
+    },
+    /// Switch back to the default profile, same as `switch --default`
+    Reset,
+    /// Set the profile `reset` switches back to, and `switch` falls back to when the terminal
+    /// isn't interactive
+    Default {
+        #[clap(subcommand)]
+        command: DefaultCommand,
     },
     /// Import your current unregistered terraform cloud profile
+    #[clap(group(clap::ArgGroup::new("token-source").args(&["token", "source"])))]
     Import {
+        #[clap(value_parser, required_unless_present = "dir")]
+        name: Option<String>,
+        /// Keep terraform working by replacing the credentials with the new profile once copied
+        #[clap(long, value_parser)]
+        copy: bool,
+        /// Import a copy of this credentials file instead of the current credentials
+        #[clap(long, value_parser, value_name = "PATH", conflicts_with = "copy")]
+        from_file: Option<PathBuf>,
+        /// Create the profile from this token, or from stdin when `-`, instead of a credentials file
+        #[clap(long, value_parser, conflicts_with_all = &["copy", "from-file"])]
+        token: Option<String>,
+        /// Create the profile from a token kept in 1Password (`op://VAULT/ITEM/FIELD`), the
+        /// password store (`pass://ENTRY`) or the notes of a Bitwarden item (`bw://ITEM`), read
+        /// whenever it is needed
+        #[clap(
+            long,
+            value_parser,
+            value_name = "REFERENCE",
+            conflicts_with_all = &["copy", "from-file", "token"]
+        )]
+        source: Option<String>,
+        /// The terraform cloud or enterprise hostname the token is for
+        #[clap(
+            long,
+            value_parser,
+            default_value = "app.terraform.io",
+            requires = "token-source"
+        )]
+        hostname: String,
+        /// Create the profile from the `TF_TOKEN_<hostname>` environment variables
+        #[clap(
+            long,
+            value_parser,
+            conflicts_with_all = &["copy", "from-file", "token", "source"]
+        )]
+        from_env: bool,
+        /// Create the profile from the credentials blocks of `~/.terraformrc`
+        #[clap(
+            long,
+            value_parser,
+            conflicts_with_all = &["copy", "from-file", "token", "source", "from-env"]
+        )]
+        from_terraformrc: bool,
+        /// Comment the imported credentials blocks out of `~/.terraformrc`, so they don't take
+        /// precedence over the managed credentials
+        #[clap(long, value_parser, requires = "from-terraformrc")]
+        comment_out: bool,
+        /// Describe what the profile is for, shown by `list --long` and `show`
+        #[clap(long, value_parser, value_name = "TEXT", conflicts_with = "to")]
+        description: Option<String>,
+        /// Move the tokens of this registered profile to another store instead of importing
+        #[clap(
+            long,
+            value_enum,
+            value_name = "STORE",
+            conflicts_with_all = &["copy", "from-file", "token", "source", "from-env", "from-terraformrc"]
+        )]
+        to: Option<TokenStore>,
+        /// Import every `*.tfrc.json` credentials file and `*.tfrc` CLI configuration of this
+        /// directory, each as the profile named after the file
+        #[clap(
+            long,
+            value_parser,
+            value_name = "PATH",
+            conflicts_with_all = &["name", "copy", "from-file", "token", "source", "from-env", "from-terraformrc", "description", "to"]
+        )]
+        dir: Option<PathBuf>,
+    },
+    /// Rename a registered terraform cloud profile
+    Rename {
+        #[clap(value_parser)]
+        name: String,
+        #[clap(value_parser)]
+        new_name: String,
+    },
+    /// Duplicate a registered terraform cloud profile under a new name
+    #[clap(visible_alias = "duplicate")]
+    Copy {
+        #[clap(value_parser)]
+        name: String,
+        #[clap(value_parser)]
+        new_name: String,
+        /// Open the new profile in your editor once copied
+        #[clap(long, value_parser)]
+        edit: bool,
+    },
+    /// Combine the credentials of several profiles into a new one
+    Merge {
+        /// Profiles to combine, in order
+        #[clap(value_parser, required = true, min_values = 2)]
+        names: Vec<String>,
+        /// Name of the new profile
+        #[clap(long, value_parser, value_name = "NAME")]
+        into: String,
+        /// What to do when several profiles hold different credentials for a hostname
+        #[clap(long, value_enum, default_value_t = MergeConflicts::Fail)]
+        conflicts: MergeConflicts,
+    },
+    /// Show the credentials of a registered terraform cloud profile
+    Show {
+        #[clap(value_parser)]
+        name: String,
+        /// Print the tokens in full instead of redacting them
+        #[clap(long, value_parser)]
+        reveal: bool,
+    },
+    /// Describe a registered terraform cloud profile, or note values about it
+    Annotate {
+        #[clap(value_parser)]
+        name: String,
+        /// Values to note, e.g. `owner=dana`, or `KEY=` to forget one
+        #[clap(
+            value_parser = parse_field,
+            value_name = "KEY=VALUE",
+            required_unless_present_any = &["description", "env"]
+        )]
+        fields: Vec<(String, String)>,
+        /// Describe what the profile is for, or forget the description when empty
+        #[clap(long, value_parser, value_name = "TEXT")]
+        description: Option<String>,
+        /// Environment variables `exec` and `env` set along with the tokens, e.g.
+        /// `AWS_PROFILE=prod`, or `KEY=` to forget one
+        #[clap(
+            long,
+            value_parser = parse_field,
+            value_name = "KEY=VALUE",
+            multiple_occurrences = true
+        )]
+        env: Vec<(String, String)>,
+    },
+    /// Compare the hostnames and tokens of two profiles, or of a profile and the credentials,
+    /// exiting with 1 when they differ
+    Diff {
+        #[clap(value_parser)]
+        name: String,
+        /// Profile to compare with
+        #[clap(value_parser, required_unless_present = "live")]
+        other: Option<String>,
+        /// Compare with the credentials terraform currently reads instead
+        #[clap(long, value_parser, conflicts_with = "other")]
+        live: bool,
+    },
+    /// Check that a registered profile or a credentials file has the structure terraform
+    /// expects
+    Validate {
+        /// Name of a registered profile, or path of a credentials file
+        #[clap(value_parser, value_name = "NAME|PATH")]
+        target: String,
+    },
+    /// Write the credentials of a registered terraform cloud profile to a file or stdout
+    Export {
+        #[clap(value_parser)]
+        name: String,
+        /// Write the credentials to this file instead of stdout
+        #[clap(long, value_parser, value_name = "PATH")]
+        to: Option<PathBuf>,
+        /// Empty the tokens, to share the structure of the profile safely
+        #[clap(long, value_parser)]
+        redact: bool,
+    },
+    /// Write the tokens of a profile to a standalone credentials file, e.g. for a CI job, without
+    /// changing anything else
+    Render {
+        /// The profile to use, `TERRAFORM_PROFILE` by default
+        #[clap(value_parser)]
+        name: Option<String>,
+        /// Path of the credentials file
+        #[clap(long, value_parser, value_name = "PATH")]
+        out: PathBuf,
+        /// Also write a CLI configuration holding the tokens, for `TF_CLI_CONFIG_FILE`
+        #[clap(long, value_parser, value_name = "PATH")]
+        terraformrc: Option<PathBuf>,
+    },
+    /// Write every registered profile, with its metadata, to a single passphrase-encrypted archive
+    #[clap(group(clap::ArgGroup::new("protection").required(true)))]
+    ExportAll {
+        /// Path of the archive
+        #[clap(long, value_parser, value_name = "PATH")]
+        out: PathBuf,
+        /// Encrypt the archive with a passphrase, read from `TERRAFORM_PROFILE_PASSPHRASE` or asked
+        #[clap(long, value_parser, group = "protection")]
+        encrypt: bool,
+        /// Write the tokens in plain text instead
+        #[clap(long, value_parser, group = "protection")]
+        insecure_plaintext: bool,
+    },
+    /// Register the profiles of an archive written by `export-all`
+    #[clap(group(clap::ArgGroup::new("conflicts")))]
+    Restore {
+        /// Path of the archive
+        #[clap(value_parser)]
+        file: PathBuf,
+        /// Leave the profiles already registered under the same name as they are
+        #[clap(long, value_parser, group = "conflicts")]
+        skip_existing: bool,
+        /// Replace the profiles already registered under the same name
+        #[clap(long, value_parser, group = "conflicts")]
+        overwrite: bool,
+        /// Register the profiles whose name is taken under their name followed by this suffix
+        #[clap(
+            long,
+            value_parser,
+            value_name = "SUFFIX",
+            group = "conflicts",
+            allow_hyphen_values = true
+        )]
+        rename_suffix: Option<String>,
+    },
+    /// Edit a registered terraform cloud profile in your editor
+    Edit {
+        #[clap(value_parser)]
+        name: String,
+    },
+    /// Check that the tokens of a profile are accepted by terraform cloud
+    Verify {
+        #[clap(value_parser, required_unless_present = "all")]
+        name: Option<String>,
+        /// Verify every registered profile
+        #[clap(long, value_parser, conflicts_with = "name")]
+        all: bool,
+    },
+    /// Show the terraform cloud account behind the active or given profile
+    Whoami {
+        #[clap(value_parser)]
+        name: Option<String>,
+    },
+    /// List the organizations available to the active or given profile
+    Orgs {
+        #[clap(value_parser)]
+        name: Option<String>,
+    },
+    /// List the workspaces of an organization visible to the active or given profile
+    Workspaces {
+        #[clap(value_parser)]
+        organization: String,
+        /// Use this profile instead of the active one
+        #[clap(long, value_parser)]
+        profile: Option<String>,
+    },
+    /// Run a command with the tokens and environment variables of a profile, without switching
+    /// globally
+    Exec {
+        /// The profile to use, `TERRAFORM_PROFILE` by default
+        #[clap(value_parser)]
+        name: Option<String>,
+        /// The command to run, after `--`
+        #[clap(value_parser, required = true, last = true)]
+        command: Vec<String>,
+    },
+    /// Print the shell exports setting the tokens and environment variables of a profile, to be
+    /// `eval`ed
+    Env {
+        /// The profile to use, `TERRAFORM_PROFILE` by default
+        #[clap(value_parser)]
+        name: Option<String>,
+        /// The shell syntax to print the exports in
+        #[clap(long, value_enum, default_value_t = Shell::Bash)]
+        shell: Shell,
+    },
+    /// Log into terraform cloud with `terraform login` and register the result as a profile
+    Login {
+        #[clap(value_parser)]
+        name: String,
+        /// The terraform cloud or enterprise hostname to log into
+        #[clap(long, value_parser, default_value = "app.terraform.io")]
+        hostname: String,
+    },
+    /// Remove a registered terraform cloud profile, optionally revoking its tokens
+    #[clap(visible_alias = "delete")]
+    Logout {
+        #[clap(value_parser)]
+        name: String,
+        /// Also invalidate the tokens through the terraform cloud API, once confirmed
+        #[clap(long, value_parser)]
+        revoke: bool,
+        /// Identifier of the account token the profile holds, e.g. `at-XXXXXXXXXXXXXXXX`, needed
+        /// for the tokens the CLI didn't create, repeatable for several hostnames
+        #[clap(
+            long,
+            value_parser,
+            value_name = "ID",
+            multiple_occurrences = true,
+            requires = "revoke"
+        )]
+        token_id: Vec<String>,
+    },
+    /// Replace the tokens of a profile with freshly minted ones and revoke the old ones
+    Rotate {
+        #[clap(value_parser)]
+        name: String,
+        /// Identifier of the account token the profile holds, e.g. `at-XXXXXXXXXXXXXXXX`, needed
+        /// to revoke the tokens the CLI didn't create, repeatable for several hostnames
+        #[clap(long, value_parser, value_name = "ID", multiple_occurrences = true)]
+        token_id: Vec<String>,
+    },
+    /// Bring back a profile removed by `logout`
+    Undelete {
+        #[clap(value_parser)]
+        name: String,
+    },
+    /// List or empty the profiles removed by `logout`
+    Trash {
+        #[clap(subcommand)]
+        command: TrashCommand,
+    },
+    /// Revert the last operation that removed or overwrote a profile or the credentials
+    Undo,
+    /// Sync the store with its git remote, when it is a git repository, or with another remote
+    Sync {
+        /// Sync an encrypted bundle of the profiles with this remote instead, a
+        /// `file://<dir>`, `ssh://[user@]host[:port]/<dir>`, `https://<url>` or
+        /// `s3://<bucket>/<prefix>` URL
+        #[clap(long, value_parser, global = true, value_name = "URL")]
+        remote: Option<String>,
+        /// How to settle the profiles changed both here and on the remote since the last sync
+        #[clap(long, value_enum, global = true, default_value = "last-writer-wins")]
+        conflicts: SyncConflicts,
+        #[clap(subcommand)]
+        command: SyncCommand,
+    },
+    /// Keep the decrypted profiles in memory and serve them to the other commands over a local
+    /// socket, so the passphrase is only asked once
+    Agent {
+        /// Decrypt the profiles again once they were kept this long, e.g. `8h`
+        #[clap(long, value_parser = humantime::parse_duration, value_name = "DURATION")]
+        lifetime: Option<std::time::Duration>,
+    },
+    /// Answer terraform as its credentials helper, with the tokens of the active profile
+    Helper {
+        #[clap(subcommand)]
+        command: HelperCommand,
+    },
+    /// Share profiles through the configured HashiCorp Vault
+    Vault {
+        #[clap(subcommand)]
+        command: VaultCommand,
+    },
+    /// Watch the credentials, warning when something replaces the managed ones, e.g.
+    /// `terraform login`
+    Watch {
+        /// Register the replacing credentials as a new profile, named after the time
+        #[clap(long, value_parser)]
+        adopt: bool,
+        /// Put the profile that was in use back in place of the replacing credentials, once
+        /// registered
+        #[clap(long, value_parser, requires = "adopt")]
+        restore: bool,
+    },
+    /// Browse and manage the profiles in a full-screen interface
+    Tui,
+    /// Catch up with the profiles changed outside of the CLI, e.g. by `git pull`, reporting
+    /// what changed since the last refresh
+    Refresh,
+    /// Check the store, the credentials and the environment for problems
+    Doctor {
+        /// Apply the fixes that can't lose any credentials
+        #[clap(long, value_parser)]
+        fix: bool,
+    },
+    /// Find the files of the store that can't be used as profiles, then delete them or set
+    /// them aside
+    Prune {
+        /// What to do with each file found
+        #[clap(long, value_enum, default_value_t = PruneAction::Prompt)]
+        action: PruneAction,
+        /// Also find the profiles whose tokens terraform cloud refuses or reports as expired
+        #[clap(long, value_parser)]
+        verify: bool,
+        /// Print what would be pruned without changing anything
+        #[clap(long, value_parser)]
+        dry_run: bool,
+    },
+    /// Install the latest release of the CLI from GitHub in place of the running binary, once
+    /// its checksum is checked
+    SelfUpdate {
+        /// Only tell whether a newer release is available
+        #[clap(long, value_parser, conflicts_with = "tag")]
+        check: bool,
+        /// Install the release of this tag instead, e.g. `v0.1.1`, even when it is older
+        #[clap(long, value_parser)]
+        tag: Option<String>,
+    },
+    /// Print the completion script of the CLI for a shell
+    Completions {
+        #[clap(value_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// List the registered profile names for the completion scripts
+    #[clap(name = completions::CALLBACK, hide = true)]
+    Complete,
+    /// Write the man pages and the markdown reference of the CLI and its subcommands, for
+    /// packagers
+    #[clap(hide = true)]
+    GenerateDocs {
+        /// Directory to write the `man` and `markdown` directories into
+        #[clap(long, value_parser, value_name = "DIR")]
+        out: PathBuf,
+    },
+    /// Print the shell integration providing `tfp` and prompt variables, to be `eval`ed
+    Init {
+        #[clap(value_enum)]
+        shell: InitShell,
+    },
+    /// Pin a profile for the current directory through a `.terraform-profile` file
+    Use {
         #[clap(value_parser)]
         name: String,
     },
+    /// Switch to the profile pinned for the current directory, if any
+    Auto,
+    /// Detect the terraform cloud hostname of the current configuration and the profiles for it
+    Detect {
+        /// Switch to the matching profile when there's only one
+        #[clap(long, value_parser)]
+        switch: bool,
+    },
+    /// Print the active profile name for shell prompts, or nothing, never failing
+    Prompt {
+        /// Format of the output, where `{profile}` is replaced by the profile name
+        #[clap(long, value_parser, default_value = "{profile}")]
+        format: String,
+    },
+    /// Show the history of the profile switches
+    History {
+        /// Only show the most recent switches
+        #[clap(long, value_parser)]
+        limit: Option<usize>,
+        /// Print the history as JSON, same as `--output json`
+        #[clap(long, value_parser)]
+        json: bool,
+    },
+    /// Print the effective configuration, read from `config.toml` in the configuration directory
+    Config,
+    /// Convert the credentials, the configuration and the CLI configuration of terraform to
+    /// another switching mode, keeping the active profile
+    Migrate {
+        /// Mode to convert to
+        #[clap(long, value_enum)]
+        to: config::SwitchMode,
+        /// Print what would change without changing anything
+        #[clap(long, value_parser)]
+        dry_run: bool,
+    },
     /// Check which terraform cloud profile is currently used
-    Status,
+    Status {
+        /// How to print the status
+        #[clap(long, value_enum, default_value_t = StatusFormat::Text)]
+        format: StatusFormat,
+        /// Print the status as JSON, same as `--output json`
+        #[clap(long, value_parser, conflicts_with = "format")]
+        json: bool,
+        /// Print nothing, only exit with 0 when a profile is in use, 2 when there are no
+        /// credentials, 3 when they aren't a registered profile, or 8 and 10 when they link to
+        /// a missing or unknown file
+        #[clap(long, value_parser, conflicts_with_all = &["format", "json"])]
+        check: bool,
+    },
     /// List all the different registered terraform cloud profiles
-    List,
+    List {
+        /// Show a table of the hostnames of each profile, the fingerprints and expirations of its
+        /// tokens, the size of its file, when it was created, modified, last switched to and read,
+        /// and whether it is active
+        #[clap(long, short, value_parser)]
+        long: bool,
+        /// Print the profiles as JSON, same as `--output json`
+        #[clap(long, value_parser, conflicts_with = "long")]
+        json: bool,
+        /// Only list the profiles with this tag, repeatable
+        #[clap(long, value_parser, multiple_occurrences = true)]
+        tag: Vec<String>,
+    },
+    /// Group profiles under tags, e.g. `prod` or a client name
+    Tag {
+        #[clap(subcommand)]
+        command: TagCommand,
+    },
+    /// Give profiles short names, accepted anywhere a profile name is
+    Alias {
+        #[clap(subcommand)]
+        command: AliasCommand,
+    },
 }
 
-/// Fetch and initialize the root project directory
-fn initialize_folder() -> Result<PathBuf> {
-    let home_dir = home::home_dir().context("Impossible to get your home dir!")?;
+/// Shells the environment exports can be formatted for
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+/// Stores the tokens of a profile can be moved to
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum TokenStore {
+    /// The standard Unix password store, under `terraform-profile/<name>`
+    Pass,
+}
+
+/// Shells the integration hooks can be printed for
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum InitShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// How a partial name given in place of the name of a registered profile may be completed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Completion {
+    /// To the only registered name it stands for
+    Partial,
+    /// Only once confirmed, the command destroying or replacing what the profile holds
+    Confirmed,
+    /// Unless a profile only in Vault has the very name, the command fetching those
+    Remote,
+}
+
+impl Commands {
+    /// Replace the aliases and partial names given in place of the names of registered profiles,
+    /// telling how each may be completed
+    fn resolve_names(
+        &mut self,
+        resolve: impl Fn(&mut String, Completion) -> Result<()>,
+    ) -> Result<()> {
+        let partial = |name: &mut String| resolve(name, Completion::Partial);
+        match self {
+            Commands::Switch {
+                name: Some(name), ..
+            } if name != "-" => resolve(name, Completion::Remote),
+            Commands::Rename { name, .. }
+            | Commands::Logout { name, .. }
+            | Commands::Rotate { name, .. }
+            | Commands::Vault {
+                command: VaultCommand::Push { name },
+            } => resolve(name, Completion::Confirmed),
+            Commands::Default {
+                command: DefaultCommand::Set { name },
+            } => partial(name),
+            Commands::Copy { name, .. }
+            | Commands::Show { name, .. }
+            | Commands::Annotate { name, .. }
+            | Commands::Validate { target: name }
+            | Commands::Export { name, .. }
+            | Commands::Edit { name }
+            | Commands::Use { name }
+            | Commands::Tag {
+                command: TagCommand::Add { name, .. } | TagCommand::Remove { name, .. },
+            }
+            | Commands::Alias {
+                command: AliasCommand::Set { name, .. },
+            }
+            | Commands::Import {
+                name: Some(name),
+                to: Some(_),
+                ..
+            } => partial(name),
+            Commands::Verify {
+                name: Some(name), ..
+            }
+            | Commands::Render {
+                name: Some(name), ..
+            }
+            | Commands::Exec {
+                name: Some(name), ..
+            }
+            | Commands::Env {
+                name: Some(name), ..
+            }
+            | Commands::Whoami { name: Some(name) }
+            | Commands::Orgs { name: Some(name) }
+            | Commands::Workspaces {
+                profile: Some(name),
+                ..
+            } => partial(name),
+            Commands::Merge { names, .. } => names.iter_mut().try_for_each(partial),
+            Commands::Diff { name, other, .. } => {
+                partial(name)?;
+                other.iter_mut().try_for_each(partial)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Use the profile named by `TERRAFORM_PROFILE` for the commands given no name that read it
+    fn default_to_override(&mut self, profile_override: Option<&String>) {
+        if let Commands::Render { name, .. }
+        | Commands::Exec { name, .. }
+        | Commands::Env { name, .. } = self
+        {
+            if name.is_none() {
+                *name = profile_override.cloned();
+            }
+        }
+    }
+
+    /// Whether the command may tell about a newer release, which the commands terraform, the
+    /// completion scripts or the other commands run on their own don't
+    fn checks_for_updates(&self) -> bool {
+        !matches!(
+            self,
+            Commands::Helper { .. }
+                | Commands::Agent { .. }
+                | Commands::Complete
+                | Commands::GenerateDocs { .. }
+                | Commands::Prompt { .. }
+                | Commands::Render { .. }
+                | Commands::SelfUpdate { .. }
+        )
+    }
 
-    let project_dir = home_dir.join(format!(".{}", env!("CARGO_PKG_NAME")));
+    /// Whether the command may write to the home directory, which `render`, run by CI jobs to
+    /// write the credentials elsewhere, never does
+    fn writes_home(&self) -> bool {
+        !matches!(self, Commands::Render { .. })
+    }
 
-    if !project_dir.exists() {
-        std::fs::create_dir(&project_dir)?;
+    /// Whether the command changes the credentials, the profiles or the state, and so has to
+    /// hold the lock
+    fn changes_registry(&self) -> bool {
+        matches!(
+            self,
+            Commands::Switch { .. }
+                | Commands::Reset
+                | Commands::Import { .. }
+                | Commands::Restore { .. }
+                | Commands::Rename { .. }
+                | Commands::Copy { .. }
+                | Commands::Merge { .. }
+                | Commands::Annotate { .. }
+                | Commands::Tag {
+                    command: TagCommand::Add { .. } | TagCommand::Remove { .. }
+                }
+                | Commands::Alias {
+                    command: AliasCommand::Set { .. } | AliasCommand::Remove { .. }
+                }
+                | Commands::Edit { .. }
+                | Commands::Verify { .. }
+                | Commands::Login { .. }
+                | Commands::Logout { .. }
+                | Commands::Rotate { .. }
+                | Commands::Undelete { .. }
+                | Commands::Trash {
+                    command: TrashCommand::Empty { .. }
+                }
+                | Commands::Undo
+                | Commands::Helper {
+                    command: HelperCommand::Store { .. } | HelperCommand::Forget { .. }
+                }
+                | Commands::Vault { .. }
+                | Commands::Sync { .. }
+                | Commands::Tui
+                | Commands::Auto
+                | Commands::Detect { switch: true }
+                | Commands::Refresh
+                | Commands::Doctor { fix: true }
+                | Commands::Prune { dry_run: false, .. }
+                | Commands::Migrate { dry_run: false, .. }
+        )
     }
-    Ok(project_dir)
 }
 
-/// Get all the files and register their profiles names
-fn get_profiles<P: AsRef<Path>>(path: P) -> Result<HashMap<String, PathBuf>> {
-    let mut entries = HashMap::new();
+/// What to do with the trash
+#[derive(Subcommand, Debug)]
+enum TrashCommand {
+    /// List the removed profiles, oldest first
+    List,
+    /// Permanently remove the profiles in the trash
+    Empty {
+        /// Only remove the profiles removed longer ago than this, e.g. `30d`
+        #[clap(long, value_parser = humantime::parse_duration, value_name = "AGE")]
+        older_than: Option<std::time::Duration>,
+    },
+}
+
+/// What to do with the tags
+#[derive(Subcommand, Debug)]
+enum TagCommand {
+    /// Tag a registered profile
+    Add {
+        #[clap(value_parser)]
+        name: String,
+        #[clap(value_parser, required = true)]
+        tags: Vec<String>,
+    },
+    /// Remove tags from a registered profile
+    Remove {
+        #[clap(value_parser)]
+        name: String,
+        #[clap(value_parser, required = true)]
+        tags: Vec<String>,
+    },
+    /// List the tags along with the profiles having each
+    List,
+}
+
+/// What to do with the default profile
+#[derive(Subcommand, Debug)]
+enum DefaultCommand {
+    /// Make a registered profile the default one
+    Set {
+        #[clap(value_parser)]
+        name: String,
+    },
+    /// Stop having a default profile
+    Unset,
+    /// Print the default profile, if any
+    Show,
+}
+
+/// What to do with the aliases
+#[derive(Subcommand, Debug)]
+enum AliasCommand {
+    /// Make a short name stand for a registered profile
+    Set {
+        #[clap(value_parser)]
+        alias: String,
+        #[clap(value_parser)]
+        name: String,
+    },
+    /// Forget an alias set with `alias set`
+    Remove {
+        #[clap(value_parser)]
+        alias: String,
+    },
+    /// List the aliases along with the profiles they stand for
+    List,
+}
+
+/// Which way to sync the store
+#[derive(Subcommand, Debug)]
+enum SyncCommand {
+    /// Fetch the profiles committed on other machines
+    Pull,
+    /// Send the profiles committed on this machine
+    Push,
+}
+
+/// How to settle the profiles changed both here and on a remote
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum SyncConflicts {
+    /// Keep the most recently written version
+    LastWriterWins,
+    /// Ask which version to keep
+    Prompt,
+}
+
+/// How `merge` settles the hostnames several profiles hold different credentials for
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum MergeConflicts {
+    /// Refuse to merge, listing the conflicting hostnames
+    Fail,
+    /// Keep the credentials of the first profile given
+    First,
+    /// Keep the credentials of the last profile given
+    Last,
+    /// Ask which profile to keep the credentials of
+    Prompt,
+}
+
+/// What `prune` does with the files of the store that can't be used as profiles
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum PruneAction {
+    /// Ask what to do with each file
+    Prompt,
+    /// Move the files to the `quarantine` directory of the store
+    Quarantine,
+    /// Delete the files
+    Delete,
+}
+
+/// What to do with the profiles shared through Vault
+#[derive(Subcommand, Debug)]
+enum VaultCommand {
+    /// Move the tokens of a profile to Vault, keeping only a reference to them locally
+    Push {
+        #[clap(value_parser)]
+        name: String,
+    },
+}
 
-    for file in std::fs::read_dir(path)? {
-        if let Ok(file) = file {
-            let file = file;
+/// Requests of terraform to its credentials helper, which it makes itself when the binary is
+/// named `terraform-credentials-*`, and its installation
+#[derive(Subcommand, Debug)]
+enum HelperCommand {
+    /// Print the credentials of a hostname as JSON, `{}` when the active profile has none
+    Get {
+        #[clap(value_parser)]
+        hostname: String,
+    },
+    /// Store the credentials read as JSON from stdin for a hostname in the active profile
+    Store {
+        #[clap(value_parser)]
+        hostname: String,
+    },
+    /// Remove the credentials of a hostname from the active profile
+    Forget {
+        #[clap(value_parser)]
+        hostname: String,
+    },
+    /// Configure terraform to run the binary as its credentials helper, and check it answers
+    Install,
+}
 
-            let file_name = file
-                .file_name()
-                .to_str()
-                .context("Couldn't convert OsString to &str")?
-                .split_once(".tfrc.json")
-                .context("Couldn't split file name")?
-                .0
-                .to_string();
+/// Formats the status can be printed in
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum StatusFormat {
+    /// The bare profile name
+    Text,
+    /// A color-coded segment for the tmux status line
+    Tmux,
+}
 
-            entries.insert(file_name, file.path());
+/// Entrypoint of the CLI, the one place the errors are printed and turned into exit codes
+fn main() {
+    let mut command_line: Vec<std::ffi::OsString> = std::env::args_os().collect();
+    // Terraform runs its credentials helpers with the request as the only arguments
+    if command_line
+        .first()
+        .is_some_and(|program| helper::is_helper_program(program))
+    {
+        command_line.insert(1, "helper".into());
+    }
+    let args = match Cli::try_parse_from(command_line) {
+        Ok(args) => args,
+        Err(e) if e.use_stderr() => {
+            let _ = e.print();
+            std::process::exit(error::USAGE_EXIT_CODE);
+        }
+        Err(e) => e.exit(),
+    };
+    if let Err(e) = run(args) {
+        if !error::is_silent(&e) {
+            eprintln!(
+                "{} {e:?}",
+                console::style("Error:").red().bold().for_stderr()
+            );
         }
+        std::process::exit(error::exit_code(&e));
     }
-    Ok(entries)
 }
 
-/// Entrypoint of the CLI
-fn main() -> Result<()> {
-    let terraform_directory = home::home_dir()
-        .context("Impossible to get your home dir!")?
-        .join(".terraform.d");
-    let project_directory = initialize_folder()?;
+/// Run the command given on the command line
+fn run(mut args: Cli) -> Result<()> {
+    // The prompt runs before every shell prompt, so it skips the registry scan entirely
+    if let Commands::Prompt { format } = &args.command {
+        print_prompt(format, args.terraform_dir.as_deref());
+        return Ok(());
+    }
+
+    output::set_quiet(args.quiet);
+    output::set_non_interactive(args.non_interactive);
+    output::init_logging(args.verbose, args.no_color);
+
+    let home_dir = home::home_dir().context("Impossible to get your home dir!")?;
+    let writes_home = args.command.writes_home();
+    // The legacy configuration predates `terraform_directory`, so the defaults are enough to
+    // find the credentials to re-point
+    if writes_home {
+        paths::migrate_legacy_directory(
+            &home_dir,
+            &paths::terraform_directory(
+                &home_dir,
+                args.terraform_dir.as_deref(),
+                &config::Config::default(),
+            ),
+        )?;
+    }
+
+    let config_file = paths::config_file(&home_dir);
+    tracing::debug!("Reading the configuration in {}", config_file.display());
+    let config = config::Config::load(&config_file)?;
+    let terraform_directory =
+        paths::terraform_directory(&home_dir, args.terraform_dir.as_deref(), &config);
+    output::configure_colors(args.no_color, config.color);
+    api::set_timeout(config.api_timeout);
+    vault::configure(config.vault.as_ref());
+
+    let profile_store = if writes_home {
+        ProfileStore::initialize(&home_dir, &config)?
+    } else {
+        ProfileStore::open(paths::storage_directory(&home_dir, &config))
+    };
+    let project_directory = profile_store.directory().to_path_buf();
+    encryption::configure(config.encryption.as_ref(), &home_dir, &project_directory)?;
+    // `render` reads the profiles straight from the store, leaving no Bitwarden session there
+    if writes_home {
+        bitwarden::configure(&project_directory);
+        agent::configure(&project_directory);
+    }
+    if config.update_check && args.command.checks_for_updates() {
+        update::notify_newer_release(&project_directory);
+    }
+    let changes_registry = args.command.changes_registry();
+    let _lock = if changes_registry {
+        let lock = lock::acquire(&project_directory)?;
+        if let Some(operation) = journal::recover(&project_directory)? {
+            output::warning(format!(
+                "`{operation}` was interrupted, its changes were rolled back"
+            ));
+        }
+        Some(lock)
+    } else {
+        None
+    };
 
-    let profiles = get_profiles(&project_directory)?;
+    let profiles = profile_store.profiles()?;
+    let aliases = metadata::aliases(&project_directory, &config)?;
+    let resolve_name = |name: &mut String, completion: Completion| -> Result<()> {
+        if profiles.contains_key(name) {
+            return Ok(());
+        }
+        if let Some(profile) = aliases.get(name) {
+            tracing::debug!("`{name}` is an alias of `{profile}`");
+            *name = profile.clone();
+            return Ok(());
+        }
+        if config.exact_names || completion == Completion::Remote && is_remote_profile(name) {
+            return Ok(());
+        }
+        if let Some(profile) = store::complete_name(name, profiles.keys())? {
+            if completion == Completion::Confirmed
+                && !confirm(&format!("`{name}` matches profile `{profile}`, use it?"))?
+            {
+                anyhow::bail!("Pass the full name of the profile");
+            }
+            output::notice(format!("`{name}` matches profile `{profile}`"));
+            *name = profile;
+        }
+        Ok(())
+    };
+    let partial = |name: &mut String| resolve_name(name, Completion::Partial);
+    let mut profile_override = profile_override();
+    profile_override.iter_mut().try_for_each(partial)?;
+    args.command.default_to_override(profile_override.as_ref());
+    args.command.resolve_names(resolve_name)?;
+    let mut default_profile = config.default_profile.clone();
+    default_profile.iter_mut().try_for_each(partial)?;
+    let output = args.output;
+    let github_actions = args.github_actions || github::is_detected();
+    // Noted once the profile is imported, whichever way its credentials are
+    let imported_description = match &args.command {
+        Commands::Import {
+            name: Some(name),
+            description: Some(description),
+            ..
+        } => Some((name.clone(), description.clone())),
+        _ => None,
+    };
+    // Told to the post-import hook once imported, along with those of `import --dir`
+    let registered_before: Option<HashSet<String>> = match &args.command {
+        Commands::Import { to: None, .. } => Some(profiles.keys().cloned().collect()),
+        _ => None,
+    };
 
-    match Cli::try_parse() {
-        Ok(args) => match args.command {
-            Commands::Switch { name } => switch_profile(&terraform_directory, &profiles, name)?,
-            Commands::Import { name } => {
-                import_profile(name, &terraform_directory, &profiles, project_directory)?
+    match args.command {
+        Commands::Switch { repair: true, .. } => {
+            repair_credentials(&terraform_directory, &profiles)?
+        }
+        Commands::Switch {
+            name,
+            adopt: Some(backup),
+            ..
+        } => Switcher::new(
+            profile_store.clone(),
+            CredentialsFile::new(&terraform_directory),
+            config.mode,
+        )
+        .adopt_and_switch(&backup, config.backup_retention, |adopted| {
+            let mut profiles = profiles;
+            report_adopted(&backup, adopted, &mut profiles);
+            let name = switch_target(
+                name,
+                &terraform_directory,
+                &project_directory,
+                &profiles,
+                &config,
+            )?;
+            switch_profile(
+                &terraform_directory,
+                &project_directory,
+                &profiles,
+                name,
+                &config,
+            )
+        })?,
+        Commands::Switch {
+            name: Some(name),
+            host: Some(host),
+            ..
+        } => switch_host(
+            &terraform_directory,
+            &project_directory,
+            &profiles,
+            name,
+            &host,
+            &config,
+        )?,
+        Commands::Switch { default: true, .. } | Commands::Reset => {
+            let name = default_profile
+                .clone()
+                .context("No default profile is set, set one with `default set <name>`")?;
+            switch_profile(
+                &terraform_directory,
+                &project_directory,
+                &profiles,
+                name,
+                &config,
+            )?
+        }
+        Commands::Default { command } => {
+            set_default_profile(command, &home_dir, &config, &profiles)?
+        }
+        Commands::Switch { name, tag, .. } => {
+            let name = if tag.is_empty() {
+                switch_target(
+                    name,
+                    &terraform_directory,
+                    &project_directory,
+                    &profiles,
+                    &config,
+                )?
+            } else {
+                pick_tagged_profile(
+                    &tag,
+                    &terraform_directory,
+                    &project_directory,
+                    &profiles,
+                    &config,
+                )?
+            };
+            let mut profiles = profiles;
+            if !profiles.contains_key(&name) {
+                if let Some(profile_path) = vault::fetch(&name, &project_directory)? {
+                    output::notice(format!("Fetched profile `{name}` from Vault"));
+                    profiles.insert(name.clone(), profile_path);
+                }
             }
-            Commands::Status => show_profile_status(terraform_directory, &profiles)?,
-            Commands::List => show_profiles_list(&profiles),
-        },
-        Err(e) => match e.kind() {
-            clap::ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand => e.exit(),
-            _ => e.exit(),
-        },
+            switch_profile(
+                &terraform_directory,
+                &project_directory,
+                &profiles,
+                name,
+                &config,
+            )?
+        }
+        Commands::Import {
+            name: Some(name),
+            to: Some(TokenStore::Pass),
+            ..
+        } => {
+            import::to_pass(
+                &ProfileStore::open(&project_directory),
+                &CredentialsFile::new(&terraform_directory),
+                &name,
+                config.backup_retention,
+            )?;
+            output::notice(format!(
+                "Moved the tokens of profile `{name}` to the password store"
+            ));
+        }
+        Commands::Import {
+            name: Some(name),
+            from_file: Some(path),
+            ..
+        } => {
+            import::file(&ProfileStore::open(&project_directory), &name, &path)?;
+            output::notice("The terraform cloud profile was safely registered");
+        }
+        Commands::Import {
+            name: Some(name),
+            token: Some(token),
+            hostname,
+            ..
+        } => import_profile_token(name, &hostname, token, &profiles, &project_directory)?,
+        Commands::Import {
+            name: Some(name),
+            source: Some(source),
+            hostname,
+            ..
+        } => {
+            import::source(
+                &ProfileStore::open(&project_directory),
+                &name,
+                &hostname,
+                &source,
+            )?;
+            output::notice("The terraform cloud profile was safely registered");
+        }
+        Commands::Import {
+            name: Some(name),
+            from_env: true,
+            ..
+        } => {
+            let variables = std::env::vars_os().filter_map(|(key, value)| {
+                Some((key.into_string().ok()?, value.into_string().ok()?))
+            });
+            let hostnames =
+                import::environment(&ProfileStore::open(&project_directory), &name, variables)?;
+            output::notice(format!(
+                "The terraform cloud profile was safely registered with the tokens of {}",
+                hostnames.join(", ")
+            ));
+        }
+        Commands::Import {
+            name: Some(name),
+            from_terraformrc: true,
+            comment_out,
+            ..
+        } => {
+            let path = terraformrc::path(&home_dir);
+            import::terraformrc(
+                &ProfileStore::open(&project_directory),
+                &name,
+                &path,
+                comment_out,
+            )?;
+            output::notice("The terraform cloud profile was safely registered");
+            if comment_out {
+                output::notice(format!(
+                    "Commented the credentials blocks out of {}",
+                    path.display()
+                ));
+            }
+        }
+        Commands::Import {
+            dir: Some(directory),
+            ..
+        } => import_profile_directory(&directory, &project_directory)?,
+        Commands::Import { name, copy, .. } => import_profile(
+            name.context("A profile name is required")?,
+            copy,
+            &terraform_directory,
+            &project_directory,
+            &config,
+        )?,
+        Commands::Rename { name, new_name } => {
+            rename_profile(name, new_name, &terraform_directory, &project_directory)?
+        }
+        Commands::Copy {
+            name,
+            new_name,
+            edit,
+        } => copy_profile(name, new_name, edit, &project_directory)?,
+        Commands::Merge {
+            names,
+            into,
+            conflicts,
+        } => merge_profiles(&names, into, conflicts, &profiles, &project_directory)?,
+        Commands::Show { name, reveal } => {
+            show_profile(name, reveal, output, &profiles, &project_directory)?
+        }
+        Commands::Annotate {
+            name,
+            fields,
+            description,
+            env,
+        } => annotate_profile(
+            name,
+            fields,
+            description,
+            env,
+            &profiles,
+            &project_directory,
+        )?,
+        Commands::Validate { target } => validate_target(&target, &profiles)?,
+        Commands::Diff { name, other, .. } => {
+            diff_profiles(name, other, output, &terraform_directory, &profiles)?
+        }
+        Commands::Export { name, to, redact } => export_profile(name, to, redact, &profiles)?,
+        Commands::Render {
+            name,
+            out,
+            terraformrc,
+        } => render_profile(
+            given_profile(name)?,
+            &out,
+            terraformrc.as_deref(),
+            github_actions,
+            &terraform_directory,
+            &project_directory,
+        )?,
+        Commands::ExportAll { out, encrypt, .. } => {
+            export_all_profiles(&out, encrypt, &profiles, &project_directory)?
+        }
+        Commands::Restore {
+            file,
+            skip_existing,
+            overwrite,
+            rename_suffix,
+        } => {
+            let conflicts = match (skip_existing, overwrite, rename_suffix) {
+                (true, _, _) => archive::Conflicts::Skip,
+                (_, true, _) => archive::Conflicts::Overwrite,
+                (_, _, Some(suffix)) => archive::Conflicts::Rename(suffix),
+                _ => archive::Conflicts::Fail,
+            };
+            restore_profiles(
+                &file,
+                &conflicts,
+                &terraform_directory,
+                &project_directory,
+                &config,
+            )?
+        }
+        Commands::Edit { name } => {
+            edit_profile(name, &terraform_directory, &profiles, &project_directory)?
+        }
+        Commands::Verify { name, all } => {
+            verify_profiles(name, all, output, &profiles, &project_directory)?
+        }
+        Commands::Whoami { name } => show_whoami(
+            name,
+            output,
+            &terraform_directory,
+            &project_directory,
+            &profiles,
+        )?,
+        Commands::Orgs { name } => show_organizations(
+            name,
+            output,
+            &terraform_directory,
+            &project_directory,
+            &profiles,
+        )?,
+        Commands::Workspaces {
+            organization,
+            profile,
+        } => show_workspaces(
+            organization,
+            profile,
+            output,
+            &terraform_directory,
+            &project_directory,
+            &profiles,
+        )?,
+        Commands::Exec { name, command } => exec_with_profile(
+            given_profile(name)?,
+            command,
+            github_actions,
+            &profiles,
+            &project_directory,
+        )?,
+        Commands::Env { name, shell } => print_profile_env(
+            given_profile(name)?,
+            shell,
+            github_actions,
+            &profiles,
+            &project_directory,
+        )?,
+        Commands::Login { name, hostname } => {
+            login_profile(name, hostname, &profiles, &project_directory)?
+        }
+        Commands::Logout {
+            name,
+            revoke,
+            token_id,
+        } => logout_profile(
+            name,
+            revoke,
+            &token_id,
+            &terraform_directory,
+            &profiles,
+            &project_directory,
+            &config,
+        )?,
+        Commands::Rotate { name, token_id } => rotate_profile(
+            name,
+            &token_id,
+            &terraform_directory,
+            &project_directory,
+            &config,
+        )?,
+        Commands::Undelete { name } => undelete_profile(name, &profiles, &project_directory)?,
+        Commands::Trash {
+            command: TrashCommand::List,
+        } => output.print(&trash::list(&project_directory)?, |trashed| {
+            for profile in trashed {
+                println!(
+                    "{}\tremoved {}",
+                    profile.name,
+                    format_age(
+                        std::time::UNIX_EPOCH + std::time::Duration::from_secs(profile.deleted_at)
+                    )
+                );
+            }
+            Ok(())
+        })?,
+        Commands::Trash {
+            command: TrashCommand::Empty { older_than },
+        } => {
+            let removed = trash::empty(&project_directory, older_than.map(|age| age.as_secs()))?;
+            output::notice(format!("Permanently removed {removed} profiles"));
+        }
+        Commands::Undo => undo_operation(&terraform_directory, &project_directory)?,
+        Commands::Sync {
+            remote: Some(remote),
+            conflicts,
+            command: SyncCommand::Pull,
+        } => pull_bundle(
+            &remote,
+            conflicts,
+            &terraform_directory,
+            &project_directory,
+            &config,
+        )?,
+        Commands::Sync {
+            remote: Some(remote),
+            conflicts,
+            command: SyncCommand::Push,
+        } => push_bundle(&remote, conflicts, &project_directory)?,
+        Commands::Sync {
+            command: SyncCommand::Pull,
+            ..
+        } => pull_profiles(&terraform_directory, &project_directory)?,
+        Commands::Sync {
+            command: SyncCommand::Push,
+            ..
+        } => {
+            ensure_repository(&project_directory)?;
+            git::push(&project_directory)?;
+            output::notice("Pushed the profiles");
+        }
+        Commands::Agent { lifetime } => agent::serve(&project_directory, lifetime)?,
+        Commands::Helper {
+            command: HelperCommand::Get { hostname },
+        } => helper_get(
+            &hostname,
+            profile_override.as_deref(),
+            &terraform_directory,
+            &project_directory,
+        )?,
+        Commands::Helper {
+            command: HelperCommand::Store { hostname },
+        } => helper_store(
+            &hostname,
+            profile_override.as_deref(),
+            &terraform_directory,
+            &project_directory,
+            &config,
+        )?,
+        Commands::Helper {
+            command: HelperCommand::Forget { hostname },
+        } => helper_forget(
+            &hostname,
+            profile_override.as_deref(),
+            &terraform_directory,
+            &project_directory,
+            &config,
+        )?,
+        Commands::Helper {
+            command: HelperCommand::Install,
+        } => install_helper(
+            &home_dir,
+            &terraform_directory,
+            &profiles,
+            &project_directory,
+            &config,
+        )?,
+        Commands::Vault {
+            command: VaultCommand::Push { name },
+        } => push_profile(
+            name,
+            &terraform_directory,
+            &profiles,
+            &project_directory,
+            &config,
+        )?,
+        Commands::Tui => tui::run(&terraform_directory, &project_directory, &config)?,
+        Commands::Refresh => refresh_store(output, &terraform_directory, &project_directory)?,
+        Commands::Doctor { fix } => run_doctor(
+            fix,
+            output,
+            &home_dir,
+            &terraform_directory,
+            &project_directory,
+            &profiles,
+            default_profile.as_deref(),
+        )?,
+        Commands::Prune {
+            action,
+            verify,
+            dry_run,
+        } => prune_store(
+            action,
+            verify,
+            dry_run,
+            output,
+            &terraform_directory,
+            &project_directory,
+            &profiles,
+        )?,
+        Commands::Config => {
+            let effective = config::Config {
+                storage_directory: Some(project_directory.clone()),
+                terraform_directory: Some(terraform_directory.clone()),
+                ..config.clone()
+            };
+            output.print(&effective, |effective| {
+                print!("{}", toml::to_string(effective)?);
+                Ok(())
+            })?
+        }
+        Commands::Migrate { to, dry_run } => migrate_mode(
+            to,
+            dry_run,
+            &home_dir,
+            &terraform_directory,
+            &project_directory,
+            &config,
+        )?,
+        Commands::SelfUpdate { check, tag } => self_update(check, tag.as_deref(), &config)?,
+        Commands::Completions { shell } => completions::print(shell)?,
+        Commands::GenerateDocs { out } => docs::generate(&out)?,
+        Commands::Complete => {
+            let mut names: Vec<&String> = profiles.keys().collect();
+            names.sort();
+            for name in names {
+                println!("{name}");
+            }
+        }
+        Commands::Init { shell } => print!(
+            "{}",
+            match shell {
+                InitShell::Bash => include_str!("init/bash.sh"),
+                InitShell::Zsh => include_str!("init/zsh.sh"),
+                InitShell::Fish => include_str!("init/fish.fish"),
+            }
+        ),
+        Commands::Use { name } => use_profile(name, &profiles)?,
+        Commands::Watch { adopt, restore } => watch_credentials(
+            adopt,
+            restore,
+            &terraform_directory,
+            &project_directory,
+            &config,
+        )?,
+        Commands::Auto => {
+            auto_switch_profile(&terraform_directory, &project_directory, &profiles, &config)?
+        }
+        Commands::Detect { switch } => detect_profile(
+            switch,
+            output,
+            &terraform_directory,
+            &project_directory,
+            &profiles,
+            &config,
+        )?,
+        Commands::Status { check: true, .. } => {
+            check_profile_status(&terraform_directory, &project_directory, &profiles)?
+        }
+        Commands::Status {
+            format: StatusFormat::Tmux,
+            json: false,
+            ..
+        } if output == Output::Text => {
+            show_tmux_status(&terraform_directory, &project_directory, &profiles)?
+        }
+        Commands::Status { json, .. } => show_profile_status(
+            output.or_json(json),
+            profile_override,
+            &terraform_directory,
+            &project_directory,
+        )?,
+        Commands::History { limit, json } => {
+            show_history(limit, output.or_json(json), &project_directory)?
+        }
+        Commands::List { long, json, tag } => {
+            if long {
+                expiry::refresh_profiles(&project_directory, &profiles)?;
+            }
+            let mut entries = listing::entries(
+                &profile_store,
+                &CredentialsFile::new(&terraform_directory),
+                &aliases,
+            )?;
+            if !tag.is_empty() {
+                entries.retain(|entry| tag.iter().all(|tag| entry.tags.contains(tag)));
+                if entries.is_empty() {
+                    anyhow::bail!("No profile is tagged {}", describe_tags(&tag));
+                }
+            }
+            output.or_json(json).print(&entries, |entries| {
+                if long {
+                    show_profiles_details(entries)
+                } else {
+                    show_profiles_list(entries)
+                }
+            })?
+        }
+        Commands::Tag { command } => tag_profiles(command, &profiles, &project_directory)?,
+        Commands::Alias { command } => {
+            alias_profiles(command, &aliases, &profiles, &project_directory)?
+        }
+        Commands::Prompt { .. } => {}
+    }
+    if let Some((name, description)) = imported_description {
+        if profile_store.profile_path(&name).is_file() {
+            metadata::update(&project_directory, &name, |metadata| {
+                metadata.description = Some(description).filter(|text| !text.is_empty());
+            })?;
+        }
+    }
+    if let Some(registered_before) = registered_before {
+        let mut imported: Vec<String> = profile_store
+            .profiles()?
+            .into_keys()
+            .filter(|name| !registered_before.contains(name))
+            .collect();
+        imported.sort();
+        for name in imported {
+            run_hook(Hook::PostImport, &name, None, &config)?;
+        }
+    }
+    if changes_registry {
+        if let Err(error) = git::commit(&project_directory) {
+            output::warning(format!(
+                "Couldn't commit the changes to the store: {error:#}"
+            ));
+        }
     }
     Ok(())
 }
 
 /// Switch an old credentials files with a new profile
 fn switch_profile(
-    terraform_directory: &PathBuf,
+    terraform_directory: &Path,
+    project_directory: &Path,
     profiles: &HashMap<String, PathBuf>,
     name: String,
+    config: &config::Config,
 ) -> Result<(), anyhow::Error> {
-    let credentials_files = terraform_directory.join("credentials.tfrc.json");
-    let profile_path = if let Some(profile_path) = profiles.get(&name) {
-        profile_path
-    } else {
-        eprintln!("Couldn't find the profile to switch with.");
-        std::process::exit(1);
-    };
-    if credentials_files.exists() {
-        if credentials_files.is_symlink() {
-            std::fs::remove_file(&credentials_files)?;
-            symlink_credentials(profile_path, credentials_files)?;
-            println!("Switched credentials with the new profile");
-        } else {
-            eprintln!("A non-profile credentials already exists. This is a destructive operation, you should import or delete it first.");
-            std::process::exit(1);
+    find_profile(&name, profiles)?;
+    let switcher = Switcher::new(
+        ProfileStore::open(project_directory),
+        CredentialsFile::new(terraform_directory),
+        config.mode,
+    );
+    let previous = switcher.active()?;
+    run_hook(Hook::PreSwitch, &name, previous.as_deref(), config)?;
+    switcher.switch(&name)?;
+    output::notice("Switched credentials with the new profile");
+    warn_expiring_tokens(project_directory, &name, &profiles[&name], None);
+    run_hook(Hook::PostSwitch, &name, previous.as_deref(), config)
+}
+
+/// Switch the credentials of a single hostname to those of a profile, keeping the others
+fn switch_host(
+    terraform_directory: &Path,
+    project_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+    name: String,
+    hostname: &str,
+    config: &config::Config,
+) -> Result<()> {
+    find_profile(&name, profiles)?;
+    let switcher = Switcher::new(
+        ProfileStore::open(project_directory),
+        CredentialsFile::new(terraform_directory),
+        config.mode,
+    );
+    let previous = switcher.active()?;
+    run_hook(Hook::PreSwitch, &name, previous.as_deref(), config)?;
+    let host = switcher.switch_host(&name, hostname)?;
+    output::notice(format!(
+        "Switched the `{host}` credentials to profile `{name}`"
+    ));
+    warn_expiring_tokens(project_directory, &name, &profiles[&name], Some(&host));
+    run_hook(Hook::PostSwitch, &name, previous.as_deref(), config)
+}
+
+/// Warn about the tokens of a switched to profile that expire soon, only those of a hostname
+/// when given, never failing the switch that already happened
+fn warn_expiring_tokens(
+    project_directory: &Path,
+    name: &str,
+    profile_path: &Path,
+    hostname: Option<&str>,
+) {
+    match expiry::refresh_profile(project_directory, name, profile_path) {
+        Ok(mut expiries) => {
+            expiries.retain(|host, _| hostname.is_none_or(|hostname| host == hostname));
+            expiry::warn(name, &expiries);
         }
-    } else {
-        symlink_credentials(profile_path, credentials_files)?;
-        println!("Switched credentials with the new profile");
+        Err(error) => tracing::debug!("Couldn't check the expiration of the tokens: {error:#}"),
     }
-    Ok(())
 }
 
-/// Symlink credentials with new profiles credentials depending on platform
-fn symlink_credentials(
-    profile_path: &PathBuf,
-    credentials_files: PathBuf,
-) -> Result<(), anyhow::Error> {
-    #[cfg(target_family = "windows")]
-    std::os::windows::fs::symlink_file(profile_path, credentials_files)?;
-    #[cfg(target_family = "unix")]
-    std::os::unix::fs::symlink(profile_path, credentials_files)?;
+/// Run a configured hook, if any, failing when it does
+fn run_hook(hook: Hook, name: &str, previous: Option<&str>, config: &config::Config) -> Result<()> {
+    if let Some(mut command) = config.hooks.command(hook, name, previous) {
+        tracing::info!("Running the {} hook {command:?}", hook.name());
+        let status = command
+            .status()
+            .with_context(|| format!("Couldn't run the {} hook", hook.name()))?;
+        if !status.success() {
+            anyhow::bail!("The {} hook exited with {status}", hook.name());
+        }
+    }
     Ok(())
 }
 
-/// Import a new profile into the registry
-fn import_profile(
-    name: String,
-    terraform_directory: &PathBuf,
+/// Get the profile to switch to, `-` meaning the previous one, asking for it or falling back to
+/// the default one when no name is given
+fn switch_target(
+    name: Option<String>,
+    terraform_directory: &Path,
+    project_directory: &Path,
     profiles: &HashMap<String, PathBuf>,
-    project_directory: PathBuf,
-) -> Result<()> {
-    let credentials_files = terraform_directory.join("credentials.tfrc.json");
-
-    if credentials_files.is_symlink() {
-        let link = credentials_files.read_link()?;
-        if let Some(key) = get_profile_name_for_path(link, profiles) {
-            eprintln!("The profile is already imported under `{key}`");
-            std::process::exit(1)
-        } else {
-            eprintln!("The profile is an unknown symbolic link.");
-            std::process::exit(1)
+    config: &config::Config,
+) -> Result<String> {
+    match name {
+        Some(name) if name == "-" => {
+            match state::State::load(project_directory)?.previous_profile {
+                Some(previous) => Ok(previous),
+                None => anyhow::bail!("No previous profile to switch back to."),
+            }
         }
-    } else {
-        let new_path = project_directory.join(format!("{name}.tfrc.json"));
-        std::fs::rename(credentials_files, new_path)?;
-        println!("The terraform cloud profile was safely registered");
+        Some(name) => Ok(name),
+        None => pick_profile(
+            terraform_directory,
+            project_directory,
+            profiles,
+            config.default_profile.as_ref(),
+        ),
     }
-    Ok(())
 }
 
-/// Get profile name for path
-fn get_profile_name_for_path<P: AsRef<Path>>(
-    path: P,
-    profiles: &HashMap<String, PathBuf>,
-) -> Option<&String> {
-    for (key, value) in profiles {
-        if value == path.as_ref() {
-            return Some(key);
+/// Tell whether the unmanaged credentials were registered as a new profile, and add it to the
+/// profiles
+fn report_adopted(name: &str, adopted: Option<PathBuf>, profiles: &mut HashMap<String, PathBuf>) {
+    match adopted {
+        Some(profile_path) => {
+            output::notice(format!("Registered the current credentials as `{name}`"));
+            profiles.insert(name.to_string(), profile_path);
         }
+        None => output::notice("There are no unmanaged credentials to adopt"),
     }
-    None
 }
 
-/// Show the current profile status
-fn show_profile_status<P: AsRef<Path>>(
-    path: P,
+/// Re-point credentials linking to a missing profile to the registered one with the same file
+/// name, or else remove them
+fn repair_credentials(
+    terraform_directory: &Path,
     profiles: &HashMap<String, PathBuf>,
-) -> Result<(), anyhow::Error> {
-    let credentials_files = path.as_ref().join("credentials.tfrc.json");
-    if credentials_files.is_symlink() {
-        let link = credentials_files.read_link()?;
+) -> Result<()> {
+    print_repair(credentials::repair(terraform_directory, profiles)?);
+    Ok(())
+}
 
-        if let Some(key) = get_profile_name_for_path(link, profiles) {
-            println!("{key}");
-        } else {
-            eprintln!("No profile is currently in use.");
-            std::process::exit(1);
+/// Tell what repairing the credentials did
+fn print_repair(repair: Option<credentials::Repair>) {
+    match repair {
+        Some(credentials::Repair::Relinked(name)) => {
+            output::notice(format!("Re-pointed the credentials to profile `{name}`"))
         }
-    } else {
-        eprintln!("No profile is currently in use.");
-        std::process::exit(1);
+        Some(credentials::Repair::Removed) => output::notice(
+            "Removed the credentials, as no registered profile matches the missing one",
+        ),
+        None => output::notice("The credentials don't need any repair"),
     }
+}
+
+/// Pin a registered profile for the current directory
+fn use_profile(name: String, profiles: &HashMap<String, PathBuf>) -> Result<()> {
+    find_profile(&name, profiles)?;
+    let pin_file = pin::pin_profile(&std::env::current_dir()?, &name)?;
+    output::notice(format!("Pinned profile `{name}` in {}", pin_file.display()));
     Ok(())
 }
 
-/// Show the different profiles list
-fn show_profiles_list(profiles: &HashMap<String, PathBuf>) {
+/// Switch to the profile pinned for the current directory, or to the only one holding the
+/// hostname of its terraform configuration, doing nothing when there is none
+fn auto_switch_profile(
+    terraform_directory: &Path,
+    project_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+    config: &config::Config,
+) -> Result<()> {
+    let current_directory = std::env::current_dir()?;
+    let (name, pin_file) = match pin::find_pinned_profile(&current_directory)? {
+        Some(pinned) => pinned,
+        None => {
+            return auto_switch_detected_profile(
+                &current_directory,
+                terraform_directory,
+                project_directory,
+                profiles,
+                config,
+            )
+        }
+    };
+    find_profile(&name, profiles).with_context(|| {
+        format!(
+            "The profile pinned in {} isn't registered",
+            pin_file.display()
+        )
+    })?;
+    if get_active_profile(terraform_directory, project_directory, profiles)? != Some(&name) {
+        switch_profile(
+            terraform_directory,
+            project_directory,
+            profiles,
+            name,
+            config,
+        )?;
+    }
+    Ok(())
+}
+
+/// Switch to the only profile holding the hostname of the configuration in a directory,
+/// unless the active profile already holds it
+fn auto_switch_detected_profile(
+    directory: &Path,
+    terraform_directory: &Path,
+    project_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+    config: &config::Config,
+) -> Result<()> {
+    // Configurations that can't be parsed are ignored, as this runs on every `cd`
+    let target = match detect::detect_cloud_target(directory) {
+        Ok(Some(target)) => target,
+        _ => return Ok(()),
+    };
+    let candidates = profiles_for_hostname(&target.hostname, profiles);
+    if let Some(active) = get_active_profile(terraform_directory, project_directory, profiles)? {
+        if candidates.contains(active) {
+            return Ok(());
+        }
+    }
+    if let [name] = candidates.as_slice() {
+        switch_profile(
+            terraform_directory,
+            project_directory,
+            profiles,
+            name.clone(),
+            config,
+        )?;
+    }
+    Ok(())
+}
+
+/// Cloud target of a configuration along with the profiles holding credentials for it
+#[derive(serde::Serialize, Debug)]
+struct Detection {
+    /// Hostname of the terraform cloud or enterprise instance
+    hostname: String,
+    /// Organization owning the workspaces, when set in the configuration
+    organization: Option<String>,
+    /// Sorted names of the profiles holding credentials for the hostname
+    profiles: Vec<String>,
+}
+
+/// Show the cloud target of the configuration in the current directory and the matching profiles
+fn detect_profile(
+    switch: bool,
+    output: Output,
+    terraform_directory: &Path,
+    project_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+    config: &config::Config,
+) -> Result<()> {
+    let target = match detect::detect_cloud_target(&std::env::current_dir()?)? {
+        Some(target) => target,
+        None => {
+            anyhow::bail!(
+                "No `cloud` or `remote` backend block was found in the current directory."
+            )
+        }
+    };
+    let detection = Detection {
+        profiles: profiles_for_hostname(&target.hostname, profiles),
+        hostname: target.hostname,
+        organization: target.organization,
+    };
+    output.print(&detection, |detection| {
+        println!("hostname: {}", detection.hostname);
+        if let Some(organization) = &detection.organization {
+            println!("organization: {organization}");
+        }
+        if !switch {
+            match detection.profiles.as_slice() {
+                [] => {}
+                [name] => println!("Matching profile: {name}"),
+                names => println!("Matching profiles: {}", names.join(", ")),
+            }
+        }
+        Ok(())
+    })?;
+
+    match detection.profiles.as_slice() {
+        [] => anyhow::bail!("No profile holds credentials for {}.", detection.hostname),
+        [name] if switch => switch_profile(
+            terraform_directory,
+            project_directory,
+            profiles,
+            name.clone(),
+            config,
+        )?,
+        _ if switch => anyhow::bail!(
+            "Several profiles hold credentials for {}: {}",
+            detection.hostname,
+            detection.profiles.join(", ")
+        ),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Get the sorted names of the profiles holding credentials for a hostname
+fn profiles_for_hostname(hostname: &str, profiles: &HashMap<String, PathBuf>) -> Vec<String> {
+    let mut names: Vec<String> = profiles
+        .iter()
+        .filter(|(_, path)| {
+            store::read_profile_hostnames(path)
+                .map(|hostnames| hostnames.iter().any(|host| host == hostname))
+                .unwrap_or(false)
+        })
+        .map(|(name, _)| name.clone())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Install the latest release, or the one of a tag, in place of the running binary, once its
+/// checksum and, when a signing key is configured, its signature are checked
+fn self_update(check: bool, tag: Option<&str>, config: &config::Config) -> Result<()> {
+    update::remove_leftover();
+    let release = update::fetch_release(tag)?;
+    let version = release.version()?;
+    let current = update::current_version()?;
+    if tag.is_none() && version <= current {
+        output::notice(format!("terraform-profile {current} is the latest release"));
+        return Ok(());
+    }
+    if check {
+        output::notice(format!(
+            "terraform-profile {version} is available, run `self-update` to install it in place of {current}"
+        ));
+        return Ok(());
+    }
+
+    let archive = release.archive()?;
+    let scratch = tempfile::tempdir()?;
+    output::notice(format!("Downloading {}", archive.name));
+    let path = update::download(archive, scratch.path())?;
+    update::verify_checksum(&release, archive, &path)?;
+    match &config.update_signing_key {
+        Some(fingerprint) => update::verify_signature(&release, archive, &path, fingerprint)?,
+        None => output::warning(
+            "Only the checksum of the release was checked, set `update_signing_key` to check its signature",
+        ),
+    }
+    let binary = update::extract(&path, scratch.path())?;
+    let executable = update::replace_executable(&binary)?;
+    output::notice(format!(
+        "Updated {} from {current} to {version}",
+        executable.display()
+    ));
+    Ok(())
+}
+
+/// Let the user pick a profile in an interactive, filterable list
+///
+/// Falls back to the configured default profile when the CLI can't prompt
+fn pick_profile(
+    terraform_directory: &Path,
+    project_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+    default_profile: Option<&String>,
+) -> Result<String> {
+    if profiles.is_empty() {
+        return Err(error::CliError::NoProfiles.into());
+    }
+    if !output::is_interactive() {
+        return default_profile.cloned().context(
+            "No profile name was given, no default profile is configured and the CLI can't prompt.",
+        );
+    }
+    let active = get_active_profile(terraform_directory, project_directory, profiles)?;
+    let mut names: Vec<&String> = profiles.keys().collect();
+    names.sort();
+
+    let items: Vec<String> = names
+        .iter()
+        .map(|name| {
+            if Some(*name) == active {
+                format!("{name} (active)")
+            } else {
+                name.to_string()
+            }
+        })
+        .collect();
+    let default = names
+        .iter()
+        .position(|name| Some(*name) == active)
+        .unwrap_or_default();
+
+    let selection = dialoguer::FuzzySelect::new()
+        .with_prompt("Switch to profile")
+        .items(&items)
+        .default(default)
+        .interact_opt()?;
+    selection
+        .map(|index| names[index].clone())
+        .context("No profile was picked")
+}
+
+/// Pick the profile to switch to among those with some tags, asking unless only one has them
+fn pick_tagged_profile(
+    tags: &[String],
+    terraform_directory: &Path,
+    project_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+    config: &config::Config,
+) -> Result<String> {
+    let notes = metadata::Metadata::load(project_directory)?;
+    let tagged: HashMap<String, PathBuf> = profiles
+        .iter()
+        .filter(|(name, _)| notes.get(name).has_tags(tags))
+        .map(|(name, path)| (name.clone(), path.clone()))
+        .collect();
+    if tagged.len() == 1 {
+        return Ok(tagged.into_keys().next().unwrap_or_default());
+    }
+    if tagged.is_empty() {
+        anyhow::bail!("No profile is tagged {}", describe_tags(tags));
+    }
+    let default_profile = config
+        .default_profile
+        .as_ref()
+        .filter(|name| tagged.contains_key(*name));
+    pick_profile(
+        terraform_directory,
+        project_directory,
+        &tagged,
+        default_profile,
+    )
+}
+
+/// Name some tags for a message, e.g. `prod` and `client-a`
+fn describe_tags(tags: &[String]) -> String {
+    tags.iter()
+        .map(|tag| format!("`{tag}`"))
+        .collect::<Vec<_>>()
+        .join(" and ")
+}
+
+/// Ask the user to confirm an operation, accepting it with `--yes` but refusing to guess when the
+/// terminal isn't interactive
+fn confirm(prompt: &str) -> Result<bool> {
+    if output::assumes_yes() {
+        output::notice(format!("{prompt} Yes, from `--yes`"));
+        return Ok(true);
+    }
+    if !output::is_interactive() {
+        anyhow::bail!(
+            "Confirmations are enabled but the terminal isn't interactive, pass `--yes` to accept them."
+        );
+    }
+    Ok(dialoguer::Confirm::new()
+        .with_prompt(prompt)
+        .default(false)
+        .interact()?)
+}
+
+/// Import a new profile into the registry, moving the credentials or, when asked to, copying
+/// them and putting the new profile in their place
+fn import_profile(
+    name: String,
+    copy: bool,
+    terraform_directory: &Path,
+    project_directory: &Path,
+    config: &config::Config,
+) -> Result<()> {
+    let switcher = Switcher::new(
+        ProfileStore::open(project_directory),
+        CredentialsFile::new(terraform_directory),
+        config.mode,
+    );
+    import::current(&switcher, &name, copy)?;
+    if copy {
+        output::notice("The terraform cloud profile was safely registered and is in use");
+    } else {
+        output::notice("The terraform cloud profile was safely registered");
+    }
+    Ok(())
+}
+
+/// Create a profile holding a single token, read from stdin when given as `-` so it stays out of
+/// the shell history
+fn import_profile_token(
+    name: String,
+    hostname: &str,
+    token: String,
+    profiles: &HashMap<String, PathBuf>,
+    project_directory: &Path,
+) -> Result<()> {
+    validate_new_profile_name(&name, profiles)?;
+    let token = if token != "-" {
+        token
+    } else if output::is_interactive() {
+        dialoguer::Password::new()
+            .with_prompt(format!("Token for {hostname}"))
+            .interact()?
+    } else if std::io::stdin().is_terminal() {
+        anyhow::bail!("The CLI can't prompt, pipe the token into `--token -` instead.");
+    } else {
+        let mut token = String::new();
+        std::io::stdin().read_line(&mut token)?;
+        token.trim().to_string()
+    };
+    import::token(
+        &ProfileStore::open(project_directory),
+        &name,
+        hostname,
+        &token,
+    )?;
+    output::notice("The terraform cloud profile was safely registered");
+    Ok(())
+}
+
+/// Import every credentials file of a directory as the profile named after it, telling which
+/// ones were registered and which ones couldn't be, and exiting with 1 when some couldn't
+fn import_profile_directory(directory: &Path, project_directory: &Path) -> Result<()> {
+    let files = import::directory(&ProfileStore::open(project_directory), directory)?;
+    let mut failures = 0;
+    for file in &files {
+        match &file.registered {
+            Ok(_) => output::notice(format!(
+                "Registered `{}` from {}",
+                file.name,
+                file.path.display()
+            )),
+            Err(error) => {
+                failures += 1;
+                output::warning(format!(
+                    "Couldn't import {}: {error:#}",
+                    file.path.display()
+                ));
+            }
+        }
+    }
+    output::notice(format!(
+        "Imported {} of {} files",
+        files.len() - failures,
+        files.len()
+    ));
+    if failures > 0 {
+        return Err(error::CliError::Exit(error::GENERAL_EXIT_CODE).into());
+    }
+    Ok(())
+}
+
+/// Check that a profile name can be used for a new profile
+fn validate_new_profile_name(name: &str, profiles: &HashMap<String, PathBuf>) -> Result<()> {
+    validate_profile_name(name)?;
+    if profiles.contains_key(name) {
+        return Err(error::CliError::ProfileExists(name.to_string()).into());
+    }
+    Ok(())
+}
+
+/// Rename a registered profile, re-pointing the credentials if the profile is in use
+fn rename_profile(
+    name: String,
+    new_name: String,
+    terraform_directory: &Path,
+    project_directory: &Path,
+) -> Result<()> {
+    ProfileStore::open(project_directory).rename(&name, &new_name, terraform_directory)?;
+    output::notice(format!("Renamed profile `{name}` to `{new_name}`"));
+    Ok(())
+}
+
+/// Duplicate a registered profile under a new name
+fn copy_profile(
+    name: String,
+    new_name: String,
+    edit: bool,
+    project_directory: &Path,
+) -> Result<()> {
+    let new_path = ProfileStore::open(project_directory).copy(&name, &new_name)?;
+    output::notice(format!("Copied profile `{name}` to `{new_name}`"));
+
+    if edit {
+        edit_profile_file(&new_path)?;
+    }
+    Ok(())
+}
+
+/// Register a new profile holding the credentials of every hostname of several profiles,
+/// keeping the sources and Vault references as they are
+fn merge_profiles(
+    names: &[String],
+    into: String,
+    conflicts: MergeConflicts,
+    profiles: &HashMap<String, PathBuf>,
+    project_directory: &Path,
+) -> Result<()> {
+    validate_new_profile_name(&into, profiles)?;
+    ProfileStore::open(project_directory).merge(
+        names,
+        &into,
+        |host, kept, other| match conflicts {
+            MergeConflicts::Fail => Ok(None),
+            MergeConflicts::First => Ok(Some(false)),
+            MergeConflicts::Last => Ok(Some(true)),
+            MergeConflicts::Prompt => {
+                if !output::is_interactive() {
+                    anyhow::bail!(
+                        "The CLI can't prompt, pick the credentials to keep with \
+                         `--conflicts first` or `--conflicts last`."
+                    );
+                }
+                let choices = [kept, other];
+                let choice = dialoguer::Select::new()
+                    .with_prompt(format!(
+                        "Profiles `{kept}` and `{other}` hold different credentials for {host}, \
+                         keep those of"
+                    ))
+                    .items(&choices)
+                    .default(0)
+                    .interact()?;
+                Ok(Some(choice == 1))
+            }
+        },
+    )?;
+    output::notice(format!(
+        "Merged {} into `{into}`",
+        names
+            .iter()
+            .map(|name| format!("`{name}`"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    ));
+    Ok(())
+}
+
+/// Edit a registered profile through a temporary copy, updating the credentials made from it
+fn edit_profile(
+    name: String,
+    terraform_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+    project_directory: &Path,
+) -> Result<()> {
+    let profile_path = find_profile(&name, profiles)?;
+    edit_profile_file(profile_path)?;
+    credentials::refresh(&name, profile_path, terraform_directory, project_directory)
+}
+
+/// Edit a temporary copy of a profile file, writing it back only once it validates
+fn edit_profile_file(profile_path: &Path) -> Result<()> {
+    let original = encryption::decrypt_profile(profile_path)?;
+
+    let mut scratch = tempfile::Builder::new()
+        .prefix("terraform-profile-")
+        .suffix(".tfrc.json")
+        .tempfile()?;
+    scratch.write_all(original.as_bytes())?;
+    scratch.flush()?;
+
+    open_in_editor(scratch.path())?;
+
+    let edited = std::fs::read_to_string(scratch.path())?;
+    if edited == original {
+        output::notice("No changes were made to the profile");
+        return Ok(());
+    }
+    validate_credentials(&edited).context("The edited profile was discarded")?;
+
+    store::write_file_atomically(profile_path, encryption::reseal(profile_path, &edited)?)?;
+    output::notice("The profile was safely updated");
+    Ok(())
+}
+
+/// Open a file in the user's `$VISUAL` or `$EDITOR`, waiting for it to exit
+fn open_in_editor(path: &Path) -> Result<()> {
+    if !output::is_interactive() {
+        anyhow::bail!("The CLI can't open an editor without an interactive terminal.");
+    }
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"));
+    let status = std::process::Command::new(&editor)
+        .arg(path)
+        .status()
+        .with_context(|| format!("Couldn't launch editor `{editor}`"))?;
+    if !status.success() {
+        anyhow::bail!("Editor `{editor}` exited with {status}");
+    }
+    Ok(())
+}
+
+/// Pretty-print the credentials of a profile, redacting tokens unless asked not to, after what is
+/// noted about it
+fn show_profile(
+    name: String,
+    reveal: bool,
+    output: Output,
+    profiles: &HashMap<String, PathBuf>,
+    project_directory: &Path,
+) -> Result<()> {
+    let profile_path = find_profile(&name, profiles)?;
+    let noted = metadata::Metadata::load(project_directory)?.get(&name);
+    if let Some(description) = &noted.description {
+        output::notice(description);
+    }
+    for (key, value) in &noted.fields {
+        output::notice(format!("{key}: {value}"));
+    }
+    for (key, value) in &noted.env {
+        output::notice(format!("{key}={value}"));
+    }
+    let content = encryption::read_profile(profile_path)?;
+    let mut credentials: serde_json::Value = serde_json::from_str(&content)
+        .with_context(|| format!("The profile `{name}` isn't valid JSON"))?;
+
+    if !reveal {
+        replace_tokens(&mut credentials, &redact_token);
+    }
+    output.print(&credentials, |credentials| {
+        println!("{}", serde_json::to_string_pretty(credentials)?);
+        Ok(())
+    })
+}
+
+/// Split a `KEY=VALUE` argument
+fn parse_field(argument: &str) -> Result<(String, String)> {
+    let (key, value) = argument
+        .split_once('=')
+        .with_context(|| format!("`{argument}` isn't `KEY=VALUE`"))?;
+    if key.is_empty() {
+        anyhow::bail!("`{argument}` has no key");
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Change the description of a profile, the values noted about it and its environment variables,
+/// forgetting the empty ones
+fn annotate_profile(
+    name: String,
+    fields: Vec<(String, String)>,
+    description: Option<String>,
+    env: Vec<(String, String)>,
+    profiles: &HashMap<String, PathBuf>,
+    project_directory: &Path,
+) -> Result<()> {
+    find_profile(&name, profiles)?;
+    for (key, _) in &env {
+        metadata::validate_env_var(key)?;
+    }
+    metadata::update(project_directory, &name, |metadata| {
+        if let Some(description) = description {
+            metadata.description = Some(description).filter(|text| !text.is_empty());
+        }
+        for (key, value) in fields {
+            if value.is_empty() {
+                metadata.fields.remove(&key);
+            } else {
+                metadata.fields.insert(key, value);
+            }
+        }
+        for (key, value) in env {
+            if value.is_empty() {
+                metadata.env.remove(&key);
+            } else {
+                metadata.env.insert(key, value);
+            }
+        }
+    })?;
+    output::notice(format!("Annotated profile `{name}`"));
+    Ok(())
+}
+
+/// Add or remove the tags of a profile, or list them
+fn tag_profiles(
+    command: TagCommand,
+    profiles: &HashMap<String, PathBuf>,
+    project_directory: &Path,
+) -> Result<()> {
+    match command {
+        TagCommand::Add { name, tags } => {
+            find_profile(&name, profiles)?;
+            for tag in &tags {
+                metadata::validate_tag(tag)?;
+            }
+            metadata::update(project_directory, &name, |metadata| {
+                metadata.tags.extend(tags.iter().cloned());
+            })?;
+            output::notice(format!("Tagged profile `{name}` {}", describe_tags(&tags)));
+        }
+        TagCommand::Remove { name, tags } => {
+            find_profile(&name, profiles)?;
+            metadata::update(project_directory, &name, |metadata| {
+                metadata.tags.retain(|tag| !tags.contains(tag));
+            })?;
+            output::notice(format!(
+                "Removed the tags {} of profile `{name}`",
+                describe_tags(&tags)
+            ));
+        }
+        TagCommand::List => {
+            let mut tagged: BTreeMap<String, Vec<&String>> = BTreeMap::new();
+            let notes = metadata::Metadata::load(project_directory)?;
+            for (name, noted) in &notes.profiles {
+                if !profiles.contains_key(name) {
+                    continue;
+                }
+                for tag in &noted.tags {
+                    tagged.entry(tag.clone()).or_default().push(name);
+                }
+            }
+            if tagged.is_empty() {
+                output::notice("No profile is tagged");
+            }
+            for (tag, names) in tagged {
+                println!(
+                    "{tag}\t{}",
+                    names
+                        .iter()
+                        .map(|name| name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Set the default profile in the configuration file, remove it, or print it
+fn set_default_profile(
+    command: DefaultCommand,
+    home_dir: &Path,
+    config: &config::Config,
+    profiles: &HashMap<String, PathBuf>,
+) -> Result<()> {
+    let profile = match command {
+        DefaultCommand::Show => {
+            match &config.default_profile {
+                Some(name) => println!("{name}"),
+                None => output::notice("No default profile is set"),
+            }
+            return Ok(());
+        }
+        DefaultCommand::Set { name } => {
+            find_profile(&name, profiles)?;
+            Some(name)
+        }
+        DefaultCommand::Unset => None,
+    };
+
+    let config_file = paths::config_file(home_dir);
+    let config_content = match std::fs::read_to_string(&config_file) {
+        Ok(content) => content,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(error) => {
+            return Err(error).with_context(|| format!("Couldn't read {}", config_file.display()))
+        }
+    };
+    if let Some(directory) = config_file.parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+    store::write_through_link(
+        &config_file,
+        config::set_default_profile(&config_content, profile.as_deref()),
+    )?;
+    match profile {
+        Some(name) => output::notice(format!("`{name}` is now the default profile")),
+        None => output::notice("There is no default profile anymore"),
+    }
+    Ok(())
+}
+
+/// Set or remove the aliases of the profiles, or list them
+fn alias_profiles(
+    command: AliasCommand,
+    aliases: &BTreeMap<String, String>,
+    profiles: &HashMap<String, PathBuf>,
+    project_directory: &Path,
+) -> Result<()> {
+    match command {
+        AliasCommand::Set { alias, name } => {
+            find_profile(&name, profiles)?;
+            validate_profile_name(&alias)?;
+            if profiles.contains_key(&alias) {
+                anyhow::bail!("`{alias}` is already the name of a profile");
+            }
+            let mut metadata = metadata::Metadata::load(project_directory)?;
+            metadata.aliases.insert(alias.clone(), name.clone());
+            metadata.save(project_directory)?;
+            output::notice(format!("`{alias}` now stands for profile `{name}`"));
+        }
+        AliasCommand::Remove { alias } => {
+            let mut metadata = metadata::Metadata::load(project_directory)?;
+            if metadata.aliases.remove(&alias).is_none() {
+                if aliases.contains_key(&alias) {
+                    anyhow::bail!(
+                        "The alias `{alias}` is set in the configuration file, remove it there"
+                    );
+                }
+                anyhow::bail!("There is no alias `{alias}`");
+            }
+            metadata.save(project_directory)?;
+            output::notice(format!("Removed the alias `{alias}`"));
+        }
+        AliasCommand::List => {
+            if aliases.is_empty() {
+                output::notice("No alias is set");
+            }
+            for (alias, name) in aliases {
+                println!("{alias}\t{name}");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check a registered profile, or else a credentials file, pointing to the first problem
+fn validate_target(target: &str, profiles: &HashMap<String, PathBuf>) -> Result<()> {
+    let (label, content) = match profiles.get(target) {
+        Some(profile_path) => (
+            format!("Profile `{target}`"),
+            encryption::decrypt_profile(profile_path)?,
+        ),
+        None if Path::new(target).is_file() => (
+            target.to_string(),
+            std::fs::read_to_string(target).with_context(|| format!("Couldn't read {target}"))?,
+        ),
+        None => return Err(error::CliError::unknown_profile(target, profiles.keys()).into()),
+    };
+    let hostnames =
+        store::validate_new_credentials(&content).with_context(|| format!("{label} is invalid"))?;
+    output::notice(format!(
+        "{label} is valid, with credentials for {}",
+        hostnames.join(", ")
+    ));
+    Ok(())
+}
+
+/// How a hostname differs between two sets of credentials
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum HostChange {
+    /// Both hold the same token
+    Same,
+    /// Both hold a token, but not the same
+    Changed,
+    /// Only the first one holds a token
+    Removed,
+    /// Only the second one holds a token
+    Added,
+}
+
+/// Comparison of the token of a hostname, through fingerprints that don't reveal it
+#[derive(serde::Serialize, Debug)]
+struct HostDiff {
+    /// Hostname the tokens are for
+    hostname: String,
+    /// How the token differs
+    change: HostChange,
+    /// Fingerprint of the token in the first credentials
+    before: Option<String>,
+    /// Fingerprint of the token in the second credentials
+    after: Option<String>,
+}
+
+/// Compare a profile with another or with the live credentials, exiting with 1 when they differ
+fn diff_profiles(
+    name: String,
+    other: Option<String>,
+    output: Output,
+    terraform_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+) -> Result<()> {
+    let before = read_profile_tokens(find_profile(&name, profiles)?)?;
+    let (other_label, after) = match other {
+        Some(other) => {
+            let tokens = read_profile_tokens(find_profile(&other, profiles)?)?;
+            (format!("`{other}`"), tokens)
+        }
+        None => {
+            let credentials_files = credentials::path(terraform_directory);
+            if !credentials_files.exists() {
+                return Err(CredentialsFile::new(terraform_directory)
+                    .inactive_error()
+                    .into());
+            }
+            (
+                String::from("the credentials"),
+                read_profile_tokens(&credentials_files)?,
+            )
+        }
+    };
+    // Terraform matches the hostnames regardless of their case
+    let normalize = |tokens: BTreeMap<String, String>| -> BTreeMap<String, String> {
+        tokens
+            .into_iter()
+            .map(|(host, token)| (host.to_ascii_lowercase(), token))
+            .collect()
+    };
+    let (before, after) = (normalize(before), normalize(after));
+    let hostnames: std::collections::BTreeSet<&String> =
+        before.keys().chain(after.keys()).collect();
+    let diffs: Vec<HostDiff> = hostnames
+        .into_iter()
+        .map(|host| {
+            let (old, new) = (before.get(host), after.get(host));
+            HostDiff {
+                hostname: host.clone(),
+                change: match (old, new) {
+                    (Some(old), Some(new)) if old == new => HostChange::Same,
+                    (Some(_), Some(_)) => HostChange::Changed,
+                    (Some(_), None) => HostChange::Removed,
+                    _ => HostChange::Added,
+                },
+                before: old.map(|token| store::fingerprint(token)),
+                after: new.map(|token| store::fingerprint(token)),
+            }
+        })
+        .collect();
+
+    output.print(&diffs, |diffs| {
+        output::notice(format!("Comparing `{name}` with {other_label}"));
+        for diff in diffs {
+            let fingerprint =
+                |fingerprint: &Option<String>| fingerprint.clone().unwrap_or_default();
+            match diff.change {
+                HostChange::Same => {
+                    println!("  {}\t{}", diff.hostname, fingerprint(&diff.before))
+                }
+                HostChange::Changed => println!(
+                    "{}",
+                    console::style(format!(
+                        "~ {}\t{} -> {}",
+                        diff.hostname,
+                        fingerprint(&diff.before),
+                        fingerprint(&diff.after)
+                    ))
+                    .yellow()
+                ),
+                HostChange::Removed => println!(
+                    "{}",
+                    console::style(format!(
+                        "- {}\t{}",
+                        diff.hostname,
+                        fingerprint(&diff.before)
+                    ))
+                    .red()
+                ),
+                HostChange::Added => println!(
+                    "{}",
+                    console::style(format!("+ {}\t{}", diff.hostname, fingerprint(&diff.after)))
+                        .green()
+                ),
+            }
+        }
+        Ok(())
+    })?;
+    if diffs.iter().any(|diff| diff.change != HostChange::Same) {
+        return Err(error::CliError::Exit(1).into());
+    }
+    Ok(())
+}
+
+/// Write the credentials of a profile to a file readable by its owner only, or to stdout
+fn export_profile(
+    name: String,
+    to: Option<PathBuf>,
+    redact: bool,
+    profiles: &HashMap<String, PathBuf>,
+) -> Result<()> {
+    let profile_path = find_profile(&name, profiles)?;
+    let content = encryption::read_profile(profile_path)?;
+    let mut credentials: serde_json::Value = serde_json::from_str(&content)
+        .with_context(|| format!("The profile `{name}` isn't valid JSON"))?;
+
+    if redact {
+        replace_tokens(&mut credentials, &|_| String::new());
+    }
+    let exported = store::format_like(&content, &credentials)?;
+    let exported = exported.trim_end();
+    match to {
+        Some(path) => {
+            store::write_file_atomically(&path, format!("{exported}\n"))?;
+            permissions::restrict_file(&path)?;
+            output::notice(format!("Exported profile `{name}` to {}", path.display()));
+        }
+        None => println!("{exported}"),
+    }
+    Ok(())
+}
+
+/// Write the tokens of a profile to files for a CI job or tool to read
+fn render_profile(
+    name: String,
+    out: &Path,
+    terraformrc: Option<&Path>,
+    github_actions: bool,
+    terraform_directory: &Path,
+    project_directory: &Path,
+) -> Result<()> {
+    render::render(
+        &ProfileStore::open(project_directory),
+        &CredentialsFile::new(terraform_directory),
+        &name,
+        out,
+        terraformrc,
+        github_actions,
+    )?;
+    output::notice(format!("Rendered profile `{name}` to {}", out.display()));
+    if let Some(terraformrc) = terraformrc {
+        output::notice(format!(
+            "Rendered profile `{name}` to {}, for `TF_CLI_CONFIG_FILE`",
+            terraformrc.display()
+        ));
+    }
+    Ok(())
+}
+
+/// Write every profile to an archive readable by its owner only, encrypted unless told not to
+fn export_all_profiles(
+    out: &Path,
+    encrypt: bool,
+    profiles: &HashMap<String, PathBuf>,
+    project_directory: &Path,
+) -> Result<()> {
     if profiles.is_empty() {
-        eprintln!("No profiles is currently available");
-        std::process::exit(1);
+        return Err(error::CliError::NoProfiles.into());
+    }
+    let archive = archive::Archive::new(profiles, &state::State::load(project_directory)?)?;
+    let passphrase = if encrypt {
+        Some(archive::read_passphrase(true)?)
     } else {
-        println!("Currently available profiles:");
-        for profile in profiles.keys() {
-            println!("\t{profile}");
+        None
+    };
+    store::write_file_atomically(out, archive.to_bytes(passphrase)?)?;
+    permissions::restrict_file(out)?;
+    output::notice(format!(
+        "Exported {} profiles to {}",
+        archive.profiles.len(),
+        out.display()
+    ));
+    Ok(())
+}
+
+/// Register the profiles of an archive, checking all of them before registering any
+fn restore_profiles(
+    path: &Path,
+    conflicts: &archive::Conflicts,
+    terraform_directory: &Path,
+    project_directory: &Path,
+    config: &config::Config,
+) -> Result<()> {
+    let bytes = std::fs::read(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+    let archive = archive::Archive::from_bytes(&bytes, || archive::read_passphrase(false))
+        .with_context(|| format!("Couldn't restore {}", path.display()))?;
+    restore_archive(
+        archive,
+        &path.display().to_string(),
+        conflicts,
+        terraform_directory,
+        project_directory,
+        config,
+    )
+}
+
+/// Register the profiles of an archive read from somewhere, checking all of them before
+/// registering any
+fn restore_archive(
+    archive: archive::Archive,
+    origin: &str,
+    conflicts: &archive::Conflicts,
+    terraform_directory: &Path,
+    project_directory: &Path,
+    config: &config::Config,
+) -> Result<()> {
+    let restored = archive.restore(
+        &ProfileStore::open(project_directory),
+        &CredentialsFile::new(terraform_directory),
+        origin,
+        conflicts,
+        config.backup_retention,
+    )?;
+    print_restored(&restored, origin);
+    Ok(())
+}
+
+/// Tell how many profiles were restored from where, and how many were skipped
+fn print_restored(restored: &archive::Restored, origin: &str) {
+    output::notice(format!(
+        "Restored {} profiles from {origin}{}",
+        restored.names.len(),
+        if restored.skipped > 0 {
+            format!(", skipping {} already registered", restored.skipped)
+        } else {
+            String::new()
+        }
+    ));
+}
+
+/// Recursively replace every `token` value with the result of a function of it
+fn replace_tokens(value: &mut serde_json::Value, replace: &dyn Fn(&str) -> String) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                match value {
+                    serde_json::Value::String(token) if key == "token" => {
+                        *token = replace(token);
+                    }
+                    _ => replace_tokens(value, replace),
+                }
+            }
+        }
+        serde_json::Value::Array(values) => {
+            for value in values {
+                replace_tokens(value, replace);
+            }
         }
+        _ => {}
+    }
+}
+
+/// Redact a token, only keeping its `atlasv1.` marker and last four characters
+fn redact_token(token: &str) -> String {
+    let chars: Vec<char> = token.chars().collect();
+    if chars.len() <= 8 {
+        return String::from("****");
+    }
+    let marker = if token.contains("atlasv1.") {
+        "atlasv1."
+    } else {
+        ""
+    };
+    let suffix: String = chars[chars.len() - 4..].iter().collect();
+    format!("{marker}****{suffix}")
+}
+
+/// Outcome of verifying the token of a profile for a hostname
+#[derive(serde::Serialize, Debug)]
+struct TokenCheck {
+    /// Name of the profile holding the token
+    profile: String,
+    /// Hostname the token is for
+    hostname: String,
+    /// Status reported by the API, when it could be reached
+    status: Option<api::TokenStatus>,
+    /// Why the API couldn't report a status
+    error: Option<String>,
+}
+
+/// Verify the tokens of one or all profiles against their hostnames
+fn verify_profiles(
+    name: Option<String>,
+    all: bool,
+    output: Output,
+    profiles: &HashMap<String, PathBuf>,
+    project_directory: &Path,
+) -> Result<()> {
+    let mut selected: Vec<(&String, &PathBuf)> = if all {
+        profiles.iter().collect()
+    } else {
+        let name = name.unwrap_or_default();
+        match profiles.get_key_value(&name) {
+            Some(profile) => vec![profile],
+            None => return Err(error::CliError::unknown_profile(&name, profiles.keys()).into()),
+        }
+    };
+    selected.sort();
+
+    let mut state = state::State::load(project_directory)?;
+    let mut checks = Vec::new();
+    for (name, profile_path) in selected {
+        for (host, token) in read_profile_tokens(profile_path)? {
+            let (status, error) = match api::Client::new(&host, &token).token_status() {
+                Ok(status) => {
+                    state.record_token_status(name, &host, status);
+                    (Some(status), None)
+                }
+                Err(e) => (None, Some(format!("{e:#}"))),
+            };
+            checks.push(TokenCheck {
+                profile: name.clone(),
+                hostname: host,
+                status,
+                error,
+            });
+        }
+    }
+    state.save(project_directory)?;
+
+    output.print(&checks, |checks| {
+        let mut current_profile = None;
+        for check in checks {
+            if current_profile != Some(&check.profile) {
+                println!("{}:", check.profile);
+                current_profile = Some(&check.profile);
+            }
+            match (&check.status, &check.error) {
+                (Some(status), _) => {
+                    println!("\t{}: {}", check.hostname, token_status_style(*status))
+                }
+                (None, error) => println!(
+                    "\t{}: {}",
+                    check.hostname,
+                    console::style(format!("error ({})", error.as_deref().unwrap_or_default()))
+                        .red()
+                ),
+            }
+        }
+        Ok(())
+    })?;
+    if checks
+        .iter()
+        .any(|check| check.status != Some(api::TokenStatus::Valid))
+    {
+        return Err(error::CliError::InvalidTokens.into());
+    }
+    Ok(())
+}
+
+/// Terraform cloud account behind the token of a hostname
+#[derive(serde::Serialize, Debug)]
+struct Account {
+    /// Hostname the token is for
+    hostname: String,
+    /// Name of the user owning the token
+    username: Option<String>,
+    /// Email address of the user owning the token
+    email: Option<String>,
+    /// Whether the user enabled two-factor authentication
+    two_factor: bool,
+}
+
+/// Show the account details of every hostname in the active or given profile
+fn show_whoami(
+    name: Option<String>,
+    output: Output,
+    terraform_directory: &Path,
+    project_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+) -> Result<()> {
+    let profile_path = select_profile(name, terraform_directory, project_directory, profiles)?;
+
+    let mut accounts = Vec::new();
+    for (host, token) in read_profile_tokens(profile_path)? {
+        let details = api::Client::new(&host, &token).account_details()?;
+        let attributes = &details["data"]["attributes"];
+        accounts.push(Account {
+            hostname: host,
+            username: attributes["username"].as_str().map(String::from),
+            email: attributes["email"].as_str().map(String::from),
+            two_factor: attributes["two-factor"]["enabled"].as_bool() == Some(true),
+        });
+    }
+    output.print(&accounts, |accounts| {
+        for account in accounts {
+            println!("{}:", account.hostname);
+            println!("\tusername: {}", account.username.as_deref().unwrap_or("-"));
+            println!("\temail: {}", account.email.as_deref().unwrap_or("-"));
+            println!(
+                "\ttwo-factor: {}",
+                if account.two_factor {
+                    "enabled"
+                } else {
+                    "disabled"
+                }
+            );
+        }
+        Ok(())
+    })
+}
+
+/// Organizations visible through the token of a hostname
+#[derive(serde::Serialize, Debug)]
+struct HostOrganizations {
+    /// Hostname the token is for
+    hostname: String,
+    /// Organizations the token has access to
+    organizations: Vec<Organization>,
+}
+
+/// A terraform cloud organization along with its plan
+#[derive(serde::Serialize, Debug)]
+struct Organization {
+    /// Name of the organization
+    name: String,
+    /// Plan of the organization: `enterprise`, `trial` or `standard`
+    plan: &'static str,
+    /// Features the plan of the organization enables
+    entitlements: Vec<String>,
+}
+
+/// Show the organizations, with their plan and entitlements, of the active or given profile
+fn show_organizations(
+    name: Option<String>,
+    output: Output,
+    terraform_directory: &Path,
+    project_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+) -> Result<()> {
+    let profile_path = select_profile(name, terraform_directory, project_directory, profiles)?;
+
+    let mut hosts = Vec::new();
+    for (host, token) in read_profile_tokens(profile_path)? {
+        let client = api::Client::new(&host, &token);
+        let mut organizations = Vec::new();
+        for organization in client.organizations()? {
+            let attributes = &organization["attributes"];
+            let org_name = attributes["name"].as_str().unwrap_or_default();
+            let plan = if attributes["plan-is-enterprise"].as_bool() == Some(true) {
+                "enterprise"
+            } else if attributes["plan-is-trial"].as_bool() == Some(true) {
+                "trial"
+            } else {
+                "standard"
+            };
+
+            let entitlements = client.entitlements(org_name)?;
+            organizations.push(Organization {
+                name: org_name.to_string(),
+                plan,
+                entitlements: entitlements["data"]["attributes"]
+                    .as_object()
+                    .map(|entitlements| {
+                        entitlements
+                            .iter()
+                            .filter(|(_, value)| value.as_bool() == Some(true))
+                            .map(|(key, _)| key.clone())
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            });
+        }
+        hosts.push(HostOrganizations {
+            hostname: host,
+            organizations,
+        });
+    }
+    output.print(&hosts, |hosts| {
+        for host in hosts {
+            println!("{}:", host.hostname);
+            for organization in &host.organizations {
+                println!("\t{} ({})", organization.name, organization.plan);
+                println!("\t\tentitlements: {}", organization.entitlements.join(", "));
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Workspaces of an organization visible through the token of a hostname
+#[derive(serde::Serialize, Debug)]
+struct HostWorkspaces {
+    /// Hostname the token is for
+    hostname: String,
+    /// Workspaces of the organization
+    workspaces: Vec<Workspace>,
+}
+
+/// A terraform cloud workspace along with its current run
+#[derive(serde::Serialize, Debug)]
+struct Workspace {
+    /// Name of the workspace
+    name: String,
+    /// Where the runs of the workspace are executed
+    execution_mode: Option<String>,
+    /// Status of the current run of the workspace, if any
+    run_status: Option<String>,
+}
+
+/// Show the workspaces of an organization with their execution mode and current run status
+fn show_workspaces(
+    organization: String,
+    name: Option<String>,
+    output: Output,
+    terraform_directory: &Path,
+    project_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+) -> Result<()> {
+    let profile_path = select_profile(name, terraform_directory, project_directory, profiles)?;
+
+    let mut hosts = Vec::new();
+    for (host, token) in read_profile_tokens(profile_path)? {
+        let workspaces = api::Client::new(&host, &token).workspaces(&organization)?;
+        hosts.push(HostWorkspaces {
+            hostname: host,
+            workspaces: workspaces
+                .data
+                .iter()
+                .map(|workspace| {
+                    let attributes = &workspace["attributes"];
+                    Workspace {
+                        name: attributes["name"].as_str().unwrap_or_default().to_string(),
+                        execution_mode: attributes["execution-mode"].as_str().map(String::from),
+                        run_status: workspace["relationships"]["current-run"]["data"]["id"]
+                            .as_str()
+                            .and_then(|id| workspaces.find_included("runs", id))
+                            .and_then(|run| run["attributes"]["status"].as_str())
+                            .map(String::from),
+                    }
+                })
+                .collect(),
+        });
+    }
+    output.print(&hosts, |hosts| {
+        for host in hosts {
+            println!("{}:", host.hostname);
+            for workspace in &host.workspaces {
+                println!(
+                    "\t{}\t{}\t{}",
+                    workspace.name,
+                    workspace.execution_mode.as_deref().unwrap_or("-"),
+                    workspace.run_status.as_deref().unwrap_or("none"),
+                );
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Color a token status: green when valid, yellow when expired and red when refused
+fn token_status_style(status: api::TokenStatus) -> console::StyledObject<api::TokenStatus> {
+    let style = console::style(status);
+    match status {
+        api::TokenStatus::Valid => style.green(),
+        api::TokenStatus::Expired => style.yellow(),
+        api::TokenStatus::Unauthorized => style.red(),
+    }
+}
+
+/// Run a command with `TF_TOKEN_*` variables set from a profile
+fn exec_with_profile(
+    name: String,
+    command: Vec<String>,
+    github_actions: bool,
+    profiles: &HashMap<String, PathBuf>,
+    project_directory: &Path,
+) -> Result<()> {
+    let profile_path = find_profile(&name, profiles)?;
+    let (program, args) = command.split_first().context("No command to run")?;
+
+    tracing::info!("Running `{program}` with the tokens of `{name}`");
+    let mut child = std::process::Command::new(program);
+    child.args(args);
+    child.envs(metadata::Metadata::load(project_directory)?.get(&name).env);
+    for (host, token) in read_profile_tokens(profile_path)? {
+        if github_actions {
+            github::mask(&token);
+        }
+        child.env(token_env_var(&host), token);
+    }
+    let status = child
+        .status()
+        .with_context(|| format!("Couldn't run `{program}`"))?;
+    match status.code() {
+        Some(0) => Ok(()),
+        code => Err(error::CliError::Exit(code.unwrap_or(error::GENERAL_EXIT_CODE)).into()),
+    }
+}
+
+/// Print the environment variables and `TF_TOKEN_*` exports of a profile in the syntax of the
+/// given shell, or hand them to the next steps of a GitHub Actions job with the tokens masked
+fn print_profile_env(
+    name: String,
+    shell: Shell,
+    github_actions: bool,
+    profiles: &HashMap<String, PathBuf>,
+    project_directory: &Path,
+) -> Result<()> {
+    let profile_path = find_profile(&name, profiles)?;
+    let mut variables: Vec<(String, String)> = metadata::Metadata::load(project_directory)?
+        .get(&name)
+        .env
+        .into_iter()
+        .collect();
+    let tokens = read_profile_tokens(profile_path)?;
+    if github_actions {
+        tokens.values().for_each(|token| github::mask(token));
+    }
+    variables.extend(
+        tokens
+            .into_iter()
+            .map(|(host, token)| (token_env_var(&host), token)),
+    );
+    for (key, value) in &variables {
+        if github_actions {
+            github::export(key, value)?;
+        } else {
+            println!("{}", format_export(shell, key, value));
+        }
+    }
+    if github_actions {
+        output::notice(format!(
+            "Exported {} variables to the next steps of the job",
+            variables.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Format a single environment variable export for a shell
+fn format_export(shell: Shell, key: &str, value: &str) -> String {
+    match shell {
+        Shell::Bash | Shell::Zsh => format!("export {key}='{}'", value.replace('\'', "'\\''")),
+        Shell::Fish => format!(
+            "set -gx {key} '{}'",
+            value.replace('\\', "\\\\").replace('\'', "\\'")
+        ),
+        Shell::Powershell => format!("$Env:{key} = '{}'", value.replace('\'', "''")),
+    }
+}
+
+/// Name of the environment variable terraform reads the token of `host` from
+///
+/// Dots become underscores and dashes become double underscores, as described in
+/// <https://developer.hashicorp.com/terraform/cli/config/config-file#environment-variable-credentials>
+fn token_env_var(host: &str) -> String {
+    format!("TF_TOKEN_{}", host.replace('-', "__").replace('.', "_"))
+}
+
+/// Get the path of the given profile, or of the active one when no name is given
+fn select_profile<'a>(
+    name: Option<String>,
+    terraform_directory: &Path,
+    project_directory: &Path,
+    profiles: &'a HashMap<String, PathBuf>,
+) -> Result<&'a PathBuf> {
+    let name = match name {
+        Some(name) => name,
+        None => match get_active_profile(terraform_directory, project_directory, profiles)? {
+            Some(name) => name.clone(),
+            None => {
+                return Err(CredentialsFile::new(terraform_directory)
+                    .inactive_error()
+                    .into())
+            }
+        },
+    };
+    find_profile(&name, profiles)
+}
+
+/// Get the path of a registered profile
+fn find_profile<'a>(name: &str, profiles: &'a HashMap<String, PathBuf>) -> Result<&'a PathBuf> {
+    profiles
+        .get(name)
+        .ok_or_else(|| error::CliError::unknown_profile(name, profiles.keys()).into())
+}
+
+/// Whether a profile only in Vault has the name, failing to list them being only logged
+fn is_remote_profile(name: &str) -> bool {
+    if !vault::is_enabled() {
+        return false;
+    }
+    match vault::remote_profiles() {
+        Ok(names) => names.iter().any(|remote| remote == name),
+        Err(error) => {
+            tracing::debug!("Couldn't list the profiles in Vault: {error:#}");
+            false
+        }
+    }
+}
+
+/// Get the profile `TERRAFORM_PROFILE` names, to use for the current shell or job without
+/// switching
+fn profile_override() -> Option<String> {
+    std::env::var("TERRAFORM_PROFILE")
+        .ok()
+        .filter(|name| !name.is_empty())
+}
+
+/// Get the profile a command was given, or that `TERRAFORM_PROFILE` names
+fn given_profile(name: Option<String>) -> Result<String> {
+    name.context("No profile given, pass its name or set `TERRAFORM_PROFILE`")
+}
+
+/// Get the name of the profile the credentials currently point to or are an intact copy of
+fn get_active_profile<'a>(
+    terraform_directory: &Path,
+    project_directory: &Path,
+    profiles: &'a HashMap<String, PathBuf>,
+) -> Result<Option<&'a String>> {
+    Ok(
+        credentials::Credentials::inspect(terraform_directory, project_directory)?
+            .active_profile(profiles),
+    )
+}
+
+/// Run `terraform login` in an isolated home and register the obtained credentials
+fn login_profile(
+    name: String,
+    hostname: String,
+    profiles: &HashMap<String, PathBuf>,
+    project_directory: &Path,
+) -> Result<()> {
+    validate_new_profile_name(&name, profiles)?;
+    if !output::is_interactive() {
+        anyhow::bail!(
+            "`terraform login` needs an interactive terminal, import a token with `--token` instead."
+        );
+    }
+    let content = credentials::terraform_login(&hostname)?;
+    ProfileStore::open(project_directory).register(&name, &content)?;
+    output::notice("The terraform cloud profile was safely registered");
+    Ok(())
+}
+
+/// Remove a profile, revoking its tokens first when asked to
+fn logout_profile(
+    name: String,
+    revoke: bool,
+    token_ids: &[String],
+    terraform_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+    project_directory: &Path,
+    config: &config::Config,
+) -> Result<()> {
+    let profile_path = find_profile(&name, profiles)?;
+    if config.confirm && !confirm(&format!("Remove profile `{name}`?"))? {
+        return Ok(());
+    }
+
+    if revoke {
+        let state = state::State::load(project_directory)?;
+        let revocations =
+            revocation::plan(&state, &name, read_profile_tokens(profile_path)?, token_ids)
+                .context("The profile was kept")?;
+        if !confirm_revocations(&revocations)? {
+            return Ok(());
+        }
+        revocation::revoke(&revocations).context("The profile was kept")?;
+    }
+
+    ProfileStore::open(project_directory).remove(
+        &name,
+        &CredentialsFile::new(terraform_directory),
+        config.backup_retention,
+    )?;
+    output::notice(format!(
+        "Removed profile `{name}`, `undelete {name}` brings it back"
+    ));
+    Ok(())
+}
+
+/// Move the most recently removed profile with a name back to the store
+fn undelete_profile(
+    name: String,
+    profiles: &HashMap<String, PathBuf>,
+    project_directory: &Path,
+) -> Result<()> {
+    validate_new_profile_name(&name, profiles)?;
+    match trash::restore(project_directory, &name)? {
+        Some(profile_path) => {
+            permissions::restrict_file(&profile_path)?;
+            output::notice(format!("Brought back profile `{name}`"));
+            Ok(())
+        }
+        None => {
+            let trashed = trash::list(project_directory)?;
+            let mut trashed: Vec<String> =
+                trashed.into_iter().map(|profile| profile.name).collect();
+            trashed.sort();
+            trashed.dedup();
+            Err(error::CliError::unknown_profile(&name, &trashed).into())
+        }
+    }
+}
+
+/// Mint new tokens for every hostname of a profile, store them, then revoke the old ones that
+/// can be told apart from the other tokens of the account
+fn rotate_profile(
+    name: String,
+    token_ids: &[String],
+    terraform_directory: &Path,
+    project_directory: &Path,
+    config: &config::Config,
+) -> Result<()> {
+    let store = ProfileStore::open(project_directory);
+    rotation::ensure_stored(&store, &name)?;
+    if config.confirm && !confirm(&format!("Rotate the tokens of profile `{name}`?"))? {
+        return Ok(());
+    }
+    let rotated = rotation::rotate(
+        &store,
+        &CredentialsFile::new(terraform_directory),
+        &name,
+        token_ids,
+        config.backup_retention,
+    )?;
+    output::notice(format!("Stored the new tokens of profile `{name}`"));
+
+    for (host, guess) in rotated.unconfirmed {
+        output::warning(format!(
+            "The old {host} token is still valid, the CLI couldn't tell which of the account \
+             tokens it was. Revoke it from the {host} user settings{}, or pass its id with \
+             `--token-id` when rotating.",
+            guess.map_or_else(String::new, |guess| format!(", it is likely {guess}"))
+        ));
+    }
+    if !confirm_revocations(&rotated.revocations)? {
+        output::warning("The old tokens are still valid");
+        return Ok(());
+    }
+    revocation::revoke(&rotated.revocations).context("The new tokens were stored")
+}
+
+/// Show the tokens about to be revoked and ask before revoking them, which can't be undone
+fn confirm_revocations(revocations: &[revocation::Revocation]) -> Result<bool> {
+    if revocations.is_empty() {
+        return Ok(true);
+    }
+    for revocation in revocations {
+        output::notice(format!(
+            "The {} token is {}",
+            revocation.hostname, revocation.target
+        ));
+    }
+    confirm("Revoke these tokens?")
+}
+
+/// Fail unless the store is a git repository
+fn ensure_repository(project_directory: &Path) -> Result<()> {
+    if !git::is_repository(project_directory) {
+        anyhow::bail!(
+            "The store {} isn't a git repository, run `git init` in it",
+            project_directory.display()
+        );
+    }
+    Ok(())
+}
+
+/// Fetch the profiles committed on other machines, bringing the credentials up to date
+fn pull_profiles(terraform_directory: &Path, project_directory: &Path) -> Result<()> {
+    ensure_repository(project_directory)?;
+    git::commit(project_directory)?;
+    git::pull(project_directory)?;
+    for (name, profile_path) in ProfileStore::open(project_directory).profiles()? {
+        credentials::refresh(&name, &profile_path, terraform_directory, project_directory)?;
+    }
+    output::notice("Pulled the profiles");
+    Ok(())
+}
+
+/// Whether to keep the local version of a profile changed both here and on a remote
+fn keep_local(
+    local: &archive::ArchivedProfile,
+    remote: &archive::ArchivedProfile,
+    url: &str,
+    conflicts: SyncConflicts,
+) -> Result<bool> {
+    let message = format!(
+        "Profile `{}` was changed both here and on {url}",
+        local.name
+    );
+    match conflicts {
+        SyncConflicts::LastWriterWins => {
+            let keep = sync::written_last(local, remote);
+            output::warning(format!(
+                "{message}, keeping the {} version, written last",
+                if keep { "local" } else { "remote" }
+            ));
+            Ok(keep)
+        }
+        SyncConflicts::Prompt => confirm(&format!("{message}, keep the local version?")),
+    }
+}
+
+/// Upload an encrypted bundle of every profile to a remote, keeping the profiles changed there
+/// from other machines since the last sync
+fn push_bundle(remote: &str, conflicts: SyncConflicts, project_directory: &Path) -> Result<()> {
+    let pushed = sync::push(
+        &ProfileStore::open(project_directory),
+        remote,
+        archive::read_passphrase,
+        |local, remote_profile| keep_local(local, remote_profile, remote, conflicts),
+    )?;
+    if !pushed.kept.is_empty() {
+        output::warning(format!(
+            "Kept the versions of {} on {remote}, `sync pull --remote {remote}` fetches them",
+            pushed
+                .kept
+                .iter()
+                .map(|name| format!("`{name}`"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    output::notice(format!("Pushed {} profiles to {remote}", pushed.count));
+    Ok(())
+}
+
+/// Download the encrypted bundle of a remote and register the profiles added or changed there,
+/// keeping the ones changed only here
+fn pull_bundle(
+    remote: &str,
+    conflicts: SyncConflicts,
+    terraform_directory: &Path,
+    project_directory: &Path,
+    config: &config::Config,
+) -> Result<()> {
+    let restored = sync::pull(
+        &ProfileStore::open(project_directory),
+        &CredentialsFile::new(terraform_directory),
+        remote,
+        archive::read_passphrase,
+        |local, remote_profile| keep_local(local, remote_profile, remote, conflicts),
+        config.backup_retention,
+    )?;
+    match restored {
+        Some(restored) => print_restored(&restored, remote),
+        None => output::notice(format!("The profiles are up to date with {remote}")),
+    }
+    Ok(())
+}
+
+/// Print the credentials of a hostname in the profile the credentials helper serves
+fn helper_get(
+    hostname: &str,
+    profile_override: Option<&str>,
+    terraform_directory: &Path,
+    project_directory: &Path,
+) -> Result<()> {
+    let store = ProfileStore::open(project_directory);
+    let credentials_file = CredentialsFile::new(terraform_directory);
+    let entry = match helper::served_profile(&store, &credentials_file, profile_override)? {
+        Some(name) => helper::read_entry(&store, &name, hostname)?,
+        None => None,
+    };
+    println!(
+        "{}",
+        entry.unwrap_or_else(|| serde_json::Value::Object(Default::default()))
+    );
+    Ok(())
+}
+
+/// Get the profile the credentials helper serves, along with its store and the credentials made
+/// from it, failing when there is none
+fn served_profile(
+    profile_override: Option<&str>,
+    terraform_directory: &Path,
+    project_directory: &Path,
+) -> Result<(ProfileStore, CredentialsFile, String)> {
+    let store = ProfileStore::open(project_directory);
+    let credentials_file = CredentialsFile::new(terraform_directory);
+    let Some(name) = helper::served_profile(&store, &credentials_file, profile_override)? else {
+        return Err(CredentialsFile::new(terraform_directory)
+            .inactive_error()
+            .into());
+    };
+    Ok((store, credentials_file, name))
+}
+
+/// Store the credentials terraform obtained for a hostname in the profile the credentials helper
+/// serves
+fn helper_store(
+    hostname: &str,
+    profile_override: Option<&str>,
+    terraform_directory: &Path,
+    project_directory: &Path,
+    config: &config::Config,
+) -> Result<()> {
+    let request = helper::read_request()?;
+    let (store, credentials_file, name) =
+        served_profile(profile_override, terraform_directory, project_directory)?;
+    helper::store_entry(
+        &store,
+        &credentials_file,
+        &name,
+        hostname,
+        request,
+        config.backup_retention,
+    )
+}
+
+/// Remove the credentials of a hostname from the profile the credentials helper serves
+fn helper_forget(
+    hostname: &str,
+    profile_override: Option<&str>,
+    terraform_directory: &Path,
+    project_directory: &Path,
+    config: &config::Config,
+) -> Result<()> {
+    let (store, credentials_file, name) =
+        served_profile(profile_override, terraform_directory, project_directory)?;
+    helper::forget_entry(
+        &store,
+        &credentials_file,
+        &name,
+        hostname,
+        config.backup_retention,
+    )
+}
+
+/// Set up the binary as the credentials helper of terraform, in its plugins directory and its CLI
+/// configuration, then ask it for the credentials of the active profile as terraform would
+fn install_helper(
+    home_dir: &Path,
+    terraform_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+    project_directory: &Path,
+    config: &config::Config,
+) -> Result<()> {
+    let program = helper::install_program(terraform_directory)?;
+    output::notice(format!("Installed the helper as {}", program.display()));
+
+    let path = terraformrc::path(home_dir);
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(error) => {
+            return Err(error).with_context(|| format!("Couldn't read {}", path.display()))
+        }
+    };
+    let updated = terraformrc::set_credentials_helper(&content, helper::HELPER_NAME);
+    if updated == content {
+        output::notice(format!("{} already uses the helper", path.display()));
+    } else {
+        store::write_through_link(&path, updated)?;
+        output::notice(format!("Configured the helper in {}", path.display()));
+    }
+
+    let active = get_active_profile(terraform_directory, project_directory, profiles)?;
+    let expected = match active {
+        Some(name) => read_profile_tokens(&profiles[name])?,
+        None => BTreeMap::new(),
+    };
+    let hostname = expected
+        .keys()
+        .next()
+        .map_or("app.terraform.io", String::as_str);
+    let response = helper::request(&program, hostname)?;
+    match (active, expected.get(hostname)) {
+        (Some(name), Some(token)) => {
+            if response.get("token").and_then(serde_json::Value::as_str) != Some(token.as_str()) {
+                anyhow::bail!(
+                    "The helper didn't answer with the {hostname} token of profile `{name}`"
+                );
+            }
+            output::notice(format!(
+                "The helper answers with the {hostname} token of profile `{name}`"
+            ));
+        }
+        _ => output::notice("The helper answers, though no profile is active yet"),
+    }
+    if config.mode != config::SwitchMode::Helper {
+        output::warning(format!(
+            "Terraform only asks the helper for the hostnames missing from the credentials \
+             file, set `mode = \"helper\"` in {} and switch again",
+            paths::config_file(home_dir).display()
+        ));
+    }
+    Ok(())
+}
+
+/// Convert to another switching mode: the credentials of the active profile, the mode of the
+/// configuration and the `credentials_helper` block of the CLI configuration of terraform, only
+/// printing the changes with `dry_run`
+fn migrate_mode(
+    to: config::SwitchMode,
+    dry_run: bool,
+    home_dir: &Path,
+    terraform_directory: &Path,
+    project_directory: &Path,
+    config: &config::Config,
+) -> Result<()> {
+    let store = ProfileStore::open(project_directory);
+    let credentials_file = CredentialsFile::new(terraform_directory);
+    let migration = migration::Migration::plan(
+        to,
+        config.mode,
+        &store,
+        &credentials_file,
+        &terraformrc::path(home_dir),
+        &paths::config_file(home_dir),
+    )?;
+    if migration.changes().is_empty() {
+        output::notice(format!("Already in the {} mode", to.name()));
+        return Ok(());
+    }
+    if dry_run {
+        for change in migration.changes() {
+            println!("Would {change}");
+        }
+        return Ok(());
+    }
+    migration.apply(&store, &credentials_file)?;
+    output::notice(format!("Migrated to the {} mode", to.name()));
+    Ok(())
+}
+
+/// Move the tokens of a profile to Vault, leaving a profile referencing them in its place
+fn push_profile(
+    name: String,
+    terraform_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+    project_directory: &Path,
+    config: &config::Config,
+) -> Result<()> {
+    let profile_path = find_profile(&name, profiles)?;
+    if vault::is_skeleton(&std::fs::read_to_string(profile_path)?) {
+        anyhow::bail!("The tokens of profile `{name}` are already kept in Vault");
+    }
+    let plaintext = encryption::read_profile(profile_path)?;
+    let skeleton = vault::push(&name, &plaintext)?;
+    backup::snapshot(
+        project_directory,
+        &format!("vault push {name}"),
+        &[profile_path],
+        config.backup_retention,
+    )?;
+    journal::transaction(
+        project_directory,
+        &format!("vault push {name}"),
+        &[profile_path, &credentials::path(terraform_directory)],
+        || {
+            store::write_file_atomically(profile_path, &skeleton)?;
+            permissions::restrict_file(profile_path)?;
+            credentials::refresh(&name, profile_path, terraform_directory, project_directory)
+        },
+    )?;
+    output::notice(format!("Moved the tokens of profile `{name}` to Vault"));
+    Ok(())
+}
+
+/// Put back the files of the last destructive operation, bringing the credentials up to date
+fn undo_operation(terraform_directory: &Path, project_directory: &Path) -> Result<()> {
+    let Some(backup) = backup::undo(project_directory)? else {
+        anyhow::bail!("There is nothing to undo");
+    };
+    let credentials_files = credentials::path(terraform_directory);
+    for file in &backup.files {
+        if file.path == credentials_files {
+            credentials::untrack(project_directory)?;
+            continue;
+        }
+        let name = file
+            .path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".tfrc.json"));
+        if let (Some(name), Some(_)) = (name, &file.content) {
+            credentials::refresh(name, &file.path, terraform_directory, project_directory)?;
+        }
+    }
+    output::notice(format!("Reverted `{}`", backup.operation));
+    Ok(())
+}
+
+/// Show the current profile status
+fn show_profile_status(
+    output: Output,
+    profile_override: Option<String>,
+    terraform_directory: &Path,
+    project_directory: &Path,
+) -> Result<(), anyhow::Error> {
+    let report = Status::gather(
+        &ProfileStore::open(project_directory),
+        &CredentialsFile::new(terraform_directory),
+        profile_override,
+    )?;
+    for path in &report.exposed_paths {
+        output::warning(format!(
+            "{} can be read by other users, run `chmod go-rwx {}`",
+            path.display(),
+            path.display()
+        ));
+    }
+    output.print(&report, |report| {
+        if let Some(name) = &report.profile_override {
+            output::notice(format!(
+                "`TERRAFORM_PROFILE` selects `{name}` for exec, env, render and the \
+                 credentials helper"
+            ));
+        }
+        match &report.active {
+            Some(name) => {
+                println!("{}", active_profile_style(name, true));
+                if !report.hostnames.is_empty() {
+                    let hostnames: Vec<String> = report
+                        .hostnames
+                        .iter()
+                        .map(|host| match report.switched_hosts.get(host) {
+                            Some(profile) => format!("{host} (from `{profile}`)"),
+                            None => host.clone(),
+                        })
+                        .collect();
+                    output::notice(format!("Hostnames: {}", hostnames.join(", ")));
+                }
+                expiry::warn(name, &report.token_expiries);
+                Ok(())
+            }
+            None => Err(CredentialsFile::new(terraform_directory)
+                .inactive_error()
+                .into()),
+        }
+    })
+}
+
+/// Exit silently with the code telling whether a profile is in use, for scripts
+fn check_profile_status(
+    terraform_directory: &Path,
+    project_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+) -> Result<()> {
+    if get_active_profile(terraform_directory, project_directory, profiles)?.is_none() {
+        let code = CredentialsFile::new(terraform_directory)
+            .inactive_error()
+            .exit_code();
+        return Err(error::CliError::Exit(code).into());
+    }
+    Ok(())
+}
+
+/// Show the current profile as a tmux status segment, flagging broken links, drifted copies and
+/// known bad tokens
+fn show_tmux_status(
+    terraform_directory: &Path,
+    project_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+) -> Result<()> {
+    let credentials_files = credentials::path(terraform_directory);
+    let current = credentials::Credentials::inspect(terraform_directory, project_directory)?;
+    if credentials_files.is_symlink() && !credentials_files.exists() {
+        println!("#[fg=red]⚠ broken#[default]");
+    } else if let credentials::Credentials::Hardlink {
+        profile,
+        broken: true,
+    }
+    | credentials::Credentials::Copy {
+        profile,
+        drifted: true,
+    } = &current
+    {
+        println!("#[fg=yellow]⚠ {profile} (drifted)#[default]");
+    } else if let Some(name) = current.active_profile(profiles) {
+        if state::State::load(project_directory)?.has_known_invalid_token(name) {
+            println!("#[fg=yellow]⚠ {name}#[default]");
+        } else {
+            println!("#[fg=green]{name}#[default]");
+        }
+    } else if credentials_files.exists() {
+        println!("#[fg=yellow]unmanaged#[default]");
+    }
+    Ok(())
+}
+
+/// Print the prompt segment of the active profile, printing nothing on any failure
+fn print_prompt(format: &str, terraform_dir: Option<&Path>) {
+    if let Some(name) = prompt_profile_name(terraform_dir) {
+        let _ = write!(std::io::stdout(), "{}", format.replace("{profile}", &name));
+    }
+}
+
+/// Get the active profile name from the credentials alone, without listing the registry
+fn prompt_profile_name(terraform_dir: Option<&Path>) -> Option<String> {
+    let home_dir = home::home_dir()?;
+    let config = config::Config::load(&paths::config_file(&home_dir)).ok()?;
+    let terraform_directory = paths::terraform_directory(&home_dir, terraform_dir, &config);
+    let storage_directory = paths::storage_directory(&home_dir, &config);
+    let link =
+        match credentials::Credentials::inspect(&terraform_directory, &storage_directory).ok()? {
+            credentials::Credentials::Symlink(link) => link,
+            credentials::Credentials::Hardlink {
+                profile,
+                broken: false,
+            }
+            | credentials::Credentials::Copy {
+                profile,
+                drifted: false,
+            }
+            | credentials::Credentials::Helper(profile) => return Some(profile),
+            _ => return None,
+        };
+    if !link.is_file() || link.parent()? != storage_directory {
+        return None;
+    }
+    link.file_name()?
+        .to_str()?
+        .strip_suffix(".tfrc.json")
+        .map(String::from)
+}
+
+/// Index the content of the profiles, forget what is remembered about the removed ones and the
+/// token statuses and expirations of the changed ones, and bring the credentials up to date with
+/// the active one
+fn refresh_store(
+    output: Output,
+    terraform_directory: &Path,
+    project_directory: &Path,
+) -> Result<()> {
+    let store = ProfileStore::open(project_directory);
+    let changes = store.index()?;
+    match CredentialsFile::new(terraform_directory).follow_changes(&store, &changes.changed)? {
+        Some(credentials::Followed::Repaired(repair)) => print_repair(Some(repair)),
+        Some(credentials::Followed::Relinked(profile)) => {
+            output::notice(format!("Re-linked the credentials to profile `{profile}`"))
+        }
+        Some(credentials::Followed::Updated(profile)) => output::notice(format!(
+            "Brought the credentials up to date with profile `{profile}`"
+        )),
+        Some(credentials::Followed::Drifted(profile)) => output::warning(format!(
+            "Profile `{profile}` changed, but so did its copy in the credentials, which are left \
+             as they are"
+        )),
+        None => {}
+    }
+
+    output.print(&changes, |changes| {
+        if changes.first {
+            output::notice(format!("Indexed {} profiles", store.profiles()?.len()));
+        } else if changes.added.is_empty()
+            && changes.changed.is_empty()
+            && changes.removed.is_empty()
+        {
+            output::notice("Nothing changed since the last refresh");
+        }
+        for name in &changes.added {
+            println!("{}", console::style(format!("+ {name}")).green());
+        }
+        for name in &changes.changed {
+            println!("{}", console::style(format!("~ {name}")).yellow());
+        }
+        for name in &changes.removed {
+            println!("{}", console::style(format!("- {name}")).red());
+        }
+        Ok(())
+    })
+}
+
+/// Watch the credentials until interrupted, warning whenever they stop being a registered
+/// profile, and registering the replacing credentials then putting the profile back when asked to
+///
+/// The lock is only held while looking at a change, so the other commands can still be run
+fn watch_credentials(
+    adopt: bool,
+    restore: bool,
+    terraform_directory: &Path,
+    project_directory: &Path,
+    config: &config::Config,
+) -> Result<()> {
+    let credentials_file = credentials::path(terraform_directory);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    notify::Watcher::watch(
+        &mut watcher,
+        terraform_directory,
+        notify::RecursiveMode::NonRecursive,
+    )
+    .with_context(|| format!("Couldn't watch {}", terraform_directory.display()))?;
+
+    let mut watch = CredentialsWatch::new(Switcher::new(
+        ProfileStore::open(project_directory),
+        CredentialsFile::new(terraform_directory),
+        config.mode,
+    ))?;
+    output::notice(format!(
+        "Watching {}, press Ctrl-C to stop",
+        credentials_file.display()
+    ));
+    for event in &receiver {
+        let event = event?;
+        if !event
+            .paths
+            .iter()
+            .any(|path| path.file_name() == credentials_file.file_name())
+        {
+            continue;
+        }
+        // Writers often touch the credentials several times in a row, so let them finish
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        while receiver.try_recv().is_ok() {}
+
+        let _lock = match lock::acquire(project_directory) {
+            Ok(lock) => lock,
+            Err(error) => {
+                output::warning(format!("Couldn't look at the change: {error:#}"));
+                continue;
+            }
+        };
+        let Some(change) = watch.check(adopt, config.backup_retention)? else {
+            continue;
+        };
+        let mut profiles = ProfileStore::open(project_directory).profiles()?;
+        let previous = watch
+            .managed()
+            .map_or(String::new(), |name| format!(" of profile `{name}`"));
+        if change == watch::Change::Removed {
+            output::warning(format!("The credentials{previous} were removed"));
+        } else {
+            output::warning(format!(
+                "The credentials{previous} were replaced by unmanaged ones, e.g. by `terraform \
+                 login`"
+            ));
+        }
+        match change {
+            watch::Change::Removed => {}
+            watch::Change::Replaced => {
+                output::notice("Register them with `switch <name> --adopt <new-name>`");
+                continue;
+            }
+            watch::Change::Adopted { name, profile_path } => {
+                report_adopted(&name, profile_path, &mut profiles)
+            }
+        }
+        if let Some(name) = watch
+            .managed()
+            .filter(|name| restore && profiles.contains_key(*name))
+        {
+            switch_profile(
+                terraform_directory,
+                project_directory,
+                &profiles,
+                name.to_string(),
+                config,
+            )?;
+            output::notice(format!("Put profile `{name}` back in place"));
+        }
+    }
+    Ok(())
+}
+
+/// Show the problems found in the store, the credentials and the environment, fixing the safe
+/// ones when asked to
+fn run_doctor(
+    fix: bool,
+    output: Output,
+    home_dir: &Path,
+    terraform_directory: &Path,
+    project_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+    default_profile: Option<&str>,
+) -> Result<()> {
+    let mut findings = doctor::diagnose(
+        home_dir,
+        terraform_directory,
+        project_directory,
+        profiles,
+        default_profile,
+    )?;
+    if fix {
+        for finding in &mut findings {
+            finding.apply()?;
+        }
+    }
+
+    output.print(&findings, |findings| {
+        if findings.is_empty() {
+            output::notice("No problem found");
+        }
+        for finding in findings {
+            let (mark, suggestion) = if finding.fixed {
+                (console::style("✓").green(), "Fixed")
+            } else {
+                (console::style("✗").red(), finding.suggestion.as_str())
+            };
+            println!("{mark} {}", finding.problem);
+            if finding.fixable && !finding.fixed {
+                println!("  → {suggestion} (`doctor --fix` does it)");
+            } else {
+                println!("  → {suggestion}");
+            }
+        }
+        Ok(())
+    })?;
+    let remaining = findings.iter().filter(|finding| !finding.fixed).count();
+    if remaining > 0 {
+        anyhow::bail!("{remaining} problem(s) need attention");
+    }
+    Ok(())
+}
+
+/// Delete or set aside the files of the store that can't be used as profiles, leaving the
+/// profile in use alone
+fn prune_store(
+    action: PruneAction,
+    verify: bool,
+    dry_run: bool,
+    output: Output,
+    terraform_directory: &Path,
+    project_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+) -> Result<()> {
+    let strays = prune::find(project_directory, profiles, verify)?;
+    if dry_run {
+        return output.print(&strays, |strays| {
+            if strays.is_empty() {
+                output::notice("Nothing to prune");
+            }
+            for stray in strays {
+                println!("Would prune {}: {}", stray.path.display(), stray.problem);
+            }
+            Ok(())
+        });
+    }
+    if strays.is_empty() {
+        output::notice("Nothing to prune");
+        return Ok(());
+    }
+
+    let active = credentials::Credentials::inspect(terraform_directory, project_directory)?
+        .active_profile(profiles);
+    let mut pruned = 0;
+    for stray in &strays {
+        let path = stray.path.display();
+        if active.is_some() && stray.name.as_ref() == active {
+            output::warning(format!(
+                "Keeping {path}, as it is in use: {}. Switch to another profile first.",
+                stray.problem
+            ));
+            continue;
+        }
+        let disposal = match action {
+            PruneAction::Prompt => {
+                if !output::is_interactive() {
+                    anyhow::bail!(
+                        "The CLI can't prompt, pick what to do with the files with \
+                         `--action quarantine` or `--action delete`."
+                    );
+                }
+                let choices = ["Keep it", "Quarantine it", "Delete it"];
+                let choice = dialoguer::Select::new()
+                    .with_prompt(format!("{path}: {}", stray.problem))
+                    .items(&choices)
+                    .default(0)
+                    .interact()?;
+                match choice {
+                    1 => prune::Disposal::Quarantine,
+                    2 => prune::Disposal::Delete,
+                    _ => continue,
+                }
+            }
+            PruneAction::Quarantine => prune::Disposal::Quarantine,
+            PruneAction::Delete => prune::Disposal::Delete,
+        };
+        match prune::dispose(project_directory, stray, disposal)? {
+            Some(quarantined) => {
+                output::notice(format!("Moved {path} to {}", quarantined.display()))
+            }
+            None => output::notice(format!("Deleted {path}")),
+        }
+        pruned += 1;
+    }
+    output::notice(format!("Pruned {pruned} of {} files", strays.len()));
+    Ok(())
+}
+
+/// Show the most recent profile switches, oldest first
+fn show_history(limit: Option<usize>, output: Output, project_directory: &Path) -> Result<()> {
+    let mut switches = history::read(project_directory)?;
+    if let Some(limit) = limit {
+        switches.drain(..switches.len().saturating_sub(limit));
+    }
+
+    output.print(&switches, |switches| {
+        for switch in switches {
+            let timestamp =
+                std::time::UNIX_EPOCH + std::time::Duration::from_secs(switch.timestamp);
+            println!(
+                "{}\t{} -> {}\t{}",
+                humantime::format_rfc3339_seconds(timestamp),
+                switch.from.as_deref().unwrap_or("-"),
+                switch.to,
+                switch.hostnames.join(", ")
+            );
+        }
+        Ok(())
+    })
+}
+
+/// Show the different profiles list
+fn show_profiles_list(entries: &[listing::ProfileEntry]) -> Result<()> {
+    if entries.is_empty() {
+        return Err(error::CliError::NoProfiles.into());
+    }
+    output::notice("Currently available profiles:");
+    for entry in entries {
+        let aliases = if entry.aliases.is_empty() {
+            String::new()
+        } else {
+            format!(" (aliases: {})", entry.aliases.join(", "))
+        };
+        if entry.remote {
+            println!("\t{} (vault){aliases}", entry.name);
+        } else {
+            println!(
+                "\t{}{aliases}",
+                active_profile_style(&entry.name, entry.active)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Show the profiles as a table of their hostnames, token fingerprints and expirations, file
+/// sizes, when each profile was created, modified, last switched to and read, and their tags and
+/// descriptions, then warn about the tokens expiring soon
+///
+/// The last read time is the access time of the file, whose precision depends on the
+/// filesystem mount options
+fn show_profiles_details(entries: &[listing::ProfileEntry]) -> Result<()> {
+    if entries.is_empty() {
+        return Err(error::CliError::NoProfiles.into());
+    }
+    let describe = |timestamp: Option<u64>, missing: &str| {
+        timestamp.map_or_else(
+            || missing.to_string(),
+            |timestamp| {
+                format_age(std::time::UNIX_EPOCH + std::time::Duration::from_secs(timestamp))
+            },
+        )
+    };
+
+    let header = [
+        "NAME",
+        "ALIASES",
+        "HOSTNAMES",
+        "TOKENS",
+        "EXPIRES",
+        "SIZE",
+        "CREATED",
+        "MODIFIED",
+        "LAST SWITCHED",
+        "LAST READ",
+        "TAGS",
+        "DESCRIPTION",
+    ];
+    let rows: Vec<[String; 12]> = entries
+        .iter()
+        .map(|entry| {
+            let name = if entry.active {
+                format!("{} (active)", entry.name)
+            } else if entry.remote {
+                format!("{} (vault)", entry.name)
+            } else {
+                entry.name.clone()
+            };
+            let (hostnames, tokens, expirations) = if entry.remote {
+                let unknown = String::from("unknown");
+                (unknown.clone(), unknown.clone(), unknown)
+            } else if entry.hostnames.is_empty() {
+                let none = String::from("none");
+                (none.clone(), none.clone(), none)
+            } else {
+                let tokens = entry
+                    .hostnames
+                    .iter()
+                    .map(|host| {
+                        entry
+                            .fingerprints
+                            .get(host)
+                            .map_or("source", String::as_str)
+                    })
+                    .collect::<Vec<_>>();
+                let expirations = entry
+                    .hostnames
+                    .iter()
+                    .map(|host| {
+                        entry
+                            .token_expiries
+                            .get(host)
+                            .map_or_else(|| String::from("-"), |at| expiry::describe(*at))
+                    })
+                    .collect::<Vec<_>>();
+                (
+                    entry.hostnames.join(", "),
+                    tokens.join(", "),
+                    expirations.join(", "),
+                )
+            };
+            [
+                name,
+                entry.aliases.join(", "),
+                hostnames,
+                tokens,
+                expirations,
+                entry.size.map_or_else(|| String::from("-"), format_size),
+                describe(entry.created, "unknown"),
+                describe(entry.modified, "unknown"),
+                describe(entry.last_switched, "never"),
+                describe(entry.last_read, "unknown"),
+                entry.tags.iter().cloned().collect::<Vec<_>>().join(", "),
+                entry.description.clone().unwrap_or_default(),
+            ]
+        })
+        .collect();
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(console::measure_text_width(cell));
+        }
+    }
+    let line = |cells: [String; 12]| {
+        cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    output::notice("Currently available profiles:");
+    println!("{}", console::style(line(header.map(String::from))).bold());
+    for (entry, row) in entries.iter().zip(rows) {
+        println!("{}", active_profile_style(line(row), entry.active));
+    }
+    for entry in entries {
+        expiry::warn(&entry.name, &entry.token_expiries);
+    }
+    Ok(())
+}
+
+/// Highlight a profile name when the profile is the active one
+fn active_profile_style<D>(name: D, active: bool) -> console::StyledObject<D> {
+    let style = console::style(name);
+    if active {
+        style.green().bold()
+    } else {
+        style
+    }
+}
+
+/// Describe a size in bytes with the largest binary unit it reaches, e.g. `1.5 KiB`
+fn format_size(size: u64) -> String {
+    match size {
+        0..=1023 => format!("{size} B"),
+        1024..=1_048_575 => format!("{:.1} KiB", size as f64 / 1024.0),
+        _ => format!("{:.1} MiB", size as f64 / 1_048_576.0),
+    }
+}
+
+/// Describe how long ago a point in time was, e.g. `3 days ago`
+fn format_age(time: SystemTime) -> String {
+    let seconds = SystemTime::now()
+        .duration_since(time)
+        .map(|age| age.as_secs())
+        .unwrap_or_default();
+    match seconds {
+        0..=59 => String::from("just now"),
+        60..=3599 => format!("{} minutes ago", seconds / 60),
+        3600..=86399 => format!("{} hours ago", seconds / 3600),
+        _ => format!("{} days ago", seconds / 86400),
     }
 }
@@ -0,0 +1,170 @@
+//! Descriptions and other notes about the registered profiles, kept next to them in a sidecar
+//! file that is synced along with them
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// Name of the metadata file inside the project directory
+pub const METADATA_FILE: &str = "metadata.json";
+
+/// What is noted about a profile
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileMetadata {
+    /// Free text telling what the profile is for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Arbitrary values, by key
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, String>,
+    /// Labels grouping the profile with others, e.g. `prod` or a client name
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    /// Environment variables `exec` and `env` set along with the tokens, e.g. `AWS_PROFILE`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+impl ProfileMetadata {
+    /// Whether nothing is noted about the profile
+    pub fn is_empty(&self) -> bool {
+        self.description.is_none()
+            && self.fields.is_empty()
+            && self.tags.is_empty()
+            && self.env.is_empty()
+    }
+
+    /// Whether the profile has every one of some tags
+    pub fn has_tags(&self, tags: &[String]) -> bool {
+        tags.iter().all(|tag| self.tags.contains(tag))
+    }
+}
+
+/// What is noted about every profile
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Metadata {
+    /// Metadata per profile name, leaving out the profiles without any
+    #[serde(default)]
+    pub profiles: BTreeMap<String, ProfileMetadata>,
+    /// Short names standing for profiles set with `alias`, by alias
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub aliases: BTreeMap<String, String>,
+}
+
+impl Metadata {
+    /// Path of the metadata file inside a project directory
+    fn path(project_directory: &Path) -> PathBuf {
+        project_directory.join(METADATA_FILE)
+    }
+
+    /// Load the metadata, starting from scratch when it was never saved
+    pub fn load(project_directory: &Path) -> Result<Self> {
+        let path = Self::path(project_directory);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        tracing::debug!("Reading the metadata in {}", path.display());
+        let content = std::fs::read_to_string(&path)?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("The metadata file {} is corrupted", path.display()))
+    }
+
+    /// Save the metadata through a rename, so it is never left half-written
+    pub fn save(&self, project_directory: &Path) -> Result<()> {
+        crate::store::write_file_atomically(
+            &Self::path(project_directory),
+            format!("{}\n", serde_json::to_string_pretty(self)?),
+        )
+    }
+
+    /// Get what is noted about a profile, empty when nothing is
+    pub fn get(&self, name: &str) -> ProfileMetadata {
+        self.profiles.get(name).cloned().unwrap_or_default()
+    }
+
+    /// Change what is noted about a profile, forgetting it once empty
+    pub fn update(&mut self, name: &str, change: impl FnOnce(&mut ProfileMetadata)) {
+        let metadata = self.profiles.entry(name.to_string()).or_default();
+        change(metadata);
+        if metadata.is_empty() {
+            self.profiles.remove(name);
+        }
+    }
+
+    /// Carry what is noted about a profile over to its new name
+    pub fn rename_profile(&mut self, name: &str, new_name: &str) {
+        if let Some(metadata) = self.profiles.remove(name) {
+            self.profiles.insert(new_name.to_string(), metadata);
+        }
+        for profile in self.aliases.values_mut() {
+            if profile == name {
+                *profile = new_name.to_string();
+            }
+        }
+    }
+
+    /// Note about a copy of a profile what is noted about the profile
+    pub fn copy_profile(&mut self, name: &str, new_name: &str) {
+        if let Some(metadata) = self.profiles.get(name).cloned() {
+            self.profiles.insert(new_name.to_string(), metadata);
+        }
+    }
+
+    /// Forget what is noted about a removed profile
+    pub fn forget_profile(&mut self, name: &str) {
+        self.profiles.remove(name);
+        self.aliases.retain(|_, profile| profile != name);
+    }
+}
+
+/// Get the profile each alias stands for, those set with `alias` taking precedence over those
+/// of the configuration
+pub fn aliases(project_directory: &Path, config: &Config) -> Result<BTreeMap<String, String>> {
+    let mut aliases = config.aliases.clone();
+    aliases.extend(Metadata::load(project_directory)?.aliases);
+    Ok(aliases)
+}
+
+/// Check that a tag is a single word, as it is listed and matched as is
+pub fn validate_tag(tag: &str) -> Result<()> {
+    if tag.is_empty()
+        || tag.contains(|character: char| character.is_whitespace() || character == ',')
+    {
+        anyhow::bail!("The tag `{tag}` can't be empty or contain spaces or commas");
+    }
+    Ok(())
+}
+
+/// Check that an environment variable name can be exported by every shell, and doesn't stand
+/// for a token, which the profile itself sets
+pub fn validate_env_var(key: &str) -> Result<()> {
+    if key.is_empty()
+        || key.starts_with(|character: char| character.is_ascii_digit())
+        || !key
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || character == '_')
+    {
+        anyhow::bail!("`{key}` isn't an environment variable name, e.g. `AWS_PROFILE`");
+    }
+    if key.starts_with("TF_TOKEN_") {
+        anyhow::bail!("`{key}` is set from the tokens of the profile");
+    }
+    Ok(())
+}
+
+/// Change what is noted about a profile of a project directory
+pub fn update(
+    project_directory: &Path,
+    name: &str,
+    change: impl FnOnce(&mut ProfileMetadata),
+) -> Result<()> {
+    let mut metadata = Metadata::load(project_directory)?;
+    metadata.update(name, change);
+    metadata.save(project_directory)
+}
@@ -0,0 +1,340 @@
+//! Converting to another switching mode: the credentials of the active profile, the mode of the
+//! configuration and the `credentials_helper` block of the CLI configuration of terraform
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    config::{self, SwitchMode},
+    credentials::{Credentials, CredentialsFile},
+    encryption, helper, journal,
+    store::{self, ProfileStore},
+    terraformrc,
+};
+
+/// A file a migration rewrites, along with its current and new content
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rewrite {
+    path: PathBuf,
+    content: String,
+    updated: String,
+}
+
+impl Rewrite {
+    /// Read a file about to be rewritten, empty when it doesn't exist yet
+    fn read(path: PathBuf, update: impl FnOnce(&str) -> String) -> Result<Self> {
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(error) => {
+                return Err(error).with_context(|| format!("Couldn't read {}", path.display()))
+            }
+        };
+        let updated = update(&content);
+        Ok(Rewrite {
+            path,
+            content,
+            updated,
+        })
+    }
+
+    /// Whether the new content differs from the current one
+    fn changes(&self) -> bool {
+        self.updated != self.content
+    }
+}
+
+/// The changes converting to a switching mode makes, planned before any is made
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    to: SwitchMode,
+    active: Option<String>,
+    install_program: bool,
+    terraformrc: Rewrite,
+    config: Option<Rewrite>,
+    changes: Vec<String>,
+}
+
+impl Migration {
+    /// Plan the conversion from the mode of the configuration to another, with the paths of the
+    /// CLI configuration of terraform and of the configuration, failing when the credentials are
+    /// neither missing nor made from a registered profile
+    pub fn plan(
+        to: SwitchMode,
+        mode: SwitchMode,
+        store: &ProfileStore,
+        credentials_file: &CredentialsFile,
+        terraformrc_path: &Path,
+        config_file: &Path,
+    ) -> Result<Self> {
+        let current = credentials_file.inspect(store)?;
+        let active = current.active_profile(&store.profiles()?).cloned();
+        if active.is_none() && current != Credentials::Missing {
+            return Err(credentials_file.inactive_error().into());
+        }
+        let mut changes = Vec::new();
+
+        if let Some(name) = &active {
+            let is_current_mode = match (&current, to) {
+                (Credentials::Symlink(_), SwitchMode::Symlink)
+                | (Credentials::Hardlink { .. }, SwitchMode::Hardlink)
+                | (Credentials::Copy { .. }, SwitchMode::Copy)
+                | (Credentials::Helper(_), SwitchMode::Helper) => true,
+                // Encrypted profiles are always copied, terraform can't read them through a link
+                (Credentials::Copy { .. }, SwitchMode::Symlink | SwitchMode::Hardlink) => {
+                    encryption::is_encrypted_file(&store.find(name)?)?
+                }
+                _ => false,
+            };
+            let credentials_files = credentials_file.path();
+            if !is_current_mode {
+                changes.push(match to {
+                    SwitchMode::Symlink => {
+                        format!("link {} to profile `{name}`", credentials_files.display())
+                    }
+                    SwitchMode::Hardlink => format!(
+                        "hard link {} to profile `{name}`",
+                        credentials_files.display()
+                    ),
+                    SwitchMode::Copy => {
+                        format!("copy profile `{name}` to {}", credentials_files.display())
+                    }
+                    SwitchMode::Helper => format!(
+                        "remove {} and serve profile `{name}` through the credentials helper",
+                        credentials_files.display()
+                    ),
+                });
+            }
+        }
+
+        let program = helper::program_path(credentials_file.terraform_directory());
+        let install_program = to == SwitchMode::Helper && !program.exists();
+        if install_program {
+            changes.push(format!("install the helper as {}", program.display()));
+        }
+        let terraformrc = Rewrite::read(terraformrc_path.to_path_buf(), |content| {
+            if to == SwitchMode::Helper {
+                terraformrc::set_credentials_helper(content, helper::HELPER_NAME)
+            } else {
+                terraformrc::remove_credentials_helper(content, helper::HELPER_NAME)
+            }
+        })?;
+        if terraformrc.changes() {
+            changes.push(if to == SwitchMode::Helper {
+                format!(
+                    "write the `credentials_helper` block into {}",
+                    terraformrc.path.display()
+                )
+            } else {
+                format!(
+                    "remove the `credentials_helper` block from {}",
+                    terraformrc.path.display()
+                )
+            });
+        }
+
+        let config = if mode == to {
+            None
+        } else {
+            let config = Rewrite::read(config_file.to_path_buf(), |content| {
+                config::set_mode(content, to)
+            })?;
+            changes.push(format!(
+                "set `mode = \"{}\"` in {}",
+                to.name(),
+                config.path.display()
+            ));
+            Some(config)
+        };
+
+        Ok(Migration {
+            to,
+            active,
+            install_program,
+            terraformrc,
+            config,
+            changes,
+        })
+    }
+
+    /// Describe the changes the migration makes, e.g. `copy profile `work` to <path>`, none
+    /// when already in the mode
+    pub fn changes(&self) -> &[String] {
+        &self.changes
+    }
+
+    /// Make the changes, all of them or none
+    pub fn apply(&self, store: &ProfileStore, credentials_file: &CredentialsFile) -> Result<()> {
+        let mut paths = vec![credentials_file.path(), self.terraformrc.path.clone()];
+        paths.extend(self.config.iter().map(|config| config.path.clone()));
+        journal::transaction(
+            store.directory(),
+            &format!("migrate --to {}", self.to.name()),
+            &paths.iter().map(PathBuf::as_path).collect::<Vec<_>>(),
+            || {
+                if self.install_program {
+                    helper::install_program(credentials_file.terraform_directory())?;
+                }
+                if let Some(name) = &self.active {
+                    credentials_file.install(store, name, self.to)?;
+                }
+                if self.terraformrc.changes() {
+                    store::write_through_link(&self.terraformrc.path, &self.terraformrc.updated)?;
+                }
+                if let Some(config) = &self.config {
+                    if let Some(directory) = config.path.parent() {
+                        std::fs::create_dir_all(directory)?;
+                    }
+                    store::write_through_link(&config.path, &config.updated)?;
+                }
+                Ok(())
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Credentials holding a single token for `app.terraform.io`
+    const CREDENTIALS: &str = r#"{"credentials": {"app.terraform.io": {"token": "secret"}}}"#;
+
+    /// Plan a migration in a home directory, with the configurations in it
+    fn plan(
+        home_dir: &Path,
+        to: SwitchMode,
+        mode: SwitchMode,
+        store: &ProfileStore,
+        credentials_file: &CredentialsFile,
+    ) -> Result<Migration> {
+        Migration::plan(
+            to,
+            mode,
+            store,
+            credentials_file,
+            &home_dir.join(".terraformrc"),
+            &home_dir.join("config.toml"),
+        )
+    }
+
+    /// A home directory whose store holds the work profile, linked in place of the credentials
+    fn linked_home() -> (tempfile::TempDir, ProfileStore, CredentialsFile) {
+        let home = tempfile::tempdir().unwrap();
+        let store = ProfileStore::open(home.path().join("store"));
+        let credentials_file = CredentialsFile::new(home.path().join(".terraform.d"));
+        std::fs::create_dir_all(store.directory()).unwrap();
+        std::fs::create_dir_all(credentials_file.terraform_directory()).unwrap();
+        store.register("work", CREDENTIALS).unwrap();
+        credentials_file
+            .install(&store, "work", SwitchMode::Symlink)
+            .unwrap();
+        (home, store, credentials_file)
+    }
+
+    #[test]
+    fn migrating_to_copies_converts_the_credentials_and_the_configuration() {
+        let (home, store, credentials_file) = linked_home();
+        let migration = plan(
+            home.path(),
+            SwitchMode::Copy,
+            SwitchMode::Symlink,
+            &store,
+            &credentials_file,
+        )
+        .unwrap();
+        assert_eq!(migration.changes().len(), 2);
+        assert!(migration.changes()[0].starts_with("copy profile `work`"));
+        migration.apply(&store, &credentials_file).unwrap();
+
+        assert!(!credentials_file.path().is_symlink());
+        assert_eq!(
+            std::fs::read_to_string(credentials_file.path()).unwrap(),
+            CREDENTIALS
+        );
+        assert_eq!(
+            credentials_file.active_profile(&store).unwrap().as_deref(),
+            Some("work")
+        );
+        let config = std::fs::read_to_string(home.path().join("config.toml")).unwrap();
+        assert!(config.contains("mode = \"copy\""));
+        assert!(!home.path().join(".terraformrc").exists());
+
+        let again = plan(
+            home.path(),
+            SwitchMode::Copy,
+            SwitchMode::Copy,
+            &store,
+            &credentials_file,
+        )
+        .unwrap();
+        assert!(again.changes().is_empty());
+    }
+
+    #[test]
+    fn migrating_to_the_helper_and_back_rewrites_the_cli_configuration() {
+        let (home, store, credentials_file) = linked_home();
+        let terraformrc_path = home.path().join(".terraformrc");
+        std::fs::write(&terraformrc_path, "plugin_cache_dir = \"/tmp\"\n").unwrap();
+
+        plan(
+            home.path(),
+            SwitchMode::Helper,
+            SwitchMode::Symlink,
+            &store,
+            &credentials_file,
+        )
+        .unwrap()
+        .apply(&store, &credentials_file)
+        .unwrap();
+        assert!(!credentials_file.path().exists());
+        assert!(helper::program_path(credentials_file.terraform_directory()).is_symlink());
+        let configured = std::fs::read_to_string(&terraformrc_path).unwrap();
+        assert!(configured.starts_with("plugin_cache_dir = \"/tmp\"\n"));
+        assert!(configured.contains("credentials_helper"));
+
+        plan(
+            home.path(),
+            SwitchMode::Symlink,
+            SwitchMode::Helper,
+            &store,
+            &credentials_file,
+        )
+        .unwrap()
+        .apply(&store, &credentials_file)
+        .unwrap();
+        assert_eq!(
+            credentials_file.path().read_link().unwrap(),
+            store.profile_path("work")
+        );
+        assert_eq!(
+            std::fs::read_to_string(&terraformrc_path).unwrap(),
+            "plugin_cache_dir = \"/tmp\"\n"
+        );
+    }
+
+    #[test]
+    fn refuses_unmanaged_credentials() {
+        let (home, store, credentials_file) = linked_home();
+        std::fs::remove_file(credentials_file.path()).unwrap();
+        std::fs::write(
+            credentials_file.path(),
+            CREDENTIALS.replace("secret", "other"),
+        )
+        .unwrap();
+        let error = plan(
+            home.path(),
+            SwitchMode::Copy,
+            SwitchMode::Symlink,
+            &store,
+            &credentials_file,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<crate::error::CliError>(),
+            Some(crate::error::CliError::UnmanagedCredentials)
+        ));
+        assert!(!home.path().join("config.toml").exists());
+    }
+}
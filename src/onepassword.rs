@@ -0,0 +1,23 @@
+//! Tokens kept in 1Password, which the `op` CLI reads by their `op://<vault>/<item>/<field>`
+//! reference
+
+use std::process::Command;
+
+use anyhow::{Context, Result};
+
+/// Read the secret behind a reference with `op read`
+pub fn read(reference: &str) -> Result<String> {
+    tracing::info!("Running `op read {reference}`");
+    let output = Command::new("op")
+        .args(["read", "--no-newline", reference])
+        .output()
+        .context("Couldn't run `op`, is the 1Password CLI installed?")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "`op read {reference}` exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
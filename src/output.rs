@@ -0,0 +1,137 @@
+//! Rendering of the command results in the format picked with `--output`
+
+use std::{
+    io::IsTerminal,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::config::ColorChoice;
+
+/// Whether the informational messages are silenced, set once from `--quiet`
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Silence the informational messages for the rest of the run
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// Whether the prompts are turned off, set once from `--non-interactive`
+static NON_INTERACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Turn the prompts off for the rest of the run, accepting the confirmations
+pub fn set_non_interactive(non_interactive: bool) {
+    NON_INTERACTIVE.store(non_interactive, Ordering::Relaxed);
+}
+
+/// Whether the user can be asked, the prompts being on and both stdin and stderr terminals
+pub fn is_interactive() -> bool {
+    !NON_INTERACTIVE.load(Ordering::Relaxed)
+        && std::io::stdin().is_terminal()
+        && std::io::stderr().is_terminal()
+}
+
+/// Whether `--yes` accepted the confirmations ahead, which a missing terminal never does
+pub fn assumes_yes() -> bool {
+    NON_INTERACTIVE.load(Ordering::Relaxed)
+}
+
+/// Whether the colors are turned off through `--no-color` or `NO_COLOR`
+fn colors_disabled(no_color: bool) -> bool {
+    no_color || std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+}
+
+/// Turn the colors off when asked to through `--no-color` or `NO_COLOR`, or else follow the
+/// configured choice
+///
+/// By default each stream is colored only when it is a terminal, unless `CLICOLOR=0`
+pub fn configure_colors(no_color: bool, choice: ColorChoice) {
+    let enabled = if colors_disabled(no_color) {
+        false
+    } else {
+        match choice {
+            ColorChoice::Auto => return,
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    };
+    console::set_colors_enabled(enabled);
+    console::set_colors_enabled_stderr(enabled);
+}
+
+/// Log on stderr at the level picked with `-v`, unless `RUST_LOG` sets the filter itself
+///
+/// The logs start before the configuration is read, so only `--no-color` and `NO_COLOR`
+/// turn their colors off
+pub fn init_logging(verbosity: u8, no_color: bool) {
+    let default_level = match verbosity {
+        0 => "warn",
+        1 => "info",
+        2 => "debug",
+        _ => "trace",
+    };
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(default_level));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_ansi(!colors_disabled(no_color) && std::io::stderr().is_terminal())
+        .without_time()
+        .init();
+}
+
+/// Tell the user what a command did, on stderr so it never ends up in a command substitution
+///
+/// Silenced by `--quiet`, unlike errors and command results
+pub fn notice(message: impl std::fmt::Display) {
+    if !QUIET.load(Ordering::Relaxed) {
+        eprintln!("{message}");
+    }
+}
+
+/// Warn the user about something needing their attention, on stderr even with `--quiet`
+pub fn warning(message: impl std::fmt::Display) {
+    eprintln!(
+        "{} {message}",
+        console::style("Warning:").yellow().bold().for_stderr()
+    );
+}
+
+/// Formats the command results can be printed in
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Output {
+    /// Human-readable text
+    Text,
+    /// Pretty-printed JSON
+    Json,
+    /// YAML
+    Yaml,
+}
+
+impl Output {
+    /// Use JSON instead when a command's own `--json` flag is set
+    pub fn or_json(self, json: bool) -> Self {
+        if json {
+            Output::Json
+        } else {
+            self
+        }
+    }
+
+    /// Print a command result, through `text` for the human-readable format
+    pub fn print<T: Serialize + ?Sized>(
+        self,
+        value: &T,
+        text: impl FnOnce(&T) -> Result<()>,
+    ) -> Result<()> {
+        match self {
+            Output::Text => text(value)?,
+            Output::Json => println!("{}", serde_json::to_string_pretty(value)?),
+            Output::Yaml => print!("{}", serde_yaml::to_string(value)?),
+        }
+        Ok(())
+    }
+}
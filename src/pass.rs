@@ -0,0 +1,86 @@
+//! Tokens kept in the standard Unix password store, which `pass` reads and writes under
+//! `terraform-profile/<name>`
+
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use anyhow::{Context, Result};
+
+/// Prefix of the sources naming an entry of the password store
+pub const REFERENCE_PREFIX: &str = "pass://";
+
+/// Folder of the password store the profiles are kept in
+const FOLDER: &str = env!("CARGO_PKG_NAME");
+
+/// Read the token of an entry, which is its first line
+pub fn read(entry: &str) -> Result<String> {
+    tracing::info!("Running `pass show {entry}`");
+    let output = Command::new("pass")
+        .args(["show", entry])
+        .output()
+        .context("Couldn't run `pass`, is the password store installed?")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "`pass show {entry}` exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let content = String::from_utf8(output.stdout)?;
+    Ok(content.lines().next().unwrap_or_default().to_string())
+}
+
+/// Write a token to an entry, replacing it if it exists
+fn insert(entry: &str, token: &str) -> Result<()> {
+    tracing::info!("Running `pass insert {entry}`");
+    let mut child = Command::new("pass")
+        .args(["insert", "--multiline", "--force", entry])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .context("Couldn't run `pass`, is the password store installed?")?;
+    child
+        .stdin
+        .take()
+        .context("Couldn't write to `pass`")?
+        .write_all(format!("{token}\n").as_bytes())?;
+    let status = child.wait()?;
+    if !status.success() {
+        anyhow::bail!("`pass insert {entry}` exited with {status}");
+    }
+    Ok(())
+}
+
+/// Move the tokens of a profile to the password store, returning the profile referencing them
+///
+/// The token of a single hostname goes to `terraform-profile/<name>`, those of several
+/// hostnames to `terraform-profile/<name>/<hostname>`
+pub fn store(name: &str, plaintext: &str) -> Result<String> {
+    let mut value: serde_json::Value =
+        serde_json::from_str(plaintext).context("The profile isn't valid JSON")?;
+    let Some(hosts) = value["credentials"].as_object_mut() else {
+        anyhow::bail!("The profile holds no token");
+    };
+    let several = hosts.len() > 1;
+    for (host, entry) in hosts.iter_mut() {
+        let Some(token) = entry["token"].as_str() else {
+            continue;
+        };
+        let path = if several {
+            format!("{FOLDER}/{name}/{host}")
+        } else {
+            format!("{FOLDER}/{name}")
+        };
+        insert(&path, token)?;
+        if let Some(entry) = entry.as_object_mut() {
+            entry.shift_remove("token");
+            entry.insert(
+                String::from("source"),
+                serde_json::Value::String(format!("{REFERENCE_PREFIX}{path}")),
+            );
+        }
+    }
+    crate::store::format_like(plaintext, &value)
+}
@@ -0,0 +1,134 @@
+//! Locations of the configuration and of the stored profiles, following the XDG base directories
+//!
+//! Each location can be overridden through an environment variable, for containers and CI
+//! sandboxes where the home directory is synthetic or read-only
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    config::{Config, CONFIG_FILE},
+    credentials, output,
+};
+
+/// Name of the directories of the tool inside the XDG base directories
+const DIRECTORY_NAME: &str = env!("CARGO_PKG_NAME");
+
+/// Variable overriding the directory the profiles are stored in
+pub const STORE_VARIABLE: &str = "TERRAFORM_PROFILE_HOME";
+
+/// Variable overriding the path of the configuration file
+pub const CONFIG_VARIABLE: &str = "TERRAFORM_PROFILE_CONFIG";
+
+/// Variable overriding the directory terraform reads its credentials from
+pub const TERRAFORM_DIRECTORY_VARIABLE: &str = "TERRAFORM_PROFILE_TF_DIR";
+
+/// Get the path set in a variable, treating an empty one as unset
+fn path_variable(variable: &str) -> Option<PathBuf> {
+    std::env::var_os(variable)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Get an XDG base directory from its variable, which is ignored unless absolute as the
+/// specification requires
+fn xdg_directory(variable: &str, home_dir: &Path, default: &str) -> PathBuf {
+    std::env::var_os(variable)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .unwrap_or_else(|| home_dir.join(default))
+}
+
+/// Get the directory the configuration is read from
+pub fn config_directory(home_dir: &Path) -> PathBuf {
+    xdg_directory("XDG_CONFIG_HOME", home_dir, ".config").join(DIRECTORY_NAME)
+}
+
+/// Get the path of the configuration file
+pub fn config_file(home_dir: &Path) -> PathBuf {
+    path_variable(CONFIG_VARIABLE).unwrap_or_else(|| config_directory(home_dir).join(CONFIG_FILE))
+}
+
+/// Get the directory the profiles, the state and the history are stored in
+///
+/// The variable takes precedence over the configuration, which takes precedence over the XDG
+/// data directory
+pub fn storage_directory(home_dir: &Path, config: &Config) -> PathBuf {
+    path_variable(STORE_VARIABLE)
+        .unwrap_or_else(|| config.storage_directory(home_dir, &data_directory(home_dir)))
+}
+
+/// Get the directory terraform reads its credentials from
+///
+/// The flag takes precedence over the variable, which takes precedence over the configuration
+pub fn terraform_directory(home_dir: &Path, flag: Option<&Path>, config: &Config) -> PathBuf {
+    flag.map(Path::to_path_buf)
+        .or_else(|| path_variable(TERRAFORM_DIRECTORY_VARIABLE))
+        .unwrap_or_else(|| config.terraform_directory(home_dir, &home_dir.join(".terraform.d")))
+}
+
+/// Get the directory the profiles, the state and the history are stored in by default
+pub fn data_directory(home_dir: &Path) -> PathBuf {
+    xdg_directory("XDG_DATA_HOME", home_dir, ".local/share").join(DIRECTORY_NAME)
+}
+
+/// Get the directory everything was kept in before following the XDG base directories
+pub fn legacy_directory(home_dir: &Path) -> PathBuf {
+    home_dir.join(format!(".{DIRECTORY_NAME}"))
+}
+
+/// Move the legacy directory to the data directory and its configuration to the configuration
+/// directory, re-pointing the credentials if they link to a moved profile
+///
+/// Does nothing once migrated, when the data directory already exists, or when the store is
+/// overridden through its variable
+pub fn migrate_legacy_directory(home_dir: &Path, terraform_directory: &Path) -> Result<()> {
+    let legacy = legacy_directory(home_dir);
+    if !legacy.is_dir() || path_variable(STORE_VARIABLE).is_some() {
+        return Ok(());
+    }
+    let data = data_directory(home_dir);
+    if data.exists() {
+        tracing::warn!(
+            "Both {} and {} exist, the legacy one is ignored",
+            legacy.display(),
+            data.display()
+        );
+        return Ok(());
+    }
+
+    tracing::info!("Moving {} to {}", legacy.display(), data.display());
+    if let Some(parent) = data.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if std::fs::rename(&legacy, &data).is_err() {
+        // Renaming fails across filesystems, where the flat directory is copied instead
+        std::fs::create_dir(&data)?;
+        for entry in std::fs::read_dir(&legacy)?.flatten() {
+            std::fs::copy(entry.path(), data.join(entry.file_name()))
+                .with_context(|| format!("Couldn't copy {}", entry.path().display()))?;
+        }
+        std::fs::remove_dir_all(&legacy)?;
+    }
+
+    let config_file = data.join(CONFIG_FILE);
+    if config_file.exists() {
+        let config = config_directory(home_dir);
+        std::fs::create_dir_all(&config)?;
+        tracing::info!("Moving {} to {}", config_file.display(), config.display());
+        std::fs::rename(&config_file, config.join(CONFIG_FILE))?;
+    }
+
+    if let Ok(link) = credentials::path(terraform_directory).read_link() {
+        if let (Ok(_), Some(file_name)) = (link.strip_prefix(&legacy), link.file_name()) {
+            credentials::follow_move(terraform_directory, &link, &data.join(file_name))?;
+        }
+    }
+    output::notice(format!(
+        "Moved the profiles from {} to {}",
+        legacy.display(),
+        data.display()
+    ));
+    Ok(())
+}
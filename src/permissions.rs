@@ -0,0 +1,92 @@
+//! Restriction and audit of the permissions of the stored profiles, which hold plaintext tokens
+
+use std::{io::Write, path::Path};
+
+use anyhow::Result;
+
+/// Make a stored profile readable and writable by its owner only, `0600` on unix
+pub fn restrict_file(path: &Path) -> Result<()> {
+    restrict(path, 0o600)
+}
+
+/// Make the store directory accessible by its owner only, `0700` on unix
+pub fn restrict_directory(path: &Path) -> Result<()> {
+    restrict(path, 0o700)
+}
+
+/// Write a file readable and writable by its owner only from its creation, `0600` on unix, as
+/// restricting it once written would leave its tokens readable by others meanwhile
+pub fn write_restricted(path: &Path, content: impl AsRef<[u8]>) -> Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(target_family = "unix")]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(content.as_ref())?;
+    // The mode only applies to new files, not to those written over
+    restrict_file(path)
+}
+
+#[cfg(target_family = "unix")]
+fn restrict(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    tracing::debug!("Setting the mode of {} to {mode:o}", path.display());
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+/// Windows has no modes, so the inherited entries of the ACL are replaced by full control for the
+/// current user alone
+#[cfg(target_family = "windows")]
+fn restrict(path: &Path, _mode: u32) -> Result<()> {
+    let user = std::env::var("USERNAME")?;
+    tracing::debug!("Granting {} to {user} alone", path.display());
+    let status = std::process::Command::new("icacls")
+        .arg(path)
+        .args(["/inheritance:r", "/grant:r"])
+        .arg(format!("{user}:F"))
+        .stdout(std::process::Stdio::null())
+        .status()?;
+    if !status.success() {
+        anyhow::bail!("icacls couldn't restrict {}", path.display());
+    }
+    Ok(())
+}
+
+/// Whether a file or directory can be accessed by the group or other users
+#[cfg(target_family = "unix")]
+pub fn is_exposed(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o077 != 0)
+}
+
+/// Whether a file or directory can be accessed by other users, which isn't audited on Windows as
+/// reading its ACL needs the Win32 API
+#[cfg(target_family = "windows")]
+pub fn is_exposed(_path: &Path) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn writes_files_readable_by_their_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let directory = tempfile::tempdir().unwrap();
+        let new = directory.path().join("new.tfrc.json");
+        write_restricted(&new, "{}").unwrap();
+        assert!(!is_exposed(&new));
+
+        let existing = directory.path().join("existing.tfrc.json");
+        std::fs::write(&existing, "").unwrap();
+        std::fs::set_permissions(&existing, std::fs::Permissions::from_mode(0o644)).unwrap();
+        write_restricted(&existing, "{}").unwrap();
+        assert!(!is_exposed(&existing));
+        assert_eq!(std::fs::read_to_string(&existing).unwrap(), "{}");
+    }
+}
@@ -0,0 +1,41 @@
+//! Per-directory profile pinning through `.terraform-profile` files
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// Name of the file pinning a profile for a directory and its children
+pub const PIN_FILE: &str = ".terraform-profile";
+
+/// Find the profile pinned for a directory, walking up to the git root
+///
+/// Returns the pinned profile name along with the pin file it was read from
+pub fn find_pinned_profile(start: &Path) -> Result<Option<(String, PathBuf)>> {
+    for directory in start.ancestors() {
+        let pin_file = directory.join(PIN_FILE);
+        tracing::trace!("Looking for a pin in {}", pin_file.display());
+        if pin_file.is_file() {
+            tracing::debug!("Reading the pinned profile in {}", pin_file.display());
+            let content = std::fs::read_to_string(&pin_file)
+                .with_context(|| format!("Couldn't read {}", pin_file.display()))?;
+            let name = content.lines().next().unwrap_or_default().trim();
+            if name.is_empty() {
+                anyhow::bail!("{} doesn't name any profile", pin_file.display());
+            }
+            return Ok(Some((name.to_string(), pin_file)));
+        }
+        if directory.join(".git").exists() {
+            break;
+        }
+    }
+    Ok(None)
+}
+
+/// Pin a profile for a directory by writing its `.terraform-profile` file
+pub fn pin_profile(directory: &Path, name: &str) -> Result<PathBuf> {
+    let pin_file = directory.join(PIN_FILE);
+    tracing::info!("Writing {}", pin_file.display());
+    std::fs::write(&pin_file, format!("{name}\n"))
+        .with_context(|| format!("Couldn't write {}", pin_file.display()))?;
+    Ok(pin_file)
+}
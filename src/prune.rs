@@ -0,0 +1,281 @@
+//! Files of the store that can't be used as profiles, found by `prune` to be deleted or set
+//! aside
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{agent, api, encryption, metadata, permissions, state, store};
+
+/// Name of the directory inside the project directory the pruned files are set aside in
+pub const QUARANTINE_DIRECTORY: &str = "quarantine";
+
+/// Why a file of the store can't be used as a profile
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case", tag = "kind", content = "detail")]
+pub enum Problem {
+    /// The file is empty
+    Empty,
+    /// The file name isn't `<name>.tfrc.json`, with a name a profile can have
+    UnexpectedName,
+    /// The file doesn't hold the credentials terraform expects
+    InvalidCredentials(String),
+    /// Terraform cloud refused the tokens of these hostnames or reported them as expired
+    RefusedTokens(Vec<String>),
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::Empty => write!(f, "it is empty"),
+            Problem::UnexpectedName => write!(f, "its name isn't `<name>.tfrc.json`"),
+            Problem::InvalidCredentials(error) => write!(f, "{error}"),
+            Problem::RefusedTokens(hostnames) => write!(
+                f,
+                "the tokens of {} are expired or were refused",
+                hostnames.join(", ")
+            ),
+        }
+    }
+}
+
+/// What to do with a file of the store that can't be used as a profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disposal {
+    /// Move it to the quarantine
+    Quarantine,
+    /// Delete it
+    Delete,
+}
+
+/// A file of the store that can't be used as a profile
+#[derive(Debug, Serialize)]
+pub struct Stray {
+    /// Path of the file
+    pub path: PathBuf,
+    /// Name of the profile the file is registered as, if it is one
+    pub name: Option<String>,
+    /// Why it can't be used
+    pub problem: Problem,
+}
+
+/// Find the files of a project directory that can't be used as profiles, checking the tokens of
+/// the others with terraform cloud when asked to
+///
+/// The profiles that can't be decrypted or fetched are left out, as nothing tells their content
+/// is wrong, and so are the leftovers of interrupted writes, which `doctor --fix` removes
+pub fn find(
+    project_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+    verify: bool,
+) -> Result<Vec<Stray>> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(project_directory)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+
+    let mut strays = Vec::new();
+    for path in paths {
+        let file_name = path.file_name().and_then(|name| name.to_str());
+        if file_name.is_some_and(|name| store::is_reserved_file(name) || name.starts_with(".tmp")) {
+            continue;
+        }
+        let name = profiles
+            .iter()
+            .find(|(_, profile_path)| **profile_path == path)
+            .map(|(name, _)| name.clone());
+        let problem = if std::fs::metadata(&path)?.len() == 0 {
+            Problem::Empty
+        } else if name.is_none() {
+            Problem::UnexpectedName
+        } else {
+            match check_profile(&path, verify) {
+                Some(problem) => problem,
+                None => continue,
+            }
+        };
+        strays.push(Stray {
+            path,
+            name,
+            problem,
+        });
+    }
+    Ok(strays)
+}
+
+/// Check the content of a registered profile, and its tokens when asked to
+fn check_profile(profile_path: &Path, verify: bool) -> Option<Problem> {
+    let content = match agent::read(profile_path)
+        .map_or_else(|| encryption::decrypt_profile(profile_path), Ok)
+    {
+        Ok(content) => content,
+        Err(error) => {
+            tracing::warn!("Skipping {}: {error:#}", profile_path.display());
+            return None;
+        }
+    };
+    if let Err(error) = store::validate_new_credentials(&content) {
+        return Some(Problem::InvalidCredentials(format!("{error:#}")));
+    }
+    if !verify {
+        return None;
+    }
+    // The tokens read from a source are left out, and so are those that couldn't be checked
+    let refused: Vec<String> = store::tokens(&content)
+        .ok()?
+        .into_iter()
+        .filter(|(host, token)| {
+            matches!(
+                api::Client::new(host, token).token_status(),
+                Ok(api::TokenStatus::Expired | api::TokenStatus::Unauthorized)
+            )
+        })
+        .map(|(host, _)| host)
+        .collect();
+    (!refused.is_empty()).then_some(Problem::RefusedTokens(refused))
+}
+
+/// Delete a file that can't be used as a profile or move it to the quarantine, forgetting what
+/// is noted about the profile it is registered as, and returning where it was moved if it was
+pub fn dispose(
+    project_directory: &Path,
+    stray: &Stray,
+    disposal: Disposal,
+) -> Result<Option<PathBuf>> {
+    let quarantined = match disposal {
+        Disposal::Quarantine => Some(quarantine(project_directory, &stray.path)?),
+        Disposal::Delete => {
+            tracing::info!("Removing {}", stray.path.display());
+            std::fs::remove_file(&stray.path)?;
+            None
+        }
+    };
+    if let Some(name) = &stray.name {
+        let mut metadata = metadata::Metadata::load(project_directory)?;
+        metadata.forget_profile(name);
+        metadata.save(project_directory)?;
+    }
+    Ok(quarantined)
+}
+
+/// Move a file of the store to the quarantine, as `<file name>.<timestamp>`, so it is neither
+/// listed nor synced but can still be looked at
+pub fn quarantine(project_directory: &Path, path: &Path) -> Result<PathBuf> {
+    let directory = project_directory.join(QUARANTINE_DIRECTORY);
+    if !directory.exists() {
+        std::fs::create_dir(&directory)?;
+        permissions::restrict_directory(&directory)?;
+    }
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let quarantined = directory.join(format!("{file_name}.{}", state::now()));
+    tracing::info!("Moving {} to {}", path.display(), quarantined.display());
+    std::fs::rename(path, &quarantined)?;
+    Ok(quarantined)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Credentials holding a single token for `app.terraform.io`
+    const CREDENTIALS: &str = r#"{"credentials": {"app.terraform.io": {"token": "secret"}}}"#;
+
+    /// A store holding a valid profile, an empty one, a profile without hostnames, a file named
+    /// like no profile and the state
+    fn cluttered_store() -> (tempfile::TempDir, store::ProfileStore) {
+        let directory = tempfile::tempdir().unwrap();
+        let store = store::ProfileStore::open(directory.path());
+        store.register("work", CREDENTIALS).unwrap();
+        std::fs::write(store.profile_path("empty"), "").unwrap();
+        std::fs::write(store.profile_path("hostless"), r#"{"credentials": {}}"#).unwrap();
+        std::fs::write(directory.path().join("notes.txt"), "todo").unwrap();
+        state::State::load(directory.path())
+            .unwrap()
+            .save(directory.path())
+            .unwrap();
+        (directory, store)
+    }
+
+    #[test]
+    fn finds_the_files_that_cant_be_profiles() {
+        let (_directory, store) = cluttered_store();
+        let strays = find(store.directory(), &store.profiles().unwrap(), false).unwrap();
+        let found: Vec<(String, Option<&str>, String)> = strays
+            .iter()
+            .map(|stray| {
+                (
+                    stray
+                        .path
+                        .file_name()
+                        .unwrap()
+                        .to_string_lossy()
+                        .to_string(),
+                    stray.name.as_deref(),
+                    stray.problem.to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    String::from("empty.tfrc.json"),
+                    Some("empty"),
+                    String::from("it is empty")
+                ),
+                (
+                    String::from("hostless.tfrc.json"),
+                    Some("hostless"),
+                    String::from("The credentials don't hold any hostname")
+                ),
+                (
+                    String::from("notes.txt"),
+                    None,
+                    String::from("its name isn't `<name>.tfrc.json`")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn disposing_forgets_the_profile() {
+        let (_directory, store) = cluttered_store();
+        let mut noted = metadata::Metadata::load(store.directory()).unwrap();
+        noted.update("empty", |metadata| {
+            metadata.description = Some(String::from("Never filled"))
+        });
+        noted.save(store.directory()).unwrap();
+
+        let strays = find(store.directory(), &store.profiles().unwrap(), false).unwrap();
+        let quarantined = dispose(store.directory(), &strays[0], Disposal::Quarantine)
+            .unwrap()
+            .unwrap();
+        assert!(quarantined.starts_with(store.directory().join(QUARANTINE_DIRECTORY)));
+        assert!(quarantined.exists());
+        assert!(metadata::Metadata::load(store.directory())
+            .unwrap()
+            .get("empty")
+            .is_empty());
+        assert_eq!(
+            dispose(store.directory(), &strays[2], Disposal::Delete).unwrap(),
+            None
+        );
+        assert!(!strays[2].path.exists());
+
+        let mut profiles: Vec<String> = store.profiles().unwrap().into_keys().collect();
+        profiles.sort();
+        assert_eq!(profiles, vec!["hostless", "work"]);
+        assert_eq!(
+            find(store.directory(), &store.profiles().unwrap(), false)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Rendering the tokens of a profile to files outside the store, for CI jobs and tools pointed at
+//! them through `TF_CLI_CONFIG_FILE` rather than reading the managed credentials
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    credentials::CredentialsFile, github, permissions, store, store::ProfileStore, terraformrc,
+};
+
+/// Write the tokens of a profile to a credentials file at `out`, and to a CLI configuration
+/// holding a credentials block per hostname when given one, both readable by their owner only
+///
+/// Refuses to write inside the store or the terraform directory, whose files terraform-profile
+/// manages, even through a link to them. In a GitHub Actions job, the tokens are masked in the
+/// logs and the paths set as the `credentials-file` and `terraformrc` outputs of the step.
+pub fn render(
+    store: &ProfileStore,
+    credentials_file: &CredentialsFile,
+    name: &str,
+    out: &Path,
+    terraformrc: Option<&Path>,
+    github_actions: bool,
+) -> Result<()> {
+    let profile_path = store.find(name)?;
+    let managed = [credentials_file.terraform_directory(), store.directory()]
+        .map(|directory| std::fs::canonicalize(directory).unwrap_or_else(|_| directory.into()));
+    for path in std::iter::once(out).chain(terraformrc) {
+        let path = resolve(path)?;
+        if managed.iter().any(|directory| path.starts_with(directory)) {
+            anyhow::bail!(
+                "{} is managed by terraform-profile, render to another path",
+                path.display()
+            );
+        }
+    }
+
+    let tokens = store::read_profile_tokens(&profile_path)?;
+    if tokens.is_empty() {
+        anyhow::bail!("The profile `{name}` holds no token");
+    }
+    if github_actions {
+        tokens.values().for_each(|token| github::mask(token));
+    }
+    // The temporary files are only readable by their owner, so the tokens are never exposed
+    store::write_file_atomically(
+        out,
+        format!("{}\n", store::credentials_with_tokens(&tokens)),
+    )?;
+    permissions::restrict_file(out)?;
+    if let Some(terraformrc) = terraformrc {
+        store::write_file_atomically(terraformrc, terraformrc::render_credentials(&tokens))?;
+        permissions::restrict_file(terraformrc)?;
+    }
+    if github_actions {
+        github::set_output(
+            "credentials-file",
+            &std::path::absolute(out)?.to_string_lossy(),
+        )?;
+        if let Some(terraformrc) = terraformrc {
+            github::set_output(
+                "terraformrc",
+                &std::path::absolute(terraformrc)?.to_string_lossy(),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Get the path a file is written at, following the links to its directory, which has to exist
+fn resolve(path: &Path) -> Result<PathBuf> {
+    let path = std::path::absolute(path)?;
+    let (Some(directory), Some(file_name)) = (path.parent(), path.file_name()) else {
+        anyhow::bail!("{} isn't the path of a file", path.display());
+    };
+    let directory = std::fs::canonicalize(directory)
+        .with_context(|| format!("Couldn't find the directory of {}", path.display()))?;
+    Ok(directory.join(file_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A store holding the work profile, with a token, and the empty profile, with no hostname
+    fn store() -> (tempfile::TempDir, ProfileStore, CredentialsFile) {
+        let home = tempfile::tempdir().unwrap();
+        let store = ProfileStore::open(home.path().join("store"));
+        let credentials_file = CredentialsFile::new(home.path().join(".terraform.d"));
+        std::fs::create_dir_all(store.directory()).unwrap();
+        std::fs::create_dir_all(credentials_file.terraform_directory()).unwrap();
+        store
+            .register(
+                "work",
+                r#"{"credentials": {"app.terraform.io": {"token": "secret"}}}"#,
+            )
+            .unwrap();
+        std::fs::write(store.profile_path("empty"), r#"{"credentials": {}}"#).unwrap();
+        (home, store, credentials_file)
+    }
+
+    #[test]
+    fn renders_the_tokens_of_a_profile() {
+        let (home, store, credentials_file) = store();
+        let out = home.path().join("credentials.tfrc.json");
+        let terraformrc = home.path().join("terraformrc");
+
+        render(
+            &store,
+            &credentials_file,
+            "work",
+            &out,
+            Some(&terraformrc),
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            store::read_profile_tokens(&out).unwrap(),
+            store.tokens("work").unwrap()
+        );
+        assert_eq!(
+            terraformrc::read_tokens(&std::fs::read_to_string(&terraformrc).unwrap()).unwrap(),
+            store.tokens("work").unwrap()
+        );
+    }
+
+    #[test]
+    fn refuses_managed_paths_and_profiles_without_tokens() {
+        let (home, store, credentials_file) = store();
+        for out in [store.profile_path("copy"), credentials_file.path()] {
+            let error = render(&store, &credentials_file, "work", &out, None, false).unwrap_err();
+            assert!(error.to_string().ends_with("render to another path"));
+            assert!(!out.exists());
+        }
+
+        let out = home.path().join("credentials.tfrc.json");
+        let error = render(&store, &credentials_file, "empty", &out, None, false).unwrap_err();
+        assert_eq!(error.to_string(), "The profile `empty` holds no token");
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn refuses_links_to_managed_directories() {
+        let (home, store, credentials_file) = store();
+        let link = home.path().join("link");
+        std::os::unix::fs::symlink(store.directory(), &link).unwrap();
+
+        let out = link.join("copy.tfrc.json");
+        let error = render(&store, &credentials_file, "work", &out, None, false).unwrap_err();
+        assert!(error.to_string().ends_with("render to another path"));
+        assert!(!store.profile_path("copy").exists());
+    }
+}
@@ -0,0 +1,137 @@
+//! Telling which of the account tokens a profile holds, so revoking it never hits a token that
+//! other machines or pipelines of the account still use
+
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+
+use crate::{
+    api::{self, TokenDetails},
+    output,
+    state::State,
+};
+
+/// Which of the account tokens a profile holds for a hostname
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Identified {
+    /// The token is known for sure, the CLI having created it or its identifier being given
+    Confirmed(TokenDetails),
+    /// The token can only be guessed, the guess being the most recently used one if any
+    Unconfirmed(Option<TokenDetails>),
+}
+
+/// A confirmed token to revoke
+#[derive(Debug, Clone)]
+pub struct Revocation {
+    /// Hostname the token is for
+    pub hostname: String,
+    /// Token to authenticate the revocation with, either the one revoked or a newer one
+    pub token: String,
+    /// The token revoked
+    pub target: TokenDetails,
+}
+
+/// Tell which of the account tokens a profile holds for a hostname, from the tokens the CLI
+/// created and the identifiers given with `--token-id`
+///
+/// The API never exposes token values, so without either the most recently used token is
+/// only returned as a guess, for the user to confirm.
+pub fn identify(
+    state: &State,
+    profile: &str,
+    hostname: &str,
+    token: &str,
+    token_ids: &[String],
+) -> Result<Identified> {
+    let tokens = api::Client::new(hostname, token).tokens()?;
+    let known: Vec<&str> = state
+        .minted_token_id(profile, hostname, token)
+        .into_iter()
+        .chain(token_ids.iter().map(String::as_str))
+        .collect();
+    let mut confirmed = tokens
+        .iter()
+        .filter(|details| known.contains(&details.id.as_str()));
+    match (confirmed.next(), confirmed.next()) {
+        (Some(details), None) => Ok(Identified::Confirmed(details.clone())),
+        (Some(_), Some(_)) => anyhow::bail!(
+            "Several tokens of the {hostname} account are known to be that of profile \
+             `{profile}`, pass only the id of the one it holds with `--token-id`"
+        ),
+        (None, _) => Ok(Identified::Unconfirmed(
+            api::guess_current_token(tokens).ok(),
+        )),
+    }
+}
+
+/// Tell apart every token of a profile before any is revoked, so it is never left half revoked,
+/// failing on the first token that can only be guessed
+pub fn plan(
+    state: &State,
+    profile: &str,
+    tokens: BTreeMap<String, String>,
+    token_ids: &[String],
+) -> Result<Vec<Revocation>> {
+    let mut revocations = Vec::new();
+    for (hostname, token) in tokens {
+        let identified = identify(state, profile, &hostname, &token, token_ids)
+            .with_context(|| format!("Couldn't list the {hostname} tokens"))?;
+        match identified {
+            Identified::Confirmed(target) => revocations.push(Revocation {
+                hostname,
+                token,
+                target,
+            }),
+            Identified::Unconfirmed(guess) => {
+                anyhow::bail!(unconfirmed(profile, &hostname, guess.as_ref()))
+            }
+        }
+    }
+    Ok(revocations)
+}
+
+/// Explain that a profile's token for a hostname can't be told apart from the other tokens of
+/// the account, suggesting the guess if any
+pub fn unconfirmed(profile: &str, hostname: &str, guess: Option<&TokenDetails>) -> String {
+    let advice = match guess {
+        Some(guess) => format!(
+            "The most recently used one is {guess}, pass `--token-id {}` if it is this one",
+            guess.id
+        ),
+        None => format!("Pass its id, from the {hostname} user settings, with `--token-id`"),
+    };
+    format!(
+        "Couldn't tell which {hostname} token profile `{profile}` holds, the CLI only knows \
+         those it created. {advice}."
+    )
+}
+
+/// Revoke tokens one hostname after the other, telling on failure which were revoked and which
+/// are still valid
+pub fn revoke(revocations: &[Revocation]) -> Result<()> {
+    for (index, revocation) in revocations.iter().enumerate() {
+        let revoked = api::Client::new(&revocation.hostname, &revocation.token)
+            .revoke_token(&revocation.target.id);
+        if let Err(error) = revoked {
+            let hostnames = |revocations: &[Revocation]| match revocations {
+                [] => String::from("none"),
+                revocations => revocations
+                    .iter()
+                    .map(|revocation| revocation.hostname.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            };
+            return Err(error.context(format!(
+                "Couldn't revoke the {} token. Revoked: {}. Still valid: {}",
+                revocation.hostname,
+                hostnames(&revocations[..index]),
+                hostnames(&revocations[index..])
+            )));
+        }
+        output::notice(format!(
+            "Revoked the {} token `{}`",
+            revocation.hostname, revocation.target.id
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,171 @@
+//! Replacing the tokens of a profile with new ones minted through the API, the old ones being
+//! left for the caller to revoke once told apart from the other tokens of the account
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+
+use crate::{
+    api::{self, TokenDetails},
+    backup,
+    credentials::{self, CredentialsFile},
+    encryption, journal,
+    revocation::{self, Identified, Revocation},
+    source,
+    state::State,
+    store::{self, ProfileStore},
+};
+
+/// The old tokens of a rotated profile, all still valid
+#[derive(Debug, Default)]
+pub struct Rotated {
+    /// The old tokens told apart for sure, to revoke with the new ones
+    pub revocations: Vec<Revocation>,
+    /// The hostnames whose old token couldn't be told apart, with the most recently used token
+    /// of the account if any
+    pub unconfirmed: Vec<(String, Option<TokenDetails>)>,
+}
+
+/// Fail when the tokens of a registered profile are kept outside the store, where they have to
+/// be rotated
+pub fn ensure_stored(store: &ProfileStore, name: &str) -> Result<()> {
+    if source::has_sources(&encryption::decrypt_profile(&store.find(name)?)?) {
+        anyhow::bail!(
+            "The tokens of profile `{name}` are kept outside the store, rotate them there"
+        );
+    }
+    Ok(())
+}
+
+/// Mint new tokens for every hostname of a registered profile and store them, after backing the
+/// profile up, bringing the credentials made from it up to date
+///
+/// The old tokens are told apart before anything is minted, from the tokens the CLI created and
+/// the identifiers given with `--token-id`.
+pub fn rotate(
+    store: &ProfileStore,
+    credentials_file: &CredentialsFile,
+    name: &str,
+    token_ids: &[String],
+    backup_retention: usize,
+) -> Result<Rotated> {
+    ensure_stored(store, name)?;
+    let profile_path = store.find(name)?;
+    let project_directory = store.directory();
+    let original = encryption::read_profile(&profile_path)?;
+
+    let state = State::load(project_directory)?;
+    let mut tokens = BTreeMap::new();
+    let mut minted = Vec::new();
+    for (host, token) in store::tokens(&original)? {
+        let identified = revocation::identify(&state, name, &host, &token, token_ids)?;
+        let created = api::Client::new(&host, &token)
+            .create_token(&format!("{} ({name})", env!("CARGO_PKG_NAME")))?;
+        tokens.insert(host.clone(), created.token.clone());
+        minted.push((host, created, identified));
+    }
+    let content = with_tokens(&original, &tokens)?;
+    backup::snapshot(
+        project_directory,
+        &format!("rotate {name}"),
+        &[&profile_path],
+        backup_retention,
+    )?;
+    journal::transaction(
+        project_directory,
+        &format!("rotate {name}"),
+        &[&profile_path, &credentials_file.path()],
+        || {
+            store::write_file_atomically(
+                &profile_path,
+                encryption::reseal(&profile_path, &content)?,
+            )?;
+            credentials::refresh(
+                name,
+                &profile_path,
+                credentials_file.terraform_directory(),
+                project_directory,
+            )
+        },
+    )?;
+    let mut state = State::load(project_directory)?;
+    for (host, created, _) in &minted {
+        state.record_minted_token(name, host, &created.id, &created.token);
+    }
+    // The new tokens expire differently, if ever
+    state.token_expiries.remove(name);
+    state.save(project_directory)?;
+
+    let mut rotated = Rotated::default();
+    for (hostname, created, identified) in minted {
+        match identified {
+            Identified::Confirmed(target) => rotated.revocations.push(Revocation {
+                hostname,
+                token: created.token,
+                target,
+            }),
+            Identified::Unconfirmed(guess) => rotated.unconfirmed.push((hostname, guess)),
+        }
+    }
+    Ok(rotated)
+}
+
+/// Replace the tokens of credentials by hostname, keeping their formatting and the other keys
+fn with_tokens(original: &str, tokens: &BTreeMap<String, String>) -> Result<String> {
+    let mut credentials: serde_json::Value = serde_json::from_str(original)?;
+    for (host, token) in tokens {
+        credentials["credentials"][host]["token"] = serde_json::Value::String(token.clone());
+    }
+    store::format_like(original, &credentials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replacing_tokens_keeps_the_rest() {
+        let original = r#"{
+   "credentials": {
+      "app.terraform.io": {
+         "token": "old-app"
+      },
+      "tfe.example.com": {
+         "token": "old-tfe",
+         "x-owner": "platform"
+      }
+   },
+   "comment": "work"
+}
+"#;
+        let tokens = BTreeMap::from([(String::from("tfe.example.com"), String::from("new-tfe"))]);
+        assert_eq!(
+            with_tokens(original, &tokens).unwrap(),
+            original.replace("old-tfe", "new-tfe")
+        );
+    }
+
+    #[test]
+    fn refuses_tokens_kept_outside_the_store() {
+        let directory = tempfile::tempdir().unwrap();
+        let store = ProfileStore::open(directory.path());
+        store
+            .register(
+                "sourced",
+                r#"{"credentials": {"app.terraform.io": {"source": "pass://terraform/app"}}}"#,
+            )
+            .unwrap();
+        store
+            .register(
+                "stored",
+                r#"{"credentials": {"app.terraform.io": {"token": "secret"}}}"#,
+            )
+            .unwrap();
+
+        let error = ensure_stored(&store, "sourced").unwrap_err();
+        assert!(error.to_string().contains("kept outside the store"));
+        let credentials_file = CredentialsFile::new(directory.path().join("terraform.d"));
+        assert!(rotate(&store, &credentials_file, "sourced", &[], 0).is_err());
+        ensure_stored(&store, "stored").unwrap();
+    }
+}
@@ -0,0 +1,53 @@
+//! Tokens kept outside the store, which profiles reference with a `"source"` in place of the
+//! `"token"` of a hostname, read from 1Password (`op://`), the password store (`pass://`) or
+//! Bitwarden (`bw://`) whenever they are needed
+
+use anyhow::{Context, Result};
+
+use crate::{bitwarden, onepassword, pass};
+
+/// Prefix of the secret references of the 1Password CLI
+pub const ONEPASSWORD_PREFIX: &str = "op://";
+
+/// Whether a profile references tokens kept outside the store
+pub fn has_sources(content: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(content).is_ok_and(|value| {
+        value["credentials"].as_object().is_some_and(|hosts| {
+            hosts
+                .values()
+                .any(|entry| entry["source"].as_str().is_some())
+        })
+    })
+}
+
+/// Read the token a source references
+fn read(source: &str) -> Result<String> {
+    if source.starts_with(ONEPASSWORD_PREFIX) {
+        onepassword::read(source)
+    } else if let Some(entry) = source.strip_prefix(pass::REFERENCE_PREFIX) {
+        pass::read(entry)
+    } else if let Some(item) = source.strip_prefix(bitwarden::REFERENCE_PREFIX) {
+        bitwarden::read(item)
+    } else {
+        anyhow::bail!("Unknown source `{source}`, expected `op://`, `pass://` or `bw://`")
+    }
+}
+
+/// Replace the sources of a profile with the tokens they reference
+pub fn resolve(content: &str) -> Result<String> {
+    let mut value: serde_json::Value =
+        serde_json::from_str(content).context("The profile isn't valid JSON")?;
+    if let Some(hosts) = value["credentials"].as_object_mut() {
+        for (host, entry) in hosts.iter_mut() {
+            let Some(source) = entry["source"].as_str().map(str::to_string) else {
+                continue;
+            };
+            let token = read(&source).with_context(|| format!("Couldn't read the {host} token"))?;
+            if let Some(entry) = entry.as_object_mut() {
+                entry.shift_remove("source");
+                entry.insert(String::from("token"), serde_json::Value::String(token));
+            }
+        }
+    }
+    crate::store::format_like(content, &value)
+}
@@ -0,0 +1,266 @@
+//! Small persistent state kept next to the registered profiles
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::api::TokenStatus;
+
+/// Name of the state file inside the project directory
+pub const STATE_FILE: &str = "state.json";
+
+/// Last known status of a token, as reported by the API
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CachedTokenStatus {
+    /// Status reported by the API
+    pub status: TokenStatus,
+    /// When the status was checked, in seconds since the Unix epoch
+    pub checked_at: u64,
+}
+
+/// Last known expiration of a token, as reported by the API
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CachedTokenExpiry {
+    /// When the token expires, in seconds since the Unix epoch, none when it never does or the
+    /// API couldn't tell
+    pub expires_at: Option<u64>,
+    /// When the expiration was checked, in seconds since the Unix epoch
+    pub checked_at: u64,
+}
+
+/// A token the CLI created, telling for sure which of the account tokens a profile holds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MintedToken {
+    /// Identifier of the token, e.g. `at-XXXXXXXXXXXXXXXX`
+    pub id: String,
+    /// Fingerprint of the token, telling whether the profile still holds it
+    pub fingerprint: String,
+}
+
+/// Profile the credentials were copied from, in the copy switching mode
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CopiedProfile {
+    /// Name of the profile that was copied
+    pub profile: String,
+    /// SHA-256 of the copy when it was made, telling whether it was changed since
+    pub sha256: String,
+    /// Hostnames switched to another profile with `switch --host`, and the profile of each
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hosts: BTreeMap<String, String>,
+}
+
+/// A profile as it was when last synced with a remote
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncedProfile {
+    /// SHA-256 of the content, telling whether it was changed since
+    pub sha256: String,
+    /// How many times each machine changed the profile
+    pub versions: BTreeMap<String, u64>,
+}
+
+/// Everything the tool remembers between invocations
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    /// Token statuses per profile, then per hostname
+    #[serde(default)]
+    pub token_statuses: BTreeMap<String, BTreeMap<String, CachedTokenStatus>>,
+    /// Token expirations per profile, then per hostname
+    #[serde(default)]
+    pub token_expiries: BTreeMap<String, BTreeMap<String, CachedTokenExpiry>>,
+    /// Tokens the CLI created, per profile then per hostname
+    #[serde(default)]
+    pub minted_tokens: BTreeMap<String, BTreeMap<String, MintedToken>>,
+    /// Profile that was active before the last switch
+    #[serde(default)]
+    pub previous_profile: Option<String>,
+    /// When each profile was last switched to, in seconds since the Unix epoch
+    #[serde(default)]
+    pub last_switched: BTreeMap<String, u64>,
+    /// Profile the credentials are a copy of, in the copy switching mode
+    #[serde(default)]
+    pub copied_profile: Option<CopiedProfile>,
+    /// Profile the credentials are a hard link to, in the hard link switching mode
+    #[serde(default)]
+    pub hard_linked_profile: Option<String>,
+    /// Profile the credentials helper serves, in the helper switching mode
+    #[serde(default)]
+    pub helper_profile: Option<String>,
+    /// Identifier of this machine in the versions of the synced profiles
+    #[serde(default)]
+    pub machine_id: Option<String>,
+    /// Profiles as they were when last synced, per remote then per name
+    #[serde(default)]
+    pub synced: BTreeMap<String, BTreeMap<String, SyncedProfile>>,
+    /// SHA-256 of each registered profile when `refresh` last indexed the store
+    #[serde(default)]
+    pub indexed: BTreeMap<String, String>,
+}
+
+impl State {
+    /// Path of the state file inside a project directory
+    fn path(project_directory: &Path) -> PathBuf {
+        project_directory.join(STATE_FILE)
+    }
+
+    /// Load the state, starting from scratch when it was never saved
+    pub fn load(project_directory: &Path) -> Result<Self> {
+        let path = Self::path(project_directory);
+        if !path.exists() {
+            tracing::debug!("No state in {}, starting from scratch", path.display());
+            return Ok(Self::default());
+        }
+        tracing::debug!("Reading the state in {}", path.display());
+        let content = std::fs::read_to_string(&path)?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("The state file {} is corrupted", path.display()))
+    }
+
+    /// Save the state through a rename, so it is never left half-written
+    pub fn save(&self, project_directory: &Path) -> Result<()> {
+        crate::store::write_file_atomically(
+            &Self::path(project_directory),
+            &serde_json::to_string_pretty(self)?,
+        )
+    }
+
+    /// Carry what is remembered about a profile over to its new name
+    pub fn rename_profile(&mut self, name: &str, new_name: &str) {
+        if let Some(statuses) = self.token_statuses.remove(name) {
+            self.token_statuses.insert(new_name.to_string(), statuses);
+        }
+        if let Some(expiries) = self.token_expiries.remove(name) {
+            self.token_expiries.insert(new_name.to_string(), expiries);
+        }
+        if let Some(minted) = self.minted_tokens.remove(name) {
+            self.minted_tokens.insert(new_name.to_string(), minted);
+        }
+        if self.previous_profile.as_deref() == Some(name) {
+            self.previous_profile = Some(new_name.to_string());
+        }
+        if let Some(last_switched) = self.last_switched.remove(name) {
+            self.last_switched
+                .insert(new_name.to_string(), last_switched);
+        }
+        if let Some(sha256) = self.indexed.remove(name) {
+            self.indexed.insert(new_name.to_string(), sha256);
+        }
+        if let Some(copied) = &mut self.copied_profile {
+            if copied.profile == name {
+                copied.profile = new_name.to_string();
+            }
+            for profile in copied.hosts.values_mut() {
+                if profile == name {
+                    *profile = new_name.to_string();
+                }
+            }
+        }
+        if self.hard_linked_profile.as_deref() == Some(name) {
+            self.hard_linked_profile = Some(new_name.to_string());
+        }
+        if self.helper_profile.as_deref() == Some(name) {
+            self.helper_profile = Some(new_name.to_string());
+        }
+    }
+
+    /// Forget everything remembered about a removed profile
+    pub fn forget_profile(&mut self, name: &str) {
+        self.token_statuses.remove(name);
+        self.token_expiries.remove(name);
+        self.minted_tokens.remove(name);
+        if self.previous_profile.as_deref() == Some(name) {
+            self.previous_profile = None;
+        }
+        self.last_switched.remove(name);
+        self.indexed.remove(name);
+        if self
+            .copied_profile
+            .as_ref()
+            .is_some_and(|copied| copied.profile == name)
+        {
+            self.copied_profile = None;
+        }
+        if let Some(copied) = &mut self.copied_profile {
+            copied.hosts.retain(|_, profile| profile != name);
+        }
+        if self.hard_linked_profile.as_deref() == Some(name) {
+            self.hard_linked_profile = None;
+        }
+        if self.helper_profile.as_deref() == Some(name) {
+            self.helper_profile = None;
+        }
+    }
+
+    /// Remember the status of a profile's token for a hostname
+    pub fn record_token_status(&mut self, profile: &str, hostname: &str, status: TokenStatus) {
+        self.token_statuses
+            .entry(profile.to_string())
+            .or_default()
+            .insert(
+                hostname.to_string(),
+                CachedTokenStatus {
+                    status,
+                    checked_at: now(),
+                },
+            );
+    }
+
+    /// Remember when a profile's token for a hostname expires
+    pub fn record_token_expiry(&mut self, profile: &str, hostname: &str, expires_at: Option<u64>) {
+        self.token_expiries
+            .entry(profile.to_string())
+            .or_default()
+            .insert(
+                hostname.to_string(),
+                CachedTokenExpiry {
+                    expires_at,
+                    checked_at: now(),
+                },
+            );
+    }
+
+    /// Remember the identifier of a token created for a profile's hostname
+    pub fn record_minted_token(&mut self, profile: &str, hostname: &str, id: &str, token: &str) {
+        self.minted_tokens
+            .entry(profile.to_string())
+            .or_default()
+            .insert(
+                hostname.to_string(),
+                MintedToken {
+                    id: id.to_string(),
+                    fingerprint: crate::store::fingerprint(token),
+                },
+            );
+    }
+
+    /// Get the identifier of a profile's token for a hostname, when the CLI created it and the
+    /// profile still holds it
+    pub fn minted_token_id(&self, profile: &str, hostname: &str, token: &str) -> Option<&str> {
+        self.minted_tokens
+            .get(profile)?
+            .get(hostname)
+            .filter(|minted| minted.fingerprint == crate::store::fingerprint(token))
+            .map(|minted| minted.id.as_str())
+    }
+
+    /// Whether any token of a profile was last seen expired or refused
+    pub fn has_known_invalid_token(&self, profile: &str) -> bool {
+        self.token_statuses.get(profile).is_some_and(|statuses| {
+            statuses
+                .values()
+                .any(|cached| cached.status != TokenStatus::Valid)
+        })
+    }
+}
+
+/// Current time in seconds since the Unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
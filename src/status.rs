@@ -0,0 +1,228 @@
+//! The status of the credentials terraform currently uses, as `status` reports it
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+use crate::{
+    credentials::{Credentials, CredentialsFile},
+    expiry, permissions,
+    state::State,
+    store::{self, ProfileStore},
+};
+
+/// Everything known about the credentials terraform currently uses
+#[derive(serde::Serialize, Debug)]
+pub struct Status {
+    /// Name of the profile the credentials point to, if any
+    pub active: Option<String>,
+    /// Path the credentials symlink points to, or of the profile they are a hard link to
+    pub target: Option<PathBuf>,
+    /// Whether the credentials point to a registered profile
+    pub managed: bool,
+    /// Whether the credentials are a symbolic link to a file that doesn't exist
+    pub dangling: bool,
+    /// Profile the credentials were copied from, in the copy switching mode
+    pub copy_of: Option<String>,
+    /// Whether the credentials were changed since they were copied from the profile, or the hard
+    /// link to it broke
+    pub drifted: bool,
+    /// Hostnames the credentials hold tokens or sources for
+    pub hostnames: Vec<String>,
+    /// Hostnames switched to another profile than the active one with `switch --host`, and the
+    /// profile of each
+    pub switched_hosts: BTreeMap<String, String>,
+    /// Names of the environment variables overriding the credentials file
+    pub environment_overrides: Vec<String>,
+    /// When the tokens of the active profile expire, per hostname, in seconds since the Unix
+    /// epoch, leaving out those that never do
+    pub token_expiries: BTreeMap<String, u64>,
+    /// Profile `TERRAFORM_PROFILE` names, used instead of the active one by `exec`, `env`,
+    /// `render` and the credentials helper
+    pub profile_override: Option<String>,
+    /// Stored profiles, and the store itself, the group or other users can access
+    pub exposed_paths: Vec<PathBuf>,
+}
+
+impl Status {
+    /// Gather the status of the credentials, fetching the expirations of the active profile's
+    /// tokens that are due a check
+    pub fn gather(
+        store: &ProfileStore,
+        credentials_file: &CredentialsFile,
+        profile_override: Option<String>,
+    ) -> Result<Self> {
+        let profiles = store.profiles()?;
+        let credentials_files = credentials_file.path();
+        let current = credentials_file.inspect(store)?;
+        let active = current.active_profile(&profiles).cloned();
+        let (target, copy_of, drifted) = match current {
+            Credentials::Symlink(link) => (Some(link), None, false),
+            Credentials::Hardlink { profile, broken } => {
+                (Some(store.profile_path(&profile)), None, broken)
+            }
+            Credentials::Copy { profile, drifted } => (None, Some(profile), drifted),
+            _ => (None, None, false),
+        };
+        // The credentials helper serves the profile itself, there being no credentials file
+        let hostnames = store::read_profile_hostnames(&credentials_files)
+            .or_else(
+                |_| match active.as_ref().and_then(|name| profiles.get(name)) {
+                    Some(profile_path) => store::read_profile_hostnames(profile_path),
+                    None => Ok(Vec::new()),
+                },
+            )
+            .unwrap_or_default();
+
+        let switched_hosts = match copy_of {
+            Some(_) if !drifted => State::load(store.directory())?
+                .copied_profile
+                .map(|copied| copied.hosts)
+                .unwrap_or_default(),
+            _ => BTreeMap::new(),
+        };
+
+        let mut token_expiries = match active
+            .as_ref()
+            .and_then(|name| profiles.get_key_value(name))
+        {
+            Some((name, profile_path)) => {
+                expiry::refresh_profile(store.directory(), name, profile_path)?
+            }
+            None => BTreeMap::new(),
+        };
+        // The hostnames switched to another profile don't use the active profile's tokens
+        token_expiries.retain(|host, _| !switched_hosts.contains_key(host));
+
+        Ok(Status {
+            managed: active.is_some(),
+            active,
+            target,
+            copy_of,
+            drifted,
+            dangling: credentials_files.is_symlink() && !credentials_files.exists(),
+            hostnames,
+            switched_hosts,
+            token_expiries,
+            environment_overrides: environment_overrides(),
+            profile_override,
+            exposed_paths: exposed_paths(store.directory(), &profiles),
+        })
+    }
+}
+
+/// Get the sorted paths of the store and the stored profiles the group or other users can access
+pub fn exposed_paths(
+    project_directory: &Path,
+    profiles: &HashMap<String, PathBuf>,
+) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = std::iter::once(project_directory)
+        .chain(profiles.values().map(PathBuf::as_path))
+        .filter(|path| permissions::is_exposed(path))
+        .map(Path::to_path_buf)
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// Get the sorted names of the set variables terraform reads credentials from before the file
+pub fn environment_overrides() -> Vec<String> {
+    let mut names: Vec<String> = std::env::vars_os()
+        .filter_map(|(key, _)| key.into_string().ok())
+        .filter(|key| is_environment_override(key))
+        .collect();
+    names.sort();
+    names
+}
+
+/// Whether terraform reads credentials from a variable before the file
+fn is_environment_override(key: &str) -> bool {
+    key.starts_with("TF_TOKEN_") || key == "TF_CLI_CONFIG_FILE"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SwitchMode;
+
+    /// Credentials holding a single token for `app.terraform.io`
+    const CREDENTIALS: &str = r#"{"credentials": {"app.terraform.io": {"token": "secret"}}}"#;
+
+    /// A store holding the work profile, whose token expiry was just fetched so nothing is
+    /// fetched again, and credentials that aren't installed yet
+    fn store() -> (tempfile::TempDir, ProfileStore, CredentialsFile) {
+        let home = tempfile::tempdir().unwrap();
+        let store = ProfileStore::open(home.path().join("store"));
+        let credentials_file = CredentialsFile::new(home.path().join(".terraform.d"));
+        std::fs::create_dir_all(store.directory()).unwrap();
+        std::fs::create_dir_all(credentials_file.terraform_directory()).unwrap();
+        permissions::restrict_directory(store.directory()).unwrap();
+        store.register("work", CREDENTIALS).unwrap();
+        let mut state = State::load(store.directory()).unwrap();
+        state.record_token_expiry("work", "app.terraform.io", Some(1_000));
+        state.save(store.directory()).unwrap();
+        (home, store, credentials_file)
+    }
+
+    #[test]
+    fn reports_the_linked_profile() {
+        let (_home, store, credentials_file) = store();
+        credentials_file
+            .install(&store, "work", SwitchMode::Symlink)
+            .unwrap();
+
+        let status = Status::gather(&store, &credentials_file, None).unwrap();
+        assert_eq!(status.active.as_deref(), Some("work"));
+        assert!(status.managed);
+        assert!(!status.dangling);
+        assert_eq!(status.target, Some(store.profile_path("work")));
+        assert_eq!(status.hostnames, ["app.terraform.io"]);
+        assert_eq!(
+            status.token_expiries,
+            BTreeMap::from([(String::from("app.terraform.io"), 1_000)])
+        );
+        assert!(status.exposed_paths.is_empty());
+    }
+
+    #[test]
+    fn reports_copies_and_dangling_links() {
+        let (_home, store, credentials_file) = store();
+        credentials_file
+            .install(&store, "work", SwitchMode::Copy)
+            .unwrap();
+        let copied = Status::gather(&store, &credentials_file, Some(String::from("ci"))).unwrap();
+        assert_eq!(copied.copy_of.as_deref(), Some("work"));
+        assert!(!copied.drifted);
+        assert_eq!(copied.profile_override.as_deref(), Some("ci"));
+
+        std::fs::remove_file(credentials_file.path()).unwrap();
+        crate::credentials::symlink(&store.profile_path("gone"), &credentials_file.path()).unwrap();
+        let dangling = Status::gather(&store, &credentials_file, None).unwrap();
+        assert!(dangling.dangling);
+        assert!(!dangling.managed);
+        assert!(dangling.hostnames.is_empty());
+        assert!(dangling.token_expiries.is_empty());
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn reports_the_profiles_others_can_read() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (_home, store, credentials_file) = store();
+        let profile_path = store.profile_path("work");
+        std::fs::set_permissions(&profile_path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let status = Status::gather(&store, &credentials_file, None).unwrap();
+        assert_eq!(status.exposed_paths, [profile_path]);
+    }
+
+    #[test]
+    fn tells_the_variables_overriding_the_file() {
+        assert!(is_environment_override("TF_TOKEN_app_terraform_io"));
+        assert!(is_environment_override("TF_CLI_CONFIG_FILE"));
+        assert!(!is_environment_override("TF_LOG"));
+    }
+}
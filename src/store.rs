@@ -0,0 +1,1312 @@
+//! The store of the registered profiles, one `<name>.tfrc.json` credentials file per profile
+//! in a directory readable by its owner only
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    agent, backup, bitwarden,
+    config::Config,
+    credentials, encryption,
+    error::{CliError, InvalidName, SchemaError},
+    git, helper, history, journal, lock, metadata, paths, permissions, prune, state, trash, update,
+};
+
+/// Extension of the profiles inside the store
+pub const PROFILE_EXTENSION: &str = ".tfrc.json";
+
+/// Profiles found changed since the store was last indexed
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct StoreChanges {
+    /// Whether the store was never indexed before, so no profile is told to be added
+    #[serde(skip)]
+    pub first: bool,
+    /// Profiles registered since
+    pub added: Vec<String>,
+    /// Profiles whose content changed since
+    pub changed: Vec<String>,
+    /// Profiles removed since, or that the state or the metadata still remembered
+    pub removed: Vec<String>,
+}
+
+/// Directory holding the registered profiles, along with what is remembered about them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileStore {
+    directory: PathBuf,
+}
+
+impl ProfileStore {
+    /// Use the profiles of a directory, which must already exist
+    pub fn open(directory: impl Into<PathBuf>) -> Self {
+        ProfileStore {
+            directory: directory.into(),
+        }
+    }
+
+    /// Use the store configured for a home directory, creating it readable by its owner only the
+    /// first time
+    pub fn initialize(home_dir: &Path, config: &Config) -> Result<Self> {
+        let directory = paths::storage_directory(home_dir, config);
+        if !directory.exists() {
+            std::fs::create_dir_all(&directory)
+                .with_context(|| format!("Couldn't create {}", directory.display()))?;
+            permissions::restrict_directory(&directory)?;
+        }
+        Ok(ProfileStore { directory })
+    }
+
+    /// Get the directory of the store
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Get the path a profile is stored at, whether it exists or not
+    pub fn profile_path(&self, name: &str) -> PathBuf {
+        self.directory.join(format!("{name}{PROFILE_EXTENSION}"))
+    }
+
+    /// Get all the files and register their profiles names
+    pub fn profiles(&self) -> Result<HashMap<String, PathBuf>> {
+        let mut entries = HashMap::new();
+        tracing::debug!("Listing the profiles in {}", self.directory.display());
+
+        let files = std::fs::read_dir(&self.directory).with_context(|| {
+            format!("Couldn't list the profiles in {}", self.directory.display())
+        })?;
+        for file in files.flatten() {
+            // The state kept next to the profiles isn't a profile, and `doctor` and `prune`
+            // report the other files along with the leftovers of interrupted writes
+            let name = file.file_name();
+            let name = name.to_str().unwrap_or_default();
+            if is_reserved_file(name) {
+                continue;
+            }
+            let Some(file_name) = name
+                .strip_suffix(PROFILE_EXTENSION)
+                .filter(|name| validate_profile_name(name).is_ok())
+            else {
+                continue;
+            };
+            let file_name = file_name.to_string();
+
+            tracing::trace!("Found profile `{file_name}` in {}", file.path().display());
+            entries.insert(file_name, file.path());
+        }
+        Ok(entries)
+    }
+
+    /// Get the path of a registered profile, failing when there is none by that name
+    pub fn find(&self, name: &str) -> Result<PathBuf> {
+        validate_profile_name(name)?;
+        let profile_path = self.profile_path(name);
+        if !profile_path.is_file() {
+            let profiles = self.profiles().unwrap_or_default();
+            return Err(CliError::unknown_profile(name, profiles.keys()).into());
+        }
+        Ok(profile_path)
+    }
+
+    /// Get the path of a profile about to be registered, refusing names that aren't safe as a
+    /// file name or are already registered
+    pub fn vacant_path(&self, name: &str) -> Result<PathBuf> {
+        validate_profile_name(name)?;
+        let profile_path = self.profile_path(name);
+        if profile_path.exists() {
+            return Err(CliError::ProfileExists(name.to_string()).into());
+        }
+        Ok(profile_path)
+    }
+
+    /// Rename a registered profile along with what is remembered and noted about it,
+    /// re-pointing the credentials when they link to it
+    pub fn rename(
+        &self,
+        name: &str,
+        new_name: &str,
+        terraform_directory: &Path,
+    ) -> Result<PathBuf> {
+        let profile_path = self.find(name)?;
+        let new_path = self.vacant_path(new_name)?;
+        tracing::info!(
+            "Moving {} to {}",
+            profile_path.display(),
+            new_path.display()
+        );
+        std::fs::rename(&profile_path, &new_path)?;
+        credentials::follow_move(terraform_directory, &profile_path, &new_path)?;
+        let mut state = state::State::load(&self.directory)?;
+        state.rename_profile(name, new_name);
+        state.save(&self.directory)?;
+        let mut metadata = metadata::Metadata::load(&self.directory)?;
+        metadata.rename_profile(name, new_name);
+        metadata.save(&self.directory)?;
+        Ok(new_path)
+    }
+
+    /// Duplicate a registered profile under a new name, along with what is noted about it
+    pub fn copy(&self, name: &str, new_name: &str) -> Result<PathBuf> {
+        let profile_path = self.find(name)?;
+        let new_path = self.vacant_path(new_name)?;
+        tracing::info!(
+            "Copying {} to {}",
+            profile_path.display(),
+            new_path.display()
+        );
+        std::fs::copy(&profile_path, &new_path)?;
+        permissions::restrict_file(&new_path)?;
+        let mut metadata = metadata::Metadata::load(&self.directory)?;
+        metadata.copy_profile(name, new_name);
+        metadata.save(&self.directory)?;
+        Ok(new_path)
+    }
+
+    /// Move a registered profile to the trash after backing it up, removing the credentials when
+    /// they are made from it and forgetting what is remembered and noted about it
+    pub fn remove(
+        &self,
+        name: &str,
+        credentials_file: &credentials::CredentialsFile,
+        backup_retention: usize,
+    ) -> Result<PathBuf> {
+        let profile_path = self.find(name)?;
+        backup::snapshot(
+            &self.directory,
+            &format!("logout {name}"),
+            &[&profile_path],
+            backup_retention,
+        )?;
+        if credentials_file.active_profile(self)?.as_deref() == Some(name) {
+            credentials_file.remove(self)?;
+        }
+        let trashed = trash::discard(&self.directory, name, &profile_path)?;
+        let mut state = state::State::load(&self.directory)?;
+        state.forget_profile(name);
+        state.save(&self.directory)?;
+        let mut metadata = metadata::Metadata::load(&self.directory)?;
+        metadata.forget_profile(name);
+        metadata.save(&self.directory)?;
+        Ok(trashed)
+    }
+
+    /// Index the content of the profiles, forgetting what is remembered and noted about the
+    /// removed ones, and the token statuses and expirations of the changed ones
+    pub fn index(&self) -> Result<StoreChanges> {
+        let profiles = self.profiles()?;
+        let mut state = state::State::load(&self.directory)?;
+        let mut metadata = metadata::Metadata::load(&self.directory)?;
+        let mut indexed = BTreeMap::new();
+        for (name, profile_path) in &profiles {
+            indexed.insert(name.clone(), credentials::sha256_file(profile_path)?);
+        }
+
+        let mut changes = StoreChanges {
+            first: state.indexed.is_empty(),
+            ..StoreChanges::default()
+        };
+        for (name, sha256) in &indexed {
+            match state.indexed.get(name) {
+                None if !changes.first => changes.added.push(name.clone()),
+                Some(previous) if previous != sha256 => {
+                    // The statuses were checked for tokens the profile may not hold anymore
+                    state.token_statuses.remove(name);
+                    state.token_expiries.remove(name);
+                    changes.changed.push(name.clone());
+                }
+                _ => {}
+            }
+        }
+        let remembered: BTreeSet<String> = state
+            .indexed
+            .keys()
+            .chain(state.last_switched.keys())
+            .chain(state.token_statuses.keys())
+            .chain(state.token_expiries.keys())
+            .chain(&state.previous_profile)
+            .chain(metadata.profiles.keys())
+            .chain(metadata.aliases.values())
+            .filter(|name| !profiles.contains_key(*name))
+            .cloned()
+            .collect();
+        for name in remembered {
+            state.forget_profile(&name);
+            metadata.forget_profile(&name);
+            changes.removed.push(name);
+        }
+        state.indexed = indexed;
+        state.save(&self.directory)?;
+        if !changes.removed.is_empty() {
+            metadata.save(&self.directory)?;
+        }
+        Ok(changes)
+    }
+
+    /// Store credentials as a new profile readable by its owner only, refusing credentials
+    /// without any hostname, names that aren't safe as a file name and registered names
+    pub fn register(&self, name: &str, content: &str) -> Result<PathBuf> {
+        validate_new_credentials(content)?;
+        let profile_path = self.vacant_path(name)?;
+        write_profile(&profile_path, content)?;
+        Ok(profile_path)
+    }
+
+    /// Store credentials as a profile readable by its owner only, in place of the registered one
+    /// by that name if any, refusing credentials without any hostname and names that aren't safe
+    /// as a file name
+    pub fn overwrite(&self, name: &str, content: &str) -> Result<PathBuf> {
+        validate_new_credentials(content)?;
+        validate_profile_name(name)?;
+        let profile_path = self.profile_path(name);
+        write_profile(&profile_path, content)?;
+        Ok(profile_path)
+    }
+
+    /// Register a new profile holding the credentials of every hostname of several profiles,
+    /// keeping the sources and Vault references as they are, and the other top-level keys and
+    /// the formatting of the first profile
+    ///
+    /// When a profile holds different credentials for a hostname than an earlier one, `keep_new`
+    /// is told the hostname, the profile the credentials were taken from and the one holding
+    /// the others, and returns whether to keep the latter, or none to refuse the merge once
+    /// every such hostname is known.
+    pub fn merge(
+        &self,
+        names: &[String],
+        into: &str,
+        mut keep_new: impl FnMut(&str, &str, &str) -> Result<Option<bool>>,
+    ) -> Result<PathBuf> {
+        self.vacant_path(into)?;
+        let mut merged = serde_json::Map::new();
+        // The other top-level keys of the profiles, those of the first profile holding each one
+        let mut root = serde_json::Map::new();
+        let mut first_content = None;
+        // Profile each merged hostname comes from
+        let mut origins: HashMap<String, &String> = HashMap::new();
+        let mut conflicting = Vec::new();
+        for name in names {
+            let content = encryption::decrypt_profile(&self.find(name)?)?;
+            validate_credentials(&content)
+                .with_context(|| format!("Invalid credentials in profile `{name}`"))?;
+            let value: serde_json::Value = serde_json::from_str(&content)?;
+            let hosts = value["credentials"]
+                .as_object()
+                .cloned()
+                .unwrap_or_default();
+            for (key, other) in value.as_object().into_iter().flatten() {
+                if !root.contains_key(key) {
+                    root.insert(key.clone(), other.clone());
+                }
+            }
+            first_content.get_or_insert(content);
+            for (host, entry) in hosts {
+                let Some(known) = helper::find_host(&merged, &host) else {
+                    origins.insert(host.clone(), name);
+                    merged.insert(host, entry);
+                    continue;
+                };
+                if merged[&known] == entry {
+                    continue;
+                }
+                match keep_new(&known, origins[&known], name)? {
+                    None => conflicting.push(format!("{known} (`{}`, `{name}`)", origins[&known])),
+                    Some(false) => {}
+                    Some(true) => {
+                        tracing::info!("Keeping the `{host}` credentials of `{name}`");
+                        origins.remove(&known);
+                        origins.insert(host.clone(), name);
+                        // Replacing the entry in place keeps the hostnames in the order they
+                        // were written
+                        merged = merged
+                            .into_iter()
+                            .map(|(merged_host, merged_entry)| {
+                                if merged_host == known {
+                                    (host.clone(), entry.clone())
+                                } else {
+                                    (merged_host, merged_entry)
+                                }
+                            })
+                            .collect();
+                    }
+                }
+            }
+        }
+        if !conflicting.is_empty() {
+            anyhow::bail!(
+                "The profiles hold different credentials for {}, pick those to keep with \
+                 `--conflicts first`, `last` or `prompt`.",
+                conflicting.join(", ")
+            );
+        }
+
+        root.insert(
+            String::from("credentials"),
+            serde_json::Value::Object(merged),
+        );
+        let content = format_like(
+            first_content.as_deref().unwrap_or_default(),
+            &serde_json::Value::Object(root),
+        )?;
+        self.register(into, &content)
+    }
+
+    /// Read the credentials of a registered profile, decrypted when needed
+    pub fn read(&self, name: &str) -> Result<String> {
+        encryption::read_profile(&self.find(name)?)
+    }
+
+    /// Read the token of every hostname of a registered profile
+    pub fn tokens(&self, name: &str) -> Result<BTreeMap<String, String>> {
+        read_profile_tokens(&self.find(name)?)
+    }
+
+    /// Read the hostnames a registered profile holds credentials for
+    pub fn hostnames(&self, name: &str) -> Result<Vec<String>> {
+        read_profile_hostnames(&self.find(name)?)
+    }
+}
+
+/// Whether a file of the project directory is one the registry keeps for itself, next to the
+/// profiles
+pub fn is_reserved_file(file_name: &str) -> bool {
+    [
+        state::STATE_FILE,
+        metadata::METADATA_FILE,
+        history::HISTORY_FILE,
+        lock::LOCK_FILE,
+        journal::JOURNAL_FILE,
+        backup::BACKUPS_DIRECTORY,
+        trash::TRASH_DIRECTORY,
+        prune::QUARANTINE_DIRECTORY,
+        bitwarden::SESSION_FILE,
+        agent::SOCKET_FILE,
+        encryption::SOPS_CONFIG,
+        update::CACHE_FILE,
+        git::GIT_DIRECTORY,
+    ]
+    .contains(&file_name)
+}
+
+/// Check that a profile name can safely be used as a file name in the registry
+pub fn validate_profile_name(name: &str) -> Result<()> {
+    let invalid = if name.is_empty() {
+        InvalidName::Empty
+    } else if name.contains(['/', '\\']) {
+        InvalidName::PathSeparator(name.to_string())
+    } else if name.starts_with('.') {
+        InvalidName::LeadingDot(name.to_string())
+    } else if name.contains(char::is_control) {
+        InvalidName::ControlCharacter(name.to_string())
+    } else if is_reserved_name(name) {
+        InvalidName::Reserved(name.to_string())
+    } else {
+        return Ok(());
+    };
+    Err(CliError::from(invalid).into())
+}
+
+/// Whether a name is a device name of Windows, e.g. `nul` or `COM1.backup`, ignoring case and
+/// what follows the first dot
+fn is_reserved_name(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or(name).trim_end();
+    let stem = stem.to_ascii_uppercase();
+    matches!(stem.as_str(), "CON" | "PRN" | "AUX" | "NUL")
+        || ["COM", "LPT"].iter().any(|device| {
+            stem.strip_prefix(device).is_some_and(|number| {
+                matches!(number, "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9")
+            })
+        })
+}
+
+/// Get the only registered name a partial one stands for: the very name, or else the name
+/// starting with it, or else the name holding its letters in order, ignoring case, failing when
+/// several do
+pub fn complete_name<'a>(
+    name: &str,
+    names: impl IntoIterator<Item = &'a String>,
+) -> Result<Option<String>> {
+    if validate_profile_name(name).is_err() {
+        return Ok(None);
+    }
+    let partial = name.to_lowercase();
+    let mut names: Vec<&String> = names.into_iter().collect();
+    if names.iter().any(|candidate| *candidate == name) {
+        return Ok(Some(name.to_string()));
+    }
+    names.sort();
+    let starting: Vec<&String> = names
+        .iter()
+        .copied()
+        .filter(|candidate| candidate.to_lowercase().starts_with(&partial))
+        .collect();
+    let candidates = if starting.is_empty() {
+        names
+            .into_iter()
+            .filter(|candidate| {
+                let candidate = candidate.to_lowercase();
+                let mut letters = candidate.chars();
+                partial
+                    .chars()
+                    .all(|letter| letters.any(|other| other == letter))
+            })
+            .collect()
+    } else {
+        starting
+    };
+    match candidates.as_slice() {
+        [] => Ok(None),
+        [candidate] => Ok(Some(candidate.to_string())),
+        _ => Err(CliError::AmbiguousProfile {
+            name: name.to_string(),
+            candidates: candidates.into_iter().cloned().collect(),
+        }
+        .into()),
+    }
+}
+
+/// Get the names close enough to a mistyped one to be what was meant, closest first
+///
+/// Names are compared by edit distance, ignoring case, and only those at most a third of the
+/// name apart, or two edits for short names, are kept
+pub fn closest_names<'a>(name: &str, names: impl IntoIterator<Item = &'a String>) -> Vec<String> {
+    let name = name.to_lowercase();
+    let threshold = (name.chars().count() / 3).max(2);
+    let mut close: Vec<(usize, &String)> = names
+        .into_iter()
+        .map(|candidate| (edit_distance(&name, &candidate.to_lowercase()), candidate))
+        .filter(|(distance, _)| *distance <= threshold)
+        .collect();
+    close.sort();
+    close
+        .into_iter()
+        .take(3)
+        .map(|(_, candidate)| candidate.clone())
+        .collect()
+}
+
+/// Number of characters to insert, remove or replace to turn one string into another
+fn edit_distance(from: &str, to: &str) -> usize {
+    let to: Vec<char> = to.chars().collect();
+    let mut previous: Vec<usize> = (0..=to.len()).collect();
+    for (i, from_char) in from.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, to_char) in to.iter().enumerate() {
+            let replace = previous[j] + usize::from(from_char != *to_char);
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[to.len()]
+}
+
+/// Get the line and column of the first occurrence of a JSON key at or after an offset, or of
+/// the start of the content when it can't be found
+fn locate(content: &str, key: &str, from: usize) -> (usize, usize, usize) {
+    let needle = serde_json::to_string(key).unwrap_or_default();
+    let offset = content
+        .get(from..)
+        .and_then(|rest| rest.find(&needle))
+        .map_or(0, |index| from + index);
+    let before = &content[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |newline| newline + 1) + 1;
+    (line, column, offset)
+}
+
+/// Check that a credentials file content has the structure terraform expects,
+/// `{"credentials": {"<hostname>": {"token": "<token>"}}}`, pointing to the first problem
+pub fn validate_credentials(content: &str) -> Result<()> {
+    let problem = |(line, column, _): (usize, usize, usize), problem: String| {
+        anyhow::Error::from(CliError::from(SchemaError {
+            line,
+            column,
+            problem,
+        }))
+    };
+    let value: serde_json::Value = serde_json::from_str(content).map_err(|error| {
+        let message = error.to_string();
+        let message = message
+            .rsplit_once(" at line ")
+            .map_or(message.as_str(), |(message, _)| message);
+        problem(
+            (error.line(), error.column(), 0),
+            format!("The credentials aren't valid JSON: {message}"),
+        )
+    })?;
+    let start = (1, 1, 0);
+    let root = value
+        .as_object()
+        .ok_or_else(|| problem(start, String::from("The credentials must be a JSON object")))?;
+    let hosts = root.get("credentials").ok_or_else(|| {
+        problem(
+            start,
+            String::from("The credentials are missing a `credentials` object"),
+        )
+    })?;
+    let at_credentials = locate(content, "credentials", 0);
+    let hosts = hosts.as_object().ok_or_else(|| {
+        problem(
+            at_credentials,
+            String::from("`credentials` must be an object of hostnames"),
+        )
+    })?;
+    for (host, entry) in hosts {
+        let at_host = locate(content, host, at_credentials.2);
+        if host.is_empty() || host.contains("://") || host.contains(['/', ' ']) {
+            return Err(problem(
+                at_host,
+                format!("`{host}` isn't a hostname, e.g. `app.terraform.io`"),
+            ));
+        }
+        let Some(entry) = entry.as_object() else {
+            return Err(problem(
+                at_host,
+                format!("The `{host}` credentials must be an object holding a `token`"),
+            ));
+        };
+        let secret = entry
+            .get("token")
+            .map(|token| ("token", token))
+            .or_else(|| entry.get("source").map(|source| ("source", source)));
+        match secret {
+            None => {
+                return Err(problem(
+                    at_host,
+                    format!("The `{host}` credentials are missing a `token` or `source` string"),
+                ))
+            }
+            Some((key, value)) if value.as_str().is_none_or(str::is_empty) => {
+                return Err(problem(
+                    locate(content, key, at_host.2),
+                    format!("The `{key}` of `{host}` must be a non-empty string"),
+                ))
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Get the sorted hostnames a credentials file content holds a token or a `source` for
+pub fn hostnames(content: &str) -> Result<Vec<String>> {
+    validate_credentials(content)?;
+    let value: serde_json::Value = serde_json::from_str(content)?;
+    let mut hostnames: Vec<String> = value["credentials"]
+        .as_object()
+        .map(|hosts| hosts.keys().cloned().collect())
+        .unwrap_or_default();
+    hostnames.sort();
+    Ok(hostnames)
+}
+
+/// Check that credentials about to be registered are well-formed and hold at least one hostname
+pub fn validate_new_credentials(content: &str) -> Result<Vec<String>> {
+    let hostnames = hostnames(content)?;
+    if hostnames.is_empty() {
+        anyhow::bail!("The credentials don't hold any hostname");
+    }
+    Ok(hostnames)
+}
+
+/// Read the hostnames registered in a credentials file
+pub fn read_profile_hostnames(profile_path: &Path) -> Result<Vec<String>> {
+    hostnames(&encryption::read_profile(profile_path)?)
+        .with_context(|| format!("Invalid credentials in {}", profile_path.display()))
+}
+
+/// Read the token of every hostname registered in a credentials file
+pub fn read_profile_tokens(profile_path: &Path) -> Result<BTreeMap<String, String>> {
+    tracing::debug!("Reading the credentials in {}", profile_path.display());
+    tokens(&encryption::read_profile(profile_path)?)
+        .with_context(|| format!("Invalid credentials in {}", profile_path.display()))
+}
+
+/// Get the token of every hostname of a credentials file content, leaving out the hostnames
+/// whose token is read from a `source`
+pub fn tokens(content: &str) -> Result<BTreeMap<String, String>> {
+    validate_credentials(content)?;
+    let value: serde_json::Value = serde_json::from_str(content)?;
+
+    let mut tokens = BTreeMap::new();
+    if let Some(hosts) = value["credentials"].as_object() {
+        for (host, entry) in hosts {
+            if let Some(token) = entry["token"].as_str() {
+                tokens.insert(host.clone(), token.to_string());
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Format credentials holding a token for each hostname, as `terraform login` writes them
+pub fn credentials_with_tokens<H, T>(tokens: impl IntoIterator<Item = (H, T)>) -> String
+where
+    H: Into<String>,
+    T: Into<String>,
+{
+    let hosts: serde_json::Map<String, serde_json::Value> = tokens
+        .into_iter()
+        .map(|(host, token)| (host.into(), serde_json::json!({ "token": token.into() })))
+        .collect();
+    format!("{:#}", serde_json::json!({ "credentials": hosts }))
+}
+
+/// Serialize credentials rewritten from an original content the way it was written, with the
+/// same indentation, key order and final newline, so only the values that changed differ
+pub fn format_like(original: &str, value: &serde_json::Value) -> Result<String> {
+    use serde::Serialize;
+
+    let body = original.trim_end();
+    let mut content = if body.contains('\n') {
+        let indent = body
+            .lines()
+            .skip(1)
+            .map(|line| &line[..line.len() - line.trim_start().len()])
+            .find(|indent| !indent.is_empty())
+            .unwrap_or("  ");
+        let mut serializer = serde_json::Serializer::with_formatter(
+            Vec::new(),
+            serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes()),
+        );
+        value.serialize(&mut serializer)?;
+        String::from_utf8(serializer.into_inner())?
+    } else {
+        serde_json::to_string(value)?
+    };
+    content.push_str(&original[body.len()..]);
+    Ok(content)
+}
+
+/// Fingerprint of a token, the start of its SHA-256, telling tokens apart without revealing them
+pub fn fingerprint(token: &str) -> String {
+    let digest = Sha256::digest(token.as_bytes());
+    let hex: String = digest[..6]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    format!("sha256:{hex}")
+}
+
+/// Get the name of the registered profile stored at a path
+pub fn profile_name_for_path<'a>(
+    path: &Path,
+    profiles: &'a HashMap<String, PathBuf>,
+) -> Option<&'a String> {
+    profiles
+        .iter()
+        .find(|(_, profile_path)| *profile_path == path)
+        .map(|(name, _)| name)
+}
+
+/// Encrypt credentials into a profile file readable by its owner only
+fn write_profile(profile_path: &Path, content: &str) -> Result<()> {
+    write_file_atomically(profile_path, encryption::seal(content)?)?;
+    permissions::restrict_file(profile_path)
+}
+
+/// Replace the content of a file through a rename, so it is never left half-written
+pub fn write_file_atomically(profile_path: &Path, content: impl AsRef<[u8]>) -> Result<()> {
+    tracing::debug!("Writing {}", profile_path.display());
+    let directory = profile_path
+        .parent()
+        .context("Couldn't get the profile directory")?;
+    let mut replacement = tempfile::NamedTempFile::new_in(directory)?;
+    replacement.write_all(content.as_ref())?;
+    replacement.flush()?;
+    replacement.persist(profile_path)?;
+    Ok(())
+}
+
+/// Replace the content of a configuration file, writing through the link dotfile managers often
+/// make it, which has to stay a link, and keeping the permissions the user gave it
+pub fn write_through_link(path: &Path, content: impl AsRef<[u8]>) -> Result<()> {
+    let target = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let permissions = std::fs::metadata(&target).map(|metadata| metadata.permissions());
+    write_file_atomically(&target, content)?;
+    if let Ok(permissions) = permissions {
+        std::fs::set_permissions(&target, permissions)
+            .with_context(|| format!("Couldn't restore the permissions of {}", target.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Names that would escape the store, hide the profile or can't be a file name
+    const UNSAFE_NAMES: &[&str] = &[
+        "",
+        "../../etc/cron.d/x",
+        "..",
+        "a/b",
+        "a\\b",
+        ".hidden",
+        "tab\tname",
+        "new\nline",
+        "bell\u{7}",
+        "CON",
+        "con",
+        "nul.txt",
+        "COM1.backup",
+        "lpt9",
+    ];
+
+    /// Credentials holding a single token for `app.terraform.io`
+    const CREDENTIALS: &str = r#"{"credentials": {"app.terraform.io": {"token": "secret"}}}"#;
+
+    /// Whether an error is the refusal of an unsafe profile name
+    fn is_invalid_name(error: &anyhow::Error) -> bool {
+        matches!(
+            error.downcast_ref::<CliError>(),
+            Some(CliError::InvalidProfileName(_))
+        )
+    }
+
+    /// Every path under a directory, recursively and sorted, to tell what was written there
+    fn tree(directory: &Path) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(directory).unwrap().flatten() {
+            if entry.path().is_dir() {
+                paths.extend(tree(&entry.path()));
+            }
+            paths.push(entry.path());
+        }
+        paths.sort();
+        paths
+    }
+
+    /// A store nested a few directories deep in a temporary directory, so traversals stay
+    /// inside the latter, along with a terraform directory
+    fn nested_store() -> (tempfile::TempDir, ProfileStore, PathBuf) {
+        let directory = tempfile::tempdir().unwrap();
+        let store_directory = directory.path().join("a").join("b").join("store");
+        let terraform_directory = directory.path().join("a").join("b").join("terraform.d");
+        std::fs::create_dir_all(&store_directory).unwrap();
+        std::fs::create_dir_all(&terraform_directory).unwrap();
+        (
+            directory,
+            ProfileStore::open(store_directory),
+            terraform_directory,
+        )
+    }
+
+    #[test]
+    fn refuses_unsafe_names() {
+        for name in UNSAFE_NAMES {
+            let error = validate_profile_name(name).unwrap_err();
+            assert!(is_invalid_name(&error), "{name:?} wasn't refused");
+        }
+    }
+
+    #[test]
+    fn accepts_ordinary_names() {
+        for name in [
+            "work",
+            "client-a.prod",
+            "personal_2",
+            "Übung",
+            "console",
+            "com10",
+        ] {
+            validate_profile_name(name).unwrap();
+        }
+    }
+
+    #[test]
+    fn import_refuses_unsafe_names() {
+        let (directory, store, _) = nested_store();
+        let before = tree(directory.path());
+        for name in UNSAFE_NAMES {
+            let error = store.register(name, CREDENTIALS).unwrap_err();
+            assert!(is_invalid_name(&error), "{name:?} wasn't refused");
+        }
+        assert_eq!(tree(directory.path()), before);
+    }
+
+    #[test]
+    fn registering_refuses_registered_names_and_empty_credentials() {
+        let (_directory, store, _) = nested_store();
+        store.register("work", CREDENTIALS).unwrap();
+        let error = store.register("work", CREDENTIALS).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(CliError::ProfileExists(name)) if name == "work"
+        ));
+        let error = store
+            .register("empty", r#"{"credentials": {}}"#)
+            .unwrap_err();
+        assert_eq!(error.to_string(), "The credentials don't hold any hostname");
+        assert!(!store.profile_path("empty").exists());
+
+        let replacement = credentials_with_tokens([("tfe.example.com", "other")]);
+        store.overwrite("work", &replacement).unwrap();
+        assert_eq!(store.read("work").unwrap(), replacement);
+    }
+
+    #[test]
+    fn adopt_refuses_unsafe_names() {
+        let (directory, store, terraform_directory) = nested_store();
+        std::fs::write(credentials::path(&terraform_directory), CREDENTIALS).unwrap();
+        let before = tree(directory.path());
+        for name in UNSAFE_NAMES {
+            let error =
+                credentials::adopt(name, &terraform_directory, store.directory()).unwrap_err();
+            assert!(is_invalid_name(&error), "{name:?} wasn't refused");
+        }
+        assert_eq!(tree(directory.path()), before);
+    }
+
+    #[test]
+    fn finding_refuses_unsafe_names() {
+        let (_directory, store, _) = nested_store();
+        for name in UNSAFE_NAMES {
+            let error = store.find(name).unwrap_err();
+            assert!(is_invalid_name(&error), "{name:?} wasn't refused");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn renaming_carries_everything_over() {
+        let (_directory, store, terraform_directory) = nested_store();
+        store.register("work", CREDENTIALS).unwrap();
+        credentials::install(
+            "work",
+            &store.find("work").unwrap(),
+            &terraform_directory,
+            store.directory(),
+            crate::config::SwitchMode::Symlink,
+        )
+        .unwrap();
+        let mut state = state::State::load(store.directory()).unwrap();
+        state.previous_profile = Some(String::from("work"));
+        state.save(store.directory()).unwrap();
+        let mut metadata = metadata::Metadata::load(store.directory()).unwrap();
+        metadata.update("work", |noted| {
+            noted.description = Some(String::from("Work account"))
+        });
+        metadata.save(store.directory()).unwrap();
+
+        let new_path = store.rename("work", "job", &terraform_directory).unwrap();
+        assert!(!store.profile_path("work").exists());
+        assert_eq!(
+            credentials::path(&terraform_directory).read_link().unwrap(),
+            new_path
+        );
+        let state = state::State::load(store.directory()).unwrap();
+        assert_eq!(state.previous_profile.as_deref(), Some("job"));
+        let metadata = metadata::Metadata::load(store.directory()).unwrap();
+        assert_eq!(
+            metadata.get("job").description.as_deref(),
+            Some("Work account")
+        );
+        assert!(metadata.get("work").is_empty());
+    }
+
+    #[test]
+    fn renaming_refuses_registered_names() {
+        let (_directory, store, terraform_directory) = nested_store();
+        store.register("work", CREDENTIALS).unwrap();
+        store.register("personal", CREDENTIALS).unwrap();
+        let error = store
+            .rename("work", "personal", &terraform_directory)
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<CliError>(),
+            Some(CliError::ProfileExists(_))
+        ));
+        let error = store
+            .rename("work", "../work", &terraform_directory)
+            .unwrap_err();
+        assert!(is_invalid_name(&error));
+        assert!(store.profile_path("work").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn removing_trashes_the_profile_and_forgets_it() {
+        let (_directory, store, terraform_directory) = nested_store();
+        store.register("work", CREDENTIALS).unwrap();
+        store.register("personal", CREDENTIALS).unwrap();
+        let credentials_file = credentials::CredentialsFile::new(&terraform_directory);
+        credentials_file
+            .install(&store, "work", crate::config::SwitchMode::Symlink)
+            .unwrap();
+        let mut metadata = metadata::Metadata::load(store.directory()).unwrap();
+        metadata
+            .aliases
+            .insert(String::from("w"), String::from("work"));
+        metadata.save(store.directory()).unwrap();
+
+        store.remove("personal", &credentials_file, 0).unwrap();
+        assert_eq!(
+            credentials_file.active_profile(&store).unwrap().as_deref(),
+            Some("work")
+        );
+
+        let trashed = store.remove("work", &credentials_file, 0).unwrap();
+        assert_eq!(std::fs::read_to_string(trashed).unwrap(), CREDENTIALS);
+        assert!(!credentials_file.path().exists());
+        assert!(store.profiles().unwrap().is_empty());
+        let metadata = metadata::Metadata::load(store.directory()).unwrap();
+        assert!(metadata.aliases.is_empty());
+        assert_eq!(trash::list(store.directory()).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn indexing_tells_what_changed_since_last_time() {
+        let (_directory, store, _) = nested_store();
+        store.register("work", CREDENTIALS).unwrap();
+        store.register("personal", CREDENTIALS).unwrap();
+        let first = store.index().unwrap();
+        assert!(first.first);
+        assert_eq!(first.added, Vec::<String>::new());
+
+        store.register("client", CREDENTIALS).unwrap();
+        std::fs::write(
+            store.profile_path("work"),
+            CREDENTIALS.replace("secret", "rotated"),
+        )
+        .unwrap();
+        std::fs::remove_file(store.profile_path("personal")).unwrap();
+        let mut state = state::State::load(store.directory()).unwrap();
+        state.record_token_expiry("work", "app.terraform.io", Some(1_000));
+        state.save(store.directory()).unwrap();
+        let mut metadata = metadata::Metadata::load(store.directory()).unwrap();
+        metadata.update("personal", |noted| {
+            noted.description = Some(String::from("Own account"))
+        });
+        metadata.save(store.directory()).unwrap();
+
+        assert_eq!(
+            store.index().unwrap(),
+            StoreChanges {
+                first: false,
+                added: vec![String::from("client")],
+                changed: vec![String::from("work")],
+                removed: vec![String::from("personal")],
+            }
+        );
+        let state = state::State::load(store.directory()).unwrap();
+        assert!(state.token_expiries.is_empty());
+        assert!(!state.indexed.contains_key("personal"));
+        let metadata = metadata::Metadata::load(store.directory()).unwrap();
+        assert!(metadata.get("personal").is_empty());
+        assert_eq!(store.index().unwrap(), StoreChanges::default());
+    }
+
+    #[test]
+    fn copying_keeps_the_original() {
+        let (_directory, store, _) = nested_store();
+        store.register("work", CREDENTIALS).unwrap();
+        let mut metadata = metadata::Metadata::load(store.directory()).unwrap();
+        metadata.update("work", |noted| {
+            noted.tags.insert(String::from("prod"));
+        });
+        metadata.save(store.directory()).unwrap();
+
+        store.copy("work", "work-backup").unwrap();
+        assert_eq!(store.read("work").unwrap(), CREDENTIALS);
+        assert_eq!(store.read("work-backup").unwrap(), CREDENTIALS);
+        let metadata = metadata::Metadata::load(store.directory()).unwrap();
+        assert!(metadata.get("work-backup").tags.contains("prod"));
+        assert!(metadata.get("work").tags.contains("prod"));
+        assert!(store.copy("missing", "other").is_err());
+    }
+
+    /// A profile indented with four spaces, with top-level keys terraform doesn't know about
+    const FIRST: &str = r#"{
+    "version": 2,
+    "credentials": {
+        "tfe.example.com": {
+            "token": "first-tfe"
+        },
+        "app.terraform.io": {
+            "token": "first-app"
+        }
+    },
+    "comment": "work"
+}
+"#;
+
+    /// A profile indented with two spaces, sharing a hostname with the first one
+    const SECOND: &str = r#"{
+  "credentials": {
+    "app.terraform.io": {"token": "second-app"},
+    "private.example.org": {"token": "second-private"}
+  },
+  "extra": true
+}
+"#;
+
+    /// A store holding the first and second profiles
+    fn store_with_profiles() -> (tempfile::TempDir, ProfileStore) {
+        let (directory, store, _) = nested_store();
+        store.register("first", FIRST).unwrap();
+        store.register("second", SECOND).unwrap();
+        (directory, store)
+    }
+
+    #[test]
+    fn formats_like_the_original() {
+        let value: serde_json::Value = serde_json::from_str(FIRST).unwrap();
+        assert_eq!(format_like(FIRST, &value).unwrap(), FIRST);
+        let compact =
+            r#"{"credentials":{"b.example.com":{"token":"b"},"a.example.com":{"token":"a"}}}"#;
+        let value: serde_json::Value = serde_json::from_str(compact).unwrap();
+        assert_eq!(format_like(compact, &value).unwrap(), compact);
+        let tabs = "{\n\t\"credentials\": {}\n}\n\n";
+        let value: serde_json::Value = serde_json::from_str(tabs).unwrap();
+        assert_eq!(format_like(tabs, &value).unwrap(), tabs);
+    }
+
+    #[test]
+    fn merging_keeps_the_first_formatting_and_order() {
+        let (_directory, store) = store_with_profiles();
+        let names = [String::from("first"), String::from("second")];
+        store
+            .merge(&names, "merged", |_, _, _| Ok(Some(false)))
+            .unwrap();
+        assert_eq!(
+            store.read("merged").unwrap(),
+            r#"{
+    "version": 2,
+    "credentials": {
+        "tfe.example.com": {
+            "token": "first-tfe"
+        },
+        "app.terraform.io": {
+            "token": "first-app"
+        },
+        "private.example.org": {
+            "token": "second-private"
+        }
+    },
+    "comment": "work",
+    "extra": true
+}
+"#
+        );
+    }
+
+    #[test]
+    fn merging_replaces_the_kept_entries_in_place() {
+        let (_directory, store) = store_with_profiles();
+        let names = [String::from("first"), String::from("second")];
+        let mut asked = Vec::new();
+        store
+            .merge(&names, "merged", |host, kept, other| {
+                asked.push(format!("{host} {kept} {other}"));
+                Ok(Some(true))
+            })
+            .unwrap();
+        assert_eq!(asked, ["app.terraform.io first second"]);
+        assert_eq!(
+            store.read("merged").unwrap(),
+            r#"{
+    "version": 2,
+    "credentials": {
+        "tfe.example.com": {
+            "token": "first-tfe"
+        },
+        "app.terraform.io": {
+            "token": "second-app"
+        },
+        "private.example.org": {
+            "token": "second-private"
+        }
+    },
+    "comment": "work",
+    "extra": true
+}
+"#
+        );
+    }
+
+    #[test]
+    fn merging_refuses_conflicts_left_unresolved() {
+        let (_directory, store) = store_with_profiles();
+        let names = [String::from("first"), String::from("second")];
+        let error = store
+            .merge(&names, "merged", |_, _, _| Ok(None))
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("app.terraform.io (`first`, `second`)"));
+        assert!(!store.profile_path("merged").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writing_through_a_link_keeps_it_and_the_permissions() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let directory = tempfile::tempdir().unwrap();
+        let target = directory.path().join("dotfiles-terraformrc");
+        let link = directory.path().join(".terraformrc");
+        std::fs::write(&target, "plugin_cache_dir = \"/tmp\"\n").unwrap();
+        std::fs::set_permissions(&target, std::fs::Permissions::from_mode(0o644)).unwrap();
+        symlink(&target, &link).unwrap();
+
+        write_through_link(&link, "disable_checkpoint = true\n").unwrap();
+        assert!(link.symlink_metadata().unwrap().file_type().is_symlink());
+        assert_eq!(
+            std::fs::read_to_string(&target).unwrap(),
+            "disable_checkpoint = true\n"
+        );
+        let mode = std::fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o644);
+
+        let created = directory.path().join("config.toml");
+        write_through_link(&created, "color = false\n").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&created).unwrap(),
+            "color = false\n"
+        );
+    }
+
+    #[test]
+    fn formats_credentials_holding_tokens() {
+        let tokens = BTreeMap::from([
+            (String::from("app.terraform.io"), String::from("app-token")),
+            (String::from("tfe.example.com"), String::from("tfe-token")),
+        ]);
+        let content = credentials_with_tokens(&tokens);
+        validate_new_credentials(&content).unwrap();
+        assert_eq!(self::tokens(&content).unwrap(), tokens);
+        assert_eq!(
+            credentials_with_tokens([("app.terraform.io", "secret")]),
+            "{\n  \"credentials\": {\n    \"app.terraform.io\": {\n      \"token\": \"secret\"\n    }\n  }\n}"
+        );
+    }
+
+    /// Names registered in the store the completion tests pick from
+    fn registered(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn completes_an_unambiguous_prefix() {
+        let names = registered(&["acme-production", "personal", "work"]);
+        assert_eq!(
+            complete_name("acme", &names).unwrap().as_deref(),
+            Some("acme-production")
+        );
+        assert_eq!(
+            complete_name("PER", &names).unwrap().as_deref(),
+            Some("personal")
+        );
+        assert_eq!(complete_name("staging", &names).unwrap(), None);
+    }
+
+    #[test]
+    fn completes_the_letters_in_order() {
+        let names = registered(&["acme-production", "personal", "work"]);
+        assert_eq!(
+            complete_name("aprd", &names).unwrap().as_deref(),
+            Some("acme-production")
+        );
+        assert_eq!(complete_name("dorp", &names).unwrap(), None);
+    }
+
+    #[test]
+    fn refuses_ambiguous_names() {
+        let names = registered(&["acme-staging", "acme-production", "work"]);
+        let error = complete_name("acme", &names).unwrap_err();
+        match error.downcast_ref::<CliError>() {
+            Some(CliError::AmbiguousProfile { name, candidates }) => {
+                assert_eq!(name, "acme");
+                assert_eq!(candidates, &["acme-production", "acme-staging"]);
+            }
+            other => panic!("unexpected error {other:?}"),
+        }
+    }
+
+    #[test]
+    fn prefers_the_exact_name_to_a_prefix() {
+        let names = registered(&["acme", "acme-production"]);
+        assert_eq!(
+            complete_name("acme", &names).unwrap().as_deref(),
+            Some("acme")
+        );
+        assert_eq!(
+            complete_name("acme-", &names).unwrap().as_deref(),
+            Some("acme-production")
+        );
+    }
+
+    /// Get where and why credentials aren't valid
+    fn schema_error(content: &str) -> (usize, usize, String) {
+        match validate_credentials(content).unwrap_err().downcast() {
+            Ok(CliError::InvalidCredentials(error)) => (error.line, error.column, error.problem),
+            other => panic!("unexpected error {other:?}"),
+        }
+    }
+
+    #[test]
+    fn points_to_malformed_json() {
+        let (line, column, problem) = schema_error(
+            "{\n  \"credentials\": {\n    \"app.terraform.io\": {\"token\": secret}\n  }\n}",
+        );
+        assert_eq!((line, column), (3, 35));
+        assert!(problem.starts_with("The credentials aren't valid JSON: expected value"));
+        // The location is reported once, in the fields rather than in the message
+        assert!(!problem.contains(" at line "));
+    }
+
+    #[test]
+    fn points_to_the_start_when_credentials_are_missing() {
+        let (line, column, problem) = schema_error("{\n  \"hosts\": {}\n}");
+        assert_eq!((line, column), (1, 1));
+        assert_eq!(
+            problem,
+            "The credentials are missing a `credentials` object"
+        );
+    }
+
+    #[test]
+    fn points_to_a_token_that_isnt_a_string() {
+        let (line, column, problem) = schema_error(
+            "{\n  \"credentials\": {\n    \"app.terraform.io\": {\"token\": 42}\n  }\n}",
+        );
+        assert_eq!((line, column), (3, 26));
+        assert_eq!(
+            problem,
+            "The `token` of `app.terraform.io` must be a non-empty string"
+        );
+    }
+
+    #[test]
+    fn suggests_the_names_close_to_a_typo() {
+        let names = registered(&["work", "personal"]);
+        assert_eq!(closest_names("wrok", &names), ["work"]);
+        assert_eq!(closest_names("WORK", &names), ["work"]);
+        assert!(closest_names("staging", &names).is_empty());
+    }
+
+    #[test]
+    fn suggests_the_closest_names_first() {
+        let mut names = registered(&["staging-2", "stagin", "sta", "staging-eu", "stoging"]);
+        assert_eq!(
+            closest_names("staging", &names),
+            ["stagin", "stoging", "staging-2"]
+        );
+        names.push(String::from("stageing"));
+        assert_eq!(
+            closest_names("staging", &names),
+            ["stageing", "stagin", "stoging"]
+        );
+    }
+
+    #[test]
+    fn counts_the_edits_between_names() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("work", "work"), 0);
+    }
+}
@@ -0,0 +1,243 @@
+//! Switching the credentials terraform reads from one registered profile to another
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::{
+    backup,
+    config::SwitchMode,
+    credentials::{self, Credentials, CredentialsFile},
+    history, journal,
+    state::{self, State},
+    store::{self, ProfileStore},
+};
+
+/// Puts the profiles of a store in place of a credentials file, remembering the switches
+#[derive(Debug, Clone)]
+pub struct Switcher {
+    store: ProfileStore,
+    credentials: CredentialsFile,
+    mode: SwitchMode,
+}
+
+impl Switcher {
+    /// Switch the credentials file to the profiles of a store, in a mode
+    pub fn new(store: ProfileStore, credentials: CredentialsFile, mode: SwitchMode) -> Self {
+        Switcher {
+            store,
+            credentials,
+            mode,
+        }
+    }
+
+    /// Get the store the profiles are switched from
+    pub fn store(&self) -> &ProfileStore {
+        &self.store
+    }
+
+    /// Get the credentials file switched
+    pub fn credentials(&self) -> &CredentialsFile {
+        &self.credentials
+    }
+
+    /// Get the way profiles are put in place of the credentials
+    pub fn mode(&self) -> SwitchMode {
+        self.mode
+    }
+
+    /// Get the name of the profile the credentials currently are
+    pub fn active(&self) -> Result<Option<String>> {
+        self.credentials.active_profile(&self.store)
+    }
+
+    /// Put a registered profile in place of the credentials, refusing to overwrite unmanaged
+    /// ones, and remember the previous one as the way back
+    pub fn switch(&self, name: &str) -> Result<()> {
+        let profile_path = self.store.find(name)?;
+        let previous = self.active()?;
+        self.credentials.install(&self.store, name, self.mode)?;
+        let hostnames = store::read_profile_tokens(&profile_path)
+            .map(|tokens| tokens.into_keys().collect())
+            .unwrap_or_default();
+        record(self.store.directory(), previous, name, hostnames)
+    }
+
+    /// Put the credentials a registered profile holds for one hostname in place of those the
+    /// credentials hold for it, leaving the other hostnames untouched, and return the hostname
+    /// as the profile spells it
+    pub fn switch_host(&self, name: &str, hostname: &str) -> Result<String> {
+        let profile_path = self.store.find(name)?;
+        let previous = self.active()?;
+        let host = credentials::install_host(
+            name,
+            &profile_path,
+            hostname,
+            self.credentials.terraform_directory(),
+            self.store.directory(),
+            &self.store.profiles()?,
+        )?;
+        record(self.store.directory(), previous, name, vec![host.clone()])?;
+        Ok(host)
+    }
+
+    /// Register the unmanaged credentials as a new profile after backing them up, leaving them
+    /// in place until a switch replaces them so terraform always finds credentials, and return
+    /// the path of the profile unless there were no such credentials
+    pub fn adopt(&self, name: &str, backup_retention: usize) -> Result<Option<PathBuf>> {
+        let profile_path = self.store.vacant_path(name)?;
+        let current = self.credentials.inspect(&self.store)?;
+        if current.active_profile(&self.store.profiles()?).is_some()
+            || matches!(
+                current,
+                Credentials::Missing | Credentials::Symlink(_) | Credentials::Helper(_)
+            )
+        {
+            return Ok(None);
+        }
+        backup::snapshot(
+            self.store.directory(),
+            &format!("switch --adopt {name}"),
+            &[&self.credentials.path(), &profile_path],
+            backup_retention,
+        )?;
+        credentials::adopt(
+            name,
+            self.credentials.terraform_directory(),
+            self.store.directory(),
+        )
+    }
+
+    /// Adopt the unmanaged credentials as a new profile then switch away from them with
+    /// `switch`, told the path of the adopted profile if any
+    ///
+    /// When the switch fails, the credentials are put back as they were and the new profile
+    /// removed, so they are never lost nor registered halfway.
+    pub fn adopt_and_switch<T>(
+        &self,
+        name: &str,
+        backup_retention: usize,
+        switch: impl FnOnce(Option<PathBuf>) -> Result<T>,
+    ) -> Result<T> {
+        // The name ends up in the journaled paths, which must stay inside the store
+        store::validate_profile_name(name)?;
+        journal::transaction(
+            self.store.directory(),
+            &format!("switch --adopt {name}"),
+            &[&self.credentials.path(), &self.store.profile_path(name)],
+            || switch(self.adopt(name, backup_retention)?),
+        )
+    }
+}
+
+/// Remember a successful switch as the way back for `switch -` and in the history
+fn record(
+    project_directory: &Path,
+    previous: Option<String>,
+    name: &str,
+    hostnames: Vec<String>,
+) -> Result<()> {
+    let mut state = State::load(project_directory)?;
+    if let Some(previous) = previous.as_ref().filter(|previous| *previous != name) {
+        state.previous_profile = Some(previous.clone());
+    }
+    state.last_switched.insert(name.to_string(), state::now());
+    state.save(project_directory)?;
+    history::record(
+        project_directory,
+        &history::Switch {
+            timestamp: state::now(),
+            from: previous,
+            to: name.to_string(),
+            hostnames,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::CliError;
+
+    /// Credentials holding a single token for `app.terraform.io`
+    const CREDENTIALS: &str = r#"{"credentials": {"app.terraform.io": {"token": "secret"}}}"#;
+
+    /// Credentials `terraform login` wrote, which no profile holds
+    const UNMANAGED: &str = r#"{"credentials": {"app.terraform.io": {"token": "login"}}}"#;
+
+    /// A switcher in a mode, whose store holds the work profile, with unmanaged credentials
+    fn switcher(mode: SwitchMode) -> (tempfile::TempDir, Switcher) {
+        let home = tempfile::tempdir().unwrap();
+        let store = ProfileStore::open(home.path().join("store"));
+        let credentials = CredentialsFile::new(home.path().join(".terraform.d"));
+        std::fs::create_dir_all(store.directory()).unwrap();
+        std::fs::create_dir_all(credentials.terraform_directory()).unwrap();
+        store.register("work", CREDENTIALS).unwrap();
+        std::fs::write(credentials.path(), UNMANAGED).unwrap();
+        (home, Switcher::new(store, credentials, mode))
+    }
+
+    #[test]
+    fn adopting_registers_the_credentials_and_switches_back_to_them() {
+        for mode in [SwitchMode::Symlink, SwitchMode::Copy] {
+            let (_home, switcher) = switcher(mode);
+            switcher
+                .adopt_and_switch("login", 0, |adopted| {
+                    assert_eq!(adopted, Some(switcher.store().profile_path("login")));
+                    switcher.switch("work")
+                })
+                .unwrap();
+            assert_eq!(switcher.store().read("login").unwrap(), UNMANAGED);
+            assert_eq!(switcher.active().unwrap().as_deref(), Some("work"));
+
+            switcher.switch("login").unwrap();
+            assert_eq!(switcher.active().unwrap().as_deref(), Some("login"));
+            assert_eq!(
+                std::fs::read_to_string(switcher.credentials().path()).unwrap(),
+                UNMANAGED
+            );
+            assert_eq!(
+                switcher.credentials().path().is_symlink(),
+                mode == SwitchMode::Symlink
+            );
+        }
+    }
+
+    #[test]
+    fn failing_to_switch_keeps_the_credentials() {
+        let (_home, switcher) = switcher(SwitchMode::Symlink);
+        let error = switcher
+            .adopt_and_switch("login", 0, |_| switcher.switch("missing"))
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<CliError>(),
+            Some(CliError::UnknownProfile { .. })
+        ));
+
+        let credentials_path = switcher.credentials().path();
+        assert!(!credentials_path.is_symlink());
+        assert_eq!(
+            std::fs::read_to_string(credentials_path).unwrap(),
+            UNMANAGED
+        );
+        assert!(!switcher.store().profile_path("login").exists());
+        assert!(switcher.active().unwrap().is_none());
+    }
+
+    #[test]
+    fn adopting_refuses_registered_names() {
+        let (_home, switcher) = switcher(SwitchMode::Symlink);
+        let error = switcher
+            .adopt_and_switch("work", 0, |_| switcher.switch("work"))
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<CliError>(),
+            Some(CliError::ProfileExists(name)) if name == "work"
+        ));
+        assert_eq!(switcher.store().read("work").unwrap(), CREDENTIALS);
+        assert_eq!(
+            std::fs::read_to_string(switcher.credentials().path()).unwrap(),
+            UNMANAGED
+        );
+    }
+}
@@ -0,0 +1,727 @@
+//! Sync of the profiles with a remote holding an encrypted bundle of them, through transports
+//! picked by the scheme of its URL, settling the profiles changed on several machines with
+//! their content hashes and version vectors
+
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use age::secrecy::SecretString;
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    api,
+    archive::{self, Archive, ArchivedProfile, Restored},
+    credentials::CredentialsFile,
+    s3,
+    state::{State, SyncedProfile},
+    store::ProfileStore,
+};
+
+/// Name of the bundle of the profiles on the remotes
+pub const BUNDLE_NAME: &str = "profiles.tfp";
+
+/// Variable the bearer token of the HTTPS remotes is read from
+pub const TOKEN_VARIABLE: &str = "TERRAFORM_PROFILE_SYNC_TOKEN";
+
+/// Way of reaching a remote, each only moving the bundle back and forth
+pub trait Transport {
+    /// Download the bundle, or nothing when the remote has none yet
+    fn get(&self) -> Result<Option<Vec<u8>>>;
+    /// Upload the bundle, replacing the previous one
+    fn put(&self, bytes: &[u8]) -> Result<()>;
+}
+
+/// Remote in a directory, e.g. a network share or a folder synced by another tool
+struct Directory {
+    path: PathBuf,
+}
+
+impl Transport for Directory {
+    fn get(&self) -> Result<Option<Vec<u8>>> {
+        let path = self.path.join(BUNDLE_NAME);
+        match std::fs::read(&path) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error).with_context(|| format!("Couldn't read {}", path.display())),
+        }
+    }
+
+    fn put(&self, bytes: &[u8]) -> Result<()> {
+        std::fs::create_dir_all(&self.path)
+            .with_context(|| format!("Couldn't create {}", self.path.display()))?;
+        crate::store::write_file_atomically(&self.path.join(BUNDLE_NAME), bytes)
+    }
+}
+
+/// Remote on another machine, reached with `ssh`
+struct Ssh {
+    /// Host, along with the user when given
+    destination: String,
+    port: Option<String>,
+    /// Directory of the bundle, relative to the home of the user unless absolute
+    directory: String,
+}
+
+impl Ssh {
+    /// Run a shell command on the remote machine, feeding it the input
+    fn run(&self, command: &str, input: &[u8]) -> Result<Vec<u8>> {
+        tracing::info!("Running `{command}` on {}", self.destination);
+        let mut ssh = Command::new("ssh");
+        if let Some(port) = &self.port {
+            ssh.args(["-p", port]);
+        }
+        let mut child = ssh
+            .arg(&self.destination)
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("Couldn't run `ssh`, is OpenSSH installed?")?;
+        child
+            .stdin
+            .take()
+            .context("Couldn't write to `ssh`")?
+            .write_all(input)?;
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            anyhow::bail!("`ssh {}` exited with {}", self.destination, output.status);
+        }
+        Ok(output.stdout)
+    }
+
+    /// Parse what follows `ssh://` in a remote URL, e.g. `user@host:2222/~/path`, the IPv6
+    /// hosts being bracketed as in `user@[2001:db8::1]:2222/path`
+    fn parse(rest: &str) -> Self {
+        let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+        let (user, host) = match authority.rsplit_once('@') {
+            Some((user, host)) => (Some(user), host),
+            None => (None, authority),
+        };
+        let (host, port) = match host.strip_prefix('[').and_then(|host| host.split_once(']')) {
+            Some((host, port)) => (host, port.strip_prefix(':')),
+            // A bare IPv6 address can't have a port, its colons being ambiguous
+            None => match host.split_once(':') {
+                Some((host, port)) if !port.contains(':') => (host, Some(port)),
+                _ => (host, None),
+            },
+        };
+        // `ssh://host/~/path` is relative to the home of the user, as remote commands run
+        let directory = match path.strip_prefix("~/") {
+            Some(relative) => relative.to_string(),
+            None => format!("/{path}"),
+        };
+        Ssh {
+            destination: match user {
+                Some(user) => format!("{user}@{host}"),
+                None => host.to_string(),
+            },
+            port: port.filter(|port| !port.is_empty()).map(String::from),
+            directory: directory.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Quote a path for the remote shell
+    fn quote(path: &str) -> String {
+        format!("'{}'", path.replace('\'', r"'\''"))
+    }
+}
+
+impl Transport for Ssh {
+    fn get(&self) -> Result<Option<Vec<u8>>> {
+        let path = Self::quote(&format!("{}/{BUNDLE_NAME}", self.directory));
+        let bytes = self.run(&format!("if [ -e {path} ]; then cat {path}; fi"), b"")?;
+        Ok(Some(bytes).filter(|bytes| !bytes.is_empty()))
+    }
+
+    fn put(&self, bytes: &[u8]) -> Result<()> {
+        let directory = Self::quote(&self.directory);
+        let path = Self::quote(&format!("{}/{BUNDLE_NAME}", self.directory));
+        let partial = Self::quote(&format!("{}/.{BUNDLE_NAME}.partial", self.directory));
+        self.run(
+            &format!("mkdir -p {directory} && cat > {partial} && mv {partial} {path}"),
+            bytes,
+        )?;
+        Ok(())
+    }
+}
+
+/// Remote on a web server accepting uploads, such as WebDAV, authenticated with the bearer
+/// token of `TERRAFORM_PROFILE_SYNC_TOKEN` when set
+struct Https {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl Https {
+    /// Build a request on the bundle
+    fn request(&self, method: &str) -> ureq::Request {
+        let url = format!("{}/{BUNDLE_NAME}", self.url.trim_end_matches('/'));
+        tracing::info!("{method} {url}");
+        let request = self.agent.request(method, &url);
+        match std::env::var(TOKEN_VARIABLE) {
+            Ok(token) if !token.is_empty() => {
+                request.set("Authorization", &format!("Bearer {token}"))
+            }
+            _ => request,
+        }
+    }
+}
+
+impl Transport for Https {
+    fn get(&self) -> Result<Option<Vec<u8>>> {
+        let response = match self.request("GET").call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(404, _)) => return Ok(None),
+            Err(error) => {
+                return Err(error).with_context(|| format!("Couldn't download from {}", self.url))
+            }
+        };
+        let mut bytes = Vec::new();
+        std::io::Read::read_to_end(&mut response.into_reader(), &mut bytes)?;
+        Ok(Some(bytes))
+    }
+
+    fn put(&self, bytes: &[u8]) -> Result<()> {
+        self.request("PUT")
+            .set("Content-Type", "application/octet-stream")
+            .send_bytes(bytes)
+            .with_context(|| format!("Couldn't upload to {}", self.url))?;
+        Ok(())
+    }
+}
+
+/// Remote in an S3 compatible bucket
+struct Bucket {
+    client: s3::Client,
+}
+
+impl Transport for Bucket {
+    fn get(&self) -> Result<Option<Vec<u8>>> {
+        self.client.get(BUNDLE_NAME)
+    }
+
+    fn put(&self, bytes: &[u8]) -> Result<()> {
+        self.client.put(BUNDLE_NAME, bytes)
+    }
+}
+
+/// Get the transport of a remote from the scheme of its URL
+pub fn transport(url: &str) -> Result<Box<dyn Transport>> {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        anyhow::bail!("`{url}` isn't a remote URL, e.g. `ssh://host/path`");
+    };
+    match scheme {
+        "file" => Ok(Box::new(Directory {
+            path: PathBuf::from(rest),
+        })),
+        "ssh" => Ok(Box::new(Ssh::parse(rest))),
+        "https" | "http" => Ok(Box::new(Https {
+            url: url.to_string(),
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(api::timeout()))
+                .build(),
+        })),
+        "s3" => Ok(Box::new(Bucket {
+            client: s3::Client::new(s3::Remote::parse(url)?)?,
+        })),
+        _ => anyhow::bail!(
+            "Unknown remote scheme `{scheme}`, expected `file`, `ssh`, `https` or `s3`"
+        ),
+    }
+}
+
+/// Get the identifier of this machine, generating it the first time
+pub fn machine_id(state: &mut State) -> String {
+    state
+        .machine_id
+        .get_or_insert_with(|| {
+            let nanoseconds = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_nanos())
+                .unwrap_or_default();
+            let digest = Sha256::digest(format!("{nanoseconds}-{}", std::process::id()));
+            digest[..6]
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect()
+        })
+        .clone()
+}
+
+/// SHA-256 of the content of a profile
+pub fn hash(credentials: &serde_json::Value) -> String {
+    Sha256::digest(credentials.to_string().as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Versions of a local profile: those it had when last synced, counting one more change by
+/// this machine when its content changed since
+pub fn local_versions(
+    synced: Option<&SyncedProfile>,
+    credentials: &serde_json::Value,
+    machine: &str,
+) -> BTreeMap<String, u64> {
+    match synced {
+        Some(synced) if synced.sha256 == hash(credentials) => synced.versions.clone(),
+        _ => {
+            let mut versions = synced
+                .map(|synced| synced.versions.clone())
+                .unwrap_or_default();
+            *versions.entry(machine.to_string()).or_default() += 1;
+            versions
+        }
+    }
+}
+
+/// Versions holding every change of two others
+pub fn merge(a: &BTreeMap<String, u64>, b: &BTreeMap<String, u64>) -> BTreeMap<String, u64> {
+    let mut merged = a.clone();
+    for (machine, count) in b {
+        let known = merged.entry(machine.clone()).or_default();
+        *known = (*known).max(*count);
+    }
+    merged
+}
+
+/// Which version of a profile known on both sides to keep
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// Both have the same content
+    Same,
+    /// The local version holds every change of the remote one
+    Local,
+    /// The remote version holds every change of the local one
+    Remote,
+    /// Both were changed independently
+    Conflict,
+}
+
+/// Compare the local and remote versions of a profile
+pub fn decide(local: &ArchivedProfile, remote: &ArchivedProfile) -> Decision {
+    if hash(&local.credentials) == hash(&remote.credentials) {
+        return Decision::Same;
+    }
+    let machines = local.versions.keys().chain(remote.versions.keys());
+    let (mut ahead, mut behind) = (false, false);
+    for machine in machines {
+        let local = local.versions.get(machine).copied().unwrap_or_default();
+        let remote = remote.versions.get(machine).copied().unwrap_or_default();
+        ahead |= local > remote;
+        behind |= local < remote;
+    }
+    match (ahead, behind) {
+        (true, false) => Decision::Local,
+        (false, true) => Decision::Remote,
+        _ => Decision::Conflict,
+    }
+}
+
+/// Whether the local version of a profile changed on both sides was written after the remote
+/// one, for the last writer to win
+pub fn written_last(local: &ArchivedProfile, remote: &ArchivedProfile) -> bool {
+    local.modified_at.unwrap_or_default() >= remote.modified_at.unwrap_or_default()
+}
+
+/// Gather every registered profile along with its versions on a remote
+fn local_bundle(store: &ProfileStore, remote: &str, state: &mut State) -> Result<Archive> {
+    let machine = machine_id(state);
+    let mut bundle = Archive::new(&store.profiles()?, state)?;
+    let synced = state.synced.get(remote);
+    for profile in &mut bundle.profiles {
+        profile.versions = local_versions(
+            synced.and_then(|synced| synced.get(&profile.name)),
+            &profile.credentials,
+            &machine,
+        );
+    }
+    Ok(bundle)
+}
+
+/// Remember the profiles as they are on a remote after a sync
+fn record_synced<'a>(
+    remote: &str,
+    synced: impl IntoIterator<Item = &'a ArchivedProfile>,
+    state: &mut State,
+) {
+    let known = state.synced.entry(remote.to_string()).or_default();
+    for profile in synced {
+        known.insert(
+            profile.name.clone(),
+            SyncedProfile {
+                sha256: hash(&profile.credentials),
+                versions: profile.versions.clone(),
+            },
+        );
+    }
+}
+
+/// Outcome of pushing the profiles to a remote
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Pushed {
+    /// How many profiles were sent
+    pub count: usize,
+    /// Names of the profiles whose version on the remote was kept, being newer or preferred
+    pub kept: Vec<String>,
+}
+
+/// Upload an encrypted bundle of every profile of a store to a remote, keeping the profiles
+/// changed there from other machines since the last sync
+///
+/// `passphrase` is told whether the bundle is new, to confirm the passphrase, and `keep_local`
+/// whether to keep the local version of a profile changed both here and on the remote, given
+/// both versions.
+pub fn push(
+    store: &ProfileStore,
+    remote: &str,
+    passphrase: impl FnOnce(bool) -> Result<SecretString>,
+    mut keep_local: impl FnMut(&ArchivedProfile, &ArchivedProfile) -> Result<bool>,
+) -> Result<Pushed> {
+    let transport = transport(remote)?;
+    let current = transport.get()?;
+    let passphrase = passphrase(current.is_none())?;
+    let mut remote_profiles: BTreeMap<String, ArchivedProfile> = match &current {
+        Some(bytes) => Archive::from_bytes(bytes, || Ok(passphrase.clone()))
+            .with_context(|| format!("Couldn't read the profiles of {remote}"))?
+            .profiles
+            .into_iter()
+            .map(|profile| (profile.name.clone(), profile))
+            .collect(),
+        None => BTreeMap::new(),
+    };
+
+    let mut state = State::load(store.directory())?;
+    let mut bundle = local_bundle(store, remote, &mut state)?;
+    let mut pushed = Vec::new();
+    let mut kept = Vec::new();
+    for mut local in std::mem::take(&mut bundle.profiles) {
+        let Some(remote_profile) = remote_profiles.remove(&local.name) else {
+            pushed.push(local);
+            continue;
+        };
+        let keep = match decide(&local, &remote_profile) {
+            Decision::Same | Decision::Local => true,
+            Decision::Remote => false,
+            Decision::Conflict => keep_local(&local, &remote_profile)?,
+        };
+        if keep {
+            local.versions = merge(&local.versions, &remote_profile.versions);
+            pushed.push(local);
+        } else {
+            kept.push(remote_profile);
+        }
+    }
+
+    record_synced(remote, &pushed, &mut state);
+    let outcome = Pushed {
+        count: pushed.len(),
+        kept: kept.iter().map(|profile| profile.name.clone()).collect(),
+    };
+    bundle.profiles = pushed;
+    bundle.profiles.extend(kept);
+    bundle.profiles.extend(remote_profiles.into_values());
+    bundle.profiles.sort_by(|a, b| a.name.cmp(&b.name));
+    transport.put(&bundle.to_bytes(Some(passphrase))?)?;
+    state.save(store.directory())?;
+    Ok(outcome)
+}
+
+/// Download the encrypted bundle of a remote and register the profiles added or changed there
+/// in a store, keeping the ones changed only here, and return what was registered unless the
+/// profiles were up to date
+///
+/// `keep_local` is asked as when pushing.
+pub fn pull(
+    store: &ProfileStore,
+    credentials_file: &CredentialsFile,
+    remote: &str,
+    passphrase: impl FnOnce(bool) -> Result<SecretString>,
+    mut keep_local: impl FnMut(&ArchivedProfile, &ArchivedProfile) -> Result<bool>,
+    backup_retention: usize,
+) -> Result<Option<Restored>> {
+    let transport = transport(remote)?;
+    let Some(bytes) = transport.get()? else {
+        anyhow::bail!(
+            "There are no profiles on {remote} yet, `sync push --remote {remote}` sends them"
+        );
+    };
+    let mut bundle = Archive::from_bytes(&bytes, || passphrase(false))
+        .with_context(|| format!("Couldn't read the profiles of {remote}"))?;
+
+    let project_directory = store.directory();
+    let mut state = State::load(project_directory)?;
+    let mut local_profiles: BTreeMap<String, ArchivedProfile> =
+        local_bundle(store, remote, &mut state)?
+            .profiles
+            .into_iter()
+            .map(|profile| (profile.name.clone(), profile))
+            .collect();
+    let mut pulled = Vec::new();
+    let mut settled = Vec::new();
+    for remote_profile in std::mem::take(&mut bundle.profiles) {
+        let Some(mut local) = local_profiles.remove(&remote_profile.name) else {
+            pulled.push(remote_profile);
+            continue;
+        };
+        let take = match decide(&local, &remote_profile) {
+            Decision::Same | Decision::Local => false,
+            Decision::Remote => true,
+            Decision::Conflict => !keep_local(&local, &remote_profile)?,
+        };
+        if take {
+            pulled.push(remote_profile);
+        } else {
+            local.versions = merge(&local.versions, &remote_profile.versions);
+            settled.push(local);
+        }
+    }
+
+    let restored = if pulled.is_empty() {
+        None
+    } else {
+        let records: Vec<SyncedProfile> = pulled
+            .iter()
+            .map(|profile| SyncedProfile {
+                sha256: hash(&profile.credentials),
+                versions: profile.versions.clone(),
+            })
+            .collect();
+        let names: Vec<String> = pulled.iter().map(|profile| profile.name.clone()).collect();
+        bundle.profiles = pulled;
+        let restored = bundle.restore(
+            store,
+            credentials_file,
+            remote,
+            &archive::Conflicts::Overwrite,
+            backup_retention,
+        )?;
+        // Registering the profiles saved the state, which is read again not to lose that
+        let machine_id = state.machine_id.take();
+        state = State::load(project_directory)?;
+        state.machine_id = machine_id;
+        let known = state.synced.entry(remote.to_string()).or_default();
+        known.extend(names.into_iter().zip(records));
+        Some(restored)
+    };
+    record_synced(remote, &settled, &mut state);
+    state.save(project_directory)?;
+    Ok(restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Versions from how many times each machine changed a profile
+    fn versions(counts: &[(&str, u64)]) -> BTreeMap<String, u64> {
+        counts
+            .iter()
+            .map(|(machine, count)| (machine.to_string(), *count))
+            .collect()
+    }
+
+    /// A profile holding a token, changed as many times as told by each machine
+    fn profile(token: &str, counts: &[(&str, u64)]) -> ArchivedProfile {
+        ArchivedProfile {
+            name: String::from("work"),
+            last_switched: None,
+            credentials: serde_json::json!({"credentials": {"app.terraform.io": {"token": token}}}),
+            modified_at: None,
+            versions: versions(counts),
+        }
+    }
+
+    #[test]
+    fn decides_which_version_to_keep() {
+        let cases = [
+            (
+                "local ahead",
+                profile("new", &[("laptop", 2), ("desktop", 1)]),
+                profile("old", &[("laptop", 1), ("desktop", 1)]),
+                Decision::Local,
+            ),
+            (
+                "remote ahead",
+                profile("old", &[("laptop", 1)]),
+                profile("new", &[("laptop", 1), ("desktop", 1)]),
+                Decision::Remote,
+            ),
+            (
+                "concurrent",
+                profile("laptop", &[("laptop", 2), ("desktop", 1)]),
+                profile("desktop", &[("laptop", 1), ("desktop", 2)]),
+                Decision::Conflict,
+            ),
+            (
+                "same versions, different content",
+                profile("laptop", &[("laptop", 1)]),
+                profile("desktop", &[("laptop", 1)]),
+                Decision::Conflict,
+            ),
+            (
+                "equal",
+                profile("same", &[("laptop", 3)]),
+                profile("same", &[("desktop", 1)]),
+                Decision::Same,
+            ),
+        ];
+        for (case, local, remote, decision) in cases {
+            assert_eq!(decide(&local, &remote), decision, "{case}");
+        }
+    }
+
+    #[test]
+    fn merging_keeps_every_change() {
+        assert_eq!(
+            merge(
+                &versions(&[("laptop", 2), ("desktop", 1)]),
+                &versions(&[("desktop", 3), ("ci", 1)])
+            ),
+            versions(&[("laptop", 2), ("desktop", 3), ("ci", 1)])
+        );
+        assert_eq!(merge(&versions(&[]), &versions(&[])), versions(&[]));
+    }
+
+    #[test]
+    fn counts_the_local_changes_since_the_last_sync() {
+        let credentials = profile("token", &[]).credentials;
+        assert_eq!(
+            local_versions(None, &credentials, "laptop"),
+            versions(&[("laptop", 1)])
+        );
+        let unchanged = SyncedProfile {
+            sha256: hash(&credentials),
+            versions: versions(&[("laptop", 1), ("desktop", 2)]),
+        };
+        assert_eq!(
+            local_versions(Some(&unchanged), &credentials, "laptop"),
+            unchanged.versions
+        );
+        let changed = SyncedProfile {
+            sha256: String::from("stale"),
+            ..unchanged
+        };
+        assert_eq!(
+            local_versions(Some(&changed), &credentials, "laptop"),
+            versions(&[("laptop", 2), ("desktop", 2)])
+        );
+    }
+
+    #[test]
+    fn parses_ssh_remotes() {
+        let cases = [
+            ("host/~/sync", "host", None, "sync"),
+            (
+                "user@host:2222/srv/sync/",
+                "user@host",
+                Some("2222"),
+                "/srv/sync",
+            ),
+            (
+                "user@[2001:db8::1]:2222/srv",
+                "user@2001:db8::1",
+                Some("2222"),
+                "/srv",
+            ),
+            ("[::1]/~/sync", "::1", None, "sync"),
+            ("2001:db8::1/srv", "2001:db8::1", None, "/srv"),
+        ];
+        for (rest, destination, port, directory) in cases {
+            let ssh = Ssh::parse(rest);
+            assert_eq!(ssh.destination, destination, "{rest}");
+            assert_eq!(ssh.port.as_deref(), port, "{rest}");
+            assert_eq!(ssh.directory, directory, "{rest}");
+        }
+    }
+
+    /// A machine: a store holding profiles, along with its credentials file
+    fn machine(
+        home: &std::path::Path,
+        profiles: &[(&str, &str)],
+    ) -> (ProfileStore, CredentialsFile) {
+        let store = ProfileStore::open(home.join("store"));
+        let credentials_file = CredentialsFile::new(home.join(".terraform.d"));
+        std::fs::create_dir_all(store.directory()).unwrap();
+        std::fs::create_dir_all(credentials_file.terraform_directory()).unwrap();
+        for (name, token) in profiles {
+            store
+                .register(
+                    name,
+                    &crate::store::credentials_with_tokens([("app.terraform.io", *token)]),
+                )
+                .unwrap();
+        }
+        (store, credentials_file)
+    }
+
+    /// Passphrase of the bundles of the tests
+    fn passphrase(_confirm: bool) -> Result<SecretString> {
+        Ok(SecretString::from(String::from(
+            "correct horse battery staple",
+        )))
+    }
+
+    /// Token a machine holds for `app.terraform.io` in a profile
+    fn token(store: &ProfileStore, name: &str) -> String {
+        store.tokens(name).unwrap()["app.terraform.io"].clone()
+    }
+
+    #[test]
+    fn pushing_and_pulling_carries_the_changes_over() {
+        let directory = tempfile::tempdir().unwrap();
+        let remote = format!("file://{}", directory.path().join("remote").display());
+        let (laptop, laptop_credentials) =
+            machine(&directory.path().join("laptop"), &[("work", "v1")]);
+        let (desktop, desktop_credentials) = machine(&directory.path().join("desktop"), &[]);
+        let never = |_: &ArchivedProfile, _: &ArchivedProfile| -> Result<bool> {
+            panic!("no profile was changed on both machines")
+        };
+
+        let pushed = push(&laptop, &remote, passphrase, never).unwrap();
+        assert_eq!(
+            pushed,
+            Pushed {
+                count: 1,
+                kept: Vec::new()
+            }
+        );
+        let restored = pull(
+            &desktop,
+            &desktop_credentials,
+            &remote,
+            passphrase,
+            never,
+            0,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(restored.names, ["work"]);
+        assert_eq!(token(&desktop, "work"), "v1");
+
+        crate::store::write_file_atomically(
+            &desktop.profile_path("work"),
+            crate::store::credentials_with_tokens([("app.terraform.io", "v2")]),
+        )
+        .unwrap();
+        assert_eq!(push(&desktop, &remote, passphrase, never).unwrap().count, 1);
+        // The laptop didn't change the profile since, the remote version is newer
+        assert!(
+            pull(&laptop, &laptop_credentials, &remote, passphrase, never, 0)
+                .unwrap()
+                .is_some()
+        );
+        assert_eq!(token(&laptop, "work"), "v2");
+        assert!(
+            pull(&laptop, &laptop_credentials, &remote, passphrase, never, 0)
+                .unwrap()
+                .is_none()
+        );
+    }
+}
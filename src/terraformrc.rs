@@ -0,0 +1,178 @@
+//! Credentials blocks of the HCL CLI configuration, which predates `credentials.tfrc.json`
+
+use std::{
+    collections::BTreeMap,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+/// Get the path of the CLI configuration, which `TF_CLI_CONFIG_FILE` overrides
+pub fn path(home_dir: &Path) -> PathBuf {
+    if let Some(path) = std::env::var_os("TF_CLI_CONFIG_FILE").filter(|path| !path.is_empty()) {
+        return PathBuf::from(path);
+    }
+    if cfg!(target_family = "windows") {
+        std::env::var_os("APPDATA")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| home_dir.to_path_buf())
+            .join("terraform.rc")
+    } else {
+        home_dir.join(".terraformrc")
+    }
+}
+
+/// Read the tokens of the `credentials "<hostname>" { token = "..." }` blocks, by hostname
+pub fn read_tokens(content: &str) -> Result<BTreeMap<String, String>> {
+    let body = hcl::parse(content).context("The CLI configuration isn't valid HCL")?;
+    let mut tokens = BTreeMap::new();
+    for block in body
+        .blocks()
+        .filter(|block| block.identifier() == "credentials")
+    {
+        let [hostname] = block.labels() else {
+            anyhow::bail!("A `credentials` block doesn't have exactly one hostname label");
+        };
+        let token = block
+            .body()
+            .attributes()
+            .find(|attribute| attribute.key() == "token")
+            .and_then(|attribute| match attribute.expr() {
+                hcl::Expression::String(token) => Some(token.clone()),
+                _ => None,
+            })
+            .with_context(|| {
+                format!(
+                    "The `{}` credentials block is missing a `token` string",
+                    hostname.as_str()
+                )
+            })?;
+        tokens.insert(hostname.as_str().to_string(), token);
+    }
+    Ok(tokens)
+}
+
+/// Comment out the credentials blocks line by line, so they no longer take precedence over the
+/// managed credentials while staying around for reference
+pub fn comment_out_credentials(content: &str) -> String {
+    let mut commented = String::with_capacity(content.len());
+    let mut depth: Option<isize> = None;
+    for line in content.split_inclusive('\n') {
+        if depth.is_none() && is_credentials_block_start(line) {
+            depth = Some(0);
+        }
+        match &mut depth {
+            Some(level) => {
+                *level += line.matches('{').count() as isize;
+                *level -= line.matches('}').count() as isize;
+                commented.push_str("# ");
+                commented.push_str(line);
+                if *level <= 0 && line.contains('}') {
+                    depth = None;
+                }
+            }
+            None => commented.push_str(line),
+        }
+    }
+    commented
+}
+
+/// Write a CLI configuration holding a credentials block per hostname, for terraform to read
+/// through `TF_CLI_CONFIG_FILE`
+pub fn render_credentials(tokens: &BTreeMap<String, String>) -> String {
+    let quote = |text: &str| text.replace('\\', "\\\\").replace('"', "\\\"");
+    tokens
+        .iter()
+        .map(|(host, token)| {
+            format!(
+                "credentials \"{}\" {{\n  token = \"{}\"\n}}\n",
+                quote(host),
+                quote(token)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Point terraform to a credentials helper, replacing the `credentials_helper` block already there
+/// as terraform only accepts one
+pub fn set_credentials_helper(content: &str, name: &str) -> String {
+    let block = format!("credentials_helper \"{name}\" {{}}\n");
+    let blocks = credentials_helper_blocks(content);
+    if blocks.is_empty() {
+        let mut updated = content.to_string();
+        if !updated.is_empty() && !updated.ends_with('\n') {
+            updated.push('\n');
+        }
+        updated.push_str(&block);
+        return updated;
+    }
+    let ranges: Vec<Range<usize>> = blocks.into_iter().map(|(range, _)| range).collect();
+    remove_ranges(content, &ranges, Some(&block))
+}
+
+/// Remove the `credentials_helper` block of a helper, leaving the others
+pub fn remove_credentials_helper(content: &str, name: &str) -> String {
+    let ranges: Vec<Range<usize>> = credentials_helper_blocks(content)
+        .into_iter()
+        .filter(|(_, label)| label == name)
+        .map(|(range, _)| range)
+        .collect();
+    remove_ranges(content, &ranges, None)
+}
+
+/// Get where the `credentials_helper` blocks are, line by line, along with their label
+fn credentials_helper_blocks(content: &str) -> Vec<(Range<usize>, String)> {
+    let mut blocks = Vec::new();
+    let mut current: Option<(usize, isize, String)> = None;
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        if current.is_none() && is_credentials_helper_block_start(line) {
+            let label = line.split('"').nth(1).unwrap_or_default().to_string();
+            current = Some((offset, 0, label));
+        }
+        offset += line.len();
+        if let Some((start, level, label)) = &mut current {
+            *level += line.matches('{').count() as isize;
+            *level -= line.matches('}').count() as isize;
+            if *level <= 0 && line.contains('}') {
+                blocks.push((*start..offset, std::mem::take(label)));
+                current = None;
+            }
+        }
+    }
+    if let Some((start, _, label)) = current {
+        blocks.push((start..content.len(), label));
+    }
+    blocks
+}
+
+/// Remove ranges of a content, putting a replacement in place of the first one
+fn remove_ranges(content: &str, ranges: &[Range<usize>], replacement: Option<&str>) -> String {
+    let mut updated = String::with_capacity(content.len());
+    let mut kept_from = 0;
+    for (index, range) in ranges.iter().enumerate() {
+        updated.push_str(&content[kept_from..range.start]);
+        if index == 0 {
+            updated.push_str(replacement.unwrap_or_default());
+        }
+        kept_from = range.end;
+    }
+    updated.push_str(&content[kept_from..]);
+    updated
+}
+
+/// Whether a line opens a `credentials_helper "<name>"` block
+fn is_credentials_helper_block_start(line: &str) -> bool {
+    line.trim_start()
+        .strip_prefix("credentials_helper")
+        .is_some_and(|rest| rest.trim_start().starts_with('"'))
+}
+
+/// Whether a line opens a `credentials "<hostname>"` block
+fn is_credentials_block_start(line: &str) -> bool {
+    line.trim_start()
+        .strip_prefix("credentials")
+        .is_some_and(|rest| rest.trim_start().starts_with('"'))
+}
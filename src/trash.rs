@@ -0,0 +1,104 @@
+//! Profiles removed by `logout`, kept aside until the trash is emptied
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{permissions, state};
+
+/// Name of the directory inside the project directory the removed profiles are kept in
+pub const TRASH_DIRECTORY: &str = "trash";
+
+/// A removed profile
+#[derive(Debug, Serialize)]
+pub struct TrashedProfile {
+    /// Name the profile was registered under
+    pub name: String,
+    /// When the profile was removed, in seconds since the Unix epoch
+    pub deleted_at: u64,
+    /// Path of the profile inside the trash
+    pub path: PathBuf,
+}
+
+/// Path of the trash inside a project directory
+fn directory(project_directory: &Path) -> PathBuf {
+    project_directory.join(TRASH_DIRECTORY)
+}
+
+/// Move a profile to the trash, as `<name>.<timestamp>.tfrc.json`
+pub fn discard(project_directory: &Path, name: &str, profile_path: &Path) -> Result<PathBuf> {
+    let directory = directory(project_directory);
+    if !directory.exists() {
+        std::fs::create_dir(&directory)?;
+        permissions::restrict_directory(&directory)?;
+    }
+    let trashed = directory.join(format!("{name}.{}.tfrc.json", state::now()));
+    tracing::info!("Moving {} to {}", profile_path.display(), trashed.display());
+    std::fs::rename(profile_path, &trashed)?;
+    Ok(trashed)
+}
+
+/// Get the removed profiles, oldest first
+pub fn list(project_directory: &Path) -> Result<Vec<TrashedProfile>> {
+    let directory = directory(project_directory);
+    if !directory.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut trashed = Vec::new();
+    for entry in std::fs::read_dir(&directory)?.flatten() {
+        let file_name = entry.file_name();
+        let Some((name, deleted_at)) = file_name
+            .to_str()
+            .and_then(|file_name| file_name.strip_suffix(".tfrc.json"))
+            .and_then(|stem| stem.rsplit_once('.'))
+        else {
+            continue;
+        };
+        let Ok(deleted_at) = deleted_at.parse() else {
+            continue;
+        };
+        trashed.push(TrashedProfile {
+            name: name.to_string(),
+            deleted_at,
+            path: entry.path(),
+        });
+    }
+    trashed.sort_by(|a, b| (a.deleted_at, &a.name).cmp(&(b.deleted_at, &b.name)));
+    Ok(trashed)
+}
+
+/// Move the most recently removed profile with a name back to the store, returning its path
+pub fn restore(project_directory: &Path, name: &str) -> Result<Option<PathBuf>> {
+    let Some(trashed) = list(project_directory)?
+        .into_iter()
+        .rev()
+        .find(|trashed| trashed.name == name)
+    else {
+        return Ok(None);
+    };
+    let profile_path = project_directory.join(format!("{name}.tfrc.json"));
+    tracing::info!(
+        "Moving {} to {}",
+        trashed.path.display(),
+        profile_path.display()
+    );
+    std::fs::rename(&trashed.path, &profile_path)?;
+    Ok(Some(profile_path))
+}
+
+/// Remove the profiles removed longer ago than a number of seconds, or all of them, returning
+/// how many were removed
+pub fn empty(project_directory: &Path, older_than: Option<u64>) -> Result<usize> {
+    let now = state::now();
+    let mut removed = 0;
+    for trashed in list(project_directory)? {
+        if older_than.is_some_and(|age| now.saturating_sub(trashed.deleted_at) < age) {
+            continue;
+        }
+        tracing::info!("Removing {}", trashed.path.display());
+        std::fs::remove_file(&trashed.path)?;
+        removed += 1;
+    }
+    Ok(removed)
+}
@@ -0,0 +1,397 @@
+//! Full-screen terminal interface over the registered profiles
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style, Stylize},
+    text::Line,
+    widgets::{Block, List, ListItem, ListState, Paragraph},
+    DefaultTerminal, Frame,
+};
+
+use crate::{
+    api, backup,
+    config::{Config, Hook},
+    credentials::{self, Credentials, CredentialsFile},
+    encryption,
+    error::CliError,
+    metadata::Metadata,
+    output, permissions,
+    state::State,
+    store::{self, ProfileStore},
+    trash, Switcher,
+};
+
+/// What the keyboard is currently used for
+enum Mode {
+    /// Browsing the profiles list
+    Normal,
+    /// Typing the name of the profile to import the current credentials as
+    Import(String),
+    /// Typing the new name of the selected profile
+    Rename(String),
+    /// Waiting for the deletion of the selected profile to be confirmed
+    ConfirmDelete,
+}
+
+/// State of the interface
+struct App {
+    terraform_directory: PathBuf,
+    project_directory: PathBuf,
+    config: Config,
+    names: Vec<String>,
+    profiles: HashMap<String, PathBuf>,
+    active: Option<String>,
+    validity: HashMap<String, String>,
+    last_switched: HashMap<String, u64>,
+    list: ListState,
+    mode: Mode,
+    message: String,
+}
+
+/// Run the interface until the user quits
+pub fn run(terraform_directory: &Path, project_directory: &Path, config: &Config) -> Result<()> {
+    if !output::is_interactive() {
+        anyhow::bail!("The interface needs an interactive terminal.");
+    }
+    let mut app = App {
+        terraform_directory: terraform_directory.to_path_buf(),
+        project_directory: project_directory.to_path_buf(),
+        config: config.clone(),
+        names: Vec::new(),
+        profiles: HashMap::new(),
+        active: None,
+        validity: HashMap::new(),
+        last_switched: HashMap::new(),
+        list: ListState::default(),
+        mode: Mode::Normal,
+        message: String::new(),
+    };
+    app.reload()?;
+
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut app);
+    ratatui::restore();
+    result
+}
+
+/// Draw and handle key presses until the user quits
+fn event_loop(terminal: &mut DefaultTerminal, app: &mut App) -> Result<()> {
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+
+        let key = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+        let outcome = match &mut app.mode {
+            Mode::Normal => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Down | KeyCode::Char('j') => {
+                    app.list.select_next();
+                    Ok(())
+                }
+                KeyCode::Up | KeyCode::Char('k') => {
+                    app.list.select_previous();
+                    Ok(())
+                }
+                KeyCode::Enter | KeyCode::Char('s') => app.switch(),
+                KeyCode::Char('v') => app.verify(),
+                KeyCode::Char('i') => {
+                    app.mode = Mode::Import(String::new());
+                    Ok(())
+                }
+                KeyCode::Char('r') if app.selected().is_some() => {
+                    app.mode = Mode::Rename(String::new());
+                    Ok(())
+                }
+                KeyCode::Char('d') if app.selected().is_some() => {
+                    app.mode = Mode::ConfirmDelete;
+                    Ok(())
+                }
+                _ => Ok(()),
+            },
+            Mode::Import(buffer) | Mode::Rename(buffer) => match key.code {
+                KeyCode::Esc => {
+                    app.mode = Mode::Normal;
+                    Ok(())
+                }
+                KeyCode::Backspace => {
+                    buffer.pop();
+                    Ok(())
+                }
+                KeyCode::Char(c) => {
+                    buffer.push(c);
+                    Ok(())
+                }
+                KeyCode::Enter => {
+                    let mode = std::mem::replace(&mut app.mode, Mode::Normal);
+                    match mode {
+                        Mode::Import(name) => app.import(name),
+                        Mode::Rename(name) => app.rename(name),
+                        _ => Ok(()),
+                    }
+                }
+                _ => Ok(()),
+            },
+            Mode::ConfirmDelete => {
+                app.mode = Mode::Normal;
+                match key.code {
+                    KeyCode::Char('y') => app.delete(),
+                    _ => Ok(()),
+                }
+            }
+        };
+        if let Err(e) = outcome {
+            app.message = format!("{e:#}");
+        }
+    }
+}
+
+impl App {
+    /// Re-read the registry and the active profile from disk
+    fn reload(&mut self) -> Result<()> {
+        self.profiles = ProfileStore::open(&self.project_directory).profiles()?;
+        self.names = self.profiles.keys().cloned().collect();
+        self.names.sort();
+        self.active = Credentials::inspect(&self.terraform_directory, &self.project_directory)?
+            .active_profile(&self.profiles)
+            .cloned();
+        self.last_switched = State::load(&self.project_directory)?
+            .last_switched
+            .into_iter()
+            .collect();
+
+        match self.list.selected() {
+            _ if self.names.is_empty() => self.list.select(None),
+            Some(index) if index < self.names.len() => {}
+            _ => self.list.select(Some(0)),
+        }
+        Ok(())
+    }
+
+    /// Name of the highlighted profile
+    fn selected(&self) -> Option<&String> {
+        self.list.selected().and_then(|index| self.names.get(index))
+    }
+
+    /// Point the credentials to the highlighted profile
+    fn switch(&mut self) -> Result<()> {
+        let Some(name) = self.selected().cloned() else {
+            return Ok(());
+        };
+        let switcher = Switcher::new(
+            ProfileStore::open(&self.project_directory),
+            CredentialsFile::new(&self.terraform_directory),
+            self.config.mode,
+        );
+        let previous = switcher.active()?;
+        // The hook output would garble the screen, so it is only shown when the hook fails
+        let run_hook = |hook: Hook| -> Result<Option<String>> {
+            let Some(mut command) = self.config.hooks.command(hook, &name, previous.as_deref())
+            else {
+                return Ok(None);
+            };
+            let output = command.output()?;
+            Ok((!output.status.success())
+                .then(|| String::from_utf8_lossy(&output.stderr).trim().to_string()))
+        };
+        if let Some(error) = run_hook(Hook::PreSwitch)? {
+            self.message = format!("The pre-switch hook failed, staying put: {error}");
+            return Ok(());
+        }
+        switcher.switch(&name)?;
+        self.message = format!("Switched to `{name}`");
+        if let Some(error) = run_hook(Hook::PostSwitch)? {
+            self.message = format!("Switched to `{name}` but the post-switch hook failed: {error}");
+        }
+        self.reload()
+    }
+
+    /// Register the unmanaged credentials file under a new name
+    fn import(&mut self, name: String) -> Result<()> {
+        store::validate_profile_name(&name)?;
+        match Credentials::inspect(&self.terraform_directory, &self.project_directory)? {
+            Credentials::Unmanaged
+            | Credentials::Hardlink { broken: true, .. }
+            | Credentials::Copy { drifted: true, .. } => {}
+            _ => anyhow::bail!("There are no unregistered credentials to import"),
+        }
+        if self.profiles.contains_key(&name) {
+            return Err(CliError::ProfileExists(name).into());
+        }
+        let new_path = self.project_directory.join(format!("{name}.tfrc.json"));
+        std::fs::rename(credentials::path(&self.terraform_directory), &new_path)?;
+        encryption::seal_file(&new_path)?;
+        permissions::restrict_file(&new_path)?;
+        credentials::untrack(&self.project_directory)?;
+        self.message = format!("Imported `{name}`");
+        self.reload()
+    }
+
+    /// Rename the highlighted profile, re-pointing the credentials if it is in use
+    fn rename(&mut self, new_name: String) -> Result<()> {
+        let Some(name) = self.selected().cloned() else {
+            return Ok(());
+        };
+        store::validate_profile_name(&new_name)?;
+        if self.profiles.contains_key(&new_name) {
+            return Err(CliError::ProfileExists(new_name).into());
+        }
+        let new_path = self.project_directory.join(format!("{new_name}.tfrc.json"));
+        std::fs::rename(&self.profiles[&name], &new_path)?;
+        credentials::follow_move(&self.terraform_directory, &self.profiles[&name], &new_path)?;
+        let mut state = State::load(&self.project_directory)?;
+        state.rename_profile(&name, &new_name);
+        state.save(&self.project_directory)?;
+        let mut metadata = Metadata::load(&self.project_directory)?;
+        metadata.rename_profile(&name, &new_name);
+        metadata.save(&self.project_directory)?;
+        if let Some(validity) = self.validity.remove(&name) {
+            self.validity.insert(new_name.clone(), validity);
+        }
+        self.message = format!("Renamed `{name}` to `{new_name}`");
+        self.reload()
+    }
+
+    /// Remove the highlighted profile, and the credentials if it is in use
+    fn delete(&mut self) -> Result<()> {
+        let Some(name) = self.selected().cloned() else {
+            return Ok(());
+        };
+        backup::snapshot(
+            &self.project_directory,
+            &format!("logout {name}"),
+            &[&self.profiles[&name]],
+            self.config.backup_retention,
+        )?;
+        if self.active.as_ref() == Some(&name) {
+            credentials::remove(&self.terraform_directory, &self.project_directory)?;
+        }
+        trash::discard(&self.project_directory, &name, &self.profiles[&name])?;
+        let mut state = State::load(&self.project_directory)?;
+        state.forget_profile(&name);
+        state.save(&self.project_directory)?;
+        let mut metadata = Metadata::load(&self.project_directory)?;
+        metadata.forget_profile(&name);
+        metadata.save(&self.project_directory)?;
+        self.validity.remove(&name);
+        self.message = format!("Removed `{name}`");
+        self.reload()
+    }
+
+    /// Check the tokens of the highlighted profile against the API
+    fn verify(&mut self) -> Result<()> {
+        let Some(name) = self.selected().cloned() else {
+            return Ok(());
+        };
+        let mut statuses = Vec::new();
+        for (host, token) in store::read_profile_tokens(&self.profiles[&name])? {
+            let status = match api::Client::new(&host, &token).token_status() {
+                Ok(status) => status.to_string(),
+                Err(_) => String::from("unreachable"),
+            };
+            statuses.push(format!("{host}: {status}"));
+        }
+        self.validity.insert(name, statuses.join(", "));
+        Ok(())
+    }
+
+    /// Render the whole interface
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, footer] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(3)]).areas(frame.area());
+        let [left, right] =
+            Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)])
+                .areas(main);
+
+        let items: Vec<ListItem> = self
+            .names
+            .iter()
+            .map(|name| {
+                if self.active.as_ref() == Some(name) {
+                    ListItem::new(format!("* {name}")).bold()
+                } else {
+                    ListItem::new(format!("  {name}"))
+                }
+            })
+            .collect();
+        let list = List::new(items)
+            .block(Block::bordered().title(" Profiles "))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, left, &mut self.list);
+
+        frame.render_widget(
+            Paragraph::new(self.details()).block(Block::bordered().title(" Details ")),
+            right,
+        );
+
+        let footer_text = match &self.mode {
+            Mode::Normal if self.message.is_empty() => {
+                String::from("enter: switch  i: import  r: rename  d: delete  v: verify  q: quit")
+            }
+            Mode::Normal => self.message.clone(),
+            Mode::Import(buffer) => format!("Import current credentials as: {buffer}_"),
+            Mode::Rename(buffer) => format!("Rename to: {buffer}_"),
+            Mode::ConfirmDelete => String::from("Delete this profile? (y/N)"),
+        };
+        frame.render_widget(Paragraph::new(footer_text).block(Block::bordered()), footer);
+    }
+
+    /// Describe the highlighted profile
+    fn details(&self) -> Vec<Line<'static>> {
+        let Some(name) = self.selected() else {
+            return vec![Line::from("No profiles is currently available")];
+        };
+        let path = &self.profiles[name];
+        let mut lines = vec![
+            Line::from(format!("Name: {name}")),
+            Line::from(format!(
+                "Active: {}",
+                if self.active.as_ref() == Some(name) {
+                    "yes"
+                } else {
+                    "no"
+                }
+            )),
+        ];
+
+        match store::read_profile_tokens(path) {
+            Ok(tokens) => {
+                lines.push(Line::from("Hostnames:"));
+                lines.extend(tokens.keys().map(|host| Line::from(format!("  {host}"))));
+            }
+            Err(e) => lines.push(Line::from(format!("Invalid credentials: {e:#}"))),
+        }
+        lines.push(Line::from(format!(
+            "Tokens: {}",
+            self.validity
+                .get(name)
+                .map(String::as_str)
+                .unwrap_or("not verified (press v)")
+        )));
+
+        lines.push(Line::from(format!(
+            "Last switched: {}",
+            self.last_switched.get(name).map_or_else(
+                || String::from("never"),
+                |timestamp| crate::format_age(UNIX_EPOCH + Duration::from_secs(*timestamp)),
+            )
+        )));
+        let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified());
+        if let Ok(modified) = modified {
+            lines.push(Line::from(format!(
+                "Modified: {}",
+                crate::format_age(modified)
+            )));
+        }
+        lines
+    }
+}
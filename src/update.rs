@@ -0,0 +1,403 @@
+//! Releases of the CLI published on GitHub, which `self-update` downloads, checks and installs
+//! in place of the running binary
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{api, output, state};
+
+/// Name of the file inside the project directory the latest release is cached in between two
+/// checks
+pub const CACHE_FILE: &str = "update-check.json";
+
+/// Seconds between two checks of the latest release, a day
+const CHECK_INTERVAL: u64 = 24 * 60 * 60;
+
+/// Timeout of the check of the latest release, in seconds, short as it delays a command
+const CHECK_TIMEOUT: u64 = 2;
+
+/// Name of the binary inside the release archives
+#[cfg(target_family = "windows")]
+const BINARY: &str = "terraform-profile.exe";
+#[cfg(target_family = "unix")]
+const BINARY: &str = "terraform-profile";
+
+/// Extension the binary replaced on Windows is renamed with, as it can't be removed while it runs
+const LEFTOVER_EXTENSION: &str = "old";
+
+/// A published release, as the GitHub API describes it
+#[derive(Debug, Deserialize)]
+pub struct Release {
+    /// Tag of the release, e.g. `v0.2.0`
+    pub tag_name: String,
+    /// Files attached to the release
+    pub assets: Vec<Asset>,
+}
+
+/// A file attached to a release
+#[derive(Debug, Deserialize)]
+pub struct Asset {
+    /// Name of the file, e.g. `terraform-profile-x86_64-unknown-linux-gnu.tar.gz`
+    pub name: String,
+    /// Where the file is downloaded from
+    pub browser_download_url: String,
+}
+
+impl Release {
+    /// Version of the release, read from its tag
+    pub fn version(&self) -> Result<semver::Version> {
+        semver::Version::parse(self.tag_name.trim_start_matches('v'))
+            .with_context(|| format!("The release tag `{}` isn't a version", self.tag_name))
+    }
+
+    /// Archive of the binary built for the platform the CLI runs on, a `.tar.gz` or a `.zip`
+    /// named after its target, e.g. `terraform-profile-x86_64-unknown-linux-gnu.tar.gz`
+    pub fn archive(&self) -> Result<&Asset> {
+        let prefix = format!("terraform-profile-{}.", target());
+        self.assets
+            .iter()
+            .find(|asset| matches!(asset.name.strip_prefix(&prefix), Some("tar.gz" | "zip")))
+            .with_context(|| {
+                format!(
+                    "The release {} has no archive for {}",
+                    self.tag_name,
+                    target()
+                )
+            })
+    }
+
+    /// Find an asset by its name
+    fn asset(&self, name: &str) -> Option<&Asset> {
+        self.assets.iter().find(|asset| asset.name == name)
+    }
+}
+
+/// Latest release as last checked, so GitHub is only asked once a day
+#[derive(Debug, Serialize, Deserialize)]
+struct Cache {
+    /// When GitHub was last asked, in seconds since the Unix epoch
+    checked_at: u64,
+    /// Tag of the latest release, empty when it was never fetched
+    latest: String,
+}
+
+/// Tell on stderr when a release newer than the running binary exists, asking GitHub at most
+/// once a day and never failing
+pub fn notify_newer_release(project_directory: &Path) {
+    match newer_release(project_directory) {
+        Ok(Some(version)) => output::notice(format!(
+            "terraform-profile {version} is available, run `self-update` to install it"
+        )),
+        Ok(None) => {}
+        Err(error) => tracing::debug!("Couldn't check for a newer release: {error:#}"),
+    }
+}
+
+/// Get the version of the latest release when it is newer than the running binary, from the
+/// cache unless it is older than a day
+fn newer_release(project_directory: &Path) -> Result<Option<semver::Version>> {
+    let path = project_directory.join(CACHE_FILE);
+    let cached: Option<Cache> = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok());
+    let latest = match cached {
+        Some(cache) if state::now().saturating_sub(cache.checked_at) < CHECK_INTERVAL => {
+            cache.latest
+        }
+        cached => {
+            let latest = match fetch(None, CHECK_TIMEOUT) {
+                Ok(release) => release.tag_name,
+                Err(error) => {
+                    tracing::debug!("Couldn't fetch the latest release: {error:#}");
+                    cached.map(|cache| cache.latest).unwrap_or_default()
+                }
+            };
+            // Cached even when the check failed, so an offline machine isn't slowed down by
+            // every command
+            let cache = Cache {
+                checked_at: state::now(),
+                latest,
+            };
+            std::fs::write(&path, serde_json::to_string_pretty(&cache)?)?;
+            cache.latest
+        }
+    };
+    if latest.is_empty() {
+        return Ok(None);
+    }
+    let version = semver::Version::parse(latest.trim_start_matches('v'))?;
+    Ok((version > current_version()?).then_some(version))
+}
+
+/// Version of the running binary
+pub fn current_version() -> Result<semver::Version> {
+    Ok(semver::Version::parse(env!("CARGO_PKG_VERSION"))?)
+}
+
+/// Target the running binary was built for, as the release archives name it, e.g.
+/// `x86_64-unknown-linux-gnu` or `aarch64-apple-darwin`
+pub fn target() -> String {
+    let system = match std::env::consts::OS {
+        "linux" => "unknown-linux",
+        "macos" => "apple-darwin",
+        "windows" => "pc-windows",
+        other => other,
+    };
+    let environment = if cfg!(target_env = "musl") {
+        "-musl"
+    } else if cfg!(target_env = "gnu") {
+        "-gnu"
+    } else if cfg!(target_env = "msvc") {
+        "-msvc"
+    } else {
+        ""
+    };
+    format!("{}-{system}{environment}", std::env::consts::ARCH)
+}
+
+/// Agent downloading the releases, with a timeout between two reads rather than for the whole
+/// download
+fn agent(timeout: u64) -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout_connect(Duration::from_secs(timeout))
+        .timeout_read(Duration::from_secs(timeout))
+        .user_agent(concat!(
+            env!("CARGO_PKG_NAME"),
+            "/",
+            env!("CARGO_PKG_VERSION")
+        ))
+        .build()
+}
+
+/// Fetch the latest release, or the one of a tag, authenticating with `GITHUB_TOKEN` when set
+/// to get past the rate limit of anonymous requests
+pub fn fetch_release(tag: Option<&str>) -> Result<Release> {
+    fetch(tag, api::timeout())
+}
+
+/// Fetch the latest release, or the one of a tag, with a timeout in seconds
+fn fetch(tag: Option<&str>, timeout: u64) -> Result<Release> {
+    let repository = env!("CARGO_PKG_REPOSITORY").trim_start_matches("https://github.com/");
+    let url = match tag {
+        Some(tag) => format!("https://api.github.com/repos/{repository}/releases/tags/{tag}"),
+        None => format!("https://api.github.com/repos/{repository}/releases/latest"),
+    };
+    tracing::info!("GET {url}");
+    let mut request = agent(timeout)
+        .get(&url)
+        .set("Accept", "application/vnd.github+json");
+    if let Some(token) = std::env::var("GITHUB_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+    {
+        request = request.set("Authorization", &format!("Bearer {token}"));
+    }
+    let body = request
+        .call()
+        .with_context(|| format!("Couldn't fetch the release from {url}"))?
+        .into_string()?;
+    serde_json::from_str(&body).with_context(|| format!("Invalid release from {url}"))
+}
+
+/// Download an asset of a release into a directory
+pub fn download(asset: &Asset, directory: &Path) -> Result<PathBuf> {
+    tracing::info!("GET {}", asset.browser_download_url);
+    let response = agent(api::timeout())
+        .get(&asset.browser_download_url)
+        .call()
+        .with_context(|| format!("Couldn't download {}", asset.name))?;
+    let path = directory.join(&asset.name);
+    let mut file = std::fs::File::create(&path)?;
+    std::io::copy(&mut response.into_reader(), &mut file)
+        .with_context(|| format!("Couldn't download {}", asset.name))?;
+    Ok(path)
+}
+
+/// Check the SHA-256 of a downloaded archive against the `<archive>.sha256` asset of its
+/// release, which starts with the hexadecimal digest as `sha256sum` prints it
+pub fn verify_checksum(release: &Release, archive: &Asset, path: &Path) -> Result<()> {
+    let checksum_name = format!("{}.sha256", archive.name);
+    let checksum_asset = release.asset(&checksum_name).with_context(|| {
+        format!(
+            "The release {} has no `{checksum_name}`, refusing an archive that can't be checked",
+            release.tag_name
+        )
+    })?;
+    let checksum_path = download(checksum_asset, path.parent().unwrap_or(Path::new(".")))?;
+    let expected = std::fs::read_to_string(&checksum_path)?
+        .split_whitespace()
+        .next()
+        .map(str::to_lowercase)
+        .with_context(|| format!("`{checksum_name}` is empty"))?;
+    let actual = format!("{:x}", Sha256::digest(std::fs::read(path)?));
+    if actual != expected {
+        anyhow::bail!(
+            "The checksum of {} is {actual} instead of {expected}, it was corrupted or tampered with",
+            archive.name
+        );
+    }
+    tracing::info!("The SHA-256 of {} is {actual}, as expected", archive.name);
+    Ok(())
+}
+
+/// Check the `<archive>.asc` asset of its release is a GnuPG signature of a downloaded archive,
+/// made by the key with the fingerprint or one of its subkeys
+///
+/// The key has to be in the keyring already, as trusting it is up to the user
+pub fn verify_signature(
+    release: &Release,
+    archive: &Asset,
+    path: &Path,
+    fingerprint: &str,
+) -> Result<()> {
+    let signature_name = format!("{}.asc", archive.name);
+    let signature_asset = release.asset(&signature_name).with_context(|| {
+        format!(
+            "The release {} has no `{signature_name}` while `update_signing_key` is set",
+            release.tag_name
+        )
+    })?;
+    let signature_path = download(signature_asset, path.parent().unwrap_or(Path::new(".")))?;
+    tracing::info!("Running `gpg --verify {signature_name} {}`", archive.name);
+    let output = Command::new("gpg")
+        .args(["--batch", "--status-fd", "1", "--verify"])
+        .arg(&signature_path)
+        .arg(path)
+        .output()
+        .context("Couldn't run `gpg`, is GnuPG installed?")?;
+    let expected: String = fingerprint
+        .chars()
+        .filter(|character| !character.is_whitespace())
+        .collect::<String>()
+        .to_uppercase();
+    // `VALIDSIG <fingerprint> ... <primary key fingerprint>`
+    let signed = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.strip_prefix("[GNUPG:] VALIDSIG "))
+        .any(|fields| {
+            let fields: Vec<&str> = fields.split_whitespace().collect();
+            fields.first() == Some(&expected.as_str()) || fields.last() == Some(&expected.as_str())
+        });
+    if !output.status.success() || !signed {
+        anyhow::bail!(
+            "{} isn't signed by the key {expected}: {}",
+            archive.name,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Extract the binary of a release archive into a directory, with `tar`, which also reads the
+/// `.zip` archives on Windows
+pub fn extract(archive: &Path, directory: &Path) -> Result<PathBuf> {
+    let destination = directory.join("extracted");
+    std::fs::create_dir(&destination)?;
+    tracing::info!("Running `tar -xf {}`", archive.display());
+    let status = Command::new("tar")
+        .arg("-xf")
+        .arg(archive)
+        .arg("-C")
+        .arg(&destination)
+        .status()
+        .context("Couldn't run `tar`")?;
+    if !status.success() {
+        anyhow::bail!(
+            "`tar` exited with {status} extracting {}",
+            archive.display()
+        );
+    }
+    find_binary(&destination)?.with_context(|| {
+        format!(
+            "{} doesn't hold a `{BINARY}` binary",
+            archive.file_name().unwrap_or_default().to_string_lossy()
+        )
+    })
+}
+
+/// Find the binary in an extracted archive, at its root or in the directory it wraps it in
+fn find_binary(directory: &Path) -> Result<Option<PathBuf>> {
+    let candidate = directory.join(BINARY);
+    if candidate.is_file() {
+        return Ok(Some(candidate));
+    }
+    for entry in std::fs::read_dir(directory)?.flatten() {
+        if entry.file_type()?.is_dir() {
+            if let Some(binary) = find_binary(&entry.path())? {
+                return Ok(Some(binary));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Put a binary in place of the running one, returning the path of the latter
+///
+/// The new binary is written next to the running one then renamed over it, which running
+/// processes don't notice. Windows refuses to replace a running executable but lets it be
+/// renamed, so it is moved aside first, and removed by the next update.
+pub fn replace_executable(binary: &Path) -> Result<PathBuf> {
+    let executable = std::env::current_exe()?.canonicalize()?;
+    let directory = executable
+        .parent()
+        .context("The running binary isn't in a directory")?;
+    let mut staged = tempfile::Builder::new()
+        .prefix(".terraform-profile-")
+        .tempfile_in(directory)
+        .with_context(|| {
+            format!(
+                "Couldn't write to {}, was the CLI installed by a package manager?",
+                directory.display()
+            )
+        })?;
+    std::io::copy(&mut std::fs::File::open(binary)?, staged.as_file_mut())?;
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        staged
+            .as_file()
+            .set_permissions(std::fs::Permissions::from_mode(0o755))?;
+    }
+    #[cfg(target_family = "windows")]
+    {
+        let leftover = leftover_path(&executable);
+        tracing::info!("Moving {} to {}", executable.display(), leftover.display());
+        std::fs::rename(&executable, &leftover)?;
+    }
+    tracing::info!("Replacing {}", executable.display());
+    if let Err(error) = staged.persist(&executable) {
+        #[cfg(target_family = "windows")]
+        std::fs::rename(leftover_path(&executable), &executable)?;
+        return Err(error.into());
+    }
+    Ok(executable)
+}
+
+/// Path the binary replaced on Windows is moved to
+fn leftover_path(executable: &Path) -> PathBuf {
+    let mut path = executable.as_os_str().to_os_string();
+    path.push(".");
+    path.push(LEFTOVER_EXTENSION);
+    PathBuf::from(path)
+}
+
+/// Remove the binary a previous update moved aside, once it no longer runs
+pub fn remove_leftover() {
+    let Ok(executable) = std::env::current_exe().and_then(|path| path.canonicalize()) else {
+        return;
+    };
+    let leftover = leftover_path(&executable);
+    if leftover.exists() {
+        tracing::info!("Removing {}", leftover.display());
+        if let Err(error) = std::fs::remove_file(&leftover) {
+            tracing::warn!("Couldn't remove {}: {error}", leftover.display());
+        }
+    }
+}
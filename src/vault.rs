@@ -0,0 +1,287 @@
+//! Profiles whose tokens are kept in a HashiCorp Vault KV version 2 secret, which the store
+//! references as `vault://<mount>/<path>/<name>` in place of each token
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+
+use crate::{api, config};
+
+/// Prefix of the tokens kept in Vault, followed by the path of their secret
+const URL_PREFIX: &str = "vault://";
+
+/// Vault the profiles are shared through, set once from the configuration
+static SETTINGS: OnceLock<config::Vault> = OnceLock::new();
+
+/// Share the profiles through the configured Vault, if any
+pub fn configure(vault: Option<&config::Vault>) {
+    if let Some(vault) = vault {
+        let _ = SETTINGS.set(vault.clone());
+    }
+}
+
+/// Whether profiles are shared through Vault
+pub fn is_enabled() -> bool {
+    SETTINGS.get().is_some()
+}
+
+/// Client of the Vault API, authenticated as the ambient `VAULT_TOKEN` or token helper
+struct Client {
+    address: String,
+    token: String,
+    agent: ureq::Agent,
+}
+
+impl Client {
+    /// Create a client for the configured address, or else `VAULT_ADDR`
+    fn new() -> Result<Self> {
+        let address = SETTINGS
+            .get()
+            .and_then(|settings| settings.address.clone())
+            .or_else(|| std::env::var("VAULT_ADDR").ok())
+            .filter(|address| !address.is_empty())
+            .context("No Vault address is configured, set `VAULT_ADDR`")?;
+        let agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_secs(api::timeout()))
+            .build();
+        Ok(Client {
+            address: address.trim_end_matches('/').to_string(),
+            token: token()?,
+            agent,
+        })
+    }
+
+    /// Build an authenticated request on a path of a KV version 2 engine, the first segment of
+    /// `path` being its mount
+    fn request(&self, method: &str, kind: &str, path: &str) -> ureq::Request {
+        let (mount, path) = path.split_once('/').unwrap_or((path, ""));
+        let url = format!("{}/v1/{mount}/{kind}/{path}", self.address);
+        tracing::info!("{method} {url}");
+        self.agent
+            .request(method, &url)
+            .set("X-Vault-Token", &self.token)
+    }
+
+    /// Read the tokens of a secret by hostname, or nothing when it doesn't exist
+    fn read(&self, path: &str) -> Result<Option<BTreeMap<String, String>>> {
+        let response = match self.request("GET", "data", path).call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(404, _)) => return Ok(None),
+            Err(error) => return Err(error).with_context(|| format!("Couldn't read {path}")),
+        };
+        let body: serde_json::Value = serde_json::from_str(&response.into_string()?)?;
+        let tokens = body["data"]["data"]
+            .as_object()
+            .with_context(|| format!("The secret {path} is missing its data"))?
+            .iter()
+            .filter_map(|(host, token)| Some((host.clone(), token.as_str()?.to_string())))
+            .collect();
+        Ok(Some(tokens))
+    }
+
+    /// Write the tokens of a secret by hostname, as a new version of it
+    fn write(&self, path: &str, tokens: &BTreeMap<String, String>) -> Result<()> {
+        self.request("POST", "data", path)
+            .set("Content-Type", "application/json")
+            .send_string(&serde_json::json!({ "data": tokens }).to_string())
+            .with_context(|| format!("Couldn't write {path}"))?;
+        Ok(())
+    }
+
+    /// List the secrets under a path, which is empty when there are none
+    fn list(&self, path: &str) -> Result<Vec<String>> {
+        let response = match self
+            .request("GET", "metadata", path)
+            .query("list", "true")
+            .call()
+        {
+            Ok(response) => response,
+            Err(ureq::Error::Status(404, _)) => return Ok(Vec::new()),
+            Err(error) => return Err(error).with_context(|| format!("Couldn't list {path}")),
+        };
+        let body: serde_json::Value = serde_json::from_str(&response.into_string()?)?;
+        Ok(body["data"]["keys"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|key| key.as_str())
+            // Keys ending with a slash are folders rather than secrets
+            .filter(|key| !key.ends_with('/'))
+            .map(str::to_string)
+            .collect())
+    }
+}
+
+/// Get the ambient Vault token, from `VAULT_TOKEN`, the configured token helper or the file the
+/// default helper keeps it in
+fn token() -> Result<String> {
+    if let Some(token) = std::env::var("VAULT_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+    {
+        return Ok(token);
+    }
+    let home_dir = home::home_dir().context("Impossible to get your home dir!")?;
+    let config_file = std::env::var_os("VAULT_CONFIG_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home_dir.join(".vault"));
+    if let Some(helper) = token_helper(&config_file)? {
+        tracing::info!("Running the Vault token helper {}", helper.display());
+        let output = std::process::Command::new(&helper)
+            .arg("get")
+            .output()
+            .with_context(|| format!("Couldn't run the token helper {}", helper.display()))?;
+        if !output.status.success() {
+            anyhow::bail!("The Vault token helper exited with {}", output.status);
+        }
+        return Ok(String::from_utf8(output.stdout)?.trim().to_string());
+    }
+    let token_file = home_dir.join(".vault-token");
+    match std::fs::read_to_string(&token_file) {
+        Ok(token) if !token.trim().is_empty() => Ok(token.trim().to_string()),
+        _ => anyhow::bail!("No Vault token was found, set `VAULT_TOKEN` or run `vault login`"),
+    }
+}
+
+/// Get the token helper set in the Vault CLI configuration, if any
+fn token_helper(config_file: &Path) -> Result<Option<PathBuf>> {
+    let Ok(content) = std::fs::read_to_string(config_file) else {
+        return Ok(None);
+    };
+    let body = hcl::parse(&content)
+        .with_context(|| format!("Couldn't parse {}", config_file.display()))?;
+    Ok(body
+        .attributes()
+        .find(|attribute| attribute.key() == "token_helper")
+        .and_then(|attribute| match attribute.expr() {
+            hcl::Expression::String(helper) => Some(PathBuf::from(helper)),
+            _ => None,
+        }))
+}
+
+/// Path of the secret of a profile under the configured path
+fn secret_path(name: &str) -> Result<String> {
+    let settings = SETTINGS
+        .get()
+        .context("No `[vault]` is configured to share the profiles through")?;
+    Ok(format!("{}/{name}", settings.path.trim_matches('/')))
+}
+
+/// Build a profile referencing the secret at a path for the tokens of every hostname
+fn skeleton<'a>(path: &str, hostnames: impl Iterator<Item = &'a String>) -> Result<String> {
+    let hosts: serde_json::Map<String, serde_json::Value> = hostnames
+        .map(|host| {
+            (
+                host.clone(),
+                serde_json::json!({ "token": format!("{URL_PREFIX}{path}") }),
+            )
+        })
+        .collect();
+    Ok(serde_json::to_string_pretty(
+        &serde_json::json!({ "credentials": hosts }),
+    )?)
+}
+
+/// Get the paths of the secrets a stored profile references, by hostname
+fn references(content: &str) -> BTreeMap<String, String> {
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(content) else {
+        return BTreeMap::new();
+    };
+    value["credentials"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(host, entry)| {
+            let path = entry["token"].as_str()?.strip_prefix(URL_PREFIX)?;
+            Some((host.clone(), path.to_string()))
+        })
+        .collect()
+}
+
+/// Whether a stored profile references tokens kept in Vault
+pub fn is_skeleton(content: &str) -> bool {
+    !references(content).is_empty()
+}
+
+/// Replace the references of a stored profile with the tokens they point to
+pub fn resolve(content: &str) -> Result<String> {
+    let client = Client::new()?;
+    let mut secrets: HashMap<String, BTreeMap<String, String>> = HashMap::new();
+    let mut value: serde_json::Value = serde_json::from_str(content)?;
+    for (host, path) in references(content) {
+        if !secrets.contains_key(&path) {
+            let secret = client
+                .read(&path)?
+                .with_context(|| format!("The Vault secret {path} doesn't exist"))?;
+            secrets.insert(path.clone(), secret);
+        }
+        let token = secrets[&path]
+            .get(&host)
+            .with_context(|| format!("The Vault secret {path} has no token for {host}"))?;
+        value["credentials"][&host]["token"] = serde_json::Value::String(token.clone());
+    }
+    crate::store::format_like(content, &value)
+}
+
+/// Write the tokens of a profile to the secret a stored profile references, returning the
+/// profile to store in its place
+pub fn store(stored: &str, plaintext: &str) -> Result<String> {
+    let path = references(stored)
+        .into_values()
+        .next()
+        .context("The profile doesn't reference any Vault secret")?;
+    write(&path, plaintext)
+}
+
+/// Write the tokens of a profile to its secret under the configured path, returning the
+/// profile to store in its place
+pub fn push(name: &str, plaintext: &str) -> Result<String> {
+    write(&secret_path(name)?, plaintext)
+}
+
+/// Write the tokens of a profile to a secret, returning the profile referencing it
+fn write(path: &str, plaintext: &str) -> Result<String> {
+    let value: serde_json::Value =
+        serde_json::from_str(plaintext).context("The profile isn't valid JSON")?;
+    let tokens: BTreeMap<String, String> = value["credentials"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(host, entry)| Some((host.clone(), entry["token"].as_str()?.to_string())))
+        .collect();
+    if tokens.is_empty() {
+        anyhow::bail!("The profile holds no token");
+    }
+    Client::new()?.write(path, &tokens)?;
+    skeleton(path, tokens.keys())
+}
+
+/// List the profiles kept under the configured path, if any
+pub fn remote_profiles() -> Result<Vec<String>> {
+    match SETTINGS.get() {
+        Some(settings) => Client::new()?.list(settings.path.trim_matches('/')),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Store a profile referencing the secret of a name under the configured path, returning its
+/// path, or nothing when there is no such secret
+pub fn fetch(name: &str, project_directory: &Path) -> Result<Option<PathBuf>> {
+    crate::store::validate_profile_name(name)?;
+    if !is_enabled() {
+        return Ok(None);
+    }
+    let path = secret_path(name)?;
+    let Some(tokens) = Client::new()?.read(&path)? else {
+        return Ok(None);
+    };
+    let profile_path = project_directory.join(format!("{name}.tfrc.json"));
+    crate::store::write_file_atomically(&profile_path, skeleton(&path, tokens.keys())?)?;
+    crate::permissions::restrict_file(&profile_path)?;
+    Ok(Some(profile_path))
+}
@@ -0,0 +1,125 @@
+//! Following the changes of the credentials, as `watch` does, to tell when they stop being a
+//! registered profile and register the unmanaged credentials replacing it
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::{credentials::Credentials, state, switcher::Switcher};
+
+/// What the credentials became after they stopped being a registered profile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// The credentials were removed
+    Removed,
+    /// The credentials were replaced by unmanaged ones, e.g. by `terraform login`
+    Replaced,
+    /// The credentials were replaced by unmanaged ones, which were registered under a new name,
+    /// unless they were gone by then
+    Adopted {
+        /// Name of the new profile, `adopted-<seconds since the Unix epoch>`
+        name: String,
+        /// Path of the new profile
+        profile_path: Option<PathBuf>,
+    },
+}
+
+/// Looks at the credentials whenever they change, remembering the last profile they were
+#[derive(Debug, Clone)]
+pub struct CredentialsWatch {
+    switcher: Switcher,
+    managed: Option<String>,
+}
+
+impl CredentialsWatch {
+    /// Start following the credentials a switcher puts the profiles in place of
+    pub fn new(switcher: Switcher) -> Result<Self> {
+        let managed = switcher.active()?;
+        Ok(CredentialsWatch { switcher, managed })
+    }
+
+    /// Get the name of the profile the credentials were last seen being
+    pub fn managed(&self) -> Option<&str> {
+        self.managed.as_deref()
+    }
+
+    /// Look at the credentials after they changed, telling when they are no longer a registered
+    /// profile, and registering the unmanaged credentials replacing it when `adopt` is set
+    ///
+    /// Links to unknown files and credentials helpers are left alone, as someone put them there
+    /// on purpose.
+    pub fn check(&mut self, adopt: bool, backup_retention: usize) -> Result<Option<Change>> {
+        let store = self.switcher.store();
+        let current = self.switcher.credentials().inspect(store)?;
+        if let Some(name) = current.active_profile(&store.profiles()?) {
+            self.managed = Some(name.clone());
+            return Ok(None);
+        }
+        Ok(match current {
+            Credentials::Symlink(_) | Credentials::Helper(_) => None,
+            Credentials::Missing => Some(Change::Removed),
+            _ if adopt => {
+                let name = format!("adopted-{}", state::now());
+                let profile_path = self.switcher.adopt(&name, backup_retention)?;
+                Some(Change::Adopted { name, profile_path })
+            }
+            _ => Some(Change::Replaced),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::SwitchMode, CredentialsFile, ProfileStore};
+
+    /// Credentials holding a single token for `app.terraform.io`
+    const CREDENTIALS: &str = r#"{"credentials": {"app.terraform.io": {"token": "secret"}}}"#;
+
+    /// A watch of credentials linking to the work profile
+    fn watch() -> (tempfile::TempDir, CredentialsWatch) {
+        let home = tempfile::tempdir().unwrap();
+        let store = ProfileStore::open(home.path().join("store"));
+        let credentials_file = CredentialsFile::new(home.path().join(".terraform.d"));
+        std::fs::create_dir_all(store.directory()).unwrap();
+        std::fs::create_dir_all(credentials_file.terraform_directory()).unwrap();
+        store.register("work", CREDENTIALS).unwrap();
+        let switcher = Switcher::new(store, credentials_file, SwitchMode::Symlink);
+        switcher.switch("work").unwrap();
+        (home, CredentialsWatch::new(switcher).unwrap())
+    }
+
+    #[test]
+    fn tells_when_the_credentials_are_replaced_or_removed() {
+        let (_home, mut watch) = watch();
+        assert_eq!(watch.managed(), Some("work"));
+        assert_eq!(watch.check(false, 0).unwrap(), None);
+
+        let credentials_files = watch.switcher.credentials().path();
+        std::fs::remove_file(&credentials_files).unwrap();
+        assert_eq!(watch.check(false, 0).unwrap(), Some(Change::Removed));
+        std::fs::write(&credentials_files, CREDENTIALS).unwrap();
+        assert_eq!(watch.check(false, 0).unwrap(), Some(Change::Replaced));
+        assert_eq!(watch.managed(), Some("work"));
+    }
+
+    #[test]
+    fn adopts_the_replacing_credentials() {
+        let (_home, mut watch) = watch();
+        let credentials_files = watch.switcher.credentials().path();
+        std::fs::remove_file(&credentials_files).unwrap();
+        std::fs::write(&credentials_files, CREDENTIALS).unwrap();
+
+        let Some(Change::Adopted { name, profile_path }) = watch.check(true, 0).unwrap() else {
+            panic!("The credentials weren't adopted");
+        };
+        assert!(name.starts_with("adopted-"));
+        assert_eq!(
+            profile_path,
+            Some(watch.switcher.store().profile_path(&name))
+        );
+        // The profile to put back is still the one the credentials were before
+        assert_eq!(watch.managed(), Some("work"));
+        assert_eq!(watch.switcher.active().unwrap(), Some(name));
+    }
+}